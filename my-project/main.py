@@ -1,2 +1,19 @@
 def add(a: int, b: int) -> int:
     return a + b
+
+
+def divide(a: float, b: float) -> float:
+    return a / b
+
+
+def summarize(request: dict) -> dict:
+    return {"total": sum(request["numbers"]), "label": request["label"].upper()}
+
+
+class Counter:
+    def __init__(self, start: int = 0):
+        self.value = start
+
+    def increment(self, by: int = 1) -> int:
+        self.value += by
+        return self.value
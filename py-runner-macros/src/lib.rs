@@ -0,0 +1,138 @@
+//! Procedural macros of `py-runner`, used through its re-exports
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
+use syn::{
+    Error, FnArg, GenericArgument, ItemTrait, Pat, PathArguments, ReturnType, TraitItem,
+    TraitItemFn, Type, parse_macro_input,
+};
+
+/// Implements the trait for `PythonModule`, every method calls the Python function of the same
+/// name
+///
+/// Arguments are passed positionally, references are converted to owned values first. A method
+/// returning `Result<T, E>` extracts `T` and converts the error with `E: From<PyRunnerError>`,
+/// any other return type is extracted directly and panics when the call fails. Methods with a
+/// default body are kept as they are.
+///```rs
+/// #[py_impl]
+/// trait Calculator {
+///     fn add(&self, a: i64, b: i64) -> i64;
+///     fn parse(&self, text: &str) -> Result<f64, PyRunnerError>;
+/// }
+///
+/// let calculator: Box<dyn Calculator> = Box::new(PythonModule::new_module("./my-module")?);
+/// ```
+#[proc_macro_attribute]
+pub fn py_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemTrait);
+    let implementation = if attr.is_empty() {
+        implementation(&item)
+    } else {
+        Err(Error::new(
+            TokenStream2::from(attr).span(),
+            "py_impl takes no arguments",
+        ))
+    };
+    let implementation = implementation.unwrap_or_else(Error::into_compile_error);
+    quote!(#item #implementation).into()
+}
+
+fn implementation(item: &ItemTrait) -> syn::Result<TokenStream2> {
+    if !item.generics.params.is_empty() {
+        return Err(Error::new(
+            item.generics.span(),
+            "py_impl doesn't support generic traits",
+        ));
+    }
+    let mut methods = Vec::new();
+    for trait_item in &item.items {
+        match trait_item {
+            TraitItem::Fn(function) if function.default.is_none() => {
+                methods.push(method(function)?)
+            }
+            TraitItem::Fn(_) => {}
+            other => {
+                return Err(Error::new(other.span(), "py_impl only implements methods"));
+            }
+        }
+    }
+    let name = &item.ident;
+    Ok(quote! {
+        impl #name for ::py_runner::PythonModule {
+            #(#methods)*
+        }
+    })
+}
+
+fn method(function: &TraitItemFn) -> syn::Result<TokenStream2> {
+    let signature = &function.sig;
+    if signature.asyncness.is_some() || !signature.generics.params.is_empty() {
+        return Err(Error::new(
+            signature.span(),
+            "py_impl doesn't support async or generic methods",
+        ));
+    }
+    if signature.receiver().is_none() {
+        return Err(Error::new(
+            signature.span(),
+            "py_impl methods need a self receiver",
+        ));
+    }
+    let mut args = Vec::new();
+    for input in &signature.inputs {
+        let FnArg::Typed(arg) = input else { continue };
+        let Pat::Ident(pat) = &*arg.pat else {
+            return Err(Error::new(arg.pat.span(), "py_impl needs named arguments"));
+        };
+        let ident = &pat.ident;
+        args.push(match &*arg.ty {
+            Type::Reference(_) => quote!(::std::borrow::ToOwned::to_owned(#ident)),
+            _ => quote!(#ident),
+        });
+    }
+    let name = signature.ident.unraw().to_string();
+    let call = quote!(::py_runner::Call::new(#name) #(.arg(#args))*);
+    let failed = quote!(|err| panic!("{}() failed: {}", #name, err));
+    let discard = quote!(self.action(move |_, module| call.invoke(module).map(|_| ())));
+
+    let body = match &signature.output {
+        ReturnType::Default => quote!(#discard.unwrap_or_else(#failed)),
+        ReturnType::Type(_, ty) => match result_value(ty) {
+            Some(value) if is_unit(value) => quote!(#discard.map_err(::std::convert::Into::into)),
+            Some(value) => quote! {
+                self.call::<#value>(call).map_err(::std::convert::Into::into)
+            },
+            None if is_unit(ty) => quote!(#discard.unwrap_or_else(#failed)),
+            None => quote!(self.call::<#ty>(call).unwrap_or_else(#failed)),
+        },
+    };
+    Ok(quote! {
+        #signature {
+            let call = #call;
+            #body
+        }
+    })
+}
+
+/// `T` of a `Result<T, ..>` return type
+fn result_value(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Tuple(tuple) if tuple.elems.is_empty())
+}
@@ -0,0 +1,272 @@
+use crate::PyRunnerError;
+use crate::bytes::owned_memoryview;
+use ndarray::{Array, Array1, ArrayD, Dimension, IxDyn};
+use numpy::PyArray;
+use pyo3::buffer::{Element, PyBuffer, ReadOnlyCell};
+use pyo3::prelude::*;
+
+/// Element type of a [`SharedArray`], with its native `struct` format
+pub trait ArrayElement: Element + numpy::Element + Copy + Send + 'static {
+    #[doc(hidden)]
+    const FORMAT: &'static str;
+}
+
+macro_rules! array_element {
+    ($($ty:ty => $format:literal),*) => {
+        $(impl ArrayElement for $ty {
+            const FORMAT: &'static str = $format;
+        })*
+    };
+}
+
+array_element!(
+    f32 => "f", f64 => "d",
+    i8 => "b", i16 => "h", i32 => "i", i64 => "q",
+    u8 => "B", u16 => "H", u32 => "I", u64 => "Q"
+);
+
+/// `ndarray` array handed to Python without copying its data
+///
+/// Converts to a `numpy.ndarray` that takes over the memory of the array, which is freed
+/// once Python drops the last array referencing it. Use
+/// [`into_memoryview`](Self::into_memoryview) where numpy isn't installed.
+///```rs
+/// let weights = SharedArray::from(ndarray::Array2::<f32>::zeros((1024, 1024)));
+/// module.action(move |_, module| module.call_method1("predict", (weights,))?.extract::<f32>())?;
+/// ```
+pub struct SharedArray<T> {
+    array: ArrayD<T>,
+}
+
+impl<T: ArrayElement> SharedArray<T> {
+    /// One-dimensional array of `data`
+    pub fn new(data: Vec<T>) -> Self {
+        Self {
+            array: Array1::from_vec(data).into_dyn(),
+        }
+    }
+
+    /// Array of `data` in row-major order, fails if `shape` doesn't match its length
+    pub fn with_shape(data: Vec<T>, shape: impl Into<Vec<usize>>) -> Result<Self, PyRunnerError> {
+        let shape = shape.into();
+        let len = data.len();
+        let array = ArrayD::from_shape_vec(IxDyn(&shape), data).map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Shape {shape:?} doesn't match {len} elements"
+            ))
+        })?;
+        Ok(Self { array })
+    }
+
+    /// `memoryview` of the array, e.g. for `numpy.frombuffer` or `array.array`
+    ///
+    /// Arrays not in row-major order, like transposed ones, are copied into it first.
+    pub fn into_memoryview(self, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        let array = match self.array.is_standard_layout() {
+            true => self.array,
+            false => self.array.as_standard_layout().into_owned(),
+        };
+        let shape = array.shape().to_vec();
+        let len = array.len() * std::mem::size_of::<T>();
+        let (mut data, offset) = array.into_raw_vec_and_offset();
+        let address = data.as_mut_ptr().wrapping_add(offset.unwrap_or(0)) as usize;
+        // the `Vec` is owned by the view alone, so Python may write to it
+        owned_memoryview(py, data, address, len, false)?.call_method1("cast", (T::FORMAT, shape))
+    }
+}
+
+impl<T, D: Dimension> From<Array<T, D>> for SharedArray<T> {
+    fn from(array: Array<T, D>) -> Self {
+        Self {
+            array: array.into_dyn(),
+        }
+    }
+}
+
+impl<'py, T: ArrayElement> IntoPyObject<'py> for SharedArray<T> {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(PyArray::from_owned_array(py, self.array).into_any())
+    }
+}
+
+/// View of a contiguous Python buffer like a `numpy.ndarray`, read without copying
+///
+/// Extracting fails if the buffer isn't C-contiguous or its elements aren't of type `T`.
+/// `numpy::PyReadonlyArray` gives an `ndarray` view of numpy arrays instead.
+///```rs
+/// let sum = module.action(|_, module| {
+///     let array: ArrayView<f64> = module.call_method0("embeddings")?.extract()?;
+///     Ok(array.as_slice().iter().map(|value| value.get()).sum::<f64>())
+/// })?;
+/// ```
+pub struct ArrayView<'py, T: Element> {
+    py: Python<'py>,
+    buffer: PyBuffer<T>,
+}
+
+impl<'py, T: Element> FromPyObject<'py> for ArrayView<'py, T> {
+    fn extract_bound(object: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let buffer = PyBuffer::get(object)?;
+        if !buffer.is_c_contiguous() {
+            return Err(PyErr::new::<pyo3::exceptions::PyBufferError, _>(
+                "Buffer is not C-contiguous",
+            ));
+        }
+        Ok(Self {
+            py: object.py(),
+            buffer,
+        })
+    }
+}
+
+impl<T: Element> ArrayView<'_, T> {
+    /// The elements in row-major order
+    ///
+    /// Cells because Python code may change the buffer while the view exists.
+    pub fn as_slice(&self) -> &[ReadOnlyCell<T>] {
+        self.buffer
+            .as_slice(self.py)
+            .expect("contiguous buffer of T")
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        self.buffer.shape()
+    }
+
+    /// Copies the elements into a `Vec`
+    pub fn to_vec(&self) -> PyResult<Vec<T>> {
+        self.buffer.to_vec(self.py)
+    }
+
+    /// Copies the elements into an `ndarray` array of the same shape
+    pub fn to_ndarray(&self) -> PyResult<ArrayD<T>> {
+        ArrayD::from_shape_vec(IxDyn(self.shape()), self.to_vec()?)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModule;
+    use std::path::Path;
+
+    #[test]
+    fn test_shared_array() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let data = vec![1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let address = data.as_ptr() as usize;
+        let array = SharedArray::with_shape(data, [2, 3]).unwrap();
+        let (shape, total, shared, doubled) = module
+            .action(move |py, _| {
+                let view = array.into_memoryview(*py)?;
+                let shape = view.getattr("shape")?.extract::<Vec<usize>>()?;
+                let total = view
+                    .call_method0("tolist")?
+                    .extract::<Vec<Vec<f64>>>()?
+                    .concat()
+                    .iter()
+                    .sum::<f64>();
+                let ctypes = py.import("ctypes")?;
+                let shared = ctypes
+                    .getattr("addressof")?
+                    .call1((ctypes
+                        .getattr("c_char")?
+                        .call_method1("from_buffer", (&view,))?,))?
+                    .extract::<usize>()?;
+                // Python writes land in the shared memory and show up in a view of it
+                let flat = view
+                    .call_method1("cast", ("B",))?
+                    .call_method1("cast", ("d",))?;
+                flat.set_item(0, 2.0)?;
+                let doubled = flat.extract::<ArrayView<f64>>()?.to_vec()?;
+                Ok((shape, total, shared, doubled))
+            })
+            .unwrap();
+        assert_eq!(shape, [2, 3]);
+        assert_eq!(total, 21.0);
+        assert_eq!(shared, address);
+        assert_eq!(doubled, [2.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        assert!(SharedArray::with_shape(vec![1u8; 5], [2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_shared_ndarray() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let array = ndarray::array![[1i32, 2, 3], [4, 5, 6]];
+        let transposed = SharedArray::from(array.clone().reversed_axes());
+        let (rows, copied) = module
+            .action(move |py, _| {
+                let rows = transposed
+                    .into_memoryview(*py)?
+                    .call_method0("tolist")?
+                    .extract::<Vec<Vec<i32>>>()?;
+                let values = py
+                    .import("array")?
+                    .call_method1("array", ("i", vec![1, 2, 3, 4, 5, 6]))?;
+                let view = py
+                    .import("builtins")?
+                    .getattr("memoryview")?
+                    .call1((values,))?
+                    .call_method1("cast", ("B",))?
+                    .call_method1("cast", ("i", [2, 3]))?;
+                let copied = view.extract::<ArrayView<i32>>()?.to_ndarray()?;
+                Ok((rows, copied))
+            })
+            .unwrap();
+        assert_eq!(rows, [[1, 4], [2, 5], [3, 6]]);
+        assert_eq!(copied, array.into_dyn());
+    }
+
+    #[test]
+    #[ignore = "needs numpy"]
+    fn test_shared_array_numpy() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let data = vec![0.5f64; 6];
+        let address = data.as_ptr() as usize;
+        let array = SharedArray::with_shape(data, [3, 2]).unwrap();
+        let (shape, pointer) = module
+            .action(move |py, _| {
+                let array = array.into_pyobject(*py)?;
+                let shape = array.getattr("shape")?.extract::<Vec<usize>>()?;
+                let pointer = array
+                    .getattr("ctypes")?
+                    .getattr("data")?
+                    .extract::<usize>()?;
+                Ok((shape, pointer))
+            })
+            .unwrap();
+        assert_eq!(shape, [3, 2]);
+        assert_eq!(pointer, address);
+    }
+
+    #[test]
+    fn test_array_view() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let (sum, shape) = module
+            .action(|py, _| {
+                let values = py
+                    .import("array")?
+                    .call_method1("array", ("d", vec![1.5, 2.5, 3.0]))?;
+                let view = values.extract::<ArrayView<f64>>()?;
+                let sum = view.as_slice().iter().map(|value| value.get()).sum::<f64>();
+                Ok((sum, view.shape().to_vec()))
+            })
+            .unwrap();
+        assert_eq!(sum, 7.0);
+        assert_eq!(shape, [3]);
+
+        let err = module
+            .action(|py, _| {
+                let values = py.import("array")?.call_method1("array", ("i", vec![1]))?;
+                values.extract::<ArrayView<f64>>().map(|_| ())
+            })
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "BufferError");
+    }
+}
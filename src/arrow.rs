@@ -0,0 +1,515 @@
+use crate::PyRunnerError;
+use arrow_array::cast::AsArray;
+use arrow_array::ffi::{FFI_ArrowArray, FFI_ArrowSchema, from_ffi, to_ffi};
+use arrow_array::types::{Float32Type, Float64Type, Int32Type, Int64Type};
+use arrow_array::{
+    Array, ArrayRef, ArrowPrimitiveType, PrimitiveArray, RecordBatch, RecordBatchOptions,
+    StringArray, StructArray,
+};
+use arrow_buffer::{Buffer, OffsetBuffer, ScalarBuffer};
+use arrow_schema::{DataType, Field, Schema};
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyCapsule, PyDict, PyTuple};
+use std::ffi::{CStr, c_char, c_void};
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+/// Column of an [`ArrowBatch`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrowColumn {
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+    Utf8(Vec<String>),
+}
+
+impl ArrowColumn {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Int32(values) => values.len(),
+            Self::Int64(values) => values.len(),
+            Self::Float32(values) => values.len(),
+            Self::Float64(values) => values.len(),
+            Self::Utf8(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Table of named columns exchanged with Python through the Arrow C Data Interface
+///
+/// Converts to an object implementing the Arrow PyCapsule interface
+/// (`__arrow_c_array__`), which `pyarrow.record_batch`, `polars.from_arrow` and other
+/// Arrow-aware libraries accept. Numeric columns are shared without copying. Extracting
+/// accepts any object implementing `__arrow_c_array__` for a struct array, e.g. a
+/// `pyarrow.RecordBatch`, and copies the data. Null values are not supported. Converts to
+/// and from [`arrow_array::RecordBatch`] for Rust code using the `arrow` crates.
+///```rs
+/// let batch = ArrowBatch::new(vec![
+///     ("id".to_string(), ArrowColumn::Int64(vec![1, 2])),
+///     ("score".to_string(), ArrowColumn::Float64(vec![0.5, 0.9])),
+/// ])
+/// .unwrap();
+/// module.action(move |py, module| {
+///     let table = py.import("pyarrow")?.call_method1("record_batch", (batch,))?;
+///     module.call_method1("train", (table,)).map(|_| ())
+/// })?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrowBatch {
+    columns: Vec<(String, ArrowColumn)>,
+    rows: usize,
+}
+
+impl ArrowBatch {
+    /// Fails if the columns differ in length
+    pub fn new(columns: Vec<(String, ArrowColumn)>) -> Result<Self, PyRunnerError> {
+        let rows = columns.first().map_or(0, |(_, column)| column.len());
+        if let Some((name, _)) = columns.iter().find(|(_, column)| column.len() != rows) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Column {name} doesn't have {rows} rows"
+            ))
+            .into());
+        }
+        Ok(Self { columns, rows })
+    }
+
+    pub fn columns(&self) -> &[(String, ArrowColumn)] {
+        &self.columns
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ArrowColumn> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column == name)
+            .map(|(_, column)| column)
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.rows
+    }
+}
+
+impl TryFrom<ArrowBatch> for RecordBatch {
+    type Error = PyRunnerError;
+
+    /// Fails if a string column holds more than the 2 GiB its 32-bit offsets can address
+    fn try_from(batch: ArrowBatch) -> Result<Self, Self::Error> {
+        record_batch(&Arc::new(batch)).map_err(PyRunnerError::from)
+    }
+}
+
+impl TryFrom<RecordBatch> for ArrowBatch {
+    type Error = PyRunnerError;
+
+    /// Fails if a column contains null values or isn't one of the types of [`ArrowColumn`]
+    fn try_from(batch: RecordBatch) -> Result<Self, Self::Error> {
+        from_record_batch(&batch).map_err(PyRunnerError::from)
+    }
+}
+
+/// Numeric columns point into the shared batch, string columns are encoded
+fn record_batch(batch: &Arc<ArrowBatch>) -> PyResult<RecordBatch> {
+    let mut fields = Vec::new();
+    let mut arrays = Vec::new();
+    for (name, column) in &batch.columns {
+        let array: ArrayRef = match column {
+            ArrowColumn::Int32(values) => Arc::new(shared::<Int32Type>(batch, values)),
+            ArrowColumn::Int64(values) => Arc::new(shared::<Int64Type>(batch, values)),
+            ArrowColumn::Float32(values) => Arc::new(shared::<Float32Type>(batch, values)),
+            ArrowColumn::Float64(values) => Arc::new(shared::<Float64Type>(batch, values)),
+            ArrowColumn::Utf8(values) => Arc::new(strings(name, values)?),
+        };
+        fields.push(Field::new(name, array.data_type().clone(), false));
+        arrays.push(array);
+    }
+    let options = RecordBatchOptions::new().with_row_count(Some(batch.rows));
+    RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), arrays, &options)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
+/// Array of `values`, which keeps the batch holding them alive
+fn shared<T: ArrowPrimitiveType>(
+    batch: &Arc<ArrowBatch>,
+    values: &[T::Native],
+) -> PrimitiveArray<T> {
+    let pointer = NonNull::from(values).cast::<u8>();
+    // the batch is never mutated once shared
+    let buffer =
+        unsafe { Buffer::from_custom_allocation(pointer, size_of_val(values), batch.clone()) };
+    PrimitiveArray::new(ScalarBuffer::new(buffer, 0, values.len()), None)
+}
+
+/// Fails if `values` hold more than the 2 GiB 32-bit offsets can address
+fn strings(name: &str, values: &[String]) -> PyResult<StringArray> {
+    let mut offsets = Vec::with_capacity(values.len() + 1);
+    let mut bytes = Vec::new();
+    offsets.push(0);
+    for value in values {
+        bytes.extend_from_slice(value.as_bytes());
+        offsets.push(i32::try_from(bytes.len()).map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyOverflowError, _>(format!(
+                "Column {name} holds more than 2 GiB of text"
+            ))
+        })?);
+    }
+    Ok(StringArray::new(
+        OffsetBuffer::new(offsets.into()),
+        Buffer::from_vec(bytes),
+        None,
+    ))
+}
+
+/// Exports `batch` as struct array through the Arrow C Data Interface
+fn export(batch: &Arc<ArrowBatch>) -> PyResult<(FFI_ArrowSchema, FFI_ArrowArray)> {
+    let array = StructArray::from(record_batch(batch)?);
+    let (array, schema) = to_ffi(&array.to_data())
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+    Ok((schema, array))
+}
+
+impl<'py> IntoPyObject<'py> for ArrowBatch {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let batch = Arc::new(self);
+        let arrow_c_array = PyCFunction::new_closure(
+            py,
+            None,
+            None,
+            move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                let py = args.py();
+                // released with the capsules unless a consumer moves them out
+                let (schema, array) = export(&batch)?;
+                let schema = PyCapsule::new(py, schema, Some(c"arrow_schema".to_owned()))?;
+                let array = PyCapsule::new(py, array, Some(c"arrow_array".to_owned()))?;
+                Ok::<_, PyErr>((schema.unbind(), array.unbind()))
+            },
+        )?;
+        let batch = py.import("types")?.getattr("SimpleNamespace")?.call0()?;
+        batch.setattr("__arrow_c_array__", arrow_c_array)?;
+        Ok(batch)
+    }
+}
+
+/// Struct of the interface, owned by whoever holds it until it is released or moved
+trait ArrowStruct: Sized {
+    /// Released structs and structs moved elsewhere don't hold data anymore
+    fn is_released(&self) -> bool;
+
+    /// Moves the struct out of `pointer`, leaving a released struct behind
+    ///
+    /// # Safety
+    /// `pointer` must point to a valid struct.
+    unsafe fn move_from(pointer: *mut Self) -> Self;
+}
+
+impl ArrowStruct for FFI_ArrowArray {
+    fn is_released(&self) -> bool {
+        FFI_ArrowArray::is_released(self)
+    }
+
+    unsafe fn move_from(pointer: *mut Self) -> Self {
+        unsafe { FFI_ArrowArray::from_raw(pointer) }
+    }
+}
+
+/// Layout of `ArrowSchema` in the C Data Interface, `FFI_ArrowSchema` keeps its fields private
+#[repr(C)]
+struct SchemaLayout {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut SchemaLayout,
+    dictionary: *mut SchemaLayout,
+    release: Option<unsafe extern "C" fn(*mut SchemaLayout)>,
+    private_data: *mut c_void,
+}
+
+const _: () = assert!(size_of::<SchemaLayout>() == size_of::<FFI_ArrowSchema>());
+
+impl ArrowStruct for FFI_ArrowSchema {
+    fn is_released(&self) -> bool {
+        let layout = (self as *const Self).cast::<SchemaLayout>();
+        unsafe { (*layout).release.is_none() }
+    }
+
+    unsafe fn move_from(pointer: *mut Self) -> Self {
+        unsafe { FFI_ArrowSchema::from_raw(pointer) }
+    }
+}
+
+/// Moves the struct out of the capsule `name`
+///
+/// # Safety
+/// The capsule must hold a `T` as required by the Arrow PyCapsule interface.
+unsafe fn take<T: ArrowStruct>(capsule: &Bound<'_, PyAny>, name: &CStr) -> PyResult<T> {
+    let capsule = capsule.downcast::<PyCapsule>()?;
+    if capsule.name()? != Some(name) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Expected a {} capsule",
+            name.to_string_lossy()
+        )));
+    }
+    let pointer = capsule.pointer().cast::<T>();
+    if unsafe { (*pointer).is_released() } {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "The {} capsule was already consumed",
+            name.to_string_lossy()
+        )));
+    }
+    Ok(unsafe { T::move_from(pointer) })
+}
+
+fn import_error(message: impl Into<String>) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyTypeError, _>(message.into())
+}
+
+/// Copies the struct array of the capsules, which are consumed
+fn import(schema: &Bound<'_, PyAny>, array: &Bound<'_, PyAny>) -> PyResult<ArrowBatch> {
+    // released when dropped
+    let schema = unsafe { take::<FFI_ArrowSchema>(schema, c"arrow_schema")? };
+    let array = unsafe { take::<FFI_ArrowArray>(array, c"arrow_array")? };
+    let data = unsafe { from_ffi(array, &schema) }.map_err(|e| import_error(e.to_string()))?;
+    if !matches!(data.data_type(), DataType::Struct(_)) {
+        return Err(import_error("Expected a struct array of columns"));
+    }
+    // offsets pointing outside their buffers or invalid UTF-8 aren't read
+    data.validate_full()
+        .map_err(|e| import_error(e.to_string()))?;
+    let array = StructArray::from(data);
+    if array.null_count() > 0 {
+        return Err(import_error("Rows of the batch are null"));
+    }
+    from_record_batch(&RecordBatch::from(array))
+}
+
+fn from_record_batch(batch: &RecordBatch) -> PyResult<ArrowBatch> {
+    let columns = batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, array)| Ok((field.name().clone(), column(field.name(), array)?)))
+        .collect::<PyResult<_>>()?;
+    Ok(ArrowBatch {
+        columns,
+        rows: batch.num_rows(),
+    })
+}
+
+fn column(name: &str, array: &ArrayRef) -> PyResult<ArrowColumn> {
+    if array.null_count() > 0 {
+        return Err(import_error(format!("Column {name} contains null values")));
+    }
+    Ok(match array.data_type() {
+        DataType::Int32 => ArrowColumn::Int32(array.as_primitive::<Int32Type>().values().to_vec()),
+        DataType::Int64 => ArrowColumn::Int64(array.as_primitive::<Int64Type>().values().to_vec()),
+        DataType::Float32 => {
+            ArrowColumn::Float32(array.as_primitive::<Float32Type>().values().to_vec())
+        }
+        DataType::Float64 => {
+            ArrowColumn::Float64(array.as_primitive::<Float64Type>().values().to_vec())
+        }
+        DataType::Utf8 => ArrowColumn::Utf8(
+            array
+                .as_string::<i32>()
+                .iter()
+                .map(|value| value.unwrap_or_default().to_owned())
+                .collect(),
+        ),
+        other => {
+            return Err(import_error(format!(
+                "Column {name} has the unsupported type {other}"
+            )));
+        }
+    })
+}
+
+impl<'py> FromPyObject<'py> for ArrowBatch {
+    fn extract_bound(object: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let (schema, array) = object
+            .call_method0("__arrow_c_array__")?
+            .extract::<(Bound<'py, PyAny>, Bound<'py, PyAny>)>()?;
+        import(&schema, &array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModule;
+    use std::path::Path;
+
+    #[test]
+    fn test_arrow_batch() {
+        let batch = ArrowBatch::new(vec![
+            ("id".to_string(), ArrowColumn::Int64(vec![1, 2, 3])),
+            (
+                "score".to_string(),
+                ArrowColumn::Float64(vec![0.5, 0.25, 1.0]),
+            ),
+            ("rank".to_string(), ArrowColumn::Int32(vec![3, 1, 2])),
+            (
+                "weight".to_string(),
+                ArrowColumn::Float32(vec![1.5, 2.5, 3.5]),
+            ),
+            (
+                "label".to_string(),
+                ArrowColumn::Utf8(vec!["a".into(), "".into(), "ünïcode".into()]),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        let record = RecordBatch::try_from(batch.clone()).unwrap();
+        assert_eq!(record.num_columns(), 5);
+        assert_eq!(ArrowBatch::try_from(record).unwrap(), batch);
+
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let expected = batch.clone();
+        let (first, second) = module
+            .action(move |py, _| {
+                let exported = batch.into_pyobject(*py)?;
+                // every call exports the batch again
+                let first = exported.extract::<ArrowBatch>()?;
+                let second = exported.extract::<ArrowBatch>()?;
+                Ok((first, second))
+            })
+            .unwrap();
+        assert_eq!(first, expected);
+        assert_eq!(second, expected);
+
+        // capsules dropped unconsumed release the exported structs
+        module
+            .action(|py, _| {
+                let batch = ArrowBatch::new(vec![("x".to_string(), ArrowColumn::Int32(vec![1]))])?;
+                batch
+                    .into_pyobject(*py)?
+                    .call_method0("__arrow_c_array__")?;
+                Ok(())
+            })
+            .unwrap();
+
+        let err = ArrowBatch::new(vec![
+            ("a".to_string(), ArrowColumn::Int32(vec![1])),
+            ("b".to_string(), ArrowColumn::Int32(vec![])),
+        ])
+        .unwrap_err();
+        assert_eq!(err.message(), "Column b doesn't have 1 rows");
+    }
+
+    #[test]
+    fn test_arrow_nulls() {
+        Python::with_gil(|py| {
+            let capsules = |array: &StructArray| {
+                let (array, schema) = to_ffi(&array.to_data()).unwrap();
+                let schema = PyCapsule::new(py, schema, Some(c"arrow_schema".to_owned())).unwrap();
+                let array = PyCapsule::new(py, array, Some(c"arrow_array".to_owned())).unwrap();
+                (schema.into_any(), array.into_any())
+            };
+            let extract = |array: &StructArray| {
+                let (schema, array) = capsules(array);
+                import(&schema, &array).map_err(|e| e.to_string())
+            };
+            let values: ArrayRef =
+                Arc::new(arrow_array::Int32Array::from(vec![Some(1), None, Some(3)]));
+            let field = Arc::new(Field::new("x", DataType::Int32, true));
+            let batch = StructArray::from(vec![(field.clone(), values.clone())]);
+            assert_eq!(
+                extract(&batch).unwrap_err(),
+                "TypeError: Column x contains null values"
+            );
+            // the null is outside the slice
+            assert_eq!(
+                extract(&batch.slice(2, 1)).unwrap().column("x"),
+                Some(&ArrowColumn::Int32(vec![3]))
+            );
+
+            let rows = arrow_buffer::NullBuffer::from(vec![true, false, true]);
+            let batch = StructArray::new(vec![field].into(), vec![values], Some(rows));
+            let (schema, array) = capsules(&batch);
+            // a count of -1 isn't computed, the bitmap is read
+            unsafe {
+                (*array
+                    .downcast::<PyCapsule>()
+                    .unwrap()
+                    .pointer()
+                    .cast::<FFI_ArrowArray>())
+                .set_null_count(-1)
+            };
+            assert_eq!(
+                import(&schema, &array).unwrap_err().to_string(),
+                "TypeError: Rows of the batch are null"
+            );
+
+            // a capsule can only be consumed once
+            let (schema, array) = capsules(&StructArray::new_empty_fields(0, None));
+            let first = unsafe { take::<FFI_ArrowArray>(&array, c"arrow_array").unwrap() };
+            let err = unsafe { take::<FFI_ArrowArray>(&array, c"arrow_array") }.err();
+            assert!(
+                err.unwrap()
+                    .is_instance_of::<pyo3::exceptions::PyValueError>(py)
+            );
+            drop(first);
+            let first = unsafe { take::<FFI_ArrowSchema>(&schema, c"arrow_schema").unwrap() };
+            assert!(unsafe { take::<FFI_ArrowSchema>(&schema, c"arrow_schema") }.is_err());
+            drop(first);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs pyarrow"]
+    fn test_pyarrow() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let batch = ArrowBatch::new(vec![
+            ("id".to_string(), ArrowColumn::Int64(vec![1, 2, 3])),
+            (
+                "label".to_string(),
+                ArrowColumn::Utf8(vec!["a".into(), "b".into(), "c".into()]),
+            ),
+        ])
+        .unwrap();
+        let expected = batch.clone();
+        let (ids, roundtrip, sliced, nulls) = module
+            .action(move |py, _| {
+                let pyarrow = py.import("pyarrow")?;
+                let table = pyarrow.call_method1("record_batch", (batch,))?;
+                let ids = table
+                    .call_method1("column", ("id",))?
+                    .call_method0("to_pylist")?
+                    .extract::<Vec<i64>>()?;
+                let roundtrip = table.extract::<ArrowBatch>()?;
+                let sliced = table
+                    .call_method1("slice", (1, 2))?
+                    .extract::<ArrowBatch>()?;
+                let nulls = pyarrow.call_method1(
+                    "record_batch",
+                    (
+                        vec![pyarrow.call_method1("array", (vec![Some(1), None],))?],
+                        vec!["x"],
+                    ),
+                )?;
+                let nulls = nulls.extract::<ArrowBatch>().err().map(|e| e.to_string());
+                Ok((ids, roundtrip, sliced, nulls))
+            })
+            .unwrap();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(roundtrip, expected);
+        assert_eq!(
+            sliced.column("label"),
+            Some(&ArrowColumn::Utf8(vec!["b".into(), "c".into()]))
+        );
+        assert_eq!(
+            nulls.as_deref(),
+            Some("TypeError: Column x contains null values")
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{Call, PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+use std::sync::{Arc, Mutex, mpsc};
+
+type Reply = Box<dyn for<'py> FnOnce(Python<'py>, PyResult<Bound<'py, PyAny>>) + Send>;
+
+/// Passes the awaited result of `value` to `reply` if it is awaitable, `value` otherwise
+///
+/// On a worker running an event loop the awaitable is scheduled as a task and `reply` is
+/// called once it is done, otherwise it runs to completion with `asyncio.run`. Errors are
+/// passed to `reply` as well.
+fn await_with(py: Python<'_>, value: PyResult<Bound<'_, PyAny>>, reply: Reply) {
+    let reply = Arc::new(Mutex::new(Some(reply)));
+    if let Err(e) = schedule(py, value, &reply)
+        && let Some(reply) = reply.lock().unwrap().take()
+    {
+        reply(py, Err(e));
+    }
+}
+
+fn schedule(
+    py: Python<'_>,
+    value: PyResult<Bound<'_, PyAny>>,
+    reply: &Arc<Mutex<Option<Reply>>>,
+) -> PyResult<()> {
+    let value = value?;
+    let respond = |result| {
+        if let Some(reply) = reply.lock().unwrap().take() {
+            reply(py, result);
+        }
+    };
+    let asyncio = py.import("asyncio")?;
+    let inspect = py.import("inspect")?;
+    if !inspect
+        .call_method1("isawaitable", (&value,))?
+        .is_truthy()?
+    {
+        respond(Ok(value));
+        return Ok(());
+    }
+    let event_loop = asyncio.call_method0("_get_running_loop")?;
+    if event_loop.is_none() {
+        if inspect
+            .call_method1("iscoroutine", (&value,))?
+            .is_truthy()?
+        {
+            respond(asyncio.call_method1("run", (value,)));
+        } else {
+            let event_loop = asyncio.call_method0("new_event_loop")?;
+            let result = event_loop.call_method1("run_until_complete", (value,));
+            event_loop.call_method0("close")?;
+            respond(result);
+        }
+        return Ok(());
+    }
+    let reply = reply.clone();
+    let done = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+            if let Some(reply) = reply.lock().unwrap().take() {
+                reply(args.py(), args.get_item(0)?.call_method0("result"));
+            }
+            Ok::<_, PyErr>(())
+        },
+    )?;
+    asyncio
+        .call_method1("ensure_future", (value,))?
+        .call_method1("add_done_callback", (done,))?;
+    Ok(())
+}
+
+impl PythonModule {
+    /// Runs action and awaits the coroutine it returns on the worker thread
+    ///
+    /// Every coroutine runs in a new event loop (`asyncio.run`), which is closed afterwards,
+    /// unless the module runs a persistent loop
+    /// ([`PythonModuleBuilder::event_loop`](crate::PythonModuleBuilder::event_loop)). Other
+    /// return values are extracted as they are.
+    ///```rs
+    /// let body = module
+    ///     .action_await(|_, module| module.call_method1("fetch", ("https://example.com",)))
+    ///     .unwrap();
+    /// ```
+    pub fn action_await<T>(
+        &self,
+        call: impl for<'py> FnOnce(&Python<'py>, &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>>
+        + Send
+        + 'static,
+    ) -> Result<T, PyRunnerError>
+    where
+        T: for<'py> FromPyObject<'py> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.dispatch_await(
+            call,
+            |_, result| result?.extract(),
+            move |result| {
+                let _ = sender.send(result);
+            },
+        )?;
+        receiver.recv().map_err(|_| self.terminated_error())?
+    }
+
+    /// Like [`call`](Self::call), but awaits the result if it is a coroutine
+    pub fn call_async<T>(&self, call: Call) -> Result<T, PyRunnerError>
+    where
+        T: for<'py> FromPyObject<'py> + Send + 'static,
+    {
+        self.action_await(move |_, module| call.invoke(module))
+    }
+
+    /// Queues `call` and passes the awaited value it returns, converted by `convert`, to `reply`
+    pub(crate) fn dispatch_await<T: Send + 'static>(
+        &self,
+        call: impl for<'py> FnOnce(&Python<'py>, &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>>
+        + Send
+        + 'static,
+        convert: impl for<'py> FnOnce(Python<'py>, PyResult<Bound<'py, PyAny>>) -> PyResult<T>
+        + Send
+        + 'static,
+        reply: impl FnOnce(Result<T, PyRunnerError>) + Send + 'static,
+    ) -> Result<(), PyRunnerError> {
+        let exceptions = self.exceptions.clone();
+        let reply: Reply = Box::new(move |py, result| {
+            reply(convert(py, result).map_err(|e| exceptions.convert(py, e)));
+        });
+        self.dispatch(
+            move |py, module| {
+                await_with(*py, call(py, module), reply);
+                Ok(())
+            },
+            |_| (),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_await() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import asyncio\n",
+                "async def double(x):\n",
+                "    await asyncio.sleep(0.01)\n",
+                "    return x * 2\n",
+                "async def fail():\n",
+                "    raise ValueError('async failure')\n",
+                "def sync(x):\n",
+                "    return x\n",
+            ),
+        )
+        .unwrap();
+
+        let module = PythonModule::new_project(dir.join("main.py")).unwrap();
+        let value: i64 = module
+            .action_await(|_, module| module.call_method1("double", (21,)))
+            .unwrap();
+        assert_eq!(value, 42);
+        let value: i64 = module.call_async(Call::new("double").arg(2)).unwrap();
+        assert_eq!(value, 4);
+        let value: i64 = module.call_async(Call::new("sync").arg(5)).unwrap();
+        assert_eq!(value, 5);
+
+        let err = module.call_async::<i64>(Call::new("fail")).unwrap_err();
+        assert_eq!(err.exception_type(), "ValueError");
+    }
+
+    #[test]
+    fn test_event_loop() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import asyncio\n",
+                "started = asyncio.Event()\n",
+                "async def wait():\n",
+                "    await started.wait()\n",
+                "    return 'woken'\n",
+                "async def wake():\n",
+                "    started.set()\n",
+                "    return asyncio.get_running_loop() is loop\n",
+                "def remember():\n",
+                "    global loop\n",
+                "    loop = asyncio.get_running_loop()\n",
+            ),
+        )
+        .unwrap();
+
+        let module = Arc::new(
+            crate::PythonModuleBuilder::new_project(dir.join("main.py"))
+                .event_loop(true)
+                .build()
+                .unwrap(),
+        );
+        module
+            .action(|_, module| module.call_method0("remember").map(|_| ()))
+            .unwrap();
+        let waiting = {
+            let module = module.clone();
+            std::thread::spawn(move || module.call_async::<String>(Call::new("wait")))
+        };
+        // the first coroutine is still pending while the second one runs on the same loop
+        let same_loop: bool = module.call_async(Call::new("wake")).unwrap();
+        assert!(same_loop);
+        assert_eq!(waiting.join().unwrap().unwrap(), "woken");
+
+        module
+            .action(|_, module| module.getattr("started")?.call_method0("clear").map(|_| ()))
+            .unwrap();
+        let pending = {
+            let module = module.clone();
+            std::thread::spawn(move || module.call_async::<String>(Call::new("wait")))
+        };
+        while module.stats().executed < 5 {
+            std::thread::yield_now();
+        }
+        assert!(module.shutdown(crate::ShutdownMode::DrainQueue));
+        let err = pending.join().unwrap().unwrap_err();
+        assert!(err.exception_type().ends_with("CancelledError"));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::call::lookup;
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+
+impl PythonModule {
+    /// Reads the module attribute `name`, which may be a dotted path
+    ///```rs
+    /// let threshold: f64 = module.get("config.threshold").unwrap();
+    /// ```
+    pub fn get<T>(&self, name: &str) -> Result<T, PyRunnerError>
+    where
+        T: for<'py> FromPyObject<'py> + Send + 'static,
+    {
+        let name = name.to_string();
+        // the stand-in of a subprocess module resolves dotted names in the child
+        let remote = self.control.child.get().is_some();
+        self.action(move |_, module| match remote {
+            true => module.getattr(name.as_str())?.extract(),
+            false => lookup(module, &name)?.extract(),
+        })
+    }
+
+    /// Sets the module attribute `name`, a dotted path sets it on the object before the last dot
+    ///```rs
+    /// module.set("threshold", 0.8).unwrap();
+    /// ```
+    pub fn set<V>(&self, name: &str, value: V) -> Result<(), PyRunnerError>
+    where
+        V: for<'py> IntoPyObject<'py> + Send + 'static,
+    {
+        let name = name.to_string();
+        let remote = self.control.child.get().is_some();
+        self.action(move |_, module| match name.rsplit_once('.') {
+            Some((parent, attribute)) if !remote => {
+                lookup(module, parent)?.setattr(attribute, value)
+            }
+            _ => module.setattr(name.as_str(), value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_set() {
+        let source = "import types\nCONFIG_VALUE = 'on'\nthreshold = 0.5\nlimits = types.SimpleNamespace(size=3)\ndef above(x):\n    return x > threshold\n";
+        let module = PythonModule::from_source("attributes", source).unwrap();
+        assert_eq!(module.get::<String>("CONFIG_VALUE").unwrap(), "on");
+        assert_eq!(module.get::<i64>("limits.size").unwrap(), 3);
+
+        module.set("threshold", 0.8).unwrap();
+        assert_eq!(module.get::<f64>("threshold").unwrap(), 0.8);
+        assert!(
+            !module
+                .call::<bool>(crate::Call::new("above").arg(0.7))
+                .unwrap()
+        );
+        module.set("limits.size", 10).unwrap();
+        assert_eq!(module.get::<i64>("limits.size").unwrap(), 10);
+
+        let err = module.get::<i64>("missing").unwrap_err();
+        assert_eq!(err.exception_type(), "AttributeError");
+    }
+
+    #[test]
+    fn test_get_set_subprocess() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            "import types\nthreshold = 0.5\nlimits = types.SimpleNamespace(size=3)\ndef size():\n    return limits.size\n",
+        )
+        .unwrap();
+
+        let module = PythonModule::new_subprocess(dir.join("main.py")).unwrap();
+        module.set("threshold", 0.8).unwrap();
+        assert_eq!(module.get::<f64>("threshold").unwrap(), 0.8);
+        module.set("limits.size", 10).unwrap();
+        assert_eq!(module.call::<i64>(crate::Call::new("size")).unwrap(), 10);
+        assert_eq!(module.get::<i64>("limits.size").unwrap(), 10);
+    }
+}
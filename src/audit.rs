@@ -0,0 +1,96 @@
+use crate::sandbox::helper;
+use crate::{PyRunnerError, PythonModule};
+use crossbeam::channel::{self, Receiver};
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+use std::convert::Infallible;
+
+/// CPython audit event raised by the code of a module, see [`PythonModule::subscribe_audit`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// Name of the event like `open`, `socket.connect` or `subprocess.Popen`
+    pub event: String,
+    /// `repr()` of the event arguments
+    pub args: Vec<String>,
+}
+
+impl PythonModule {
+    /// Delivers the audit events (`sys.addaudithook`) raised by the module's own code whose
+    /// name starts with one of `events`
+    ///
+    /// Like the [`Sandbox`](crate::Sandbox) the events are attributed by the call stack, so
+    /// operations of the libraries the module calls count and imports don't. The events are
+    /// sent on the worker thread while the operation waits; the subscription ends when the
+    /// receiver is dropped. Not supported by the subprocess backend.
+    ///```rs
+    /// let events = module.subscribe_audit(["open", "socket.", "subprocess.Popen", "os.system"]).unwrap();
+    /// for event in events.try_iter() {
+    ///     log::warn!("{}: {}", event.event, event.args.join(", "));
+    /// }
+    /// ```
+    pub fn subscribe_audit(
+        &self,
+        events: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<Receiver<AuditEvent>, PyRunnerError> {
+        if self.control.child.get().is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Audit events are not supported by the subprocess backend",
+            )
+            .into());
+        }
+        let prefixes = events.into_iter().map(Into::into).collect::<Vec<String>>();
+        let (sender, receiver) = channel::unbounded();
+        self.action(move |py, module| {
+            let callback = PyCFunction::new_closure(
+                *py,
+                Some(c"audit_event"),
+                None,
+                move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                    let (event, args) = args.extract::<(String, Vec<String>)>().unwrap_or_default();
+                    // tells the hook to drop the subscription once the receiver is gone
+                    Ok::<_, Infallible>(sender.send(AuditEvent { event, args }).is_ok())
+                },
+            )?;
+            helper(*py)?.call_method1("watch", (module, prefixes, callback))?;
+            Ok(())
+        })?;
+        Ok(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "import os\ndef read():\n    open('audit-missing.txt')\ndef run():\n    return os.system('true')\n";
+
+    #[test]
+    fn test_subscribe_audit() {
+        let module = PythonModule::from_source("audit_script", SCRIPT).unwrap();
+        let events = module.subscribe_audit(["open", "os.system"]).unwrap();
+        let other = PythonModule::from_source("audit_other", SCRIPT).unwrap();
+        other
+            .action(|_, module| module.call_method0("run").map(|_| ()))
+            .unwrap();
+        assert!(events.try_recv().is_err());
+
+        let err = module
+            .action(|_, module| module.call_method0("read").map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "FileNotFoundError");
+        module
+            .action(|_, module| module.call_method0("run").map(|_| ()))
+            .unwrap();
+        let received = events.try_iter().collect::<Vec<_>>();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].event, "open");
+        assert_eq!(received[0].args[0], "'audit-missing.txt'");
+        assert_eq!(received[1].event, "os.system");
+        assert_eq!(received[1].args, ["b'true'"]);
+
+        drop(events);
+        module
+            .action(|_, module| module.call_method0("run").map(|_| ()))
+            .unwrap();
+    }
+}
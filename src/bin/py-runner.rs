@@ -0,0 +1,335 @@
+//! `py-runner` command line tool, built with the `cli` feature
+use py_runner::{
+    ErrorKind, Interface, PluginHost, PyRunnerError, PythonModuleBuilder, Venv, discover_pythons,
+    execute_script,
+};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyTuple};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+  py-runner run <script.py> [args...]
+  py-runner call <module.py|package> <function> [--json <arguments>] [--venv <dir>]
+  py-runner check <plugin-dir> <interface.pyi>
+  py-runner env create <dir> [--python <executable>]
+  py-runner env install <dir> [-r <requirements.txt>] [packages...]
+
+`--json` takes an object of keyword arguments, an array of positional arguments or a single
+value, the result of `call` is printed as JSON.";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Run {
+        script: PathBuf,
+        args: Vec<String>,
+    },
+    Call {
+        module: PathBuf,
+        function: String,
+        json: Option<String>,
+        venv: Option<PathBuf>,
+    },
+    Check {
+        plugins: PathBuf,
+        interface: PathBuf,
+    },
+    EnvCreate {
+        root: PathBuf,
+        python: Option<PathBuf>,
+    },
+    EnvInstall {
+        root: PathBuf,
+        requirements: Option<PathBuf>,
+        packages: Vec<String>,
+    },
+    Help,
+}
+
+fn main() -> ExitCode {
+    let command = match parse(std::env::args().skip(1).collect()) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match execute(command) {
+        Ok(status) => ExitCode::from(status),
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse(args: Vec<String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or("Missing command")?;
+    let mut next = |what: &str| args.next().ok_or(format!("Missing {what}"));
+    let command = match command.as_str() {
+        "run" => Command::Run {
+            script: next("script")?.into(),
+            args: args.collect(),
+        },
+        "call" => {
+            let module = next("module")?.into();
+            let function = next("function")?;
+            let (mut json, mut venv) = (None, None);
+            while let Some(arg) = args.next() {
+                let value = args.next().ok_or(format!("Missing value of {arg}"))?;
+                match arg.as_str() {
+                    "--json" => json = Some(value),
+                    "--venv" => venv = Some(value.into()),
+                    _ => return Err(format!("Unknown option {arg}")),
+                }
+            }
+            Command::Call {
+                module,
+                function,
+                json,
+                venv,
+            }
+        }
+        "check" => Command::Check {
+            plugins: next("plugin directory")?.into(),
+            interface: next("interface file")?.into(),
+        },
+        "env" => {
+            let action = next("env command")?;
+            let root = next("environment directory")?.into();
+            match action.as_str() {
+                "create" => {
+                    let python = match args.next() {
+                        None => None,
+                        Some(arg) if arg == "--python" => {
+                            Some(args.next().ok_or(format!("Missing value of {arg}"))?.into())
+                        }
+                        Some(arg) => return Err(format!("Unexpected argument {arg}")),
+                    };
+                    if let Some(arg) = args.next() {
+                        return Err(format!("Unexpected argument {arg}"));
+                    }
+                    Command::EnvCreate { root, python }
+                }
+                "install" => {
+                    let (mut requirements, mut packages) = (None, Vec::new());
+                    while let Some(arg) = args.next() {
+                        match arg.as_str() {
+                            "-r" | "--requirements" => {
+                                let file = args.next().ok_or("Missing requirements file")?;
+                                requirements = Some(file.into());
+                            }
+                            _ => packages.push(arg),
+                        }
+                    }
+                    if requirements.is_none() && packages.is_empty() {
+                        return Err("Nothing to install".to_string());
+                    }
+                    Command::EnvInstall {
+                        root,
+                        requirements,
+                        packages,
+                    }
+                }
+                _ => return Err(format!("Unknown env command {action}")),
+            }
+        }
+        "help" | "-h" | "--help" => Command::Help,
+        _ => return Err(format!("Unknown command {command}")),
+    };
+    Ok(command)
+}
+
+/// Runs `command` and returns the exit status
+fn execute(command: Command) -> Result<u8, PyRunnerError> {
+    match command {
+        Command::Run { script, args } => {
+            let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+            let status = execute_script(&script, &args)?;
+            // like a shell, only the low byte of the status is kept
+            Ok(status as u8)
+        }
+        Command::Call {
+            module,
+            function,
+            json,
+            venv,
+        } => {
+            let mut builder = PythonModuleBuilder::new(&module);
+            if let Some(venv) = venv {
+                builder = builder.venv(&Venv::open(venv)?);
+            }
+            let module = builder.build()?;
+            let output =
+                module.action(move |py, module| call_json(*py, module, &function, json))?;
+            println!("{output}");
+            Ok(0)
+        }
+        Command::Check { plugins, interface } => {
+            let interface = Interface::from_stub(interface)?;
+            let host = PluginHost::new(plugins)
+                .configure(move |builder| builder.interface(interface.clone()));
+            match host.scan() {
+                Ok(names) => {
+                    for name in names {
+                        println!("{name}: ok");
+                    }
+                    Ok(0)
+                }
+                Err(e) => match e.kind() {
+                    // the message lists every mismatch, the traceback adds nothing
+                    ErrorKind::InterfaceMismatch { .. } => {
+                        eprintln!("{}", e.message());
+                        Ok(1)
+                    }
+                    _ => Err(e),
+                },
+            }
+        }
+        Command::EnvCreate { root, python } => {
+            let python = match python {
+                Some(python) => python,
+                None => discover_pythons()
+                    .into_iter()
+                    .find(|python| python.venv)
+                    .map(|python| python.executable)
+                    .ok_or_else(|| {
+                        PyErr::new::<pyo3::exceptions::PyFileNotFoundError, _>(
+                            "No Python interpreter with venv support found",
+                        )
+                    })?,
+            };
+            let venv = Venv::create(&root, python)?;
+            println!("{}", venv.root().display());
+            Ok(0)
+        }
+        Command::EnvInstall {
+            root,
+            requirements,
+            packages,
+        } => {
+            let venv = Venv::open(&root)?;
+            if let Some(requirements) = requirements {
+                venv.install_requirements(requirements)?;
+            }
+            if !packages.is_empty() {
+                venv.pip_install(packages)?;
+            }
+            Ok(0)
+        }
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(0)
+        }
+    }
+}
+
+/// Calls `function` with the arguments in `json` and returns its result as JSON
+fn call_json(
+    py: Python<'_>,
+    module: &Bound<'_, PyAny>,
+    function: &str,
+    json: Option<String>,
+) -> PyResult<String> {
+    let loads = py.import("json")?.getattr("loads")?;
+    let args = PyList::empty(py);
+    let kwargs = PyDict::new(py);
+    if let Some(json) = json {
+        let value = loads.call1((json,))?;
+        if let Ok(value) = value.downcast::<PyDict>() {
+            kwargs.update(value.as_mapping())?;
+        } else if let Ok(value) = value.downcast::<PyList>() {
+            for item in value {
+                args.append(item)?;
+            }
+        } else {
+            args.append(value)?;
+        }
+    }
+    let result = module
+        .getattr(function)?
+        .call(PyTuple::new(py, args)?, Some(&kwargs))?;
+    // values JSON can't represent are printed as `str`
+    let dumps_kwargs = PyDict::new(py);
+    dumps_kwargs.set_item("default", py.import("builtins")?.getattr("str")?)?;
+    py.import("json")?
+        .call_method("dumps", (result,), Some(&dumps_kwargs))?
+        .extract()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(args("run tool.py --verbose build")).unwrap(),
+            Command::Run {
+                script: "tool.py".into(),
+                args: args("--verbose build"),
+            }
+        );
+        assert_eq!(
+            parse(args("call main.py add --json [1,2] --venv .venv")).unwrap(),
+            Command::Call {
+                module: "main.py".into(),
+                function: "add".into(),
+                json: Some("[1,2]".into()),
+                venv: Some(".venv".into()),
+            }
+        );
+        assert_eq!(
+            parse(args("env install .venv -r requirements.txt numpy")).unwrap(),
+            Command::EnvInstall {
+                root: ".venv".into(),
+                requirements: Some("requirements.txt".into()),
+                packages: args("numpy"),
+            }
+        );
+        assert!(parse(args("call main.py")).is_err());
+        assert!(parse(args("call main.py add --json")).is_err());
+        assert!(parse(args("env install .venv")).is_err());
+        assert!(parse(args("env create .venv --pip")).is_err());
+        assert_eq!(
+            parse(args("env create .venv --python")).unwrap_err(),
+            "Missing value of --python"
+        );
+        assert_eq!(
+            parse(args("env create .venv --python python3.12")).unwrap(),
+            Command::EnvCreate {
+                root: ".venv".into(),
+                python: Some("python3.12".into()),
+            }
+        );
+        assert!(parse(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_call_json() {
+        let module = PythonModuleBuilder::from_source(
+            "cli_call",
+            "def add(a, b=0):\n    return {'sum': a + b, 'path': __import__('pathlib').Path('x')}\n",
+        )
+        .build()
+        .unwrap();
+        let call = |json: Option<&str>| {
+            let json = json.map(String::from);
+            module.action(move |py, module| call_json(*py, module, "add", json))
+        };
+        assert_eq!(
+            call(Some(r#"{"a": 1, "b": 2}"#)).unwrap(),
+            r#"{"sum": 3, "path": "x"}"#
+        );
+        assert_eq!(call(Some("[4, 5]")).unwrap(), r#"{"sum": 9, "path": "x"}"#);
+        assert_eq!(call(Some("7")).unwrap(), r#"{"sum": 7, "path": "x"}"#);
+        assert!(call(None).is_err());
+        assert!(call(Some("{")).is_err());
+    }
+}
@@ -0,0 +1,1085 @@
+use crate::error::{ExceptionMap, ExceptionMapper};
+use crate::event::Events;
+use crate::helper::load_helper;
+use crate::host::{Callback, HostFunction};
+use crate::importer::SourceProvider;
+#[cfg(feature = "integrity")]
+use crate::integrity::Integrity;
+use crate::interface::Interface;
+use crate::limit::Limits;
+use crate::output::{OutputLine, Sink};
+use crate::queue::Bounds;
+use crate::sandbox::Sandbox;
+use crate::subprocess::ResourceLimits;
+use crate::watchdog::{Hang, HangAction, Watchdog};
+use crate::worker::{Control, run_worker, serve, serve_loop};
+use crate::{PyRunnerError, PythonModule, PythonPath, QueuePolicy, Task, Venv};
+use crossbeam::channel::{self, Sender};
+use nanoid::nanoid;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::io::Write;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const LOADER: &str = include_str!("builder/loader.py");
+
+/// Receives the outcome of the import on the worker thread
+pub(crate) type InitReply = Box<dyn FnOnce(Result<(), PyRunnerError>) + Send>;
+
+type Warmup = Box<dyn Fn(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<()> + Send>;
+
+/// Configures how a [`PythonModule`] is loaded
+///```rs
+/// let module = PythonModuleBuilder::new_module("./my-module")
+///     .sys_path("./vendor")
+///     .env("API_URL", "http://localhost")
+///     .build()
+///     .unwrap();
+/// ```
+pub struct PythonModuleBuilder {
+    init_file: PathBuf,
+    source: Option<String>,
+    bytecode: Option<Vec<u8>>,
+    archive: bool,
+    package: bool,
+    module_name: Option<String>,
+    sys_path: PythonPath,
+    env: Vec<(String, String)>,
+    working_dir: Option<PathBuf>,
+    lazy: bool,
+    subprocess: bool,
+    python: Option<PathBuf>,
+    resource_limits: Option<ResourceLimits>,
+    sandbox: Option<Sandbox>,
+    fs_root: Option<PathBuf>,
+    #[cfg(feature = "integrity")]
+    integrity: Integrity,
+    providers: Vec<Arc<dyn SourceProvider>>,
+    interface: Option<Interface>,
+    exposed: Vec<(String, Callback)>,
+    events: Option<Arc<Events>>,
+    output: Option<Sink>,
+    event_loop: bool,
+    exceptions: Vec<(String, ExceptionMapper)>,
+    init_timeout: Option<Duration>,
+    deferred: bool,
+    queue: Option<(usize, QueuePolicy)>,
+    rate_limit: Option<f64>,
+    max_concurrent: Option<usize>,
+    circuit_breaker: Option<(u32, Duration)>,
+    watchdog: Option<Watchdog>,
+    preload: Vec<String>,
+    warmup: Option<Warmup>,
+    #[cfg(feature = "log")]
+    forward_logging: bool,
+}
+
+impl PythonModuleBuilder {
+    /// Loads a Python project from root file
+    pub fn new_project(init_file: impl Into<PathBuf>) -> Self {
+        Self {
+            init_file: init_file.into(),
+            source: None,
+            bytecode: None,
+            archive: false,
+            package: false,
+            module_name: None,
+            sys_path: PythonPath::default(),
+            env: Vec::new(),
+            working_dir: None,
+            lazy: false,
+            subprocess: false,
+            python: None,
+            resource_limits: None,
+            sandbox: None,
+            fs_root: None,
+            #[cfg(feature = "integrity")]
+            integrity: Integrity::default(),
+            providers: Vec::new(),
+            interface: None,
+            exposed: Vec::new(),
+            events: None,
+            output: None,
+            event_loop: false,
+            exceptions: Vec::new(),
+            init_timeout: None,
+            deferred: false,
+            queue: None,
+            rate_limit: None,
+            max_concurrent: None,
+            circuit_breaker: None,
+            watchdog: None,
+            preload: Vec::new(),
+            warmup: None,
+            #[cfg(feature = "log")]
+            forward_logging: false,
+        }
+    }
+
+    /// Loads a Python package from a directory
+    ///
+    /// Submodules, subpackages and relative imports resolve inside the directory, also as
+    /// `.pyc` files without sources, see [`compile_bytecode`](crate::compile_bytecode).
+    /// Without an `__init__.py` or `__init__.pyc` it is loaded as a namespace package. The
+    /// package is registered under a random id, set [`module_name`](Self::module_name), e.g.
+    /// to the directory name, for absolute imports of the package from its own submodules.
+    pub fn new_module(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let init_file = match path.join("__init__.pyc") {
+            compiled if compiled.is_file() && !path.join("__init__.py").is_file() => compiled,
+            _ => path.join("__init__.py"),
+        };
+        let mut builder = Self::new_project(init_file);
+        builder.package = true;
+        builder
+    }
+
+    /// Loads a Python module if `path` is a directory, a Python project otherwise
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        if path.is_dir() {
+            Self::new_module(path)
+        } else {
+            Self::new_project(path)
+        }
+    }
+
+    /// Loads a Python module from `source`, e.g. embedded with `include_str!`
+    ///
+    /// The module is registered as `name` and `<name>` is used as its file name in
+    /// tracebacks. Relative imports and submodules are not available. Not supported by the
+    /// subprocess backend.
+    ///```rs
+    /// let module = PythonModuleBuilder::from_source("plugin", include_str!("plugin.py"))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn from_source(name: impl Into<String>, source: impl Into<String>) -> Self {
+        let name = name.into();
+        let mut builder = Self::new_project(format!("<{name}>"));
+        builder.source = Some(source.into());
+        builder.module_name = Some(name);
+        builder
+    }
+
+    /// Loads a Python module from bytecode: a marshalled code object like
+    /// `marshal.dumps(compile(source, path, "exec"))` or the contents of a `.pyc` file
+    ///
+    /// Like [`from_source`](Self::from_source) without shipping the source, tracebacks show
+    /// file names and line numbers but no code. The bytecode has to come from the same
+    /// Python version, a `.pyc` from another one fails with an `ImportError`.
+    ///```rs
+    /// let module = PythonModuleBuilder::from_bytecode("plugin", include_bytes!(concat!(env!("OUT_DIR"), "/plugin.pyc")))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn from_bytecode(name: impl Into<String>, bytecode: impl Into<Vec<u8>>) -> Self {
+        let name = name.into();
+        let mut builder = Self::new_project(format!("<{name}>"));
+        builder.bytecode = Some(bytecode.into());
+        builder.module_name = Some(name);
+        builder
+    }
+
+    /// Loads a Python module from a zip archive like a `.pyz` bundle with `zipimport`
+    ///
+    /// The archive is added to `sys.path` and the top-level package or module named after
+    /// the archive (`analytics` for `plugins/analytics.pyz`) is imported, see
+    /// [`module_name`](Self::module_name) to choose another one. Its subpackages and data
+    /// files (`importlib.resources`) are read from the archive. `lazy` is ignored and the
+    /// subprocess backend is not supported.
+    pub fn new_zipped(path: impl Into<PathBuf>) -> Self {
+        let mut builder = Self::new_project(path);
+        builder.archive = true;
+        builder
+    }
+
+    /// Name under which the module is registered in `sys.modules`, defaults to a random id
+    pub fn module_name(mut self, name: impl Into<String>) -> Self {
+        self.module_name = Some(name.into());
+        self
+    }
+
+    /// Prepends `path` to `sys.path` before the module is executed
+    pub fn sys_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sys_path = mem::take(&mut self.sys_path).prepend(path);
+        self
+    }
+
+    /// Adds the entries of `path` to `sys.path` before the module is executed, rather than to
+    /// the `PYTHONPATH` of the whole process
+    ///
+    /// `sys.path` belongs to the interpreter, only subprocess modules keep the entries to
+    /// themselves.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module")
+    ///     .python_path(PythonPath::builder().prepend("./venv-site").append("./extra"))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn python_path(mut self, path: PythonPath) -> Self {
+        let mut merged = mem::take(&mut self.sys_path);
+        for entry in path.prepended() {
+            merged = merged.prepend(entry);
+        }
+        for entry in path.appended() {
+            merged = merged.append(entry);
+        }
+        self.sys_path = merged;
+        self
+    }
+
+    /// Sets `os.environ[key]` before the module is executed
+    ///
+    /// Subprocess modules keep the variable to themselves, other modules share `os.environ`
+    /// and the environment of the process.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Changes the working directory before the module is executed
+    ///
+    /// The working directory is shared by the whole process.
+    pub fn working_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(path.into());
+        self
+    }
+
+    /// Defers executing the module body until the first attribute access
+    /// (`importlib.util.LazyLoader`)
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    /// Builds the module without importing it, the import runs on the first action or
+    /// [`PythonModule::start`]
+    ///
+    /// Keeps heavy imports off the startup path of modules that may never be used. An import
+    /// error fails the action that triggered it with [`ErrorKind::WorkerDead`], `start` returns
+    /// the error itself. `init_timeout` is ignored.
+    pub fn deferred(mut self, deferred: bool) -> Self {
+        self.deferred = deferred;
+        self
+    }
+
+    /// Holds at most `capacity` queued tasks, `policy` decides what happens to more
+    ///
+    /// The queue is unbounded by default, so a slow worker lets it grow without limit. A
+    /// capacity of 0 is treated as 1. Releasing [`PyHandle`](crate::PyHandle)s always waits
+    /// for room. Tasks queued with a [`Priority`](crate::Priority) other than `Normal` wait in
+    /// separate lanes, which neither count towards the capacity nor apply `policy`.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module")
+    ///     .queue_capacity(64, QueuePolicy::FailFast)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn queue_capacity(mut self, capacity: usize, policy: QueuePolicy) -> Self {
+        self.queue = Some((capacity.max(1), policy));
+        self
+    }
+
+    /// Fails actions with [`ErrorKind::RateLimited`] once more than `calls_per_second` were
+    /// queued within a second
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module")
+    ///     .rate_limit(20.0)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn rate_limit(mut self, calls_per_second: f64) -> Self {
+        self.rate_limit = Some(calls_per_second);
+        self
+    }
+
+    /// Fails actions with [`ErrorKind::RateLimited`] while `max` tasks are queued or running
+    pub fn max_concurrent(mut self, max: usize) -> Self {
+        self.max_concurrent = Some(max);
+        self
+    }
+
+    /// Fails actions with [`ErrorKind::CircuitOpen`] for `cooldown` after `failures`
+    /// consecutive Python exceptions
+    ///
+    /// After the cooldown a single probe call is let through, its success closes the circuit
+    /// and another exception opens it again. Panics and other errors don't count.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./flaky-client")
+    ///     .circuit_breaker(5, Duration::from_secs(30))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn circuit_breaker(mut self, failures: u32, cooldown: Duration) -> Self {
+        self.circuit_breaker = Some((failures, cooldown));
+        self
+    }
+
+    /// Calls `on_hang` from a separate thread once a task has been running for longer than
+    /// `limit`, then applies `action`
+    ///
+    /// Surfaces silent hangs, e.g. inside a C extension that doesn't return. Each task is
+    /// reported once. Modules running an [`event_loop`](Self::event_loop) aren't watched.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module")
+    ///     .watchdog(Duration::from_secs(30), HangAction::Interrupt, |hang| {
+    ///         eprintln!("task {:?} hangs for {:?}", hang.task, hang.running_for);
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn watchdog(
+        mut self,
+        limit: Duration,
+        action: HangAction,
+        on_hang: impl Fn(&Hang) + Send + Sync + 'static,
+    ) -> Self {
+        self.watchdog = Some(Watchdog {
+            limit,
+            action,
+            on_hang: Arc::new(on_hang),
+        });
+        self
+    }
+
+    /// Imports `modules` right after the module, so their import time isn't added to the
+    /// first action
+    ///
+    /// Not supported by the subprocess backend.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module")
+    ///     .preload(["numpy", "pandas", "torch"])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn preload(mut self, modules: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.preload.extend(modules.into_iter().map(Into::into));
+        self
+    }
+
+    /// Fails the build with [`ErrorKind::InterfaceMismatch`] unless the module defines the
+    /// functions of `interface`, checked after the import and [`preload`](Self::preload)
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./plugins/webhook")
+    ///     .interface(Interface::new().func("handle_event", 2).func("init", 0))
+    ///     .build()?;
+    /// ```
+    pub fn interface(mut self, interface: Interface) -> Self {
+        self.interface = Some(interface);
+        self
+    }
+
+    /// Runs `call` on the module after the import and [`preload`](Self::preload), e.g. to
+    /// load a model before the first request arrives
+    ///
+    /// An error fails the build like an error of the import.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module")
+    ///     .warmup(|_, module| module.call_method0("load_model").map(|_| ()))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn warmup(
+        mut self,
+        call: impl Fn(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<()> + Send + 'static,
+    ) -> Self {
+        self.warmup = Some(Box::new(call));
+        self
+    }
+
+    /// Runs the module in a separate `python` process, see [`PythonModule::new_subprocess`]
+    ///
+    /// `sys_path`, `env` and `working_dir` apply to the child process, `lazy` is ignored.
+    pub fn subprocess(mut self, subprocess: bool) -> Self {
+        self.subprocess = subprocess;
+        self
+    }
+
+    /// Uses the packages of `venv` for this module only
+    ///
+    /// Prepends its `site-packages` to `sys.path` and sets `VIRTUAL_ENV` (`CONDA_PREFIX` for
+    /// conda) like [`env`](Self::env), the subprocess backend runs the environment's
+    /// interpreter unless [`python`](Self::python) is set. Modules sharing an interpreter
+    /// share `sys.path`, use subprocess modules for different environments.
+    ///```rs
+    /// let a = PythonModuleBuilder::new_module("./a").venv(&Venv::open("./a/.venv")?).subprocess(true).build()?;
+    /// let b = PythonModuleBuilder::new_module("./b").venv(&Venv::open("./b/.venv")?).subprocess(true).build()?;
+    /// ```
+    pub fn venv(mut self, venv: &Venv) -> Self {
+        self.sys_path = mem::take(&mut self.sys_path).prepend(venv.site_packages());
+        let key = match venv.is_conda() {
+            true => "CONDA_PREFIX",
+            false => "VIRTUAL_ENV",
+        };
+        self.env
+            .push((key.to_string(), venv.root().to_string_lossy().into_owned()));
+        self.python.get_or_insert_with(|| venv.executable());
+        self
+    }
+
+    /// Interpreter used for the subprocess backend, defaults to `sys.executable`
+    pub fn python(mut self, python: impl Into<PathBuf>) -> Self {
+        self.python = Some(python.into());
+        self
+    }
+
+    /// Limits memory, CPU time, open files and call duration of the child process
+    ///
+    /// Only supported by the [`subprocess`](Self::subprocess) backend on POSIX systems, see
+    /// [`ResourceLimits`].
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./untrusted")
+    ///     .subprocess(true)
+    ///     .resource_limits(ResourceLimits {
+    ///         max_memory: Some(512 << 20),
+    ///         cpu_time: Some(Duration::from_secs(60)),
+    ///         ..Default::default()
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn resource_limits(mut self, limits: ResourceLimits) -> Self {
+        self.resource_limits = Some(limits);
+        self
+    }
+
+    /// Restricts the builtins, imports and operations of the module's code, see [`Sandbox`]
+    ///
+    /// Not supported by the subprocess backend, namespace packages and zipped modules.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_project("./scripts/user.py")
+    ///     .sandbox(Sandbox::strict().allow_imports(["json", "math"]))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Confines the file access of the module's code to `root`
+    ///
+    /// Opening, listing, creating, removing or renaming paths outside of it, also through
+    /// symlinks, raises `PermissionError`. Reading stays allowed in the module's directory and
+    /// the `sys.path` entries so imports and package data keep working. In-process the check
+    /// is an audit hook scoped like the [`Sandbox`] and not a security boundary; the
+    /// subprocess backend confines the whole child and additionally lets the kernel enforce it
+    /// with Landlock on Linux 5.13 and newer. Not supported by namespace packages and zipped
+    /// modules.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_project("./scripts/user.py")
+    ///     .fs_root("./scripts/data")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn fs_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.fs_root = Some(root.into());
+        self
+    }
+
+    /// Lets the module import modules whose source `provider` returns, see [`SourceProvider`]
+    ///
+    /// The finder is put in front of `sys.meta_path` before the module is executed, so the
+    /// provider takes precedence over the file system for every import of the interpreter.
+    /// Providers added first are asked first. Not supported by the subprocess backend.
+    ///```rs
+    /// let module = PythonModuleBuilder::from_source("main", "import plugins.report\n")
+    ///     .source_provider(|name: &str| database.load_module(name))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn source_provider(mut self, provider: impl SourceProvider) -> Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+
+    /// Refuses to load the module unless its SHA-256 [`digest`](Self::digest) is `digest`
+    ///
+    /// Checked before anything is executed, a mismatch fails the build with
+    /// [`ErrorKind::Integrity`]. The files are read again for the import, so this protects
+    /// against tampered plugins at rest, not against changes while the module loads.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./plugins/analytics")
+    ///     .verify_sha256("4f47b6ea18c40692a155ed9d960925a0950d4323d09b9c16747cc5420428946a")
+    ///     .build()?;
+    /// ```
+    #[cfg(feature = "integrity")]
+    pub fn verify_sha256(mut self, digest: impl Into<String>) -> Self {
+        self.integrity.sha256 = Some(digest.into());
+        self
+    }
+
+    /// Refuses to load the module unless `signature` is a valid Ed25519 signature of its
+    /// [`digest`](Self::digest) by `public_key`, see [`verify_sha256`](Self::verify_sha256)
+    #[cfg(feature = "integrity")]
+    pub fn verify_signature(mut self, public_key: [u8; 32], signature: [u8; 64]) -> Self {
+        self.integrity.signature = Some((public_key, signature));
+        self
+    }
+
+    /// Hex SHA-256 of the module as checked by [`verify_sha256`](Self::verify_sha256)
+    ///
+    /// Covers the source or bytecode of modules loaded from memory, the archive of zipped
+    /// modules, the file of projects and the directory of packages: the digest over the
+    /// relative path and SHA-256 of every file in path order, without hidden entries and
+    /// `__pycache__`. Signatures sign the 32 raw bytes of this digest.
+    #[cfg(feature = "integrity")]
+    pub fn digest(&self) -> Result<String, PyRunnerError> {
+        crate::integrity::hex_digest(self.integrity_path(), self.in_memory())
+    }
+
+    /// Runs an asyncio event loop on the worker thread for the lifetime of the module
+    ///
+    /// Coroutines awaited with [`PythonModule::action_await`] are scheduled on the loop and
+    /// run concurrently instead of one after another, synchronous actions run as loop
+    /// callbacks and block the loop while they run. Not supported by the subprocess backend.
+    pub fn event_loop(mut self, event_loop: bool) -> Self {
+        self.event_loop = event_loop;
+        self
+    }
+
+    /// Makes `function` callable from Python as `host.<name>` after `import host`
+    ///
+    /// The function runs on the worker thread while holding the GIL. Every module gets its own
+    /// `host`, which `import host` finds in the module, its submodules and the files next to
+    /// it, anywhere in them. Not supported by the subprocess backend.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module")
+    ///     .expose("log_event", |event: String| Ok(println!("{event}")))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn expose<Args>(
+        mut self,
+        name: impl Into<String>,
+        function: impl HostFunction<Args>,
+    ) -> Self {
+        self.exposed.push((name.into(), function.into_callback()));
+        self
+    }
+
+    /// Fails [`build`](Self::build) with a `TimeoutError` if the import takes longer than `timeout`
+    ///
+    /// A hanging import can't be aborted, it keeps running on the worker thread, which exits
+    /// once the import returns.
+    pub fn init_timeout(mut self, timeout: Duration) -> Self {
+        self.init_timeout = Some(timeout);
+        self
+    }
+
+    /// Converts exceptions of the class `exception_type` (or a subclass) into `E` with `map`
+    ///
+    /// `exception_type` is the qualified name as in [`PyRunnerError::exception_type`]. The
+    /// mapped error is attached to the [`PyRunnerError`] of failed actions and imports and
+    /// read back with [`PyRunnerError::mapped`]. If several registered classes match, the
+    /// most derived one wins.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module")
+    ///     .map_exception("mypkg.errors.NotFound", |e| StoreError::NotFound(e.message().to_string()))
+    ///     .build()
+    ///     .unwrap();
+    /// if let Err(e) = module.action(|_, module| module.call_method1("get", ("key",)).map(|_| ())) {
+    ///     if let Some(StoreError::NotFound(key)) = e.mapped::<StoreError>() { ... }
+    /// }
+    /// ```
+    pub fn map_exception<E: std::error::Error + Send + Sync + 'static>(
+        mut self,
+        exception_type: impl Into<String>,
+        map: impl Fn(&PyRunnerError) -> E + Send + Sync + 'static,
+    ) -> Self {
+        self.exceptions.push((
+            exception_type.into(),
+            Arc::new(move |err: &PyRunnerError| Box::new(map(err)) as Box<_>),
+        ));
+        self
+    }
+
+    /// Streams every line the module prints to `sender`, tagged with its stream
+    ///
+    /// Covers output of the worker thread from the import on, threads started by the module
+    /// keep printing to the process' streams. Not supported by the subprocess backend.
+    ///```rs
+    /// let (sender, receiver) = crossbeam::channel::unbounded();
+    /// let module = PythonModuleBuilder::new_module("./my-module").output(sender).build().unwrap();
+    /// ```
+    pub fn output(mut self, sender: Sender<OutputLine>) -> Self {
+        self.output = Some(Arc::new(move |line| {
+            let _ = sender.send(line);
+        }));
+        self
+    }
+
+    /// Like [`output`](Self::output), but writes the lines of both streams to `writer`
+    pub fn output_writer(mut self, writer: impl Write + Send + 'static) -> Self {
+        let writer = Mutex::new(writer);
+        self.output = Some(Arc::new(move |output: OutputLine| {
+            let mut writer = writer.lock().unwrap();
+            let _ = writeln!(writer, "{}", output.line).and_then(|_| writer.flush());
+        }));
+        self
+    }
+
+    /// Forwards records of Python's `logging` to the `log` crate
+    ///
+    /// The handler is added to the root logger of the interpreter, so it also covers other
+    /// modules sharing it. Not supported by the subprocess backend.
+    #[cfg(feature = "log")]
+    pub fn forward_logging(mut self, forward: bool) -> Self {
+        self.forward_logging = forward;
+        self
+    }
+
+    /// Spawns the worker thread and imports the module
+    pub fn build(self) -> Result<PythonModule, PyRunnerError> {
+        let (init_sender, init_receiver) = std::sync::mpsc::sync_channel(1);
+        let timeout = self.init_timeout;
+        let deferred = self.deferred;
+        let module = self.spawn(Box::new(move |result| {
+            let _ = init_sender.send(result);
+        }))?;
+        if deferred {
+            *module.init.lock().unwrap() = Some(init_receiver);
+            return Ok(module);
+        }
+        let result = match timeout {
+            Some(timeout) => match init_receiver.recv_timeout(timeout) {
+                Err(RecvTimeoutError::Timeout) => return Err(init_timeout_error(timeout)),
+                result => result.ok(),
+            },
+            None => init_receiver.recv().ok(),
+        };
+        if let Some(result) = result {
+            result.map_err(|e| e.init_failed())?;
+        }
+        Ok(module)
+    }
+
+    /// Like [`build`](Self::build), but waits for the import without blocking the calling
+    /// thread
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module").build_async().await.unwrap();
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn build_async(self) -> Result<PythonModule, PyRunnerError> {
+        if self.deferred {
+            return self.build();
+        }
+        let (init_sender, init_receiver) = tokio::sync::oneshot::channel();
+        let init_sender = Arc::new(Mutex::new(Some(init_sender)));
+        let (done_sender, done_receiver) = channel::bounded::<()>(0);
+        let timeout = self.init_timeout;
+        let sender = init_sender.clone();
+        let module = self.spawn(Box::new(move |result| {
+            if let Some(sender) = sender.lock().unwrap().take() {
+                let _ = sender.send(Some(result));
+            }
+            drop(done_sender);
+        }))?;
+        if let Some(timeout) = timeout {
+            // `tokio` is only used for its channels, so a thread keeps the time
+            thread::spawn(move || {
+                if let Err(channel::RecvTimeoutError::Timeout) = done_receiver.recv_timeout(timeout)
+                    && let Some(sender) = init_sender.lock().unwrap().take()
+                {
+                    let _ = sender.send(None);
+                }
+            });
+        }
+        match init_receiver.await {
+            Ok(Some(result)) => result.map_err(|e| e.init_failed())?,
+            Ok(None) => return Err(init_timeout_error(timeout.unwrap_or_default())),
+            Err(_) => {}
+        }
+        Ok(module)
+    }
+
+    /// Spawns the worker thread, which reports the outcome of the import to `init`
+    fn spawn(mut self, init: InitReply) -> Result<PythonModule, PyRunnerError> {
+        self.validate()?;
+        let (task_sender, task_receiver) = match self.queue {
+            Some((capacity, _)) => channel::bounded::<Option<Task>>(capacity),
+            None => channel::unbounded::<Option<Task>>(),
+        };
+        let bounds = self.queue.map(|(_, policy)| Bounds {
+            policy,
+            overflow: (policy == QueuePolicy::DropOldest).then(|| task_receiver.clone()),
+        });
+        let (exit_sender, exit_receiver) = channel::bounded::<()>(0);
+        let mut control = Control::default();
+        control.limits = Limits::new(self.rate_limit, self.max_concurrent, self.circuit_breaker);
+        let control = Arc::new(control);
+        let events = Arc::new(Events::default());
+        self.events = Some(events.clone());
+        let worker_control = control.clone();
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.start(control.clone(), exit_receiver.clone());
+        }
+        let exceptions = ExceptionMap::new(std::mem::take(&mut self.exceptions));
+        let worker_exceptions = exceptions.clone();
+        let (start_sender, start_receiver) = match self.deferred {
+            true => {
+                let (sender, receiver) = channel::bounded::<bool>(1);
+                (Some(sender), Some(receiver))
+            }
+            false => (None, None),
+        };
+
+        let thread_handle = thread::spawn(move || {
+            // dropped when the thread exits, which wakes up `shutdown`
+            let _exit_sender = exit_sender;
+            run_worker(&worker_control, || {
+                if let Some(start) = start_receiver
+                    && !start.recv().unwrap_or(false)
+                {
+                    return Ok(());
+                }
+                Python::with_gil(|py| {
+                    match self.import(py) {
+                        Ok(module) => {
+                            if self.subprocess
+                                && let Ok(pid) = module
+                                    .getattr("_process")
+                                    .and_then(|process| process.getattr("pid")?.extract())
+                            {
+                                let _ = worker_control.child.set(pid);
+                            }
+                            init(Ok(()));
+                            let served = match self.event_loop {
+                                true => serve_loop(py, &module, &task_receiver, &worker_control),
+                                false => {
+                                    serve(py, &module, &task_receiver, &worker_control);
+                                    Ok(())
+                                }
+                            };
+                            crate::sandbox::forget(py, &module)?;
+                            crate::host::forget(py, &module)?;
+                            served?;
+                        }
+                        Err(e) => {
+                            init(Err(worker_exceptions.convert(py, e.clone_ref(py))));
+                            // the exit reason tells actions of a deferred module what failed
+                            return Err(e);
+                        }
+                    }
+
+                    Ok(())
+                })
+            })
+        });
+        Ok(PythonModule {
+            task_sender,
+            bounds,
+            thread_handle: Some(thread_handle),
+            exit_receiver,
+            control,
+            start: start_sender,
+            init: Mutex::new(None),
+            exceptions,
+            events,
+            #[cfg(feature = "watch")]
+            watcher: None,
+        })
+    }
+
+    fn check_init_file(&self) -> Result<(), PyRunnerError> {
+        if self.namespace() && self.package_dir().is_dir() {
+            return Ok(());
+        }
+        if self.in_memory().is_none() && !self.init_file.is_file() {
+            return Err(
+                PyErr::new::<pyo3::exceptions::PyFileNotFoundError, _>(format!(
+                    "No {} found",
+                    self.init_file.display()
+                ))
+                .into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks the configuration and the integrity of the module before it is imported
+    pub(crate) fn validate(&self) -> Result<(), PyRunnerError> {
+        self.check_init_file()?;
+        if self.sandbox.is_some() && (self.subprocess || self.archive || self.namespace()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "A sandbox is not supported by the subprocess backend, namespace packages and zipped modules",
+            )
+            .into());
+        }
+        if self.fs_root.is_some() && (self.archive || self.namespace()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "A file system root is not supported by namespace packages and zipped modules",
+            )
+            .into());
+        }
+        #[cfg(feature = "integrity")]
+        if self.integrity.is_required() {
+            self.integrity
+                .verify(self.integrity_path(), self.in_memory())?;
+        }
+        if self.resource_limits.is_some() && !self.subprocess {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Resource limits are only supported by the subprocess backend",
+            )
+            .into());
+        }
+        if self.resource_limits.is_some() && cfg!(windows) {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Resource limits need a POSIX system",
+            )
+            .into());
+        }
+        if self.event_loop && self.subprocess {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "An event loop is not supported by the subprocess backend",
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn import<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let module = self.import_module(py)?;
+        for name in &self.preload {
+            py.import(name.as_str())?;
+        }
+        if let Some(interface) = &self.interface {
+            interface.check(py, &module)?;
+        }
+        if let Some(warmup) = &self.warmup {
+            warmup(&py, &module)?;
+        }
+        Ok(module)
+    }
+
+    fn import_module<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let module_name = self.module_name.clone().unwrap_or_else(|| nanoid!(16));
+        if self.subprocess {
+            if !self.exposed.is_empty() {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Exposed functions are not supported by the subprocess backend",
+                ));
+            }
+            if self.output.is_some() {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Output streaming is not supported by the subprocess backend",
+                ));
+            }
+            #[cfg(feature = "log")]
+            if self.forward_logging {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Forwarding logging is not supported by the subprocess backend",
+                ));
+            }
+            if self.in_memory().is_some() {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Modules from source or bytecode are not supported by the subprocess backend",
+                ));
+            }
+            if self.archive {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Zipped modules are not supported by the subprocess backend",
+                ));
+            }
+            if self.namespace() {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Namespace packages are not supported by the subprocess backend",
+                ));
+            }
+            if !self.providers.is_empty() {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Source providers are not supported by the subprocess backend",
+                ));
+            }
+            if !self.preload.is_empty() {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Preloading is not supported by the subprocess backend",
+                ));
+            }
+            return crate::subprocess::spawn(
+                py,
+                crate::subprocess::Child {
+                    python: self.python.as_deref(),
+                    init_file: &self.init_file,
+                    module_name: &module_name,
+                    sys_path: &self.sys_path,
+                    env: &self.env,
+                    working_dir: self.working_dir.as_deref(),
+                    limits: self.resource_limits.as_ref(),
+                    root: self.fs_root.as_deref(),
+                },
+            );
+        }
+        let sys = py.import("sys")?;
+        let os = py.import("os")?;
+
+        let path = sys.getattr("path")?;
+        for (i, entry) in self.sys_path.prepended().iter().enumerate() {
+            path.call_method1("insert", (i, entry.as_os_str()))?;
+        }
+        for entry in self.sys_path.appended() {
+            if !path.contains(entry.as_os_str())? {
+                path.call_method1("append", (entry.as_os_str(),))?;
+            }
+        }
+        let environ = os.getattr("environ")?;
+        for (key, value) in &self.env {
+            environ.set_item(key, value)?;
+        }
+        if let Some(dir) = &self.working_dir {
+            os.call_method1("chdir", (dir.as_os_str(),))?;
+        }
+
+        if let Some(output) = &self.output {
+            crate::output::attach(py, output)?;
+        }
+        #[cfg(feature = "log")]
+        if self.forward_logging {
+            crate::logging::install(py)?;
+        }
+        if !self.exposed.is_empty() || self.events.is_some() {
+            let root = self.in_memory().is_none().then(|| self.package_dir());
+            crate::host::install(py, &module_name, root, &self.exposed, self.events.clone())?;
+        }
+        if !self.providers.is_empty() {
+            crate::importer::install(py, &self.providers)?;
+        }
+
+        if self.archive {
+            return self.import_archive(py);
+        }
+
+        let importlib_util = PyModule::import(py, "importlib.util")?;
+        let modules = sys.getattr("modules")?;
+        if self.namespace() {
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("is_package", true)?;
+            let spec = py
+                .import("importlib.machinery")?
+                .getattr("ModuleSpec")?
+                .call((&module_name, py.None()), Some(&kwargs))?;
+            spec.setattr(
+                "submodule_search_locations",
+                vec![self.package_dir().as_os_str()],
+            )?;
+            let module = importlib_util.getattr("module_from_spec")?.call1((spec,))?;
+            modules.set_item(module_name, &module)?;
+            return Ok(module);
+        }
+
+        let spec = match (&self.source, &self.bytecode) {
+            (_, Some(bytecode)) => {
+                let loader = loader(py)?
+                    .getattr("BytecodeLoader")?
+                    .call1((self.init_file.as_os_str(), &bytecode[..]))?;
+                importlib_util
+                    .getattr("spec_from_loader")?
+                    .call1((&module_name, loader))?
+            }
+            (Some(source), _) => {
+                let loader = loader(py)?
+                    .getattr("SourceLoader")?
+                    .call1((self.init_file.as_os_str(), source))?;
+                importlib_util
+                    .getattr("spec_from_loader")?
+                    .call1((&module_name, loader))?
+            }
+            _ if self.package => {
+                let kwargs = PyDict::new(py);
+                kwargs.set_item(
+                    "submodule_search_locations",
+                    vec![self.package_dir().as_os_str()],
+                )?;
+                importlib_util
+                    .getattr("spec_from_file_location")?
+                    .call((&module_name, self.init_file.as_os_str()), Some(&kwargs))?
+            }
+            _ => importlib_util
+                .getattr("spec_from_file_location")?
+                .call1((&module_name, self.init_file.as_os_str()))?,
+        };
+        let mut loader = spec.getattr("loader")?;
+        if self.lazy {
+            loader = importlib_util.getattr("LazyLoader")?.call1((loader,))?;
+            spec.setattr("loader", &loader)?;
+        }
+
+        let module = importlib_util
+            .getattr("module_from_spec")?
+            .call1((spec.clone(),))?;
+        modules.set_item(module_name, &module)?;
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.apply(py, &module)?;
+        }
+        if let Some(root) = &self.fs_root {
+            crate::sandbox::confine(py, &module, root, self.package_dir())?;
+        }
+        loader.call_method1("exec_module", (module.clone(),))?;
+        Ok(module)
+    }
+
+    fn package_dir(&self) -> &Path {
+        self.init_file.parent().unwrap_or(Path::new("."))
+    }
+
+    /// Source or bytecode of modules loaded from memory
+    fn in_memory(&self) -> Option<&[u8]> {
+        self.source
+            .as_deref()
+            .map(str::as_bytes)
+            .or(self.bytecode.as_deref())
+    }
+
+    /// File or directory covered by [`digest`](Self::digest)
+    #[cfg(feature = "integrity")]
+    fn integrity_path(&self) -> &Path {
+        match self.package_dir() {
+            _ if !self.package => &self.init_file,
+            dir if dir.as_os_str().is_empty() => Path::new("."),
+            dir => dir,
+        }
+    }
+
+    fn namespace(&self) -> bool {
+        self.package && !self.init_file.is_file()
+    }
+
+    fn import_archive<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let archive = std::path::absolute(&self.init_file)?;
+        let module_name = match &self.module_name {
+            Some(name) => name.clone(),
+            None => archive
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "No module name in {}",
+                        archive.display()
+                    ))
+                })?,
+        };
+        let path = py.import("sys")?.getattr("path")?;
+        let archive = archive.as_os_str();
+        if !path.contains(archive)? {
+            path.call_method1("insert", (0, archive))?;
+        }
+        py.import("importlib")?
+            .call_method1("import_module", (module_name,))
+    }
+}
+
+/// `builder/loader.py`, loaded once per process
+pub(crate) fn loader(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    load_helper(py, "loader", LOADER)
+}
+
+fn init_timeout_error(timeout: Duration) -> PyRunnerError {
+    PyRunnerError::timeout(format!("Module did not load within {timeout:?}"))
+}
@@ -0,0 +1,78 @@
+import importlib.abc
+import importlib.util
+import linecache
+import marshal
+
+
+class SourceLoader(importlib.abc.InspectLoader):
+    """Loads a module from source held in memory"""
+
+    def __init__(self, path, source, package=False):
+        self.path = path
+        self.source = source
+        self.package = package
+        # lets tracebacks show the offending lines
+        lines = source.splitlines(keepends=True)
+        linecache.cache[path] = (len(source), None, lines, path)
+
+    def get_source(self, fullname):
+        return self.source
+
+    def get_code(self, fullname):
+        return compile(self.source, self.path, "exec", dont_inherit=True)
+
+    def is_package(self, fullname):
+        return self.package
+
+
+class BytecodeLoader(importlib.abc.InspectLoader):
+    """Loads a module from a marshalled code object or the contents of a `.pyc` file"""
+
+    def __init__(self, path, data):
+        # a .pyc starts with the magic number, ending in \r\n, and 12 more header bytes
+        if data[2:4] == b"\r\n":
+            if data[:4] != importlib.util.MAGIC_NUMBER:
+                raise ImportError(f"Bytecode of {path} was compiled by another Python version")
+            data = data[16:]
+        self.path = path
+        self.data = data
+
+    def get_source(self, fullname):
+        return None
+
+    def get_code(self, fullname):
+        return marshal.loads(self.data)
+
+    def is_package(self, fullname):
+        return False
+
+
+class ProviderFinder(importlib.abc.MetaPathFinder):
+    """Finds modules through `lookup(name)`, which returns their source and whether they are
+    packages, or `None`"""
+
+    def __init__(self, lookup):
+        self.lookup = lookup
+
+    def find_spec(self, fullname, path=None, target=None):
+        found = self.lookup(fullname)
+        if found is None:
+            return None
+        source, package = found
+        # file name in tracebacks, never an existing path
+        origin = "<provider>/" + fullname.replace(".", "/")
+        origin += "/__init__.py" if package else ".py"
+        loader = SourceLoader(origin, source, package)
+        return importlib.util.spec_from_loader(fullname, loader, origin=origin, is_package=package)
+
+
+class InterfaceMismatch(TypeError):
+    """Raised when a module lacks functions of its required interface"""
+
+    def __init__(self, message, mismatches):
+        super().__init__(message)
+        # (problem, name, expected arity) tuples
+        self.mismatches = mismatches
+
+
+InterfaceMismatch.__module__ = "py_runner"
@@ -0,0 +1,160 @@
+use crate::PyRunnerError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Compiles the Python source tree `source_dir` into `out_dir` so it can be shipped without
+/// sources, returns the written files
+///
+/// Every `.py` file becomes a `.pyc` at the same relative path, which
+/// [`PythonModuleBuilder::new_module`](crate::PythonModuleBuilder::new_module) and imports
+/// load directly; other files like package data are copied. Hidden entries and
+/// `__pycache__` are skipped. The bytecode is tied to the version of the Python this runs
+/// with and embeds the relative paths instead of the build machine's, so calling it from
+/// `build.rs` gives reproducible bundles.
+///```rs
+/// // build.rs
+/// fn main() {
+///     let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("plugin");
+///     py_runner::compile_bytecode("python/plugin", &out).unwrap();
+///     println!("cargo:rerun-if-changed=python/plugin");
+/// }
+/// ```
+pub fn compile_bytecode(
+    source_dir: impl AsRef<Path>,
+    out_dir: impl AsRef<Path>,
+) -> Result<Vec<PathBuf>, PyRunnerError> {
+    let mut written = Vec::new();
+    Python::with_gil(|py| {
+        let py_compile = py.import("py_compile")?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("doraise", true)?;
+        // the hash of the missing source can't be checked, the timestamp would change builds
+        let mode = py_compile
+            .getattr("PycInvalidationMode")?
+            .getattr("UNCHECKED_HASH")?;
+        kwargs.set_item("invalidation_mode", mode)?;
+        compile_dir(
+            &py_compile,
+            &kwargs,
+            source_dir.as_ref(),
+            out_dir.as_ref(),
+            Path::new(""),
+            &mut written,
+        )
+    })?;
+    Ok(written)
+}
+
+fn compile_dir(
+    py_compile: &Bound<'_, PyAny>,
+    kwargs: &Bound<'_, PyDict>,
+    source_dir: &Path,
+    out_dir: &Path,
+    relative: &Path,
+    written: &mut Vec<PathBuf>,
+) -> PyResult<()> {
+    fs::create_dir_all(out_dir.join(relative))?;
+    let mut entries = fs::read_dir(source_dir.join(relative))?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.') || name == "__pycache__" {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            compile_dir(py_compile, kwargs, source_dir, out_dir, &path, written)?;
+            continue;
+        }
+        let target = match path.extension().is_some_and(|ext| ext == "py") {
+            true => {
+                let target = out_dir.join(&path).with_extension("pyc");
+                kwargs.set_item("cfile", target.as_os_str())?;
+                kwargs.set_item("dfile", path.to_string_lossy().replace('\\', "/"))?;
+                py_compile.call_method("compile", (entry.path().as_os_str(),), Some(kwargs))?;
+                target
+            }
+            false => {
+                let target = out_dir.join(&path);
+                fs::copy(entry.path(), &target)?;
+                target
+            }
+        };
+        written.push(target);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModuleBuilder;
+
+    #[test]
+    fn test_compile_bytecode() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let source = dir.join("src/compiled_pkg");
+        std::fs::create_dir_all(source.join("__pycache__")).unwrap();
+        std::fs::write(
+            source.join("__init__.py"),
+            "from importlib.resources import files\nfrom .util import double\nDATA = files(__name__).joinpath('data.txt').read_text()\n",
+        )
+        .unwrap();
+        std::fs::write(
+            source.join("util.py"),
+            "def double(x):\n    return x * 2\ndef fail():\n    raise ValueError('compiled')\n",
+        )
+        .unwrap();
+        std::fs::write(source.join("data.txt"), "payload").unwrap();
+        std::fs::write(source.join("__pycache__/util.cpython.pyc"), "stale").unwrap();
+
+        let out = dir.join("out/compiled_pkg");
+        let written = compile_bytecode(&source, &out).unwrap();
+        assert_eq!(
+            written,
+            [
+                out.join("__init__.pyc"),
+                out.join("data.txt"),
+                out.join("util.pyc")
+            ]
+        );
+
+        let module = PythonModuleBuilder::new_module(&out).build().unwrap();
+        let (doubled, data) = module
+            .action(|_, module| {
+                Ok((
+                    module.call_method1("double", (21,))?.extract::<i64>()?,
+                    module.getattr("DATA")?.extract::<String>()?,
+                ))
+            })
+            .unwrap();
+        assert_eq!((doubled, data.as_str()), (42, "payload"));
+        let err = module
+            .action(|_, module| module.getattr("util")?.call_method0("fail").map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "ValueError");
+        assert_eq!(err.file(), Some("util.py"));
+
+        // the contents of a .pyc and the bare marshalled code object
+        let pyc = std::fs::read(out.join("util.pyc")).unwrap();
+        for bytecode in [pyc.clone(), pyc[16..].to_vec()] {
+            let module = PythonModuleBuilder::from_bytecode("compiled_util", bytecode)
+                .build()
+                .unwrap();
+            let doubled = module
+                .action(|_, module| module.call_method1("double", (2,))?.extract::<i64>())
+                .unwrap();
+            assert_eq!(doubled, 4);
+        }
+        let mut foreign = pyc;
+        foreign[0] ^= 1;
+        let err = PythonModuleBuilder::from_bytecode("compiled_foreign", foreign)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.exception_type(), "ImportError");
+    }
+}
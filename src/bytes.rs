@@ -0,0 +1,263 @@
+use pyo3::buffer::{PyBuffer, ReadOnlyCell};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyMemoryView};
+use std::ffi::{c_int, c_void};
+use std::sync::Mutex;
+
+/// `memoryview` of `len` bytes at `address`, `owner` keeps the memory alive until Python
+/// dropped the last view of it
+///
+/// Only memory exclusively owned by `owner` may be exported with `readonly` unset, Python
+/// code can write to it then.
+pub(crate) fn owned_memoryview<T: Send + 'static>(
+    py: Python<'_>,
+    owner: T,
+    address: usize,
+    len: usize,
+    readonly: bool,
+) -> PyResult<Bound<'_, PyAny>> {
+    let buffer = Bound::new(
+        py,
+        OwnedBuffer {
+            _owner: Mutex::new(Box::new(owner)),
+            address,
+            len,
+            readonly,
+        },
+    )?;
+    Ok(PyMemoryView::from(buffer.as_any())?.into_any())
+}
+
+/// Exporter of Rust memory through the buffer protocol, every view holds a reference to it
+#[pyclass(frozen, module = "py_runner")]
+struct OwnedBuffer {
+    // never read, only dropped with the last view
+    _owner: Mutex<Box<dyn Send>>,
+    address: usize,
+    len: usize,
+    readonly: bool,
+}
+
+#[pymethods]
+impl OwnedBuffer {
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        let buffer = slf.get();
+        // SAFETY: `view` is provided by Python, the view keeps `slf` and with it the memory
+        // alive; a writable view of read-only memory is refused with a `BufferError`
+        let status = unsafe {
+            ffi::PyBuffer_FillInfo(
+                view,
+                slf.as_ptr(),
+                buffer.address as *mut c_void,
+                buffer.len as ffi::Py_ssize_t,
+                buffer.readonly as c_int,
+                flags,
+            )
+        };
+        match status {
+            -1 => Err(PyErr::fetch(slf.py())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Bytes handed to Python as read-only `memoryview` without copying them
+///
+/// Accepts anything that derefs to a byte slice, like `Vec<u8>`, `Arc<[u8]>` or
+/// `bytes::Bytes`, which is dropped once Python released the last view. `bytes(view)`
+/// copies the data into a Python `bytes` object.
+///```rs
+/// let frame = SharedBytes::new(std::fs::read("frame.raw")?);
+/// module.action(move |_, module| module.call_method1("decode", (frame,)).map(|_| ()))?;
+/// ```
+pub struct SharedBytes {
+    data: Box<dyn AsRef<[u8]> + Send>,
+}
+
+impl SharedBytes {
+    pub fn new(data: impl AsRef<[u8]> + Send + 'static) -> Self {
+        Self {
+            data: Box::new(data),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.as_ref().as_ref().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'py> IntoPyObject<'py> for SharedBytes {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let bytes = self.data.as_ref().as_ref();
+        let (address, len) = (bytes.as_ptr() as usize, bytes.len());
+        owned_memoryview(py, self.data, address, len, true)
+    }
+}
+
+/// Contiguous Python buffer like `bytes`, `bytearray` or a `memoryview`, read without copying
+///
+/// Tied to the action it was extracted in. Extracting fails if the buffer isn't
+/// C-contiguous.
+///```rs
+/// let checksum = module.action(|_, module| {
+///     let payload: BytesView = module.call_method0("payload")?.extract()?;
+///     Ok(crc32(&payload.to_vec()))
+/// })?;
+/// ```
+pub struct BytesView<'py> {
+    object: Bound<'py, PyAny>,
+    buffer: PyBuffer<u8>,
+}
+
+impl<'py> FromPyObject<'py> for BytesView<'py> {
+    fn extract_bound(object: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let buffer = PyBuffer::get(object)?;
+        if !buffer.is_c_contiguous() {
+            return Err(PyErr::new::<pyo3::exceptions::PyBufferError, _>(
+                "Buffer is not C-contiguous",
+            ));
+        }
+        Ok(Self {
+            object: object.clone(),
+            buffer,
+        })
+    }
+}
+
+impl BytesView<'_> {
+    /// The bytes, as cells because Python code may change a mutable buffer while the view
+    /// exists
+    pub fn as_slice(&self) -> &[ReadOnlyCell<u8>] {
+        self.buffer
+            .as_slice(self.object.py())
+            .expect("contiguous buffer of u8")
+    }
+
+    /// The bytes as plain slice, `None` unless the buffer belongs to an immutable `bytes`
+    /// object
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        let exporter = match self.object.downcast::<PyMemoryView>() {
+            Ok(view) => view.getattr("obj").ok()?,
+            Err(_) => self.object.clone(),
+        };
+        if !exporter.is_exact_instance_of::<PyBytes>() {
+            return None;
+        }
+        let cells = self.as_slice();
+        // bytes objects are never written after creation
+        Some(unsafe { std::slice::from_raw_parts(cells.as_ptr().cast::<u8>(), cells.len()) })
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.item_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn readonly(&self) -> bool {
+        self.buffer.readonly()
+    }
+
+    /// Copies the bytes into a `Vec`
+    pub fn to_vec(&self) -> PyResult<Vec<u8>> {
+        self.buffer.to_vec(self.object.py())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModule;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_shared_bytes() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let data: Arc<[u8]> = Arc::from(&b"zero-copy"[..]);
+        let address = data.as_ptr() as usize;
+        let bytes = SharedBytes::new(data.clone());
+        let (copied, readonly, shared, writable) = module
+            .action(move |py, _| {
+                let view = bytes.into_pyobject(*py)?;
+                let copied = py
+                    .import("builtins")?
+                    .getattr("bytes")?
+                    .call1((&view,))?
+                    .extract::<Vec<u8>>()?;
+                let readonly = view.getattr("readonly")?.extract::<bool>()?;
+                let shared = view.extract::<BytesView>()?.as_slice().as_ptr() as usize;
+                // the exporter refuses writable views, which ctypes reports as `TypeError`
+                let writable = py
+                    .import("ctypes")?
+                    .getattr("c_char")?
+                    .mul(9)?
+                    .call_method1("from_buffer", (view.getattr("obj")?,))
+                    .map_err(|e| e.get_type(*py).name().unwrap().to_string());
+                Ok((copied, readonly, shared, writable.err()))
+            })
+            .unwrap();
+        assert_eq!(copied, b"zero-copy");
+        assert!(readonly);
+        assert_eq!(writable.as_deref(), Some("TypeError"));
+        assert_eq!(shared, address);
+        // Python released its views
+        assert_eq!(Arc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn test_bytes_view() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let (bytes, sliced, mutable) = module
+            .action(|py, _| {
+                let builtins = py.import("builtins")?;
+                let object = PyBytes::new(*py, b"payload");
+                let view = object.extract::<BytesView>()?;
+                let bytes = view.as_bytes().map(<[u8]>::to_vec);
+                let memoryview = builtins.getattr("memoryview")?.call1((&object,))?;
+                let sliced = memoryview
+                    .get_item(pyo3::types::PySlice::new(*py, 3, 7, 1))?
+                    .extract::<BytesView>()?
+                    .as_bytes()
+                    .map(<[u8]>::to_vec);
+                let bytearray = builtins.getattr("bytearray")?.call1((&object,))?;
+                let view = bytearray.extract::<BytesView>()?;
+                Ok((bytes, sliced, (view.as_bytes().is_none(), view.to_vec()?)))
+            })
+            .unwrap();
+        assert_eq!(bytes.as_deref(), Some(&b"payload"[..]));
+        assert_eq!(sliced.as_deref(), Some(&b"load"[..]));
+        assert_eq!(mutable, (true, b"payload".to_vec()));
+
+        let err = module
+            .action(|py, _| {
+                let values = py
+                    .import("array")?
+                    .call_method1("array", ("i", vec![1, 2]))?;
+                let strided = py
+                    .import("builtins")?
+                    .getattr("memoryview")?
+                    .call1((values,))?
+                    .call_method1("cast", ("B",))?
+                    .get_item(pyo3::types::PySlice::new(*py, 0, 8, 2))?;
+                strided.extract::<BytesView>().map(|_| ())
+            })
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "BufferError");
+    }
+}
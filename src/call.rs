@@ -0,0 +1,192 @@
+use crate::{PyRunnerError, PythonModule};
+use pyo3::IntoPyObjectExt;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+
+type Value = Box<dyn for<'py> FnOnce(Python<'py>) -> PyResult<Bound<'py, PyAny>> + Send>;
+
+/// Call of a module function with positional and keyword arguments
+///
+/// Arguments are converted on the worker thread. `name` may be a dotted path like
+/// `"Client.connect"`.
+///```rs
+/// let result: i64 = module
+///     .call(Call::new("fetch").arg("https://example.com").kwarg("retries", 3))
+///     .unwrap();
+/// ```
+pub struct Call {
+    name: String,
+    args: Vec<Value>,
+    kwargs: Vec<(String, Value)>,
+}
+
+impl Call {
+    /// Call of the function `name` without arguments
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            args: Vec::new(),
+            kwargs: Vec::new(),
+        }
+    }
+
+    /// Appends a positional argument
+    pub fn arg<V>(mut self, value: V) -> Self
+    where
+        V: for<'py> IntoPyObject<'py> + Send + 'static,
+    {
+        self.args
+            .push(Box::new(move |py| value.into_bound_py_any(py)));
+        self
+    }
+
+    /// Sets a keyword argument
+    pub fn kwarg<V>(mut self, name: impl Into<String>, value: V) -> Self
+    where
+        V: for<'py> IntoPyObject<'py> + Send + 'static,
+    {
+        self.kwargs
+            .push((name.into(), Box::new(move |py| value.into_bound_py_any(py))));
+        self
+    }
+
+    /// Looks up the function on `module` and calls it, for use inside an action
+    pub fn invoke<'py>(self, module: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+        let py = module.py();
+        let function = lookup(module, &self.name)?;
+        let args = self
+            .args
+            .into_iter()
+            .map(|value| value(py))
+            .collect::<PyResult<Vec<_>>>()?;
+        let kwargs = PyDict::new(py);
+        for (name, value) in self.kwargs {
+            kwargs.set_item(name, value(py)?)?;
+        }
+        function.call(PyTuple::new(py, args)?, Some(&kwargs))
+    }
+}
+
+/// Resolves a dotted attribute path like `"calc.add"` on `module`
+pub(crate) fn lookup<'py>(module: &Bound<'py, PyAny>, name: &str) -> PyResult<Bound<'py, PyAny>> {
+    let mut attribute = module.clone();
+    for part in name.split('.') {
+        attribute = attribute.getattr(part)?;
+    }
+    Ok(attribute)
+}
+
+impl PythonModule {
+    /// Runs `call` and extracts its result
+    pub fn call<T>(&self, call: Call) -> Result<T, PyRunnerError>
+    where
+        T: for<'py> FromPyObject<'py> + Send + 'static,
+    {
+        self.action(move |_, module| call.invoke(module)?.extract())
+    }
+}
+
+/// Calls a module function in one line, expanding to [`PythonModule::call`]
+///
+/// Arguments are positional or `name = value` keywords and have to be `Send + 'static`.
+/// With `-> T` the result is extracted as `T`, without it is dropped. A module that isn't a
+/// plain variable goes in parentheses.
+///```rs
+/// let sum = py_call!(module.add(1, 2) -> i64)?;
+/// let response = py_call!(module.fetch(url, timeout = 5) -> Response)?;
+/// py_call!((self.module).reset())?;
+/// ```
+#[macro_export]
+macro_rules! py_call {
+    ($module:tt . $function:ident ( $($args:tt)* ) -> $result:ty) => {
+        $module.call::<$result>(
+            $crate::py_call!(@args $crate::Call::new(stringify!($function)); $($args)*)
+        )
+    };
+    ($module:tt . $function:ident ( $($args:tt)* )) => {{
+        let call = $crate::py_call!(@args $crate::Call::new(stringify!($function)); $($args)*);
+        $module.action(move |_, module| call.invoke(module).map(|_| ()))
+    }};
+    (@args $call:expr;) => {
+        $call
+    };
+    (@args $call:expr; $name:ident = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::py_call!(@args $call.kwarg(stringify!($name), $value); $($($rest)*)?)
+    };
+    (@args $call:expr; $value:expr $(, $($rest:tt)*)?) => {
+        $crate::py_call!(@args $call.arg($value); $($($rest)*)?)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_call() {
+        let module = PythonModule::new_project(Path::new("./my-project/main.py").into()).unwrap();
+        let sum: i64 = module.call(Call::new("add").arg(1).kwarg("b", 2)).unwrap();
+        assert_eq!(sum, 3);
+
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let sum: i64 = module
+            .call(Call::new("calc.add").kwarg("a", 1).kwarg("b", 2))
+            .unwrap();
+        assert_eq!(sum, 3);
+
+        let err = module
+            .call::<i64>(Call::new("add").arg(1).kwarg("c", 2))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "TypeError");
+    }
+
+    #[test]
+    fn test_py_call() {
+        let source = "calls = []\ndef fetch(url, timeout=1, *, retries=0):\n    calls.append(url)\n    return f'{url} {timeout} {retries}'\ndef reset():\n    calls.clear()\n";
+        let module = PythonModule::from_source("call_macro", source).unwrap();
+        let url = "https://example.com".to_string();
+        let fetched =
+            crate::py_call!(module.fetch(url.clone(), timeout = 2 + 3, retries = 1,) -> String);
+        assert_eq!(fetched.unwrap(), "https://example.com 5 1");
+        let timeout = 7;
+        let fetched = crate::py_call!(module.fetch(url, timeout) -> String).unwrap();
+        assert!(fetched.ends_with("7 0"));
+
+        let wrapper = (module,);
+        crate::py_call!((wrapper.0).reset()).unwrap();
+        let calls = crate::py_call!((wrapper.0).fetch("x") -> String).unwrap();
+        assert_eq!(calls, "x 1 0");
+        let err = crate::py_call!((wrapper.0).missing()).unwrap_err();
+        assert_eq!(err.exception_type(), "AttributeError");
+    }
+
+    #[crate::py_impl]
+    trait Greeter {
+        fn greet(&self, name: &str, excited: bool) -> String;
+        fn parse(&self, text: &str) -> Result<f64, PyRunnerError>;
+        fn reset(&mut self) -> std::result::Result<(), PyRunnerError>;
+        fn counted(&self) -> i64;
+        fn wave(&self) -> String {
+            "o/".to_string()
+        }
+    }
+
+    #[test]
+    fn test_py_impl() {
+        let source = "count = 0\ndef greet(name, excited):\n    global count\n    count += 1\n    return f'Hello {name}' + ('!' if excited else '')\ndef parse(text):\n    return float(text)\ndef reset():\n    global count\n    count = 0\ndef counted():\n    return count\n";
+        let mut module = PythonModule::from_source("py_impl", source).unwrap();
+        assert_eq!(module.greet("Ada", true), "Hello Ada!");
+        assert_eq!(module.parse("1.5").unwrap(), 1.5);
+        assert_eq!(
+            module.parse("x").unwrap_err().exception_type(),
+            "ValueError"
+        );
+        module.reset().unwrap();
+
+        let greeter: Box<dyn Greeter> = Box::new(module);
+        assert_eq!(greeter.greet("Bob", false), "Hello Bob");
+        assert_eq!(greeter.wave(), "o/");
+        assert_eq!(greeter.counted(), 1);
+    }
+}
@@ -0,0 +1,149 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+type Observer = Box<dyn Fn(f64) + Send + Sync>;
+
+/// Lets Rust cancel long-running Python code cooperatively and observe its progress
+///
+/// Converted to Python it is an object with `is_cancelled()` and `report_progress(pct)`,
+/// clones share the same state.
+///```rs
+/// let token = CancellationToken::new();
+/// token.on_progress(|pct| println!("{pct}%"));
+/// let argument = token.clone();
+/// let handle = module.spawn(move |_, module| module.call_method1("analyze", (argument,)).map(|_| ())).unwrap();
+/// token.cancel();
+/// ```
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    progress: Mutex<Option<f64>>,
+    observers: Mutex<Vec<Observer>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the Python code to stop, it is up to the code to check `is_cancelled()`
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Last progress reported by Python
+    pub fn progress(&self) -> Option<f64> {
+        *self.inner.progress.lock().unwrap()
+    }
+
+    /// Calls `observer` with every reported progress, on the worker thread
+    pub fn on_progress(&self, observer: impl Fn(f64) + Send + Sync + 'static) {
+        self.inner
+            .observers
+            .lock()
+            .unwrap()
+            .push(Box::new(observer));
+    }
+
+    fn report_progress(&self, progress: f64) {
+        *self.inner.progress.lock().unwrap() = Some(progress);
+        for observer in self.inner.observers.lock().unwrap().iter() {
+            observer(progress);
+        }
+    }
+}
+
+impl<'py> IntoPyObject<'py> for CancellationToken {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let token = self.clone();
+        let is_cancelled = PyCFunction::new_closure(
+            py,
+            Some(c"is_cancelled"),
+            None,
+            move |_: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                Ok::<_, Infallible>(token.is_cancelled())
+            },
+        )?;
+        let report_progress = PyCFunction::new_closure(
+            py,
+            Some(c"report_progress"),
+            None,
+            move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                let (progress,) = args.extract::<(f64,)>()?;
+                self.report_progress(progress);
+                Ok::<_, PyErr>(())
+            },
+        )?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("is_cancelled", is_cancelled)?;
+        kwargs.set_item("report_progress", report_progress)?;
+        py.import("types")?
+            .getattr("SimpleNamespace")?
+            .call((), Some(&kwargs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModule;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_cancellation_token() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "def analyze(token):\n",
+                "    step = 0\n",
+                "    while not token.is_cancelled():\n",
+                "        step += 1\n",
+                "        token.report_progress(min(step, 100))\n",
+                "    return step\n",
+            ),
+        )
+        .unwrap();
+
+        let module = PythonModule::new_project(dir.join("main.py")).unwrap();
+        let token = CancellationToken::new();
+        let reports = Arc::new(AtomicUsize::new(0));
+        let counter = reports.clone();
+        token.on_progress(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let argument = token.clone();
+        let handle = module
+            .spawn(move |_, module| {
+                module
+                    .call_method1("analyze", (argument,))?
+                    .extract::<usize>()
+            })
+            .unwrap();
+        while reports.load(Ordering::SeqCst) < 10 {
+            std::thread::yield_now();
+        }
+        token.cancel();
+        let steps = handle.join().unwrap();
+        assert!(steps >= 10);
+        assert_eq!(reports.load(Ordering::SeqCst), steps);
+        assert!(token.progress().is_some_and(|progress| progress <= 100.0));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{PyRunnerError, c_code};
+use pyo3::IntoPyObjectExt;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+type Value<'a> = Box<dyn for<'py> FnOnce(Python<'py>) -> PyResult<Bound<'py, PyAny>> + 'a>;
+
+/// Python code run with Rust values as globals
+///
+/// Code runs on the calling thread, like [`execute_code`](crate::execute_code). Values are
+/// converted right before execution, so they don't have to outlive the builder.
+///```rs
+/// let total: i64 = CodeRunner::new("total = sum(rows) * n")
+///     .global("n", 42)
+///     .global("rows", &my_vec)
+///     .run_with(|_, globals| globals.get_item("total")?.unwrap().extract())
+///     .unwrap();
+/// ```
+pub struct CodeRunner<'a> {
+    code: &'a str,
+    globals: Vec<(String, Value<'a>)>,
+}
+
+impl<'a> CodeRunner<'a> {
+    pub fn new(code: &'a str) -> Self {
+        Self {
+            code,
+            globals: Vec::new(),
+        }
+    }
+
+    /// Sets the global `name` to `value`
+    pub fn global<V>(mut self, name: impl Into<String>, value: V) -> Self
+    where
+        V: for<'py> IntoPyObject<'py> + 'a,
+    {
+        self.globals
+            .push((name.into(), Box::new(move |py| value.into_bound_py_any(py))));
+        self
+    }
+
+    /// Runs the code
+    pub fn run(self) -> Result<(), PyRunnerError> {
+        self.run_with(|_, _| Ok(()))
+    }
+
+    /// Runs the code and passes the resulting globals to `f`
+    pub fn run_with<T>(
+        self,
+        f: impl FnOnce(Python<'_>, &Bound<'_, PyDict>) -> PyResult<T>,
+    ) -> Result<T, PyRunnerError> {
+        let code = self.code;
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            self.globals
+                .into_iter()
+                .try_for_each(|(name, value)| globals.set_item(name, value(py)?))
+                .and_then(|_| py.run(&c_code(code)?, Some(&globals), None))
+                .and_then(|_| f(py, &globals))
+                .map_err(|e| PyRunnerError::from_py(py, e).with_source(code))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_runner() {
+        let rows = vec![1, 2, 3];
+        let total: i64 = CodeRunner::new("total = sum(rows) * n")
+            .global("n", 42)
+            .global("rows", &rows)
+            .run_with(|_, globals| globals.get_item("total")?.unwrap().extract())
+            .unwrap();
+        assert_eq!(total, 252);
+
+        let err = CodeRunner::new("assert name == 'py-runner', name")
+            .global("name", "other")
+            .run()
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "AssertionError");
+        assert_eq!(err.message(), "other");
+    }
+}
@@ -0,0 +1,325 @@
+use crate::PyRunnerError;
+use crate::helper::load_helper;
+use pyo3::prelude::*;
+use std::fmt::Write;
+use std::path::Path;
+
+const STUB: &str = include_str!("codegen/stub.py");
+
+/// `(name, keyword, annotation, optional)`
+pub(crate) type Parameter = (String, bool, Option<Annotation>, bool);
+/// `(name, doc, parameters, returns)`
+pub(crate) type Function = (String, Option<String>, Vec<Parameter>, Option<Annotation>);
+
+/// Type tree of an annotation, e.g. `("list", [("int", [])])`
+#[derive(FromPyObject)]
+pub(crate) struct Annotation(String, Vec<Annotation>);
+
+/// Generates a typed Rust wrapper of the module described by the stub file `stub`
+///
+/// The wrapper is a struct `struct_name` around a [`PythonModule`](crate::PythonModule) with
+/// one method per public function, which runs the call as an
+/// [`action`](crate::PythonModule::action) and converts arguments and result:
+/// `def add(a: int, b: int) -> int` becomes
+/// `fn add(&self, a: i64, b: i64) -> Result<i64, PyRunnerError>`. Parameters with defaults
+/// become `Option`s that are only passed when set, `*args`, `**kwargs`, classes and `async`
+/// functions are left out. `int`, `float`, `str`, `bool`, `bytes`, `None`, `list`, `tuple`,
+/// `dict`, `set` and `Optional` map to their Rust counterparts, other types to
+/// `Py<PyAny>`, so the generated code needs `pyo3` as dependency. Meant for `build.rs`:
+///```rs
+/// // build.rs
+/// let bindings = py_runner::generate_bindings("python/math.pyi", "Math").unwrap();
+/// let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("math.rs");
+/// std::fs::write(out, bindings).unwrap();
+/// println!("cargo:rerun-if-changed=python/math.pyi");
+///
+/// // src/main.rs
+/// include!(concat!(env!("OUT_DIR"), "/math.rs"));
+/// let math = Math::new(PythonModule::new("./python/math.py")?);
+/// let sum = math.add(1, 2)?;
+/// ```
+pub fn generate_bindings(
+    stub: impl AsRef<Path>,
+    struct_name: &str,
+) -> Result<String, PyRunnerError> {
+    let stub = stub.as_ref();
+    let source = std::fs::read_to_string(stub).map_err(PyErr::from)?;
+    let file_name = stub
+        .file_name()
+        .unwrap_or(stub.as_os_str())
+        .to_string_lossy();
+    generate_bindings_from_source(&source, &file_name, struct_name)
+}
+
+/// Like [`generate_bindings`] for a stub held in memory, `file_name` is used in messages and
+/// the header of the generated code
+pub fn generate_bindings_from_source(
+    source: &str,
+    file_name: &str,
+    struct_name: &str,
+) -> Result<String, PyRunnerError> {
+    let (doc, functions) = read_stub(source, file_name)?;
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "// Generated by py_runner from {file_name}, do not edit\n"
+    );
+    let doc =
+        doc.unwrap_or_else(|| format!("Typed wrapper of the module stubbed in `{file_name}`"));
+    write_doc(&mut out, "", &doc);
+    let _ = writeln!(out, "pub struct {struct_name} {{");
+    let _ = writeln!(out, "    module: ::py_runner::PythonModule,");
+    let _ = writeln!(out, "}}\n");
+    let _ = writeln!(out, "impl {struct_name} {{");
+    let _ = writeln!(
+        out,
+        "    pub fn new(module: ::py_runner::PythonModule) -> Self {{"
+    );
+    let _ = writeln!(out, "        Self {{ module }}");
+    let _ = writeln!(out, "    }}\n");
+    let _ = writeln!(
+        out,
+        "    pub fn module(&self) -> &::py_runner::PythonModule {{"
+    );
+    let _ = writeln!(out, "        &self.module");
+    let _ = writeln!(out, "    }}");
+    for function in &functions {
+        out.push('\n');
+        write_function(&mut out, function);
+    }
+    let _ = writeln!(out, "}}");
+    Ok(out)
+}
+
+/// Docstring and public functions of the stub `source`
+pub(crate) fn read_stub(
+    source: &str,
+    file_name: &str,
+) -> Result<(Option<String>, Vec<Function>), PyRunnerError> {
+    Python::with_gil(|py| {
+        load_helper(py, "stub", STUB)?
+            .call_method1("functions", (source, file_name))?
+            .extract()
+    })
+    .map_err(PyRunnerError::from)
+}
+
+fn write_function(out: &mut String, (name, doc, parameters, returns): &Function) {
+    if let Some(doc) = doc {
+        write_doc(out, "    ", doc);
+    }
+    let arguments = parameters
+        .iter()
+        .map(|(name, _, annotation, optional)| {
+            let ty = rust_type(annotation.as_ref());
+            // for `Optional` parameters `None` already means the default
+            match optional {
+                true if !ty.starts_with("Option<") => format!(", {}: Option<{ty}>", ident(name)),
+                _ => format!(", {}: {ty}", ident(name)),
+            }
+        })
+        .collect::<String>();
+    let returned = match returns {
+        Some(Annotation(name, _)) if name == "None" => "()".to_string(),
+        returns => rust_type(returns.as_ref()),
+    };
+    let (call_end, extract) = match returned.as_str() {
+        "()" => ("", ".map(|_| ())"),
+        _ => ("?", ".extract()"),
+    };
+    let _ = writeln!(
+        out,
+        "    pub fn {}(&self{arguments}) -> ::std::result::Result<{returned}, ::py_runner::PyRunnerError> {{",
+        ident(method_name(name))
+    );
+    let _ = writeln!(out, "        use ::pyo3::prelude::*;");
+    let simple = parameters
+        .iter()
+        .all(|(_, keyword, _, optional)| !keyword && !optional);
+    if simple {
+        let call = match parameters.as_slice() {
+            [] => format!("call_method0({name:?})"),
+            [(parameter, ..)] => format!("call_method1({name:?}, ({},))", ident(parameter)),
+            _ => {
+                let names = parameters.iter().map(|(name, ..)| ident(name));
+                format!(
+                    "call_method1({name:?}, ({}))",
+                    names.collect::<Vec<_>>().join(", ")
+                )
+            }
+        };
+        let _ = writeln!(
+            out,
+            "        self.module.action(move |_, module| module.{call}{call_end}{extract})"
+        );
+    } else {
+        let _ = writeln!(out, "        self.module.action(move |py, module| {{");
+        let _ = writeln!(
+            out,
+            "            let args = ::pyo3::types::PyList::empty(*py);"
+        );
+        let _ = writeln!(
+            out,
+            "            let kwargs = ::pyo3::types::PyDict::new(*py);"
+        );
+        for (parameter, keyword, _, optional) in parameters {
+            let value = ident(parameter);
+            let pass = match keyword {
+                true => format!("kwargs.set_item({parameter:?}, {value})?;"),
+                false => format!("args.append({value})?;"),
+            };
+            match optional {
+                true => {
+                    let _ = writeln!(out, "            if let Some({value}) = {value} {{");
+                    let _ = writeln!(out, "                {pass}");
+                    let _ = writeln!(out, "            }}");
+                }
+                false => {
+                    let _ = writeln!(out, "            {pass}");
+                }
+            }
+        }
+        let _ = writeln!(out, "            module");
+        let _ = writeln!(out, "                .getattr({name:?})?");
+        let _ = writeln!(
+            out,
+            "                .call(args.to_tuple(), Some(&kwargs)){call_end}"
+        );
+        let _ = writeln!(out, "                {extract}");
+        let _ = writeln!(out, "        }})");
+    }
+    let _ = writeln!(out, "    }}");
+}
+
+fn write_doc(out: &mut String, indent: &str, doc: &str) {
+    for line in doc.lines() {
+        let _ = match line.is_empty() {
+            true => writeln!(out, "{indent}///"),
+            false => writeln!(out, "{indent}/// {line}"),
+        };
+    }
+}
+
+const ANY: &str = "::pyo3::Py<::pyo3::PyAny>";
+
+fn rust_type(annotation: Option<&Annotation>) -> String {
+    let Some(Annotation(name, arguments)) = annotation else {
+        return ANY.to_string();
+    };
+    let argument = |index: usize| rust_type(arguments.get(index));
+    match (name.as_str(), arguments.as_slice()) {
+        ("int", _) => "i64".to_string(),
+        ("float", _) => "f64".to_string(),
+        ("str", _) => "String".to_string(),
+        ("bool", _) => "bool".to_string(),
+        ("bytes" | "bytearray", _) => "Vec<u8>".to_string(),
+        ("None", _) => "()".to_string(),
+        ("list" | "List" | "Sequence" | "Iterable", _) => format!("Vec<{}>", argument(0)),
+        ("tuple" | "Tuple", [_, Annotation(ellipsis, _)]) if ellipsis == "..." => {
+            format!("Vec<{}>", argument(0))
+        }
+        ("tuple" | "Tuple", [_]) => format!("({},)", argument(0)),
+        ("tuple" | "Tuple", [_, ..]) => {
+            let items = (0..arguments.len()).map(argument).collect::<Vec<_>>();
+            format!("({})", items.join(", "))
+        }
+        ("dict" | "Dict" | "Mapping", _) if hashable(&argument(0)) => format!(
+            "::std::collections::HashMap<{}, {}>",
+            argument(0),
+            argument(1)
+        ),
+        ("set" | "Set" | "frozenset" | "FrozenSet", _) if hashable(&argument(0)) => {
+            format!("::std::collections::HashSet<{}>", argument(0))
+        }
+        ("Optional", [inner]) => format!("Option<{}>", rust_type(Some(inner))),
+        ("Union", [inner, Annotation(none, _)]) | ("Union", [Annotation(none, _), inner])
+            if none == "None" =>
+        {
+            format!("Option<{}>", rust_type(Some(inner)))
+        }
+        _ => ANY.to_string(),
+    }
+}
+
+fn hashable(ty: &str) -> bool {
+    ty != ANY && !ty.contains("f64") && !ty.contains("Hash")
+}
+
+/// Methods of the wrapper itself, functions with these names get a `_` appended
+fn method_name(name: &str) -> String {
+    match name {
+        "new" | "module" => format!("{name}_"),
+        _ => name.to_string(),
+    }
+}
+
+/// `name` as Rust identifier, keywords become raw identifiers
+fn ident(name: impl AsRef<str>) -> String {
+    let name = name.as_ref();
+    match name {
+        "self" | "Self" | "super" | "crate" => format!("{name}_"),
+        "as" | "async" | "await" | "break" | "const" | "continue" | "dyn" | "else" | "enum"
+        | "extern" | "false" | "fn" | "for" | "gen" | "if" | "impl" | "in" | "let" | "loop"
+        | "match" | "mod" | "move" | "mut" | "pub" | "ref" | "return" | "static" | "struct"
+        | "trait" | "true" | "type" | "unsafe" | "use" | "where" | "while" | "abstract"
+        | "become" | "box" | "do" | "final" | "macro" | "override" | "priv" | "typeof"
+        | "unsized" | "virtual" | "yield" | "try" => format!("r#{name}"),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModule;
+
+    const STUB: &str = r#""""Small calculator"""
+from typing import Optional
+
+def add(a: int, b: int) -> int:
+    """Adds two numbers"""
+
+def mean(values: list[float]) -> float | None: ...
+def split(text: str, sep: str = ",", *, limit: Optional[int] = None) -> list[str]: ...
+def stats(values: tuple[int, ...]) -> dict[str, float]: ...
+def reset() -> None: ...
+def type(value) -> str: ...
+def _private() -> None: ...
+async def fetch(url: str) -> bytes: ...
+class Calculator: ...
+"#;
+
+    const MODULE: &str = "def add(a, b):\n    return a + b\ndef mean(values):\n    return sum(values) / len(values) if values else None\ndef split(text, sep=',', *, limit=None):\n    return text.split(sep, -1 if limit is None else limit)\ndef stats(values):\n    return {'min': min(values), 'max': max(values)}\ndef reset():\n    pass\nimport builtins\ntype = lambda value: builtins.type(value).__name__\n";
+
+    mod generated {
+        include!("codegen/calc.rs");
+    }
+
+    #[test]
+    fn test_generate_bindings() {
+        let generated = generate_bindings_from_source(STUB, "calc.pyi", "Calc").unwrap();
+        assert_eq!(generated, include_str!("codegen/calc.rs"));
+
+        let calc = generated::Calc::new(PythonModule::from_source("codegen_calc", MODULE).unwrap());
+        assert_eq!(calc.add(1, 2).unwrap(), 3);
+        assert_eq!(calc.mean(vec![1.0, 2.0]).unwrap(), Some(1.5));
+        assert_eq!(calc.mean(vec![]).unwrap(), None);
+        assert_eq!(
+            calc.split("a,b,c".into(), None, Some(1)).unwrap(),
+            ["a", "b,c"]
+        );
+        assert_eq!(
+            calc.split("a b".into(), Some(" ".into()), None).unwrap(),
+            ["a", "b"]
+        );
+        assert_eq!(calc.stats(vec![3, 1, 2]).unwrap()["max"], 3.0);
+        calc.reset().unwrap();
+        assert!(calc.module().is_alive());
+        let value =
+            pyo3::Python::with_gil(|py| 1i64.into_pyobject(py).unwrap().into_any().unbind());
+        assert_eq!(calc.r#type(value).unwrap(), "int");
+        let err = calc.stats(vec![]).unwrap_err();
+        assert_eq!(err.exception_type(), "ValueError");
+    }
+}
@@ -0,0 +1,61 @@
+// Generated by py_runner from calc.pyi, do not edit
+
+/// Small calculator
+pub struct Calc {
+    module: ::py_runner::PythonModule,
+}
+
+impl Calc {
+    pub fn new(module: ::py_runner::PythonModule) -> Self {
+        Self { module }
+    }
+
+    pub fn module(&self) -> &::py_runner::PythonModule {
+        &self.module
+    }
+
+    /// Adds two numbers
+    pub fn add(&self, a: i64, b: i64) -> ::std::result::Result<i64, ::py_runner::PyRunnerError> {
+        use ::pyo3::prelude::*;
+        self.module.action(move |_, module| module.call_method1("add", (a, b))?.extract())
+    }
+
+    pub fn mean(&self, values: Vec<f64>) -> ::std::result::Result<Option<f64>, ::py_runner::PyRunnerError> {
+        use ::pyo3::prelude::*;
+        self.module.action(move |_, module| module.call_method1("mean", (values,))?.extract())
+    }
+
+    pub fn split(&self, text: String, sep: Option<String>, limit: Option<i64>) -> ::std::result::Result<Vec<String>, ::py_runner::PyRunnerError> {
+        use ::pyo3::prelude::*;
+        self.module.action(move |py, module| {
+            let args = ::pyo3::types::PyList::empty(*py);
+            let kwargs = ::pyo3::types::PyDict::new(*py);
+            args.append(text)?;
+            if let Some(sep) = sep {
+                kwargs.set_item("sep", sep)?;
+            }
+            if let Some(limit) = limit {
+                kwargs.set_item("limit", limit)?;
+            }
+            module
+                .getattr("split")?
+                .call(args.to_tuple(), Some(&kwargs))?
+                .extract()
+        })
+    }
+
+    pub fn stats(&self, values: Vec<i64>) -> ::std::result::Result<::std::collections::HashMap<String, f64>, ::py_runner::PyRunnerError> {
+        use ::pyo3::prelude::*;
+        self.module.action(move |_, module| module.call_method1("stats", (values,))?.extract())
+    }
+
+    pub fn reset(&self) -> ::std::result::Result<(), ::py_runner::PyRunnerError> {
+        use ::pyo3::prelude::*;
+        self.module.action(move |_, module| module.call_method0("reset").map(|_| ()))
+    }
+
+    pub fn r#type(&self, value: ::pyo3::Py<::pyo3::PyAny>) -> ::std::result::Result<String, ::py_runner::PyRunnerError> {
+        use ::pyo3::prelude::*;
+        self.module.action(move |_, module| module.call_method1("type", (value,))?.extract())
+    }
+}
@@ -0,0 +1,53 @@
+# Reads the functions of a `.pyi` stub for the binding generator. Annotations become type
+# trees `(name, [arguments])`, e.g. `("dict", [("str", []), ("int", [])])`.
+import ast
+
+
+def annotation(node):
+    if node is None:
+        return None
+    if isinstance(node, ast.Constant):
+        if node.value is None:
+            return ("None", [])
+        if node.value is Ellipsis:
+            return ("...", [])
+        if isinstance(node.value, str):
+            # forward reference
+            return annotation(ast.parse(node.value, mode="eval").body)
+        return ("Any", [])
+    if isinstance(node, ast.Name):
+        return (node.id, [])
+    if isinstance(node, ast.Attribute):
+        # typing.Optional and friends
+        return (node.attr, [])
+    if isinstance(node, ast.Subscript):
+        items = node.slice.elts if isinstance(node.slice, ast.Tuple) else [node.slice]
+        return (annotation(node.value)[0], [annotation(item) for item in items])
+    if isinstance(node, ast.BinOp) and isinstance(node.op, ast.BitOr):
+        return ("Union", [annotation(node.left), annotation(node.right)])
+    return ("Any", [])
+
+
+def parameters(args):
+    """`(name, keyword, annotation, optional)` of the parameters Rust can pass, `*args` and
+    `**kwargs` are left out"""
+    positional = args.posonlyargs + args.args
+    defaults = [None] * (len(positional) - len(args.defaults)) + args.defaults
+    result = []
+    for index, (arg, default) in enumerate(zip(positional, defaults)):
+        keyword = index >= len(args.posonlyargs) and default is not None
+        result.append((arg.arg, keyword, annotation(arg.annotation), default is not None))
+    for arg, default in zip(args.kwonlyargs, args.kw_defaults):
+        result.append((arg.arg, True, annotation(arg.annotation), default is not None))
+    return result
+
+
+def functions(source, filename):
+    """Docstring of the stub and `(name, doc, parameters, returns)` of its public functions"""
+    tree = ast.parse(source, filename)
+    result = []
+    for node in tree.body:
+        if isinstance(node, ast.FunctionDef) and not node.name.startswith("_"):
+            returns = annotation(node.returns)
+            result.append((node.name, ast.get_docstring(node), parameters(node.args), returns))
+    return ast.get_docstring(tree), result
@@ -0,0 +1,258 @@
+use crate::{ErrorKind, PyRunnerError};
+use pyo3::ffi;
+use std::ffi::{CStr, CString};
+use std::mem::MaybeUninit;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+/// Options of the embedded interpreter, applied by [`initialize`](Self::initialize) before
+/// anything else uses Python
+///```rs
+/// // SAFETY: first thing in `main`, no other thread has been started yet
+/// unsafe {
+///     InterpreterConfig::new()
+///         .isolated(true)
+///         .home("/opt/python")
+///         .dont_write_bytecode(true)
+///         .warn_option("error::DeprecationWarning")
+///         .initialize()
+///         .unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterConfig {
+    isolated: bool,
+    home: Option<PathBuf>,
+    utf8_mode: bool,
+    dont_write_bytecode: bool,
+    faulthandler: bool,
+    hash_seed: Option<u32>,
+    warn_options: Vec<String>,
+}
+
+impl InterpreterConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignores `PYTHON*` environment variables and the user site directory and doesn't add
+    /// the working directory to `sys.path`
+    pub fn isolated(mut self, isolated: bool) -> Self {
+        self.isolated = isolated;
+        self
+    }
+
+    /// Location of the standard library, like `PYTHONHOME`
+    pub fn home(mut self, home: impl Into<PathBuf>) -> Self {
+        self.home = Some(home.into());
+        self
+    }
+
+    /// Uses UTF-8 for file names and the standard streams regardless of the locale
+    pub fn utf8_mode(mut self, utf8_mode: bool) -> Self {
+        self.utf8_mode = utf8_mode;
+        self
+    }
+
+    /// Doesn't write `.pyc` files on import
+    pub fn dont_write_bytecode(mut self, dont_write_bytecode: bool) -> Self {
+        self.dont_write_bytecode = dont_write_bytecode;
+        self
+    }
+
+    /// Dumps the Python tracebacks of all threads on a fatal error
+    pub fn faulthandler(mut self, faulthandler: bool) -> Self {
+        self.faulthandler = faulthandler;
+        self
+    }
+
+    /// Seed of `str` and `bytes` hashes, like `PYTHONHASHSEED`, 0 disables randomization
+    pub fn hash_seed(mut self, seed: u32) -> Self {
+        self.hash_seed = Some(seed);
+        self
+    }
+
+    /// Adds a warning filter like `-W`, e.g. `"ignore::DeprecationWarning"`
+    pub fn warn_option(mut self, option: impl Into<String>) -> Self {
+        self.warn_options.push(option.into());
+        self
+    }
+
+    /// Initializes the interpreter, fails if it already is
+    ///
+    /// Call it before the first module is loaded, later uses of Python find the interpreter
+    /// initialized and keep this configuration.
+    ///
+    /// # Safety
+    /// No other thread may use Python while this runs, e.g. through `Python::with_gil` or by
+    /// loading a module, since that would initialize the interpreter at the same time.
+    pub unsafe fn initialize(self) -> Result<(), PyRunnerError> {
+        let home = self
+            .home
+            .as_deref()
+            .map(Path::to_str)
+            .map(|home| home.ok_or_else(|| init_error("Python home is not valid UTF-8")))
+            .transpose()?
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| init_error("Python home contains a NUL byte"))?;
+        let warn_options = self
+            .warn_options
+            .iter()
+            .map(|option| CString::new(option.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| init_error("Warn option contains a NUL byte"))?;
+
+        // SAFETY: nothing else initializes the interpreter while this runs, as the caller
+        // guarantees
+        unsafe {
+            if ffi::Py_IsInitialized() != 0 {
+                return Err(init_error("The Python interpreter is already initialized"));
+            }
+            let mut preconfig = MaybeUninit::<ffi::PyPreConfig>::uninit();
+            match self.isolated {
+                true => ffi::PyPreConfig_InitIsolatedConfig(preconfig.as_mut_ptr()),
+                false => ffi::PyPreConfig_InitPythonConfig(preconfig.as_mut_ptr()),
+            }
+            let mut preconfig = preconfig.assume_init();
+            preconfig.parse_argv = 0;
+            if self.utf8_mode {
+                preconfig.utf8_mode = 1;
+            }
+            check(ffi::Py_PreInitialize(&preconfig))?;
+
+            let mut config = MaybeUninit::<ffi::PyConfig>::uninit();
+            match self.isolated {
+                true => ffi::PyConfig_InitIsolatedConfig(config.as_mut_ptr()),
+                false => ffi::PyConfig_InitPythonConfig(config.as_mut_ptr()),
+            }
+            let mut config = config.assume_init();
+            let result = configure(&mut config, &self, home.as_deref(), &warn_options)
+                .and_then(|_| check(ffi::Py_InitializeFromConfig(&config)));
+            ffi::PyConfig_Clear(&mut config);
+            result?;
+            // like pyo3's own initialization, `Python::with_gil` acquires the GIL
+            ffi::PyEval_SaveThread();
+        }
+        Ok(())
+    }
+}
+
+/// Applies `options` to `config`
+///
+/// # Safety
+/// `config` has to be initialized and Python preinitialized.
+unsafe fn configure(
+    config: &mut ffi::PyConfig,
+    options: &InterpreterConfig,
+    home: Option<&CStr>,
+    warn_options: &[CString],
+) -> Result<(), PyRunnerError> {
+    // as with `Py_InitializeEx(0)`, used by pyo3: signals and stdio stay with the host
+    config.parse_argv = 0;
+    config.install_signal_handlers = 0;
+    config.configure_c_stdio = 0;
+    if options.dont_write_bytecode {
+        config.write_bytecode = 0;
+    }
+    if options.faulthandler {
+        config.faulthandler = 1;
+    }
+    if let Some(seed) = options.hash_seed {
+        config.use_hash_seed = 1;
+        config.hash_seed = seed.into();
+    }
+    unsafe {
+        if let Some(home) = home {
+            let field = ptr::addr_of_mut!(config.home);
+            check(ffi::PyConfig_SetBytesString(config, field, home.as_ptr()))?;
+        }
+        for option in warn_options {
+            let wide = ffi::Py_DecodeLocale(option.as_ptr(), ptr::null_mut());
+            if wide.is_null() {
+                return Err(init_error("Warn option can't be decoded"));
+            }
+            let status = ffi::PyWideStringList_Append(&mut config.warnoptions, wide);
+            ffi::PyMem_RawFree(wide.cast());
+            check(status)?;
+        }
+    }
+    Ok(())
+}
+
+fn check(status: ffi::PyStatus) -> Result<(), PyRunnerError> {
+    // SAFETY: `status` was returned by the C API, its message is a static string
+    unsafe {
+        if ffi::PyStatus_Exception(status) == 0 {
+            return Ok(());
+        }
+        if status.err_msg.is_null() {
+            return Err(init_error("Failed to initialize Python"));
+        }
+        let message = CStr::from_ptr(status.err_msg).to_string_lossy();
+        Err(init_error(&format!(
+            "Failed to initialize Python: {message}"
+        )))
+    }
+}
+
+fn init_error(message: &str) -> PyRunnerError {
+    PyRunnerError::new(ErrorKind::InitFailed, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::prelude::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_initialize_twice() {
+        Python::with_gil(|_| ());
+        // SAFETY: initialized already, this only checks and returns
+        let err = unsafe { InterpreterConfig::new().initialize() }.unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InitFailed);
+    }
+
+    /// Runs in a fresh process started by `test_initialize`
+    #[test]
+    #[ignore]
+    fn initialize_child() {
+        if std::env::var_os("PY_RUNNER_CONFIG_CHILD").is_none() {
+            return;
+        }
+        let config = InterpreterConfig::new()
+            .isolated(true)
+            .utf8_mode(true)
+            .dont_write_bytecode(true)
+            .hash_seed(7)
+            .warn_option("error::DeprecationWarning");
+        // SAFETY: the only test run by this process, nothing else uses Python yet
+        unsafe { config.initialize() }.unwrap();
+        let flags = Python::with_gil(|py| {
+            let code = c"(sys.flags.isolated, sys.flags.utf8_mode, sys.dont_write_bytecode, sys.flags.hash_randomization, sys.warnoptions)";
+            let globals = pyo3::types::PyDict::new(py);
+            globals.set_item("sys", py.import("sys")?)?;
+            py.eval(code, Some(&globals), None)?
+                .extract::<(i64, i64, bool, i64, Vec<String>)>()
+        })
+        .unwrap();
+        assert_eq!(
+            flags,
+            (1, 1, true, 1, vec!["error::DeprecationWarning".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_initialize() {
+        // the interpreter of this process is shared by all tests, configure a fresh one
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["config::tests::initialize_child", "--exact", "--ignored"])
+            .env("PY_RUNNER_CONFIG_CHILD", "1")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{stdout}");
+        assert!(stdout.contains("1 passed"));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::call::lookup;
+use crate::{PyRunnerError, PythonModule};
+use pyo3::call::PyCallArgs;
+use pyo3::panic::PanicException;
+use pyo3::prelude::*;
+use std::panic::{self, AssertUnwindSafe};
+
+impl PythonModule {
+    /// Calls the context manager `name` with `args` and runs `body` with the value its
+    /// `__enter__` returned, like a Python `with` block
+    ///
+    /// `__exit__` runs even if `body` fails or panics and receives the exception. The error is
+    /// returned even if `__exit__` suppresses it, as there's no value to return instead.
+    ///
+    /// Not supported by the subprocess backend.
+    ///```rs
+    /// let rows = module
+    ///     .with_context("open_session", ("db.sqlite",), |_, session| {
+    ///         session.call_method0("rows")?.extract::<Vec<String>>()
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn with_context<T: Send + 'static>(
+        &self,
+        name: &str,
+        args: impl for<'py> PyCallArgs<'py> + Send + 'static,
+        body: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        if self.control.child.get().is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Context managers are not supported by the subprocess backend",
+            )
+            .into());
+        }
+        let name = name.to_string();
+        self.action(move |py, module| {
+            let manager = lookup(module, &name)?.call1(args)?;
+            // like `with`, the special methods are looked up on the type
+            let kind = manager.get_type();
+            let exit = kind.getattr("__exit__")?;
+            let value = kind.getattr("__enter__")?.call1((&manager,))?;
+
+            let (result, payload) = match panic::catch_unwind(AssertUnwindSafe(|| body(py, &value)))
+            {
+                Ok(result) => (result, None),
+                Err(payload) => (
+                    Err(PanicException::new_err("Action panicked")),
+                    Some(payload),
+                ),
+            };
+            let exited = match &result {
+                Ok(_) => exit.call1((&manager, py.None(), py.None(), py.None())),
+                Err(err) => exit.call1((
+                    &manager,
+                    err.get_type(*py),
+                    err.value(*py),
+                    err.traceback(*py),
+                )),
+            };
+            if let Some(payload) = payload {
+                panic::resume_unwind(payload);
+            }
+            match (exited, result) {
+                (Ok(_), result) => result,
+                (Err(exit_err), Ok(_)) => Err(exit_err),
+                (Err(exit_err), Err(err)) => {
+                    // raised while handling `err`, as in Python
+                    exit_err.value(*py).setattr("__context__", err.value(*py))?;
+                    Err(exit_err)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = concat!(
+        "events = []\n",
+        "class Session:\n",
+        "    def __init__(self, name, suppress=False):\n",
+        "        self.name = name\n",
+        "        self.suppress = suppress\n",
+        "    def __enter__(self):\n",
+        "        events.append('enter')\n",
+        "        return self.name.upper()\n",
+        "    def __exit__(self, kind, value, traceback):\n",
+        "        events.append(kind.__name__ if kind else 'exit')\n",
+        "        if self.name == 'broken':\n",
+        "            raise RuntimeError('exit failed')\n",
+        "        return self.suppress\n",
+        "open_session = Session\n",
+    );
+
+    #[test]
+    fn test_with_context() {
+        let module = PythonModule::from_source("context", SOURCE).unwrap();
+        let value = module
+            .with_context("open_session", ("db",), |_, session| {
+                session.extract::<String>()
+            })
+            .unwrap();
+        assert_eq!(value, "DB");
+
+        let err = module
+            .with_context("open_session", ("db", true), |_, session| {
+                session.extract::<i64>()
+            })
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "TypeError");
+
+        let err = module
+            .with_context("open_session", ("broken",), |py, _| {
+                py.run(c"1 / 0", None, None)
+            })
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "RuntimeError");
+        assert!(err.traceback().unwrap().contains("ZeroDivisionError"));
+
+        let err = module
+            .with_context("open_session", ("db",), |_, _| -> PyResult<()> {
+                panic!("boom")
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), &crate::ErrorKind::Panicked);
+
+        let events = module.get::<Vec<String>>("events").unwrap();
+        let expected = [
+            "enter",
+            "exit",
+            "enter",
+            "TypeError",
+            "enter",
+            "ZeroDivisionError",
+            "enter",
+            "PanicException",
+        ];
+        assert_eq!(events, expected);
+    }
+}
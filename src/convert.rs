@@ -0,0 +1,293 @@
+use crate::{Call, ErrorKind, PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyTuple};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+impl PythonModule {
+    /// Calls `name` with `request` converted to Python and converts the return value back
+    ///
+    /// Structs and maps become dicts, sequences lists. The request is serialized on the
+    /// calling thread and converted to Python objects on the worker. Dates and times in the
+    /// response become ISO 8601 strings, which `chrono` and `time` types deserialize from,
+    /// `timedelta` values seconds and `Decimal` and `UUID` values strings, which
+    /// `rust_decimal` and `uuid` types deserialize from. numpy arrays become nested lists.
+    ///```rs
+    /// let response: Response = module.call_serde("handler", &Request { id: 1 }).unwrap();
+    /// ```
+    pub fn call_serde<Req, Resp>(&self, name: &str, request: &Req) -> Result<Resp, PyRunnerError>
+    where
+        Req: Serialize + ?Sized,
+        Resp: DeserializeOwned + Send + 'static,
+    {
+        let conversion = |message: String| {
+            let err = PyErr::new::<pyo3::exceptions::PyValueError, _>(message);
+            PyRunnerError::from(err).with_kind(ErrorKind::Conversion)
+        };
+        let request = serde_json::to_value(request).map_err(|e| conversion(e.to_string()))?;
+        let call = Call::new(name);
+        self.action(move |py, module| {
+            let request = pythonize::pythonize(*py, &request)?;
+            let response = call.arg(request.unbind()).invoke(module)?;
+            Ok(pythonize::depythonize(&plain_values(&response)?).map_err(|e| e.to_string()))
+        })?
+        .map_err(conversion)
+    }
+
+    /// [`call_serde`](Self::call_serde) for ad-hoc payloads without Rust types
+    ///```rs
+    /// let response = module.call_json("handler", json!({"id": 1})).unwrap();
+    /// ```
+    pub fn call_json(&self, name: &str, request: Value) -> Result<Value, PyRunnerError> {
+        self.call_serde(name, &request)
+    }
+}
+
+/// Replaces values nested in dicts, lists and tuples that have no serde equivalent
+fn plain_values<'py>(value: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+    let py = value.py();
+    let datetime = py.import("datetime")?;
+    let mut replacements = vec![
+        (datetime.getattr("date")?, "isoformat"),
+        (datetime.getattr("time")?, "isoformat"),
+        (datetime.getattr("timedelta")?, "total_seconds"),
+        (py.import("decimal")?.getattr("Decimal")?, "__str__"),
+        (py.import("uuid")?.getattr("UUID")?, "__str__"),
+    ];
+    // numpy values can only be in the response if numpy was imported
+    if let Ok(numpy) = py.import("sys")?.getattr("modules")?.get_item("numpy") {
+        replacements.push((numpy.getattr("ndarray")?, "tolist"));
+        replacements.push((numpy.getattr("generic")?, "item"));
+    }
+    replace_values(value, &replacements)
+}
+
+/// Calls the method of the first matching type in `replacements` on `value`
+fn replace_values<'py>(
+    value: &Bound<'py, PyAny>,
+    replacements: &[(Bound<'py, PyAny>, &str)],
+) -> PyResult<Bound<'py, PyAny>> {
+    for (ty, method) in replacements {
+        if value.is_instance(ty)? {
+            return value.call_method0(*method);
+        }
+    }
+    if let Ok(dict) = value.downcast::<PyDict>() {
+        let replaced = PyDict::new(value.py());
+        for (key, item) in dict {
+            replaced.set_item(key, replace_values(&item, replacements)?)?;
+        }
+        Ok(replaced.into_any())
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        let items = value
+            .try_iter()?
+            .map(|item| replace_values(&item?, replacements))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyList::new(value.py(), items)?.into_any())
+    } else {
+        Ok(value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize)]
+    struct Request {
+        numbers: Vec<i64>,
+        label: String,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Response {
+        total: i64,
+        label: String,
+    }
+
+    #[test]
+    fn test_call_serde() {
+        let module = PythonModule::new_project("./my-project/main.py".into()).unwrap();
+        let response: Response = module
+            .call_serde(
+                "summarize",
+                &Request {
+                    numbers: vec![1, 2, 3],
+                    label: "sum".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            response,
+            Response {
+                total: 6,
+                label: "SUM".to_string()
+            }
+        );
+
+        let err = module
+            .call_serde::<_, Response>("add", &(1, 2))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "TypeError");
+        assert_eq!(err.kind(), &ErrorKind::Python);
+
+        let err = module
+            .call_serde::<_, Vec<String>>(
+                "summarize",
+                &Request {
+                    numbers: vec![1],
+                    label: "a".to_string(),
+                },
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Conversion);
+    }
+
+    #[test]
+    fn test_call_serde_plain_values() {
+        let module = PythonModule::from_source(
+            "serde_plain_values",
+            concat!(
+                "from datetime import date, datetime, timedelta, timezone\n",
+                "from decimal import Decimal\n",
+                "from uuid import UUID\n",
+                "def schedule(request):\n",
+                "    start = datetime(2024, 3, 1, 9, 30, tzinfo=timezone.utc)\n",
+                "    return {'start': start, 'day': date(2024, 3, 1), 'slots': [timedelta(minutes=90)],\n",
+                "            'price': Decimal('19.90'), 'id': UUID(int=1)}\n",
+            ),
+        )
+        .unwrap();
+        let response = module.call_json("schedule", serde_json::json!({})).unwrap();
+        assert_eq!(
+            response,
+            serde_json::json!({
+                "start": "2024-03-01T09:30:00+00:00",
+                "day": "2024-03-01",
+                "slots": [5400.0],
+                "price": "19.90",
+                "id": "00000000-0000-0000-0000-000000000001",
+            })
+        );
+    }
+
+    #[test]
+    #[ignore = "needs numpy"]
+    fn test_call_serde_numpy() {
+        let module = PythonModule::from_source(
+            "serde_numpy",
+            concat!(
+                "def scores(request):\n",
+                "    import numpy\n",
+                "    values = numpy.arange(6, dtype=numpy.float32).reshape(2, 3)\n",
+                "    return {'values': values, 'best': values.max()}\n",
+            ),
+        )
+        .unwrap();
+        let response = module.call_json("scores", serde_json::json!({})).unwrap();
+        assert_eq!(
+            response,
+            serde_json::json!({"values": [[0.0, 1.0, 2.0], [3.0, 4.0, 5.0]], "best": 5.0})
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_call_serde_chrono() {
+        use chrono::{DateTime, FixedOffset, NaiveDate, TimeDelta};
+
+        #[derive(Deserialize)]
+        struct Slot {
+            start: DateTime<FixedOffset>,
+            day: NaiveDate,
+        }
+
+        let module = PythonModule::from_source(
+            "serde_chrono",
+            concat!(
+                "from datetime import date, datetime, timedelta, timezone\n",
+                "def slot(request):\n",
+                "    tz = timezone(timedelta(hours=2))\n",
+                "    return {'start': datetime(2024, 3, 1, 9, 30, tzinfo=tz), 'day': date(2024, 3, 1)}\n",
+                "def shift(start, delta):\n",
+                "    return start + delta, delta * 2\n",
+            ),
+        )
+        .unwrap();
+        let slot: Slot = module.call_serde("slot", &()).unwrap();
+        let start = DateTime::parse_from_rfc3339("2024-03-01T09:30:00+02:00").unwrap();
+        assert_eq!(slot.start, start);
+        assert_eq!(slot.start.offset().local_minus_utc(), 2 * 3600);
+        assert_eq!(slot.day, NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+
+        // converted directly, aware datetimes keep their offset
+        let (shifted, doubled) = module
+            .action(move |_, module| {
+                module
+                    .call_method1("shift", (start, TimeDelta::minutes(90)))?
+                    .extract::<(DateTime<FixedOffset>, TimeDelta)>()
+            })
+            .unwrap();
+        assert_eq!(shifted, start + TimeDelta::minutes(90));
+        assert_eq!(shifted.offset(), start.offset());
+        assert_eq!(doubled, TimeDelta::hours(3));
+    }
+
+    #[cfg(all(feature = "rust_decimal", feature = "uuid"))]
+    #[test]
+    fn test_call_serde_decimal_uuid() {
+        use rust_decimal::Decimal;
+        use uuid::Uuid;
+
+        #[derive(Deserialize)]
+        struct Order {
+            id: Uuid,
+            total: Decimal,
+        }
+
+        let module = PythonModule::from_source(
+            "serde_decimal_uuid",
+            concat!(
+                "from decimal import Decimal\n",
+                "from uuid import UUID\n",
+                "def order(request):\n",
+                "    return {'id': UUID(int=1), 'total': Decimal('19.90') * 3}\n",
+                "def with_tax(total, id):\n",
+                "    return total * Decimal('1.1'), UUID(str(id)).version\n",
+            ),
+        )
+        .unwrap();
+        let order: Order = module.call_serde("order", &()).unwrap();
+        assert_eq!(order.id, Uuid::from_u128(1));
+        assert_eq!(order.total, Decimal::new(5970, 2));
+
+        // converted directly, the exact value and the bits survive
+        let id = Uuid::parse_str("6ba7b810-9dad-11d1-80b4-00c04fd430c8").unwrap();
+        let (taxed, version) = module
+            .action(move |_, module| {
+                module
+                    .call_method1("with_tax", (order.total, id))?
+                    .extract::<(Decimal, u8)>()
+            })
+            .unwrap();
+        assert_eq!(taxed, Decimal::new(65670, 3));
+        assert_eq!(version, 1);
+    }
+
+    #[test]
+    fn test_call_json() {
+        let module = PythonModule::new_project("./my-project/main.py".into()).unwrap();
+        let response = module
+            .call_json(
+                "summarize",
+                serde_json::json!({"numbers": [1, 2.5], "label": "mixed"}),
+            )
+            .unwrap();
+        assert_eq!(
+            response,
+            serde_json::json!({"total": 3.5, "label": "MIXED"})
+        );
+    }
+}
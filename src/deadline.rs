@@ -0,0 +1,190 @@
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+const QUEUED: u8 = 0;
+const RUNNING: u8 = 1;
+const FINISHED: u8 = 2;
+
+/// Point in time an action has to finish by, see [`PythonModule::action_with_deadline`]
+///
+/// Converted to Python it is an object with `remaining_time()`, the seconds left as a float,
+/// and `expired()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn at(instant: Instant) -> Self {
+        Self(instant)
+    }
+
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now() + timeout)
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Time left, zero once expired
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Deadline {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let remaining_time = PyCFunction::new_closure(
+            py,
+            Some(c"remaining_time"),
+            None,
+            move |_: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                Ok::<_, Infallible>(self.remaining().as_secs_f64())
+            },
+        )?;
+        let expired = PyCFunction::new_closure(
+            py,
+            Some(c"expired"),
+            None,
+            move |_: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                Ok::<_, Infallible>(self.is_expired())
+            },
+        )?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("remaining_time", remaining_time)?;
+        kwargs.set_item("expired", expired)?;
+        py.import("types")?
+            .getattr("SimpleNamespace")?
+            .call((), Some(&kwargs))
+    }
+}
+
+impl PythonModule {
+    /// Runs action on the imported module, which has to finish by `deadline`
+    ///
+    /// `call` receives the deadline to hand it to the Python code. Once it expires the
+    /// running task is interrupted like with [`interrupt`](Self::interrupt) and a
+    /// `TimeoutError` of kind [`ErrorKind::Timeout`] is returned right away. A task that only
+    /// starts after the deadline is skipped.
+    ///```rs
+    /// let deadline = Deadline::after(Duration::from_millis(500));
+    /// let reply = module
+    ///     .action_with_deadline(deadline, move |_, module, deadline| {
+    ///         module.call_method1("handle", (request, deadline))?.extract::<String>()
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn action_with_deadline<T: Send + 'static>(
+        &self,
+        deadline: Deadline,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>, Deadline) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        let phase = Arc::new(AtomicU8::new(QUEUED));
+        let task_phase = phase.clone();
+        let receiver = self.submit(move |py, module| {
+            if deadline.is_expired() {
+                return Err(PyErr::new::<pyo3::exceptions::PyTimeoutError, _>(
+                    "Deadline expired before the action started",
+                ));
+            }
+            task_phase.store(RUNNING, Ordering::SeqCst);
+            let result = call(py, module, deadline);
+            task_phase.store(FINISHED, Ordering::SeqCst);
+            result
+        })?;
+        match receiver.recv_timeout(deadline.remaining()) {
+            Ok(Err(e))
+                if deadline.is_expired()
+                    && e.is_instance_of::<pyo3::exceptions::PyKeyboardInterrupt>() =>
+            {
+                Err(deadline_error())
+            }
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                // the phase only changes while the task holds the GIL, so it can't finish and
+                // make room for the next task while this is checked
+                Python::with_gil(|_| {
+                    if phase.load(Ordering::SeqCst) == RUNNING {
+                        self.interrupt();
+                    }
+                });
+                Err(deadline_error())
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(self.terminated_error()),
+        }
+    }
+}
+
+fn deadline_error() -> PyRunnerError {
+    PyRunnerError::timeout("Action exceeded its deadline")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    const SLOW: &str = "def work(deadline, steps):\n    done = 0\n    while done < steps and deadline.remaining_time() > 0.05:\n        done += 1\n        sum(range(1000))\n    return done\ndef spin():\n    while True:\n        pass\n";
+
+    #[test]
+    fn test_deadline() {
+        let module = PythonModule::from_source("deadline_worker", SLOW).unwrap();
+        let done = module
+            .action_with_deadline(
+                Deadline::after(Duration::from_secs(5)),
+                |_, module, deadline| {
+                    module
+                        .call_method1("work", (deadline, 10))?
+                        .extract::<usize>()
+                },
+            )
+            .unwrap();
+        assert_eq!(done, 10);
+
+        // the Python code stops early on its own
+        let done = module
+            .action_with_deadline(
+                Deadline::after(Duration::from_secs(1)),
+                |_, module, deadline| {
+                    module
+                        .call_method1("work", (deadline, usize::MAX))?
+                        .extract::<usize>()
+                },
+            )
+            .unwrap();
+        assert!(done > 0);
+
+        // code ignoring the deadline is interrupted
+        let started = Instant::now();
+        let err = module
+            .action_with_deadline(
+                Deadline::after(Duration::from_millis(200)),
+                |_, module, _| module.call_method0("spin").map(|_| ()),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Timeout);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(module.action(|_, _| Ok(1)).unwrap(), 1);
+
+        let expired = Deadline::at(Instant::now());
+        assert!(expired.is_expired());
+        assert_eq!(expired.remaining(), Duration::ZERO);
+        let err = module
+            .action_with_deadline(expired, |_, _, _| Ok(()))
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Timeout);
+    }
+}
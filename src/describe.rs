@@ -0,0 +1,296 @@
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+
+/// What a module offers, see [`PythonModule::describe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleInfo {
+    /// `__name__`
+    pub name: String,
+    /// Module docstring
+    pub doc: Option<String>,
+    /// `__version__` converted with `str()`
+    pub version: Option<String>,
+    /// `__all__`, if the module defines it
+    pub all: Option<Vec<String>>,
+    /// Public callables defined by the module and the ones listed in `__all__`, by name
+    pub callables: Vec<CallableInfo>,
+}
+
+impl ModuleInfo {
+    pub fn callable(&self, name: &str) -> Option<&CallableInfo> {
+        self.callables.iter().find(|callable| callable.name == name)
+    }
+}
+
+/// Top-level function or class of a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallableInfo {
+    pub name: String,
+    pub kind: CallableKind,
+    /// Signature as Python prints it, e.g. `(path, *, retries: int = 3) -> bool`; `None` for
+    /// builtins that don't expose one
+    pub signature: Option<String>,
+    pub parameters: Vec<ParameterInfo>,
+    /// Docstring, cleaned up like `inspect.getdoc`
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallableKind {
+    Function,
+    /// `async def`, call it with [`PythonModule::action_await`]
+    Coroutine,
+    /// A class, its signature is the one of the constructor
+    Class,
+    /// Any other callable object
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterInfo {
+    pub name: String,
+    pub kind: ParameterKind,
+    /// `repr()` of the default value
+    pub default: Option<String>,
+    /// Annotation formatted like in the signature, e.g. `list[int]`
+    pub annotation: Option<String>,
+}
+
+/// How an argument is passed, see `inspect.Parameter.kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    PositionalOnly,
+    PositionalOrKeyword,
+    /// `*args`
+    VarPositional,
+    KeywordOnly,
+    /// `**kwargs`
+    VarKeyword,
+}
+
+impl PythonModule {
+    /// Reads the docstring, `__version__`, `__all__` and the signatures of the top-level
+    /// callables, e.g. to list the capabilities of a plugin
+    ///
+    /// Not supported by the subprocess backend.
+    ///```rs
+    /// let info = module.describe().unwrap();
+    /// for callable in &info.callables {
+    ///     println!("{}{}", callable.name, callable.signature.as_deref().unwrap_or("(...)"));
+    /// }
+    /// ```
+    pub fn describe(&self) -> Result<ModuleInfo, PyRunnerError> {
+        if self.control.child.get().is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Describing a module is not supported by the subprocess backend",
+            )
+            .into());
+        }
+        self.action(|py, module| describe(*py, module))
+    }
+}
+
+fn describe(py: Python<'_>, module: &Bound<'_, PyAny>) -> PyResult<ModuleInfo> {
+    let inspect = py.import("inspect")?;
+    let name = module.getattr("__name__")?.extract::<String>()?;
+    let all = match module.getattr_opt("__all__")? {
+        Some(all) => Some(all.extract::<Vec<String>>()?),
+        None => None,
+    };
+    let version = match module.getattr_opt("__version__")? {
+        Some(version) => Some(version.str()?.to_string()),
+        None => None,
+    };
+
+    let mut names = module
+        .getattr("__dict__")?
+        .call_method0("keys")?
+        .try_iter()?
+        .map(|key| key?.extract::<String>())
+        .collect::<PyResult<Vec<_>>>()?;
+    names.sort();
+    let mut callables = Vec::new();
+    for attr in names {
+        let value = module.getattr(attr.as_str())?;
+        let listed = all.as_ref().is_some_and(|all| all.contains(&attr));
+        let defined_here = value
+            .getattr_opt("__module__")?
+            .is_some_and(|owner| owner.extract::<String>().is_ok_and(|owner| owner == name));
+        if value.is_callable() && (listed || (!attr.starts_with('_') && defined_here)) {
+            callables.push(callable(&inspect, attr, &value)?);
+        }
+    }
+
+    Ok(ModuleInfo {
+        name,
+        doc: doc(&inspect, module)?,
+        version,
+        all,
+        callables,
+    })
+}
+
+fn callable(
+    inspect: &Bound<'_, PyModule>,
+    name: String,
+    value: &Bound<'_, PyAny>,
+) -> PyResult<CallableInfo> {
+    let is = |check: &str| inspect.call_method1(check, (value,))?.is_truthy();
+    let kind = if is("isclass")? {
+        CallableKind::Class
+    } else if is("iscoroutinefunction")? {
+        CallableKind::Coroutine
+    } else if is("isfunction")? || is("isbuiltin")? || is("ismethod")? {
+        CallableKind::Function
+    } else {
+        CallableKind::Other
+    };
+    // builtins without text signature raise a ValueError
+    let (signature, parameters) = match inspect.call_method1("signature", (value,)) {
+        Ok(signature) => {
+            let parameters = signature
+                .getattr("parameters")?
+                .call_method0("values")?
+                .try_iter()?
+                .map(|parameter| self::parameter(inspect, &parameter?))
+                .collect::<PyResult<Vec<_>>>()?;
+            (Some(signature.str()?.to_string()), parameters)
+        }
+        Err(_) => (None, Vec::new()),
+    };
+    Ok(CallableInfo {
+        name,
+        kind,
+        signature,
+        parameters,
+        doc: doc(inspect, value)?,
+    })
+}
+
+fn parameter(
+    inspect: &Bound<'_, PyModule>,
+    parameter: &Bound<'_, PyAny>,
+) -> PyResult<ParameterInfo> {
+    let empty = inspect.getattr("Parameter")?.getattr("empty")?;
+    let kind = match parameter
+        .getattr("kind")?
+        .getattr("name")?
+        .extract::<String>()?
+        .as_str()
+    {
+        "POSITIONAL_ONLY" => ParameterKind::PositionalOnly,
+        "VAR_POSITIONAL" => ParameterKind::VarPositional,
+        "KEYWORD_ONLY" => ParameterKind::KeywordOnly,
+        "VAR_KEYWORD" => ParameterKind::VarKeyword,
+        _ => ParameterKind::PositionalOrKeyword,
+    };
+    let default = parameter.getattr("default")?;
+    let annotation = parameter.getattr("annotation")?;
+    Ok(ParameterInfo {
+        name: parameter.getattr("name")?.extract()?,
+        kind,
+        default: match default.is(&empty) {
+            true => None,
+            false => Some(default.repr()?.to_string()),
+        },
+        annotation: match annotation.is(&empty) {
+            true => None,
+            false => Some(
+                inspect
+                    .call_method1("formatannotation", (annotation,))?
+                    .extract()?,
+            ),
+        },
+    })
+}
+
+fn doc(inspect: &Bound<'_, PyModule>, value: &Bound<'_, PyAny>) -> PyResult<Option<String>> {
+    inspect.call_method1("getdoc", (value,))?.extract()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLUGIN: &str = r#""""Resizes images"""
+from os.path import join
+from json import dumps
+__version__ = (1, 2)
+__all__ = ["resize", "Filter", "dumps"]
+
+def resize(path: str, /, width: int = 100, *sizes, keep_ratio=True, **options) -> bool:
+    """Resizes the image
+
+    Returns whether it changed"""
+    return True
+
+async def fetch(url):
+    pass
+
+class Filter:
+    def __init__(self, strength: float):
+        self.strength = strength
+
+def _helper():
+    pass
+"#;
+
+    #[test]
+    fn test_describe() {
+        let module = PythonModule::from_source("describe_plugin", PLUGIN).unwrap();
+        let info = module.describe().unwrap();
+        assert_eq!(info.name, "describe_plugin");
+        assert_eq!(info.doc.as_deref(), Some("Resizes images"));
+        assert_eq!(info.version.as_deref(), Some("(1, 2)"));
+        assert_eq!(info.all.as_deref().unwrap(), ["resize", "Filter", "dumps"]);
+        let names = info
+            .callables
+            .iter()
+            .map(|callable| callable.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Filter", "dumps", "fetch", "resize"]);
+
+        let resize = info.callable("resize").unwrap();
+        assert_eq!(resize.kind, CallableKind::Function);
+        assert_eq!(
+            resize.signature.as_deref(),
+            Some("(path: str, /, width: int = 100, *sizes, keep_ratio=True, **options) -> bool")
+        );
+        assert_eq!(
+            resize.doc.as_deref(),
+            Some("Resizes the image\n\nReturns whether it changed")
+        );
+        let kinds = resize
+            .parameters
+            .iter()
+            .map(|parameter| parameter.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                ParameterKind::PositionalOnly,
+                ParameterKind::PositionalOrKeyword,
+                ParameterKind::VarPositional,
+                ParameterKind::KeywordOnly,
+                ParameterKind::VarKeyword,
+            ]
+        );
+        assert_eq!(resize.parameters[1].default.as_deref(), Some("100"));
+        assert_eq!(resize.parameters[1].annotation.as_deref(), Some("int"));
+        assert_eq!(resize.parameters[3].annotation, None);
+
+        assert_eq!(
+            info.callable("fetch").unwrap().kind,
+            CallableKind::Coroutine
+        );
+        let filter = info.callable("Filter").unwrap();
+        assert_eq!(filter.kind, CallableKind::Class);
+        assert_eq!(filter.signature.as_deref(), Some("(strength: float)"));
+        assert!(info.callable("join").is_none() && info.callable("_helper").is_none());
+
+        let bare = PythonModule::from_source("describe_bare", "").unwrap();
+        let info = bare.describe().unwrap();
+        assert_eq!((info.doc, info.version, info.all), (None, None, None));
+        assert!(info.callables.is_empty());
+    }
+}
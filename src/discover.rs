@@ -0,0 +1,260 @@
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Prints the version, machine, pointer width and whether `venv` with `pip` works
+const PROBE: &str = "\
+import importlib.util, platform, struct, sys
+print('.'.join(map(str, sys.version_info[:3])))
+print(platform.machine())
+print(struct.calcsize('P') * 8)
+print(all(importlib.util.find_spec(name) for name in ('venv', 'ensurepip')))
+";
+
+/// Where a [`PythonInstall`] was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PythonSource {
+    Path,
+    /// The Windows registry, see PEP 514
+    Registry,
+    Pyenv,
+    /// Interpreters managed by `uv python install`
+    Uv,
+    Conda,
+}
+
+/// A Python interpreter found by [`discover_pythons`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonInstall {
+    pub executable: PathBuf,
+    pub source: PythonSource,
+    /// `(major, minor, micro)`
+    pub version: (u32, u32, u32),
+    /// `platform.machine()`, e.g. `x86_64` or `arm64`
+    pub machine: String,
+    /// 32 or 64
+    pub pointer_width: u32,
+    /// Whether `python -m venv` can create environments with pip
+    pub venv: bool,
+}
+
+/// Looks for Python interpreters on `PATH`, in the Windows registry and in pyenv, uv and conda
+/// installs and asks each one for its version
+///
+/// `PATH` entries come first in their order, interpreters that can't be run are skipped.
+///```rs
+/// let python = discover_pythons()
+///     .into_iter()
+///     .find(|python| python.version >= (3, 10, 0) && python.venv)
+///     .ok_or("Python 3.10+ with venv support is required")?;
+/// let venv = Venv::create("./venv", &python.executable)?;
+/// ```
+pub fn discover_pythons() -> Vec<PythonInstall> {
+    let mut seen = HashSet::new();
+    candidates()
+        .into_iter()
+        .filter(|(executable, _)| {
+            let resolved = fs::canonicalize(executable).unwrap_or_else(|_| executable.clone());
+            seen.insert(resolved)
+        })
+        .filter_map(|(executable, source)| probe(executable, source))
+        .collect()
+}
+
+/// Runs `executable` to read its details, `None` if it fails
+fn probe(executable: PathBuf, source: PythonSource) -> Option<PythonInstall> {
+    let output = Command::new(&executable)
+        .args(["-I", "-c", PROBE])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let mut lines = stdout.lines();
+    let mut version = lines.next()?.split('.').map(str::parse);
+    let version = (
+        version.next()?.ok()?,
+        version.next()?.ok()?,
+        version.next()?.ok()?,
+    );
+    Some(PythonInstall {
+        executable,
+        source,
+        version,
+        machine: lines.next()?.to_string(),
+        pointer_width: lines.next()?.parse().ok()?,
+        venv: lines.next()? == "True",
+    })
+}
+
+fn candidates() -> Vec<(PathBuf, PythonSource)> {
+    let mut found = Vec::new();
+    for dir in env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+    {
+        found.extend(executables_in(&dir).map(|path| (path, PythonSource::Path)));
+    }
+    if cfg!(windows) {
+        found.extend(
+            registry()
+                .into_iter()
+                .map(|path| (path, PythonSource::Registry)),
+        );
+    }
+
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    let pyenv = env::var_os("PYENV_ROOT")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".pyenv")));
+    if let Some(pyenv) = pyenv {
+        found.extend(installs_in(&pyenv.join("versions")).map(|path| (path, PythonSource::Pyenv)));
+    }
+
+    let uv = env::var_os("UV_PYTHON_INSTALL_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            let data = match cfg!(windows) {
+                true => env::var_os("APPDATA").map(PathBuf::from),
+                false => env::var_os("XDG_DATA_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| home.as_ref().map(|home| home.join(".local").join("share"))),
+            };
+            Some(data?.join("uv").join("python"))
+        });
+    if let Some(uv) = uv {
+        found.extend(installs_in(&uv).map(|path| (path, PythonSource::Uv)));
+    }
+
+    let mut conda = env::var_os("CONDA_PREFIX")
+        .map(PathBuf::from)
+        .into_iter()
+        .collect::<Vec<_>>();
+    if let Some(home) = &home {
+        for name in ["miniconda3", "anaconda3", "miniforge3", "mambaforge"] {
+            conda.push(home.join(name));
+        }
+    }
+    for root in conda {
+        found.extend(install(&root).map(|path| (path, PythonSource::Conda)));
+        found.extend(installs_in(&root.join("envs")).map(|path| (path, PythonSource::Conda)));
+    }
+    found
+}
+
+/// Interpreters named like `python`, `python3` or `python3.12` in `dir`
+fn executables_in(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    let mut names = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .filter(is_python)
+        .collect::<Vec<_>>();
+    names.sort();
+    let dir = dir.to_path_buf();
+    names.into_iter().map(move |name| dir.join(name))
+}
+
+fn is_python(name: &OsString) -> bool {
+    let Some(name) = name.to_str() else {
+        return false;
+    };
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    let Some(version) = name.strip_prefix("python") else {
+        return false;
+    };
+    // free-threaded builds end in `t`
+    let version = version.strip_suffix('t').unwrap_or(version);
+    version
+        .split('.')
+        .all(|part| part.is_empty() || part.chars().all(|c| c.is_ascii_digit()))
+        && !version.ends_with('.')
+}
+
+/// Interpreter of the installation at `root`
+fn install(root: &Path) -> Option<PathBuf> {
+    [
+        root.join("bin").join("python3"),
+        root.join("bin").join("python"),
+        root.join("python.exe"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// Interpreters of the installations in the subdirectories of `dir`
+fn installs_in(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    let mut roots = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    roots.sort();
+    roots.into_iter().filter_map(|root| install(&root))
+}
+
+/// `ExecutablePath` values of the PEP 514 registry keys
+fn registry() -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for hive in ["HKCU", "HKLM"] {
+        let key = format!(r"{hive}\Software\Python");
+        let Ok(output) = Command::new("reg")
+            .args(["query", &key, "/s", "/v", "ExecutablePath"])
+            .output()
+        else {
+            continue;
+        };
+        // `    ExecutablePath    REG_SZ    C:\Python312\python.exe`
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if let Some((_, path)) = line.trim().split_once("REG_SZ") {
+                found.push(PathBuf::from(path.trim()));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_pythons() {
+        let pythons = discover_pythons();
+        let python = pythons
+            .iter()
+            .find(|python| python.source == PythonSource::Path)
+            .unwrap();
+        assert_eq!(python.version.0, 3);
+        assert!(!python.machine.is_empty());
+        assert!([32, 64].contains(&python.pointer_width));
+
+        assert!(is_python(&"python3.12".into()));
+        assert!(is_python(&"python3.13t".into()));
+        assert!(is_python(&"python.exe".into()));
+        assert!(!is_python(&"python3-config".into()));
+        assert!(!is_python(&"python3.".into()));
+    }
+
+    #[test]
+    fn test_installs_in() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let bin = root.join("3.12.1").join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::write(bin.join("python3"), "").unwrap();
+
+        let found = installs_in(root).collect::<Vec<_>>();
+        assert_eq!(found, [bin.join("python3")]);
+        assert!(probe(bin.join("python3"), PythonSource::Pyenv).is_none());
+    }
+}
@@ -0,0 +1,483 @@
+use crate::PyRunnerError;
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyCapsule, PyDict, PyTuple};
+use pyo3::{IntoPyObjectExt, ffi};
+use std::ffi::c_void;
+use std::ptr::{self, NonNull};
+use std::sync::{Arc, Mutex};
+
+/// Element type of a [`SharedTensor`], with its DLPack type code
+pub trait TensorElement: Copy + Send + Sync + 'static {
+    #[doc(hidden)]
+    const CODE: u8;
+}
+
+macro_rules! tensor_element {
+    ($($ty:ty => $code:expr),*) => {
+        $(impl TensorElement for $ty {
+            const CODE: u8 = $code;
+        })*
+    };
+}
+
+const INT: u8 = 0;
+const UINT: u8 = 1;
+const FLOAT: u8 = 2;
+
+tensor_element!(
+    f32 => FLOAT, f64 => FLOAT,
+    i8 => INT, i16 => INT, i32 => INT, i64 => INT,
+    u8 => UINT, u16 => UINT, u32 => UINT, u64 => UINT
+);
+
+/// Device holding the memory of a [`Tensor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TensorDevice {
+    Cpu,
+    Cuda(i32),
+    /// Any other DLPack device type, with its id
+    Other(i32, i32),
+}
+
+impl TensorDevice {
+    fn from_raw(device: RawDevice) -> Self {
+        match device.device_type {
+            CPU => Self::Cpu,
+            CUDA => Self::Cuda(device.device_id),
+            other => Self::Other(other, device.device_id),
+        }
+    }
+
+    fn into_raw(self) -> RawDevice {
+        let (device_type, device_id) = match self {
+            Self::Cpu => (CPU, 0),
+            Self::Cuda(id) => (CUDA, id),
+            Self::Other(device_type, id) => (device_type, id),
+        };
+        RawDevice {
+            device_type,
+            device_id,
+        }
+    }
+}
+
+/// Element type of a [`Tensor`] as DLPack type code, bit width and lanes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TensorDtype {
+    pub code: u8,
+    pub bits: u8,
+    pub lanes: u16,
+}
+
+impl TensorDtype {
+    /// Type of `T`
+    pub fn of<T: TensorElement>() -> Self {
+        Self {
+            code: T::CODE,
+            bits: (size_of::<T>() * 8) as u8,
+            lanes: 1,
+        }
+    }
+}
+
+// Structs of the DLPack C interface,
+// https://dmlc.github.io/dlpack/latest/c_api.html
+
+const CPU: i32 = 1;
+const CUDA: i32 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawDevice {
+    device_type: i32,
+    device_id: i32,
+}
+
+#[repr(C)]
+struct RawTensor {
+    data: *mut c_void,
+    device: RawDevice,
+    ndim: i32,
+    dtype: TensorDtype,
+    shape: *mut i64,
+    strides: *mut i64,
+    byte_offset: u64,
+}
+
+#[repr(C)]
+struct ManagedTensor {
+    dl_tensor: RawTensor,
+    manager_ctx: *mut c_void,
+    deleter: Option<unsafe extern "C" fn(*mut ManagedTensor)>,
+}
+
+const CAPSULE: &std::ffi::CStr = c"dltensor";
+const USED_CAPSULE: &std::ffi::CStr = c"used_dltensor";
+
+/// Wraps `managed` in a `dltensor` capsule, which deletes it unless a consumer took it
+fn into_capsule(py: Python<'_>, managed: NonNull<ManagedTensor>) -> PyResult<Bound<'_, PyAny>> {
+    unsafe extern "C" fn destructor(capsule: *mut ffi::PyObject) {
+        // consumers rename the capsule once they own the tensor
+        if unsafe { ffi::PyCapsule_IsValid(capsule, CAPSULE.as_ptr()) } == 0 {
+            return;
+        }
+        let managed = unsafe { ffi::PyCapsule_GetPointer(capsule, CAPSULE.as_ptr()) };
+        let managed = managed.cast::<ManagedTensor>();
+        if let Some(deleter) = unsafe { (*managed).deleter } {
+            unsafe { deleter(managed) };
+        }
+    }
+
+    let capsule =
+        unsafe { ffi::PyCapsule_New(managed.as_ptr().cast(), CAPSULE.as_ptr(), Some(destructor)) };
+    unsafe { Bound::from_owned_ptr_or_err(py, capsule) }.inspect_err(|_| unsafe {
+        if let Some(deleter) = (*managed.as_ptr()).deleter {
+            deleter(managed.as_ptr());
+        }
+    })
+}
+
+/// Python object implementing `__dlpack__` and `__dlpack_device__`
+///
+/// `export` is called for every `__dlpack__` call and produces the capsule.
+fn dlpack_object<'py>(
+    py: Python<'py>,
+    device: TensorDevice,
+    export: impl Fn(Python<'_>) -> PyResult<Py<PyAny>> + Send + Sync + 'static,
+) -> PyResult<Bound<'py, PyAny>> {
+    // stream, max_version and copy are accepted and ignored, the memory is never copied
+    let dlpack = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| export(args.py()),
+    )?;
+    let device = device.into_raw();
+    let dlpack_device = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        move |_: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+            Ok::<_, PyErr>((device.device_type, device.device_id))
+        },
+    )?;
+    let object = py.import("types")?.getattr("SimpleNamespace")?.call0()?;
+    object.setattr("__dlpack__", dlpack)?;
+    object.setattr("__dlpack_device__", dlpack_device)?;
+    Ok(object)
+}
+
+/// Rust tensor handed to PyTorch, JAX, CuPy or numpy without copying its data
+///
+/// Converts to an object implementing the DLPack protocol (`__dlpack__`), which
+/// `torch.from_dlpack`, `jax.dlpack.from_dlpack`, `numpy.from_dlpack` and others accept.
+/// The memory lives on the CPU and is freed once the last consumer dropped its tensor.
+///```rs
+/// let input = SharedTensor::with_shape(vec![0.5f32; 3 * 224 * 224], [3, 224, 224]).unwrap();
+/// module.action(move |py, module| {
+///     let input = py.import("torch")?.call_method1("from_dlpack", (input,))?;
+///     module.call_method1("predict", (input,)).map(|_| ())
+/// })?;
+/// ```
+pub struct SharedTensor<T> {
+    data: Vec<T>,
+    shape: Vec<usize>,
+}
+
+impl<T: TensorElement> SharedTensor<T> {
+    /// One-dimensional tensor of `data`
+    pub fn new(data: Vec<T>) -> Self {
+        let shape = vec![data.len()];
+        Self { data, shape }
+    }
+
+    /// Tensor of `data` in row-major order, fails if `shape` doesn't match its length
+    pub fn with_shape(data: Vec<T>, shape: impl Into<Vec<usize>>) -> Result<Self, PyRunnerError> {
+        let shape = shape.into();
+        if shape.iter().product::<usize>() != data.len() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Shape {shape:?} doesn't match {} elements",
+                data.len()
+            ))
+            .into());
+        }
+        Ok(Self { data, shape })
+    }
+}
+
+struct SharedContext<T> {
+    // keeps the data alive
+    tensor: Arc<SharedTensor<T>>,
+    shape: Vec<i64>,
+}
+
+unsafe extern "C" fn delete_shared<T>(managed: *mut ManagedTensor) {
+    let managed = unsafe { Box::from_raw(managed) };
+    drop(unsafe { Box::from_raw(managed.manager_ctx.cast::<SharedContext<T>>()) });
+}
+
+/// Exports `tensor` as managed tensor pointing into the shared data
+fn export_shared<T: TensorElement>(tensor: &Arc<SharedTensor<T>>) -> NonNull<ManagedTensor> {
+    let mut context = Box::new(SharedContext {
+        tensor: tensor.clone(),
+        shape: tensor.shape.iter().map(|&len| len as i64).collect(),
+    });
+    let managed = Box::new(ManagedTensor {
+        dl_tensor: RawTensor {
+            data: context.tensor.data.as_ptr().cast_mut().cast(),
+            device: TensorDevice::Cpu.into_raw(),
+            ndim: context.shape.len() as i32,
+            dtype: TensorDtype::of::<T>(),
+            shape: context.shape.as_mut_ptr(),
+            // null strides mean row-major
+            strides: ptr::null_mut(),
+            byte_offset: 0,
+        },
+        manager_ctx: ptr::null_mut(),
+        deleter: Some(delete_shared::<T>),
+    });
+    let mut managed = NonNull::from(Box::leak(managed));
+    unsafe { managed.as_mut().manager_ctx = Box::into_raw(context).cast() };
+    managed
+}
+
+impl<'py, T: TensorElement> IntoPyObject<'py> for SharedTensor<T> {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let tensor = Arc::new(self);
+        dlpack_object(py, TensorDevice::Cpu, move |py| {
+            Ok(into_capsule(py, export_shared(&tensor))?.unbind())
+        })
+    }
+}
+
+/// Tensor borrowed from a Python object implementing the DLPack protocol
+///
+/// Extracting accepts a PyTorch, JAX or CuPy tensor, a numpy array or a raw `dltensor`
+/// capsule and keeps the producer's memory alive without copying it, also if it lives on
+/// a GPU. Converting it back to Python hands the memory on to the next consumer.
+///```rs
+/// let logits = module.action(|_, module| module.call_method0("forward")?.extract::<Tensor>())?;
+/// if logits.device() == TensorDevice::Cuda(0) {
+///     launch_kernel(logits.data_ptr(), logits.shape());
+/// }
+/// ```
+pub struct Tensor {
+    managed: NonNull<ManagedTensor>,
+}
+
+// the producer keeps the memory valid until the deleter was called
+unsafe impl Send for Tensor {}
+
+impl Tensor {
+    fn raw(&self) -> &RawTensor {
+        unsafe { &self.managed.as_ref().dl_tensor }
+    }
+
+    pub fn device(&self) -> TensorDevice {
+        TensorDevice::from_raw(self.raw().device)
+    }
+
+    pub fn dtype(&self) -> TensorDtype {
+        self.raw().dtype
+    }
+
+    pub fn shape(&self) -> &[i64] {
+        let raw = self.raw();
+        if raw.ndim == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(raw.shape, raw.ndim as usize) }
+    }
+
+    /// Strides in elements, `None` for a row-major tensor
+    pub fn strides(&self) -> Option<&[i64]> {
+        let raw = self.raw();
+        if raw.strides.is_null() || raw.ndim == 0 {
+            return None;
+        }
+        Some(unsafe { std::slice::from_raw_parts(raw.strides, raw.ndim as usize) })
+    }
+
+    /// Address of the first element on [`device`](Self::device)
+    pub fn data_ptr(&self) -> *mut c_void {
+        let raw = self.raw();
+        unsafe { raw.data.cast::<u8>().add(raw.byte_offset as usize).cast() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shape().iter().product::<i64>() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the elements of a CPU tensor into a `Vec` in row-major order
+    ///
+    /// Fails if the tensor lives on another device or its elements aren't of type `T`.
+    pub fn to_vec<T: TensorElement>(&self) -> Result<Vec<T>, PyRunnerError> {
+        if self.device() != TensorDevice::Cpu {
+            return Err(PyErr::new::<pyo3::exceptions::PyBufferError, _>(format!(
+                "Tensor lives on {:?}, not on the CPU",
+                self.device()
+            ))
+            .into());
+        }
+        if self.dtype() != TensorDtype::of::<T>() {
+            return Err(PyErr::new::<pyo3::exceptions::PyBufferError, _>(format!(
+                "Tensor has the type {:?}, expected {:?}",
+                self.dtype(),
+                TensorDtype::of::<T>()
+            ))
+            .into());
+        }
+        let data = self.data_ptr().cast::<T>();
+        let Some(strides) = self.strides() else {
+            if self.is_empty() {
+                return Ok(Vec::new());
+            }
+            return Ok(unsafe { std::slice::from_raw_parts(data, self.len()) }.to_vec());
+        };
+        let mut values = Vec::with_capacity(self.len());
+        if !self.is_empty() {
+            collect_strided(data, self.shape(), strides, &mut values);
+        }
+        Ok(values)
+    }
+}
+
+/// Appends the elements at `data` in row-major order
+fn collect_strided<T: Copy>(data: *const T, shape: &[i64], strides: &[i64], values: &mut Vec<T>) {
+    let Some((&len, shape)) = shape.split_first() else {
+        values.push(unsafe { *data });
+        return;
+    };
+    for i in 0..len {
+        let element = unsafe { data.offset((i * strides[0]) as isize) };
+        collect_strided(element, shape, &strides[1..], values);
+    }
+}
+
+impl Drop for Tensor {
+    fn drop(&mut self) {
+        if let Some(deleter) = unsafe { self.managed.as_ref().deleter } {
+            // producers like numpy call back into Python
+            Python::with_gil(|_| unsafe { deleter(self.managed.as_ptr()) });
+        }
+    }
+}
+
+impl<'py> FromPyObject<'py> for Tensor {
+    fn extract_bound(object: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let capsule = match object.downcast::<PyCapsule>() {
+            Ok(capsule) => capsule.clone(),
+            Err(_) => object.call_method0("__dlpack__")?.downcast_into()?,
+        };
+        if capsule.name()? != Some(CAPSULE) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "Expected an unused dltensor capsule",
+            ));
+        }
+        let managed = NonNull::new(capsule.pointer().cast::<ManagedTensor>()).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>("Empty dltensor capsule")
+        })?;
+        // marks the tensor as consumed, so the capsule doesn't delete it
+        if unsafe { ffi::PyCapsule_SetName(capsule.as_ptr(), USED_CAPSULE.as_ptr()) } != 0 {
+            return Err(PyErr::fetch(object.py()));
+        }
+        Ok(Self { managed })
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Tensor {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    /// Object whose `__dlpack__` hands the tensor on, it can be consumed once
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let device = self.device();
+        let tensor = Mutex::new(Some(self));
+        dlpack_object(py, device, move |py| {
+            let tensor = tensor.lock().unwrap().take().ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyBufferError, _>("Tensor was already consumed")
+            })?;
+            let managed = tensor.managed;
+            std::mem::forget(tensor);
+            into_capsule(py, managed)?.into_py_any(py)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModule;
+    use std::path::Path;
+
+    #[test]
+    fn test_shared_tensor() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let data = vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let address = data.as_ptr() as usize;
+        let tensor = SharedTensor::with_shape(data, [2, 3]).unwrap();
+        let (shape, device, values, shared) = module
+            .action(move |py, _| {
+                let exported = tensor.into_pyobject(*py)?;
+                let device = exported
+                    .call_method0("__dlpack_device__")?
+                    .extract::<(i32, i32)>()?;
+                // capsules dropped unconsumed delete the exported tensor
+                exported.call_method0("__dlpack__")?;
+                let tensor = exported.extract::<Tensor>()?;
+                let shared = tensor.data_ptr() as usize;
+                // handed on to another consumer
+                let tensor = tensor.into_pyobject(*py)?.extract::<Tensor>()?;
+                Ok((
+                    tensor.shape().to_vec(),
+                    device,
+                    tensor.to_vec::<f32>()?,
+                    shared,
+                ))
+            })
+            .unwrap();
+        assert_eq!(shape, [2, 3]);
+        assert_eq!(device, (1, 0));
+        assert_eq!(values, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(shared, address);
+
+        let err = module
+            .action(|py, _| {
+                let exported = SharedTensor::new(vec![1i64, 2]).into_pyobject(*py)?;
+                exported.extract::<Tensor>()?.to_vec::<f64>().map(|_| ())?;
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "BufferError");
+
+        let err = module
+            .action(|py, _| {
+                let tensor = SharedTensor::new(vec![1u8]).into_pyobject(*py)?;
+                let tensor = tensor.extract::<Tensor>()?.into_pyobject(*py)?;
+                tensor.extract::<Tensor>()?;
+                tensor.extract::<Tensor>().map(|_| ())
+            })
+            .unwrap_err();
+        assert_eq!(err.message(), "Tensor was already consumed");
+
+        assert!(SharedTensor::with_shape(vec![1u8; 5], [2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_strided_tensor() {
+        let values = [1, 2, 3, 4, 5, 6];
+        let mut transposed = Vec::new();
+        // column-major view of a 2x3 tensor
+        collect_strided(values.as_ptr(), &[3, 2], &[1, 3], &mut transposed);
+        assert_eq!(transposed, [1, 4, 2, 5, 3, 6]);
+    }
+}
@@ -0,0 +1,430 @@
+use crate::{ExitReason, Mismatch, Resource};
+use pyo3::exceptions::PyBaseException;
+use pyo3::prelude::*;
+use pyo3::types::{PyTraceback, PyType};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+/// What failed, see [`PyRunnerError::kind`]
+///
+/// Details like the exception type and traceback are available on the error itself for
+/// every kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Python code raised an exception
+    Python,
+    /// Importing or executing the module failed while it was loaded
+    InitFailed,
+    /// The worker thread exited before the task finished, `reason` is `None` if it wasn't
+    /// known yet
+    WorkerDead { reason: Option<ExitReason> },
+    /// No result arrived in time
+    Timeout,
+    /// The task was cancelled before it started
+    Cancelled,
+    /// The action closure panicked, the worker keeps running
+    Panicked,
+    /// The bounded task queue was full, see [`QueuePolicy`](crate::QueuePolicy)
+    QueueFull,
+    /// The module's rate or concurrency limit was reached, see
+    /// [`PythonModuleBuilder::rate_limit`](crate::PythonModuleBuilder::rate_limit)
+    RateLimited,
+    /// The circuit breaker rejected the call after repeated failures, see
+    /// [`PythonModuleBuilder::circuit_breaker`](crate::PythonModuleBuilder::circuit_breaker)
+    CircuitOpen,
+    /// The child of a subprocess module ran into one of its
+    /// [`ResourceLimits`](crate::ResourceLimits)
+    ResourceLimitExceeded { resource: Resource },
+    /// A value couldn't be converted between Rust and Python
+    Conversion,
+    /// The module's files didn't match the digest or signature it was built with, see
+    /// [`PythonModuleBuilder::verify_sha256`](crate::PythonModuleBuilder::verify_sha256);
+    /// the exception is a `py_runner.IntegrityError`
+    Integrity,
+    /// The loaded module lacks functions of its required
+    /// [`Interface`](crate::Interface), the exception is a `py_runner.InterfaceMismatch`
+    InterfaceMismatch { mismatches: Vec<Mismatch> },
+}
+
+/// Python exception together with everything needed to debug it from Rust
+///
+/// Exceptions raised on a worker are rendered while it still holds the GIL, so the traceback
+/// is captured before the exception leaves the worker thread. Other errors are only rendered
+/// once their details are read, and failures of Rust like timeouts never need the GIL.
+#[derive(Debug)]
+pub struct PyRunnerError(Box<Details>);
+
+#[derive(Debug)]
+struct Details {
+    source: PyErr,
+    rendered: OnceLock<Rendered>,
+    /// Overrides the kind derived from the exception
+    kind: Option<ErrorKind>,
+    mapped: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+#[derive(Debug)]
+struct Rendered {
+    exception_type: String,
+    message: String,
+    traceback: Option<String>,
+    file: Option<String>,
+    line: Option<usize>,
+    source_line: Option<String>,
+    kind: ErrorKind,
+}
+
+impl PyRunnerError {
+    /// Captures type, message, traceback and location of `err`
+    pub fn from_py(py: Python<'_>, err: PyErr) -> Self {
+        let rendered = Rendered::new(py, &err);
+        Self(Box::new(Details {
+            source: err,
+            rendered: OnceLock::from(rendered),
+            kind: None,
+            mapped: None,
+        }))
+    }
+
+    /// `RuntimeError` of `kind` with `message`, for failures that didn't come from Python
+    pub(crate) fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self::from_rust::<pyo3::exceptions::PyRuntimeError>("RuntimeError", kind, message.into())
+    }
+
+    /// `TimeoutError` of [`ErrorKind::Timeout`] with `message`
+    pub(crate) fn timeout(message: impl Into<String>) -> Self {
+        Self::from_rust::<pyo3::exceptions::PyTimeoutError>(
+            "TimeoutError",
+            ErrorKind::Timeout,
+            message.into(),
+        )
+    }
+
+    /// `py_runner.IntegrityError` of [`ErrorKind::Integrity`] with `message`
+    #[cfg(feature = "integrity")]
+    pub(crate) fn integrity(message: impl Into<String>) -> Self {
+        Self::from_rust::<crate::integrity::IntegrityError>(
+            "py_runner.IntegrityError",
+            ErrorKind::Integrity,
+            message.into(),
+        )
+    }
+
+    /// Error of the exception class `T` named `exception_type`, built without the GIL
+    fn from_rust<T>(exception_type: &str, kind: ErrorKind, message: String) -> Self
+    where
+        T: pyo3::PyTypeInfo,
+    {
+        // created lazily, the exception object only exists once Python asks for it
+        let source = PyErr::new::<T, _>(message.clone());
+        let rendered = Rendered {
+            exception_type: exception_type.to_string(),
+            message,
+            traceback: None,
+            file: None,
+            line: None,
+            source_line: None,
+            kind: ErrorKind::Python,
+        };
+        Self(Box::new(Details {
+            source,
+            rendered: OnceLock::from(rendered),
+            kind: Some(kind),
+            mapped: None,
+        }))
+    }
+
+    pub(crate) fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.0.kind = Some(kind);
+        self
+    }
+
+    /// Type, message and location of the exception, rendered with the GIL on first use
+    fn rendered(&self) -> &Rendered {
+        self.0
+            .rendered
+            .get_or_init(|| Python::with_gil(|py| Rendered::new(py, &self.0.source)))
+    }
+
+    /// Marks an error of the import, an interface mismatch keeps its kind
+    pub(crate) fn init_failed(self) -> Self {
+        match self.kind() {
+            ErrorKind::InterfaceMismatch { .. } => self,
+            _ => self.with_kind(ErrorKind::InitFailed),
+        }
+    }
+
+    /// Fills in the offending line from `code` for errors raised in code compiled from a string
+    pub(crate) fn with_source(mut self, code: &str) -> Self {
+        self.rendered();
+        let rendered = self.0.rendered.get_mut().expect("rendered above");
+        if rendered.source_line.is_none()
+            && rendered.file.as_deref() == Some("<string>")
+            && let Some(line) = rendered
+                .line
+                .and_then(|line| code.lines().nth(line.checked_sub(1)?))
+        {
+            rendered.source_line = Some(line.trim().to_string());
+        }
+        self
+    }
+
+    /// What failed, to branch on without matching messages
+    ///```rs
+    /// match module.action_timeout(|_, module| module.call_method0("run").map(|_| ()), timeout) {
+    ///     Err(e) if e.kind() == &ErrorKind::Timeout => retry(),
+    ///     Err(e) if matches!(e.kind(), ErrorKind::WorkerDead { .. }) => restart(),
+    ///     result => result?,
+    /// }
+    /// ```
+    pub fn kind(&self) -> &ErrorKind {
+        match &self.0.kind {
+            Some(kind) => kind,
+            None => &self.rendered().kind,
+        }
+    }
+
+    /// Qualified exception class, e.g. `ZeroDivisionError` or `mypkg.errors.NotFound`
+    pub fn exception_type(&self) -> &str {
+        &self.rendered().exception_type
+    }
+
+    /// `str(exception)`
+    pub fn message(&self) -> &str {
+        &self.rendered().message
+    }
+
+    /// Output of `traceback.format_exception`, `None` if the exception was never raised
+    ///
+    /// A `SyntaxError` from compiling code has no traceback and is formatted with
+    /// `traceback.format_exception_only` instead, which points at the offending column.
+    pub fn traceback(&self) -> Option<&str> {
+        self.rendered().traceback.as_deref()
+    }
+
+    /// File of the innermost frame (or of the offending source for a `SyntaxError`)
+    pub fn file(&self) -> Option<&str> {
+        self.rendered().file.as_deref()
+    }
+
+    /// Line of the innermost frame (or of the offending source for a `SyntaxError`)
+    pub fn line(&self) -> Option<usize> {
+        self.rendered().line
+    }
+
+    /// Source of [`line`](Self::line), if it could be found
+    pub fn source_line(&self) -> Option<&str> {
+        self.rendered().source_line.as_deref()
+    }
+
+    /// The original Python exception
+    pub fn py_err(&self) -> &PyErr {
+        &self.0.source
+    }
+
+    /// Checks whether the exception is an instance of `T`
+    pub fn is_instance_of<T: pyo3::PyTypeInfo>(&self) -> bool {
+        Python::with_gil(|py| self.0.source.is_instance_of::<T>(py))
+    }
+
+    /// Error the exception was mapped to, see [`PythonModuleBuilder::map_exception`]
+    ///
+    /// `None` if no mapping matched or it produced another type than `E`.
+    ///
+    /// [`PythonModuleBuilder::map_exception`]: crate::PythonModuleBuilder::map_exception
+    pub fn mapped<E: std::error::Error + 'static>(&self) -> Option<&E> {
+        self.0.mapped.as_deref()?.downcast_ref()
+    }
+}
+
+pub(crate) type ExceptionMapper =
+    Arc<dyn Fn(&PyRunnerError) -> Box<dyn std::error::Error + Send + Sync> + Send + Sync>;
+
+/// Exception classes registered with [`PythonModuleBuilder::map_exception`](crate::PythonModuleBuilder::map_exception)
+#[derive(Clone, Default)]
+pub(crate) struct ExceptionMap(Arc<Vec<(String, ExceptionMapper)>>);
+
+impl ExceptionMap {
+    pub(crate) fn new(mappers: Vec<(String, ExceptionMapper)>) -> Self {
+        Self(Arc::new(mappers))
+    }
+
+    /// Like [`PyRunnerError::from_py`], then applies the mapping of the most derived
+    /// registered class of the exception
+    pub(crate) fn convert(&self, py: Python<'_>, err: PyErr) -> PyRunnerError {
+        let mapper = if self.0.is_empty() {
+            None
+        } else {
+            mro(py, &err).into_iter().find_map(|name| {
+                self.0
+                    .iter()
+                    .find(|(registered, _)| *registered == name)
+                    .map(|(_, mapper)| mapper.clone())
+            })
+        };
+        let mut err = PyRunnerError::from_py(py, err);
+        if let Some(mapper) = mapper {
+            err.0.mapped = Some(mapper(&err));
+        }
+        err
+    }
+}
+
+impl Rendered {
+    fn new(py: Python<'_>, err: &PyErr) -> Self {
+        let value = err.value(py);
+        let exception_type = qualified_name(&value.get_type());
+        let message = value
+            .str()
+            .map(|v| v.to_string())
+            .unwrap_or_else(|_| "<unprintable>".to_string());
+        let traceback = format_traceback(py, err);
+        let (file, line) = location(py, err).unwrap_or((None, None));
+        let source_line = source_line(py, err, file.as_deref(), line);
+        let kind = special_kind(&exception_type, value).unwrap_or(ErrorKind::Python);
+        Self {
+            exception_type,
+            message,
+            traceback,
+            file,
+            line,
+            source_line,
+            kind,
+        }
+    }
+}
+
+/// Kind of the exceptions raised for exceeded resource limits of the subprocess proxy and
+/// for interface mismatches
+fn special_kind(exception_type: &str, value: &Bound<'_, PyBaseException>) -> Option<ErrorKind> {
+    match exception_type {
+        "py_runner.ResourceLimitExceeded" => {
+            let resource = value.getattr("resource").ok()?.extract::<String>().ok()?;
+            Some(ErrorKind::ResourceLimitExceeded {
+                resource: Resource::from_name(&resource)?,
+            })
+        }
+        "py_runner.InterfaceMismatch" => Some(ErrorKind::InterfaceMismatch {
+            mismatches: crate::interface::mismatches(value)?,
+        }),
+        _ => None,
+    }
+}
+
+/// `mypkg.errors.NotFound` or just the name for builtins
+fn qualified_name(ty: &Bound<'_, PyType>) -> String {
+    let attr = |name: &str| ty.getattr(name).and_then(|v| v.extract::<String>()).ok();
+    let name = attr("__qualname__").unwrap_or_else(|| "<unknown>".to_string());
+    match attr("__module__") {
+        Some(module) if module != "builtins" => format!("{module}.{name}"),
+        _ => name,
+    }
+}
+
+/// Qualified names of the exception class and its bases, most derived first
+fn mro(py: Python<'_>, err: &PyErr) -> Vec<String> {
+    let mro = err.get_type(py).getattr("__mro__");
+    mro.and_then(|mro| mro.extract::<Vec<Bound<'_, PyType>>>())
+        .map(|classes| classes.iter().map(qualified_name).collect())
+        .unwrap_or_default()
+}
+
+fn format_traceback(py: Python<'_>, err: &PyErr) -> Option<String> {
+    let traceback = py.import("traceback").ok()?;
+    let lines = match err.traceback(py) {
+        Some(tb) => {
+            traceback.call_method1("format_exception", (err.get_type(py), err.value(py), tb))
+        }
+        None if err.is_instance_of::<pyo3::exceptions::PySyntaxError>(py) => {
+            traceback.call_method1("format_exception_only", (err.get_type(py), err.value(py)))
+        }
+        None => return None,
+    };
+    Some(
+        lines
+            .and_then(|v| v.extract::<Vec<String>>())
+            .ok()?
+            .concat(),
+    )
+}
+
+fn source_line(
+    py: Python<'_>,
+    err: &PyErr,
+    file: Option<&str>,
+    line: Option<usize>,
+) -> Option<String> {
+    let text = if err.is_instance_of::<pyo3::exceptions::PySyntaxError>(py) {
+        err.value(py)
+            .getattr("text")
+            .ok()?
+            .extract::<Option<String>>()
+            .ok()?
+    } else {
+        py.import("linecache")
+            .ok()?
+            .call_method1("getline", (file?, line?))
+            .ok()?
+            .extract::<String>()
+            .ok()
+    }?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn location(py: Python<'_>, err: &PyErr) -> PyResult<(Option<String>, Option<usize>)> {
+    let value = err.value(py);
+    if err.is_instance_of::<pyo3::exceptions::PySyntaxError>(py) {
+        return Ok((
+            value.getattr("filename")?.extract()?,
+            value.getattr("lineno")?.extract()?,
+        ));
+    }
+    let Some(mut tb) = err.traceback(py) else {
+        return Ok((None, None));
+    };
+    while let Ok(next) = tb.getattr("tb_next")?.downcast_into::<PyTraceback>() {
+        tb = next;
+    }
+    let file = tb
+        .getattr("tb_frame")?
+        .getattr("f_code")?
+        .getattr("co_filename")?
+        .extract()?;
+    let line = tb.getattr("tb_lineno")?.extract()?;
+    Ok((Some(file), Some(line)))
+}
+
+impl fmt::Display for PyRunnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rendered = self.rendered();
+        match &rendered.traceback {
+            Some(traceback) => write!(f, "{}", traceback.trim_end()),
+            None => write!(f, "{}: {}", rendered.exception_type, rendered.message),
+        }
+    }
+}
+
+impl std::error::Error for PyRunnerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0.source)
+    }
+}
+
+impl From<PyErr> for PyRunnerError {
+    fn from(err: PyErr) -> Self {
+        Self(Box::new(Details {
+            source: err,
+            rendered: OnceLock::new(),
+            kind: None,
+            mapped: None,
+        }))
+    }
+}
+
+impl From<PyRunnerError> for PyErr {
+    fn from(err: PyRunnerError) -> Self {
+        err.0.source
+    }
+}
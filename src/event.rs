@@ -0,0 +1,137 @@
+use crate::PythonModule;
+use crossbeam::channel::{self, Receiver, Sender};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use std::sync::Mutex;
+
+/// Value passed to `host.emit` by Python code, see [`PythonModule::subscribe`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    /// A list or tuple
+    List(Vec<Event>),
+    /// A dict with `str` keys, in insertion order
+    Dict(Vec<(String, Event)>),
+}
+
+impl<'py> FromPyObject<'py> for Event {
+    fn extract_bound(value: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if value.is_none() {
+            Ok(Event::None)
+        } else if value.is_instance_of::<PyBool>() {
+            Ok(Event::Bool(value.extract()?))
+        } else if value.is_instance_of::<PyInt>() {
+            Ok(Event::Int(value.extract()?))
+        } else if value.is_instance_of::<PyFloat>() {
+            Ok(Event::Float(value.extract()?))
+        } else if value.is_instance_of::<PyString>() {
+            Ok(Event::Str(value.extract()?))
+        } else if let Ok(bytes) = value.downcast::<PyBytes>() {
+            Ok(Event::Bytes(bytes.as_bytes().to_vec()))
+        } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+            Ok(Event::List(value.extract()?))
+        } else if let Ok(dict) = value.downcast::<PyDict>() {
+            dict.iter()
+                .map(|(key, value)| Ok((key.extract()?, value.extract()?)))
+                .collect::<PyResult<_>>()
+                .map(Event::Dict)
+        } else {
+            Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                "Can't emit {}",
+                value.get_type().name()?
+            )))
+        }
+    }
+}
+
+/// Subscribers of a module's events
+#[derive(Default)]
+pub(crate) struct Events {
+    senders: Mutex<Vec<Sender<Event>>>,
+}
+
+impl Events {
+    /// Sends `event` to every subscriber and forgets the ones that went away
+    pub(crate) fn emit(&self, event: Event) {
+        self.senders
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+impl PythonModule {
+    /// Receives every value the module passes to `host.emit` from now on
+    ///
+    /// `host.emit` returns immediately, events are queued until received. Not supported by
+    /// the subprocess backend.
+    ///```rs
+    /// let events = module.subscribe();
+    /// let handle = module.spawn(|_, module| module.call_method0("train").map(|_| ())).unwrap();
+    /// for event in events.iter() {
+    ///     println!("{event:?}");
+    /// }
+    /// ```
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = channel::unbounded();
+        self.events.senders.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscribe() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import host\n",
+                "def train(epochs):\n",
+                "    for epoch in range(epochs):\n",
+                "        host.emit({'epoch': epoch, 'loss': 1 / (epoch + 1)})\n",
+                "    host.emit(None)\n",
+                "def invalid():\n",
+                "    host.emit(object())\n",
+            ),
+        )
+        .unwrap();
+
+        let module = PythonModule::new_project(dir.join("main.py")).unwrap();
+        let events = module.subscribe();
+        let dropped = module.subscribe();
+        drop(dropped);
+        module
+            .action(|_, module| module.call_method1("train", (2,)).map(|_| ()))
+            .unwrap();
+        let received = events.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            received,
+            vec![
+                Event::Dict(vec![
+                    ("epoch".to_string(), Event::Int(0)),
+                    ("loss".to_string(), Event::Float(1.0))
+                ]),
+                Event::Dict(vec![
+                    ("epoch".to_string(), Event::Int(1)),
+                    ("loss".to_string(), Event::Float(0.5))
+                ]),
+                Event::None,
+            ]
+        );
+
+        let err = module
+            .action(|_, module| module.call_method0("invalid").map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "TypeError");
+    }
+}
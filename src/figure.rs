@@ -0,0 +1,181 @@
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::{Arc, Mutex};
+
+/// File format figures are rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FigureFormat {
+    Png,
+    Svg,
+    Pdf,
+}
+
+impl FigureFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Svg => "svg",
+            Self::Pdf => "pdf",
+        }
+    }
+}
+
+/// Matplotlib figure rendered to a file, see [`PythonModule::action_figures`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Figure {
+    /// `figure.number`
+    pub number: i64,
+    /// `figure.get_label()`, empty if none was set
+    pub label: String,
+    pub format: FigureFormat,
+    pub data: Vec<u8>,
+}
+
+/// Renders every open `matplotlib.pyplot` figure in `format` and closes it
+///
+/// Returns nothing if `matplotlib.pyplot` was never imported.
+pub fn take_figures(py: Python<'_>, format: FigureFormat) -> PyResult<Vec<Figure>> {
+    let modules = py.import("sys")?.getattr("modules")?;
+    let Ok(pyplot) = modules.get_item("matplotlib.pyplot") else {
+        return Ok(Vec::new());
+    };
+    let numbers = pyplot.call_method0("get_fignums")?.extract::<Vec<i64>>()?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("format", format.as_str())?;
+    numbers
+        .into_iter()
+        .map(|number| {
+            let figure = pyplot.call_method1("figure", (number,))?;
+            let file = py.import("io")?.call_method0("BytesIO")?;
+            let saved = figure.call_method("savefig", (&file,), Some(&kwargs));
+            // closed even if it couldn't be rendered
+            pyplot.call_method1("close", (&figure,))?;
+            saved?;
+            Ok(Figure {
+                number,
+                label: figure.call_method0("get_label")?.extract()?,
+                format,
+                data: file.call_method0("getvalue")?.extract()?,
+            })
+        })
+        .collect()
+}
+
+impl PythonModule {
+    /// Runs action and collects the matplotlib figures it left open, see [`take_figures`]
+    ///
+    /// The figures are returned and closed even if the action failed. Like `pyplot` itself
+    /// this is shared by every module of the interpreter, figures opened by tasks running
+    /// concurrently on other workers are collected as well.
+    ///```rs
+    /// let (result, figures) = module.action_figures(|_, module| module.call_method0("plot_report").map(|_| ()), FigureFormat::Png);
+    /// std::fs::write("report.png", &figures[0].data)?;
+    /// ```
+    pub fn action_figures<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+        format: FigureFormat,
+    ) -> (Result<T, PyRunnerError>, Vec<Figure>) {
+        let figures = Arc::new(Mutex::new(Vec::new()));
+        let slot = figures.clone();
+        let result = self.action(move |py, module| {
+            let result = call(py, module);
+            *slot.lock().unwrap() = take_figures(*py, format)?;
+            result
+        });
+        let figures = std::mem::take(&mut *figures.lock().unwrap());
+        (result, figures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for `matplotlib.pyplot`, rendering a figure as `format:label`
+    const PYPLOT_STUB: &str = r#"
+import sys, types
+
+class Figure:
+    def __init__(self, number, label):
+        self.number, self.label = number, label
+
+    def get_label(self):
+        return self.label
+
+    def savefig(self, file, format):
+        file.write(f"{format}:{self.label}".encode())
+
+figures = {}
+
+def figure(num=None, label=""):
+    num = num or len(figures) + 1
+    return figures.setdefault(num, Figure(num, label))
+
+def get_fignums():
+    return sorted(figures)
+
+def close(figure):
+    del figures[figure.number]
+
+pyplot = types.ModuleType("matplotlib.pyplot")
+pyplot.__dict__.update(figure=figure, get_fignums=get_fignums, close=close)
+"#;
+
+    #[test]
+    fn test_action_figures() {
+        let module = PythonModule::from_source("pyplot_stub", PYPLOT_STUB).unwrap();
+        let (_, figures) = module.action_figures(|_, _| Ok(()), FigureFormat::Png);
+        assert!(figures.is_empty());
+
+        module
+            .action(|py, module| {
+                let modules = py.import("sys")?.getattr("modules")?;
+                modules.set_item("matplotlib.pyplot", module.getattr("pyplot")?)
+            })
+            .unwrap();
+        let (result, figures) = module.action_figures(
+            |_, module| -> PyResult<()> {
+                let pyplot = module.getattr("pyplot")?;
+                let kwargs = PyDict::new(module.py());
+                kwargs.set_item("label", "loss")?;
+                pyplot.call_method("figure", (), Some(&kwargs))?;
+                pyplot.call_method0("figure")?;
+                Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "plot failed",
+                ))
+            },
+            FigureFormat::Svg,
+        );
+        assert_eq!(result.unwrap_err().message(), "plot failed");
+        assert_eq!(
+            figures,
+            [
+                Figure {
+                    number: 1,
+                    label: "loss".to_string(),
+                    format: FigureFormat::Svg,
+                    data: b"svg:loss".to_vec(),
+                },
+                Figure {
+                    number: 2,
+                    label: String::new(),
+                    format: FigureFormat::Svg,
+                    data: b"svg:".to_vec(),
+                },
+            ]
+        );
+        let open = module
+            .action(|py, module| {
+                let modules = py.import("sys")?.getattr("modules")?;
+                modules.del_item("matplotlib.pyplot")?;
+                module
+                    .getattr("pyplot")?
+                    .call_method0("get_fignums")?
+                    .extract::<Vec<i64>>()
+            })
+            .unwrap();
+        assert!(open.is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::call::lookup;
+use crate::{PyRunnerError, PythonModule};
+use pyo3::call::PyCallArgs;
+use pyo3::prelude::*;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Function of a module, looked up once and called on the worker thread
+///
+/// `Args` is a tuple of the positional arguments, `Ret` the extracted return value.
+///```rs
+/// let add = module.get_function::<(i64, i64), i64>("add").unwrap();
+/// assert_eq!(add.call((1, 2)).unwrap(), 3);
+/// ```
+pub struct PyFunction<'a, Args, Ret> {
+    module: &'a PythonModule,
+    function: Option<Arc<Py<PyAny>>>,
+    types: PhantomData<fn(Args) -> Ret>,
+}
+
+impl<Args, Ret> PyFunction<'_, Args, Ret>
+where
+    Args: for<'py> PyCallArgs<'py> + Send + 'static,
+    Ret: for<'py> FromPyObject<'py> + Send + 'static,
+{
+    pub fn call(&self, args: Args) -> Result<Ret, PyRunnerError> {
+        let function = self.function.clone().expect("function is set until drop");
+        self.module
+            .action(move |py, _| function.bind(*py).call1(args)?.extract())
+    }
+}
+
+impl<Args, Ret> Drop for PyFunction<'_, Args, Ret> {
+    fn drop(&mut self) {
+        // the reference belongs to the worker's interpreter, release it there
+        let function = self.function.take();
+        if self.module.is_alive() {
+            let _ = self.module.push(Some(Box::new(move |_, _| drop(function))));
+        } else {
+            std::mem::forget(function);
+        }
+    }
+}
+
+impl PythonModule {
+    /// Looks up the function `name`, which may be a dotted path like `"calc.add"`
+    ///
+    /// Fails with a `TypeError` if the attribute isn't callable.
+    pub fn get_function<Args, Ret>(
+        &self,
+        name: &str,
+    ) -> Result<PyFunction<'_, Args, Ret>, PyRunnerError> {
+        let name = name.to_string();
+        let function = self.action(move |_, module| {
+            let function = lookup(module, &name)?;
+            if !function.is_callable() {
+                return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                    "{name} is not callable"
+                )));
+            }
+            Ok(function.unbind())
+        })?;
+        Ok(PyFunction {
+            module: self,
+            function: Some(Arc::new(function)),
+            types: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_get_function() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let add = module.get_function::<(i64, i64), i64>("calc.add").unwrap();
+        assert_eq!(add.call((1, 2)).unwrap(), 3);
+        assert_eq!(add.call((3, 4)).unwrap(), 7);
+        assert!(add.call((1, i64::MAX)).is_err());
+
+        assert!(module.get_function::<(), ()>("missing").is_err());
+        assert!(module.get_function::<(), ()>("__name__").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::worker::Control;
+use crate::{PyRunnerError, PythonModule, Task};
+use crossbeam::channel::Sender;
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+
+/// Objects kept alive by [`PyHandle`]s, only touched on the worker thread
+#[derive(Default)]
+pub(crate) struct Handles {
+    worker: OnceLock<ThreadId>,
+    next_id: AtomicU64,
+    objects: Mutex<HashMap<u64, Py<PyAny>>>,
+}
+
+impl Handles {
+    /// Remembers the current thread as the worker
+    pub(crate) fn attach(&self) {
+        let _ = self.worker.set(thread::current().id());
+    }
+
+    /// Drops every object, the worker calls this before it exits
+    pub(crate) fn clear(&self) {
+        let objects = std::mem::take(&mut *self.objects.lock().unwrap());
+        drop(objects);
+    }
+
+    fn insert(&self, object: Py<PyAny>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.objects.lock().unwrap().insert(id, object);
+        id
+    }
+}
+
+/// Python object kept alive on the worker thread between actions
+///
+/// Clones refer to the same object, it is released once every clone was dropped.
+///```rs
+/// let client = module.create_handle(|_, module| Ok(module.getattr("Client")?.call0()?.unbind())).unwrap();
+/// let handle = client.clone();
+/// module.action(move |py, _| handle.bind(*py)?.call_method0("connect").map(|_| ())).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct PyHandle {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    id: u64,
+    control: Arc<Control>,
+    task_sender: Sender<Option<Task>>,
+}
+
+impl PyHandle {
+    /// The object, only available inside actions of the module that created the handle
+    pub fn bind<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let handles = &self.inner.control.handles;
+        if handles.worker.get() != Some(&thread::current().id()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Handle used outside of its module's worker thread",
+            ));
+        }
+        match handles.objects.lock().unwrap().get(&self.inner.id) {
+            Some(object) => Ok(object.bind(py).clone()),
+            None => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Handle was released",
+            )),
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let id = self.id;
+        let control = self.control.clone();
+        // a task queued from the worker itself could wait forever for room in a full queue
+        if control.handles.worker.get() == Some(&thread::current().id()) {
+            let object = control.handles.objects.lock().unwrap().remove(&id);
+            drop(object);
+            return;
+        }
+        // the object is released on the worker, once it exited there is nothing left to do
+        let _ = self.task_sender.send(Some(Box::new(move |_, _| {
+            let object = control.handles.objects.lock().unwrap().remove(&id);
+            drop(object);
+        })));
+    }
+}
+
+impl PythonModule {
+    /// Runs action and keeps the returned object alive for later actions
+    pub fn create_handle(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> + Send + 'static,
+    ) -> Result<PyHandle, PyRunnerError> {
+        let control = self.control.clone();
+        let id = self.action(move |py, module| Ok(control.handles.insert(call(py, module)?)))?;
+        Ok(PyHandle {
+            inner: Arc::new(Inner {
+                id,
+                control: self.control.clone(),
+                task_sender: self.task_sender.clone(),
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_handle() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let handle = module
+            .create_handle(|py, _| Ok(py.eval(c"[]", None, None)?.unbind()))
+            .unwrap();
+        for i in 0..3 {
+            let handle = handle.clone();
+            module
+                .action(move |py, _| handle.bind(*py)?.call_method1("append", (i,)).map(|_| ()))
+                .unwrap();
+        }
+        let items = {
+            let handle = handle.clone();
+            module
+                .action(move |py, _| handle.bind(*py)?.extract::<Vec<i64>>())
+                .unwrap()
+        };
+        assert_eq!(items, vec![0, 1, 2]);
+
+        let other = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let moved = handle.clone();
+        assert!(
+            other
+                .action(move |py, _| moved.bind(*py).map(|_| ()))
+                .is_err()
+        );
+
+        let released = handle.inner.id;
+        let control = module.control.clone();
+        drop(handle);
+        let alive = module
+            .action(move |_, _| {
+                Ok(control
+                    .handles
+                    .objects
+                    .lock()
+                    .unwrap()
+                    .contains_key(&released))
+            })
+            .unwrap();
+        assert!(!alive);
+    }
+}
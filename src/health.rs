@@ -0,0 +1,75 @@
+use crate::{ExitReason, PyRunnerError, PythonModule, TaskId};
+use std::time::{Duration, Instant};
+
+/// What the worker of a module is doing, see [`PythonModule::status`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Waiting for tasks
+    Idle,
+    /// Running a task, the id is `None` for internal tasks like releasing a
+    /// [`PyHandle`](crate::PyHandle)
+    Busy(Option<TaskId>),
+    /// The worker exited, the reason is `None` if it wasn't known yet
+    Dead(Option<ExitReason>),
+}
+
+impl PythonModule {
+    /// Queues a no-op task and returns how long it took to come back
+    ///
+    /// The round trip includes the wait behind queued tasks, so it tells whether the module
+    /// is able to serve actions right now. Fails with a `TimeoutError` after `timeout` and
+    /// with [`ErrorKind::WorkerDead`](crate::ErrorKind::WorkerDead) once the worker exited.
+    ///```rs
+    /// match module.ping(Duration::from_secs(1)) {
+    ///     Ok(latency) => println!("ready, {latency:?}"),
+    ///     Err(e) => println!("not ready: {e}"),
+    /// }
+    /// ```
+    pub fn ping(&self, timeout: Duration) -> Result<Duration, PyRunnerError> {
+        let started = Instant::now();
+        self.action_timeout(|_, _| Ok(()), timeout)?;
+        Ok(started.elapsed())
+    }
+
+    /// Whether the worker is idle, busy or dead, without queueing anything
+    pub fn status(&self) -> Status {
+        if !self.is_alive() {
+            return Status::Dead(self.exit_reason());
+        }
+        match self.control.is_running() {
+            true => Status::Busy(self.control.backlog.running()),
+            false => Status::Idle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorKind, ShutdownMode};
+    use pyo3::prelude::*;
+    use std::thread;
+
+    #[test]
+    fn test_health() {
+        let module = PythonModule::from_source("health_sleeper", "import time\n").unwrap();
+        assert!(module.ping(Duration::from_secs(5)).unwrap() < Duration::from_secs(5));
+        // the reply arrives right before the worker marks itself idle
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(module.status(), Status::Idle);
+
+        let handle = module
+            .spawn(|py, _| py.import("time")?.call_method1("sleep", (0.3,)).map(|_| ()))
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(module.status(), Status::Busy(Some(handle.id())));
+        let err = module.ping(Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Timeout);
+        handle.join().unwrap();
+
+        assert!(module.shutdown(ShutdownMode::DrainQueue));
+        assert_eq!(module.status(), Status::Dead(Some(ExitReason::Shutdown)));
+        let err = module.ping(Duration::from_secs(1)).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::WorkerDead { .. }));
+    }
+}
@@ -0,0 +1,73 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyModule};
+use std::sync::Mutex;
+
+/// Helpers loaded so far, shared by every module of the process
+static HELPERS: Mutex<Vec<(&'static str, Py<PyModule>)>> = Mutex::new(Vec::new());
+
+/// Runs `source` as the crate's helper module `name`, once per process
+///
+/// The helper isn't registered in `sys.modules`, so the Python code the crate runs can
+/// neither import it nor replace it by its own module of that name.
+pub(crate) fn load_helper<'py>(
+    py: Python<'py>,
+    name: &'static str,
+    source: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    if let Some(helper) = find(py, name) {
+        return Ok(helper);
+    }
+    // the lock isn't held while the source runs, which may let other threads take the GIL
+    let helper = PyModule::new(py, &format!("_py_runner_{name}"))?;
+    helper.setattr("__file__", format!("py_runner_{name}.py"))?;
+    let builtins = py.import("builtins")?;
+    let code =
+        builtins.call_method1("compile", (source, format!("py_runner_{name}.py"), "exec"))?;
+    let globals = helper.dict();
+    globals.set_item("__builtins__", &builtins)?;
+    builtins.call_method1("exec", (code, &globals, None::<Bound<'_, PyDict>>))?;
+    let mut helpers = HELPERS.lock().unwrap();
+    // a thread loading the same helper meanwhile won, its copy may already be in use
+    if let Some((_, helper)) = helpers.iter().find(|(loaded, _)| *loaded == name) {
+        return Ok(helper.bind(py).clone().into_any());
+    }
+    helpers.push((name, helper.clone().unbind()));
+    Ok(helper.into_any())
+}
+
+/// The helper `name` if it was loaded before
+pub(crate) fn find<'py>(py: Python<'py>, name: &str) -> Option<Bound<'py, PyAny>> {
+    HELPERS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(loaded, _)| *loaded == name)
+        .map(|(_, helper)| helper.bind(py).clone().into_any())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_helper() {
+        Python::with_gil(|py| {
+            let source = "import json\ncalls = 0\ndef dump(value):\n    global calls\n    calls += 1\n    return json.dumps(value)\n";
+            let helper = load_helper(py, "test", source).unwrap();
+            assert_eq!(
+                helper
+                    .call_method1("dump", ([1],))
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                "[1]"
+            );
+            // loaded once, the state survives
+            let again = load_helper(py, "test", source).unwrap();
+            assert!(again.is(&helper));
+            assert_eq!(again.getattr("calls").unwrap().extract::<i64>().unwrap(), 1);
+            let modules = py.import("sys").unwrap().getattr("modules").unwrap();
+            assert!(!modules.contains("_py_runner_test").unwrap());
+        });
+    }
+}
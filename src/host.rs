@@ -0,0 +1,199 @@
+use crate::event::{Event, Events};
+use crate::helper::{find, load_helper};
+use pyo3::IntoPyObjectExt;
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyModule, PyTuple};
+use std::path::Path;
+use std::sync::Arc;
+
+const HOST: &str = include_str!("host/host.py");
+
+pub(crate) type Callback =
+    Arc<dyn Fn(&Bound<'_, PyTuple>) -> PyResult<Py<PyAny>> + Send + Sync + 'static>;
+
+/// Rust closure that Python code can call, see
+/// [`PythonModuleBuilder::expose`](crate::PythonModuleBuilder::expose)
+///
+/// Implemented for closures taking up to six arguments that can be extracted from Python and
+/// returning `PyResult<R>` with `R` convertible to Python.
+pub trait HostFunction<Args>: Send + Sync + 'static {
+    #[doc(hidden)]
+    fn into_callback(self) -> Callback;
+}
+
+macro_rules! host_function {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg,)*> HostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> PyResult<R> + Send + Sync + 'static,
+            R: for<'py> IntoPyObject<'py>,
+            $($arg: for<'py> FromPyObject<'py>,)*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_callback(self) -> Callback {
+                Arc::new(move |args| {
+                    const ARITY: usize = <[&str]>::len(&[$(stringify!($arg)),*]);
+                    if args.len() != ARITY {
+                        return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                            "expected {ARITY} arguments, got {}",
+                            args.len()
+                        )));
+                    }
+                    let mut items = args.iter();
+                    $(let $arg = items.next().expect("arity was checked").extract::<$arg>()?;)*
+                    self($($arg),*)?.into_py_any(args.py())
+                })
+            }
+        }
+    };
+}
+
+host_function!();
+host_function!(A);
+host_function!(A, B);
+host_function!(A, B, C);
+host_function!(A, B, C, D);
+host_function!(A, B, C, D, E);
+host_function!(A, B, C, D, E, G);
+
+/// Builds the `host` module holding the exposed functions and `emit` for the module
+/// `module_name`
+///
+/// Runs on the worker thread right before the module is executed. `import host` resolves to
+/// it in the module, its submodules and the files below `root`, see `host.py`; it isn't
+/// registered in `sys.modules`, so modules don't see each other's host module.
+pub(crate) fn install(
+    py: Python<'_>,
+    module_name: &str,
+    root: Option<&Path>,
+    functions: &[(String, Callback)],
+    events: Option<Arc<Events>>,
+) -> PyResult<()> {
+    let emit = events.map(|events| {
+        let emit = move |event: Event| {
+            events.emit(event);
+            Ok(())
+        };
+        ("emit".to_string(), emit.into_callback())
+    });
+    let host = PyModule::new(py, "host")?;
+    for (name, callback) in functions.iter().chain(&emit) {
+        let callback = callback.clone();
+        let function = PyCFunction::new_closure(
+            py,
+            None,
+            None,
+            move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| callback(args),
+        )?;
+        host.add(name.as_str(), function)?;
+    }
+    load_helper(py, "host", HOST)?
+        .call_method1("register", (module_name, root.map(Path::as_os_str), host))?;
+    Ok(())
+}
+
+/// Drops the host module of `module`, called once its worker let go of it
+pub(crate) fn forget(py: Python<'_>, module: &Bound<'_, PyAny>) -> PyResult<()> {
+    // nothing was registered before the helper was loaded
+    if let Some(helper) = find(py, "host") {
+        helper.call_method1("forget", (module,))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PythonModuleBuilder;
+    use pyo3::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_expose() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import host\n",
+                "host.log_event('imported')\n",
+                "def lookup(key):\n",
+                "    return host.fetch(key, 2) + 1\n",
+            ),
+        )
+        .unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        let module = PythonModuleBuilder::new_project(dir.join("main.py"))
+            .expose("log_event", move |event: String| {
+                log.lock().unwrap().push(event);
+                Ok(())
+            })
+            .expose("fetch", |key: String, times: usize| Ok(key.len() * times))
+            .build()
+            .unwrap();
+        assert_eq!(*events.lock().unwrap(), vec!["imported".to_string()]);
+
+        let value = module
+            .action(|_, module| module.call_method1("lookup", ("abc",))?.extract::<usize>())
+            .unwrap();
+        assert_eq!(value, 7);
+
+        let err = module
+            .action(|_, module| module.call_method1("lookup", (1,))?.extract::<usize>())
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "TypeError");
+    }
+
+    #[test]
+    fn test_host_per_module() {
+        use crate::Event;
+
+        let build = || {
+            let temp = tempfile::tempdir().unwrap();
+            std::fs::write(
+                temp.path().join("main.py"),
+                concat!(
+                    "import host\n",
+                    "def ping(value):\n",
+                    "    host.emit(value)\n",
+                    "def lazy(value):\n",
+                    "    from host import emit\n",
+                    "    emit(value)\n",
+                ),
+            )
+            .unwrap();
+            let module = PythonModuleBuilder::new_project(temp.path().join("main.py"))
+                .build()
+                .unwrap();
+            (temp, module)
+        };
+        let (_first_dir, first) = build();
+        let first_events = first.subscribe();
+        let (_second_dir, second) = build();
+        let second_events = second.subscribe();
+
+        first
+            .action(|_, module| {
+                module.call_method1("ping", (1,))?;
+                module.call_method1("lazy", (2,)).map(|_| ())
+            })
+            .unwrap();
+        assert_eq!(
+            first_events.try_iter().collect::<Vec<_>>(),
+            [Event::Int(1), Event::Int(2)]
+        );
+        assert!(second_events.try_recv().is_err());
+
+        second
+            .action(|_, module| module.call_method1("lazy", (3,)).map(|_| ()))
+            .unwrap();
+        assert_eq!(second_events.try_recv().unwrap(), Event::Int(3));
+        assert!(first_events.try_recv().is_err());
+
+        let global = first
+            .action(|py, _| py.import("sys")?.getattr("modules")?.contains("host"))
+            .unwrap();
+        assert!(!global);
+    }
+}
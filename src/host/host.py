@@ -0,0 +1,50 @@
+# Resolves `import host` to the host module of the importing module, found by the module's
+# name or the directory of the importing file. Other code imports a `host` package as usual.
+import builtins
+import os
+
+# (module name, directory or None, host module) of every module with a host module
+hosts = []
+original_import = builtins.__import__
+installed = False
+
+
+def host_import(name, globals=None, locals=None, fromlist=(), level=0):
+    if name == "host" and level == 0 and globals is not None:
+        host = lookup(globals.get("__name__"), globals.get("__file__"))
+        if host is not None:
+            return host
+    return original_import(name, globals, locals, fromlist, level)
+
+
+def lookup(name, file):
+    """Host module of the module `name` or one of its submodules, else of the innermost
+    registered directory holding `file`"""
+    if isinstance(name, str):
+        for registered, _, host in reversed(hosts):
+            if name == registered or name.startswith(registered + "."):
+                return host
+    if isinstance(file, str):
+        file = os.path.abspath(file)
+        beneath = [
+            (len(root), host)
+            for _, root, host in reversed(hosts)
+            if root is not None and file.startswith(root + os.sep)
+        ]
+        if beneath:
+            return max(beneath, key=lambda entry: entry[0])[1]
+    return None
+
+
+def register(name, root, host):
+    global installed
+    if not installed:
+        builtins.__import__ = host_import
+        installed = True
+    hosts.append((name, os.path.abspath(root) if root is not None else None, host))
+
+
+def forget(module):
+    """Drops the host module of `module`"""
+    name = getattr(module, "__name__", None)
+    hosts[:] = [entry for entry in hosts if entry[0] != name]
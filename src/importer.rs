@@ -0,0 +1,125 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Supplies the source of modules that aren't on the file system, e.g. from a database, an
+/// HTTP endpoint or an encrypted bundle, see
+/// [`PythonModuleBuilder::source_provider`](crate::PythonModuleBuilder::source_provider)
+///
+/// Implemented for closures `Fn(&str) -> Option<String>` and for maps from module names to
+/// sources.
+pub trait SourceProvider: Send + Sync + 'static {
+    /// Source of the module with the dotted `name`, `None` if the provider doesn't have it
+    fn get_source(&self, name: &str) -> Option<String>;
+
+    /// Whether `name` is a package, whose submodules are looked up with the provider too
+    fn is_package(&self, _name: &str) -> bool {
+        false
+    }
+}
+
+impl<F> SourceProvider for F
+where
+    F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+{
+    fn get_source(&self, name: &str) -> Option<String> {
+        self(name)
+    }
+}
+
+/// A name is a package if the map holds one of its submodules
+impl SourceProvider for HashMap<String, String> {
+    fn get_source(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+
+    fn is_package(&self, name: &str) -> bool {
+        self.keys().any(|key| {
+            key.strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+}
+
+/// Puts a finder asking `providers` in order in front of `sys.meta_path`
+///
+/// Runs on the worker thread right before the module is executed.
+pub(crate) fn install(py: Python<'_>, providers: &[Arc<dyn SourceProvider>]) -> PyResult<()> {
+    let finder = crate::builder::loader(py)?.getattr("ProviderFinder")?;
+    let meta_path = py.import("sys")?.getattr("meta_path")?;
+    for (i, provider) in providers.iter().enumerate() {
+        let provider = provider.clone();
+        let lookup = PyCFunction::new_closure(
+            py,
+            Some(c"lookup"),
+            None,
+            move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                let name = args.get_item(0)?.extract::<String>()?;
+                // slow providers shouldn't block other threads
+                let found = args.py().allow_threads(|| {
+                    let source = provider.get_source(&name)?;
+                    Some((source, provider.is_package(&name)))
+                });
+                PyResult::Ok(found)
+            },
+        )?;
+        meta_path.call_method1("insert", (i, finder.call1((lookup,))?))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModuleBuilder;
+
+    #[test]
+    fn test_source_provider() {
+        let sources = HashMap::from([
+            (
+                "remote_pkg".to_string(),
+                "from .util import double\n".to_string(),
+            ),
+            (
+                "remote_pkg.util".to_string(),
+                "def double(x):\n    return x * 2\ndef fail():\n    raise ValueError('remote')\n"
+                    .to_string(),
+            ),
+        ]);
+        let main = "import remote_pkg, remote_pkg.util, remote_config\ndef run(x):\n    return remote_pkg.double(x) + remote_config.OFFSET\n";
+        let module = PythonModuleBuilder::from_source("provider_main", main)
+            .source_provider(sources)
+            .source_provider(|name: &str| {
+                (name == "remote_config").then(|| "OFFSET = 1\n".to_string())
+            })
+            .build()
+            .unwrap();
+        let result = module
+            .action(|_, module| module.call_method1("run", (20,))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(result, 41);
+
+        let err = module
+            .action(|py, _| {
+                py.import("remote_pkg.util")?
+                    .call_method0("fail")
+                    .map(|_| ())
+            })
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "ValueError");
+        assert_eq!(err.file(), Some("<provider>/remote_pkg/util.py"));
+        assert!(
+            err.traceback()
+                .unwrap()
+                .contains("raise ValueError('remote')")
+        );
+
+        let err = PythonModuleBuilder::from_source("provider_missing", "import remote_missing\n")
+            .source_provider(HashMap::new())
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.exception_type(), "ModuleNotFoundError");
+    }
+}
@@ -0,0 +1,103 @@
+use crate::call::lookup;
+use crate::{PyHandle, PyRunnerError, PythonModule};
+use pyo3::call::PyCallArgs;
+use pyo3::prelude::*;
+
+/// Instance of a Python class, every access runs on the worker thread
+///```rs
+/// let counter = module.instantiate("Counter", (10,)).unwrap();
+/// let value: i64 = counter.call_method("increment", (5,)).unwrap();
+/// ```
+pub struct PyInstance<'a> {
+    module: &'a PythonModule,
+    handle: PyHandle,
+}
+
+impl PyInstance<'_> {
+    /// Calls the method `name` and extracts its result
+    pub fn call_method<T>(
+        &self,
+        name: &str,
+        args: impl for<'py> PyCallArgs<'py> + Send + 'static,
+    ) -> Result<T, PyRunnerError>
+    where
+        T: for<'py> FromPyObject<'py> + Send + 'static,
+    {
+        let name = name.to_string();
+        let handle = self.handle.clone();
+        self.module.action(move |py, _| {
+            handle
+                .bind(*py)?
+                .call_method1(name.as_str(), args)?
+                .extract()
+        })
+    }
+
+    pub fn getattr<T>(&self, name: &str) -> Result<T, PyRunnerError>
+    where
+        T: for<'py> FromPyObject<'py> + Send + 'static,
+    {
+        let name = name.to_string();
+        let handle = self.handle.clone();
+        self.module
+            .action(move |py, _| handle.bind(*py)?.getattr(name.as_str())?.extract())
+    }
+
+    pub fn setattr<V>(&self, name: &str, value: V) -> Result<(), PyRunnerError>
+    where
+        V: for<'py> IntoPyObject<'py> + Send + 'static,
+    {
+        let name = name.to_string();
+        let handle = self.handle.clone();
+        self.module
+            .action(move |py, _| handle.bind(*py)?.setattr(name.as_str(), value))
+    }
+
+    /// Handle of the instance, to pass it to other actions
+    pub fn handle(&self) -> &PyHandle {
+        &self.handle
+    }
+}
+
+impl PythonModule {
+    /// Creates an instance of the class `name`, which may be a dotted path
+    pub fn instantiate(
+        &self,
+        name: &str,
+        args: impl for<'py> PyCallArgs<'py> + Send + 'static,
+    ) -> Result<PyInstance<'_>, PyRunnerError> {
+        let name = name.to_string();
+        let handle =
+            self.create_handle(move |_, module| Ok(lookup(module, &name)?.call1(args)?.unbind()))?;
+        Ok(PyInstance {
+            module: self,
+            handle,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_instantiate() {
+        let module = PythonModule::new_project(Path::new("./my-project/main.py").into()).unwrap();
+        let counter = module.instantiate("Counter", (10,)).unwrap();
+        assert_eq!(counter.call_method::<i64>("increment", ()).unwrap(), 11);
+        assert_eq!(counter.call_method::<i64>("increment", (5,)).unwrap(), 16);
+
+        counter.setattr("value", 1).unwrap();
+        assert_eq!(counter.getattr::<i64>("value").unwrap(), 1);
+
+        let handle = counter.handle().clone();
+        let value = module
+            .action(move |py, _| handle.bind(*py)?.getattr("value")?.extract::<i64>())
+            .unwrap();
+        assert_eq!(value, 1);
+
+        assert!(counter.getattr::<i64>("missing").is_err());
+        assert!(module.instantiate("Missing", ()).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::PyRunnerError;
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+pyo3::create_exception!(
+    py_runner,
+    IntegrityError,
+    pyo3::exceptions::PyException,
+    "The module's files didn't match the expected digest or signature"
+);
+
+/// Expected digest and signature of a module, see
+/// [`PythonModuleBuilder::verify_sha256`](crate::PythonModuleBuilder::verify_sha256)
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Integrity {
+    pub sha256: Option<String>,
+    pub signature: Option<([u8; 32], [u8; 64])>,
+}
+
+impl Integrity {
+    pub(crate) fn is_required(&self) -> bool {
+        self.sha256.is_some() || self.signature.is_some()
+    }
+
+    /// Fails with [`ErrorKind::Integrity`](crate::ErrorKind::Integrity) unless the module at
+    /// `path`, or `source`, matches
+    pub(crate) fn verify(&self, path: &Path, source: Option<&[u8]>) -> Result<(), PyRunnerError> {
+        let digest = digest(path, source)?;
+        if let Some(expected) = &self.sha256
+            && !hex(&digest).eq_ignore_ascii_case(expected)
+        {
+            return Err(PyRunnerError::integrity(format!(
+                "SHA-256 of {} is {}, expected {expected}",
+                path.display(),
+                hex(&digest)
+            )));
+        }
+        if let Some((public_key, signature)) = &self.signature {
+            VerifyingKey::from_bytes(public_key)
+                .and_then(|key| key.verify_strict(&digest, &Signature::from_bytes(signature)))
+                .map_err(|_| {
+                    PyRunnerError::integrity(format!(
+                        "Signature of {} doesn't match its digest",
+                        path.display()
+                    ))
+                })?;
+        }
+        Ok(())
+    }
+}
+
+/// Hex SHA-256 of the module at `path`, or of `source`
+pub(crate) fn hex_digest(path: &Path, source: Option<&[u8]>) -> Result<String, PyRunnerError> {
+    Ok(hex(&digest(path, source)?))
+}
+
+/// SHA-256 of `source` or the file at `path`, for directories of every file's relative path
+/// and digest in path order, skipping hidden entries and `__pycache__`
+fn digest(path: &Path, source: Option<&[u8]>) -> Result<[u8; 32], PyRunnerError> {
+    if let Some(source) = source {
+        return Ok(Sha256::digest(source).into());
+    }
+    if !path.is_dir() {
+        return file_digest(path);
+    }
+    let mut files = Vec::new();
+    collect(path, "", &mut files)?;
+    files.sort();
+    let mut tree = Sha256::new();
+    for (relative, file) in files {
+        tree.update(relative.as_bytes());
+        tree.update([0]);
+        tree.update(file_digest(&file)?);
+    }
+    Ok(tree.finalize().into())
+}
+
+/// Adds the files below `dir` with their path relative to the digested directory
+fn collect(
+    dir: &Path,
+    prefix: &str,
+    files: &mut Vec<(String, std::path::PathBuf)>,
+) -> Result<(), PyRunnerError> {
+    for entry in fs::read_dir(dir).map_err(|e| io_error(dir, e))? {
+        let entry = entry.map_err(|e| io_error(dir, e))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let relative = format!("{prefix}{name}");
+        let file_type = entry.file_type().map_err(|e| io_error(&path, e))?;
+        if file_type.is_dir() {
+            if name != "__pycache__" {
+                collect(&path, &format!("{relative}/"), files)?;
+            }
+        } else if !path.is_dir() {
+            // like `os.walk`, symbolic links to directories aren't followed
+            files.push((relative, path));
+        }
+    }
+    Ok(())
+}
+
+fn file_digest(path: &Path) -> Result<[u8; 32], PyRunnerError> {
+    let content = fs::read(path).map_err(|e| io_error(path, e))?;
+    Ok(Sha256::digest(content).into())
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> PyRunnerError {
+    PyRunnerError::integrity(format!("Can't read {}: {err}", path.display()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ErrorKind, PythonModuleBuilder};
+    use pyo3::prelude::*;
+
+    // key pair of RFC 8032 test 1, the signature covers the digest of `VALUE = 1`
+    const PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const DIGEST: &str = "4f47b6ea18c40692a155ed9d960925a0950d4323d09b9c16747cc5420428946a";
+    const SIGNATURE: &str = "37c265847ecb71f0b53b16f822c3f2254cdb687c2f9af2021c9b9af2cb749772b432b220ca943a40b01c4ad64ba246fbc12502406a413f805cfbb79ba0433f0e";
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        std::array::from_fn(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap())
+    }
+
+    #[test]
+    fn test_integrity() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let package = dir.join("signed");
+        std::fs::create_dir_all(package.join("__pycache__")).unwrap();
+        std::fs::write(package.join("__init__.py"), "VALUE = 1\n").unwrap();
+        std::fs::write(package.join("__pycache__/stale.pyc"), "ignored").unwrap();
+
+        let builder = || PythonModuleBuilder::new_module(&package);
+        assert_eq!(builder().digest().unwrap(), DIGEST);
+        let module = builder()
+            .verify_sha256(DIGEST.to_uppercase())
+            .verify_signature(bytes(PUBLIC_KEY), bytes(SIGNATURE))
+            .build()
+            .unwrap();
+        let value = module
+            .action(|_, module| module.getattr("VALUE")?.extract::<i64>())
+            .unwrap();
+        assert_eq!(value, 1);
+
+        std::fs::write(package.join("__init__.py"), "VALUE = 2\n").unwrap();
+        for builder in [
+            builder().verify_sha256(DIGEST),
+            builder().verify_signature(bytes(PUBLIC_KEY), bytes(SIGNATURE)),
+        ] {
+            let err = builder.build().err().unwrap();
+            assert_eq!(err.kind(), &ErrorKind::Integrity);
+            assert_eq!(err.exception_type(), "py_runner.IntegrityError");
+        }
+
+        // added files change the digest as well
+        std::fs::write(package.join("__init__.py"), "VALUE = 1\n").unwrap();
+        std::fs::write(package.join("extra.py"), "").unwrap();
+        let err = builder().verify_sha256(DIGEST).build().err().unwrap();
+        assert_eq!(err.kind(), &ErrorKind::Integrity);
+        assert!(err.is_instance_of::<super::IntegrityError>());
+
+        // a project only covers its own file, not the files next to it
+        std::fs::write(dir.join("main.py"), "VALUE = 1\n").unwrap();
+        let project = || PythonModuleBuilder::new_project(dir.join("main.py"));
+        let digest = project().digest().unwrap();
+        std::fs::write(dir.join("other.py"), "").unwrap();
+        assert_eq!(project().digest().unwrap(), digest);
+        assert_ne!(digest, DIGEST);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::PyRunnerError;
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::path::Path;
+
+/// Functions a module has to define, checked when it is loaded, see
+/// [`PythonModuleBuilder::interface`](crate::PythonModuleBuilder::interface)
+///```rs
+/// let interface = Interface::new().func("handle_event", 2).func("init", 0);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Interface {
+    functions: Vec<(String, usize)>,
+}
+
+/// Way a module doesn't match its [`Interface`], see
+/// [`ErrorKind::InterfaceMismatch`](crate::ErrorKind::InterfaceMismatch)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The module has no attribute with the name
+    Missing(String),
+    /// The attribute exists but can't be called
+    NotCallable(String),
+    /// The function can't be called with `expected` positional arguments
+    Arity { name: String, expected: usize },
+}
+
+impl Interface {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires a callable `name` that accepts `arity` positional arguments
+    pub fn func(mut self, name: impl Into<String>, arity: usize) -> Self {
+        self.functions.push((name.into(), arity));
+        self
+    }
+
+    /// Requires the public functions of the stub file `stub`, each with as many positional
+    /// arguments as it has parameters without default
+    ///
+    /// The stub is read like by [`generate_bindings`](crate::generate_bindings).
+    pub fn from_stub(stub: impl AsRef<Path>) -> Result<Self, PyRunnerError> {
+        let stub = stub.as_ref();
+        let source = std::fs::read_to_string(stub).map_err(PyErr::from)?;
+        let file_name = stub.to_string_lossy();
+        let (_, functions) = crate::codegen::read_stub(&source, &file_name)?;
+        let functions = functions
+            .into_iter()
+            .map(|(name, _, parameters, _)| {
+                let arity = parameters
+                    .iter()
+                    .filter(|(_, keyword, _, optional)| !keyword && !optional)
+                    .count();
+                (name, arity)
+            })
+            .collect();
+        Ok(Self { functions })
+    }
+
+    /// Raises a `py_runner.InterfaceMismatch` listing every mismatch of `module`
+    pub(crate) fn check(&self, py: Python<'_>, module: &Bound<'_, PyAny>) -> PyResult<()> {
+        let signature = py.import("inspect")?.getattr("signature")?;
+        let mut mismatches = Vec::new();
+        for (name, arity) in &self.functions {
+            let Some(value) = module.getattr_opt(name.as_str())? else {
+                mismatches.push(("missing", name, arity));
+                continue;
+            };
+            if !value.is_callable() {
+                mismatches.push(("not_callable", name, arity));
+                continue;
+            }
+            // builtins without a signature are assumed to match
+            let Ok(signature) = signature.call1((&value,)) else {
+                continue;
+            };
+            let args = (0..*arity).map(|_| py.None());
+            if signature
+                .call_method1("bind", PyTuple::new(py, args)?)
+                .is_err()
+            {
+                mismatches.push(("arity", name, arity));
+            }
+        }
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let message = mismatches
+            .iter()
+            .map(|(problem, name, arity)| match *problem {
+                "missing" => format!("{name} is missing"),
+                "not_callable" => format!("{name} is not callable"),
+                _ => format!("{name} does not take {arity} arguments"),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let exception = crate::builder::loader(py)?
+            .getattr("InterfaceMismatch")?
+            .call1((
+                format!("Module does not match the interface: {message}"),
+                mismatches,
+            ))?;
+        Err(PyErr::from_value(exception))
+    }
+}
+
+/// Reads the mismatches back from a `py_runner.InterfaceMismatch`
+pub(crate) fn mismatches(value: &Bound<'_, PyAny>) -> Option<Vec<Mismatch>> {
+    let mismatches = value
+        .getattr("mismatches")
+        .ok()?
+        .extract::<Vec<(String, String, usize)>>()
+        .ok()?;
+    let mismatches = mismatches
+        .into_iter()
+        .map(|(problem, name, expected)| match problem.as_str() {
+            "missing" => Mismatch::Missing(name),
+            "not_callable" => Mismatch::NotCallable(name),
+            _ => Mismatch::Arity { name, expected },
+        })
+        .collect();
+    Some(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorKind, PythonModuleBuilder};
+
+    const PLUGIN: &str = "LIMIT = 3\ndef init():\n    pass\ndef handle_event(kind, payload=None):\n    pass\ndef flush(*events):\n    pass\ndef close(force):\n    pass\n";
+
+    #[test]
+    fn test_interface() {
+        let interface = Interface::new()
+            .func("init", 0)
+            .func("handle_event", 2)
+            .func("handle_event", 1)
+            .func("flush", 5);
+        let module = PythonModuleBuilder::from_source("interface_match", PLUGIN)
+            .interface(interface)
+            .build()
+            .unwrap();
+        assert!(module.is_alive());
+
+        let err = PythonModuleBuilder::from_source("interface_mismatch", PLUGIN)
+            .interface(
+                Interface::new()
+                    .func("init", 1)
+                    .func("shutdown", 0)
+                    .func("LIMIT", 0)
+                    .func("close", 1),
+            )
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::InterfaceMismatch {
+                mismatches: vec![
+                    Mismatch::Arity {
+                        name: "init".into(),
+                        expected: 1
+                    },
+                    Mismatch::Missing("shutdown".into()),
+                    Mismatch::NotCallable("LIMIT".into()),
+                ]
+            }
+        );
+        assert_eq!(err.exception_type(), "py_runner.InterfaceMismatch");
+        assert!(err.message().contains("shutdown is missing"));
+    }
+
+    #[test]
+    fn test_from_stub() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let stub = dir.join("plugin.pyi");
+        std::fs::write(
+            &stub,
+            "def init() -> None: ...\ndef handle_event(kind: str, payload: dict = ..., *, strict: bool) -> None: ...\ndef _private(x): ...\n",
+        )
+        .unwrap();
+        let interface = Interface::from_stub(&stub).unwrap();
+        assert_eq!(
+            interface,
+            Interface::new().func("init", 0).func("handle_event", 1)
+        );
+        assert!(Interface::from_stub(dir.join("missing.pyi")).is_err());
+    }
+}
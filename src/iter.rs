@@ -0,0 +1,115 @@
+use crate::call::lookup;
+use crate::{PyHandle, PyRunnerError, PythonModule};
+use pyo3::call::PyCallArgs;
+use pyo3::prelude::*;
+use pyo3::types::PyIterator;
+use std::marker::PhantomData;
+
+/// Items of a Python iterator, each pulled with its own action
+///
+/// Ends after the first error.
+///```rs
+/// for row in module.call_iter::<String>("read_rows", ("data.csv",)).unwrap() {
+///     println!("{}", row.unwrap());
+/// }
+/// ```
+pub struct PyIter<'a, T> {
+    module: &'a PythonModule,
+    handle: Option<PyHandle>,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<T> Iterator for PyIter<'_, T>
+where
+    T: for<'py> FromPyObject<'py> + Send + 'static,
+{
+    type Item = Result<T, PyRunnerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let handle = self.handle.clone()?;
+        let item = self.module.action(move |py, _| {
+            let mut iterator = handle.bind(*py)?.downcast_into::<PyIterator>()?;
+            iterator
+                .next()
+                .map(|item| item.and_then(|item| item.extract::<T>()))
+                .transpose()
+        });
+        match item {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.handle = None;
+                None
+            }
+            Err(e) => {
+                self.handle = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl PythonModule {
+    /// Calls `name`, which may be a dotted path, and iterates over the returned iterable
+    ///
+    /// Works with generators and any other iterable, items are converted one at a time.
+    pub fn call_iter<T>(
+        &self,
+        name: &str,
+        args: impl for<'py> PyCallArgs<'py> + Send + 'static,
+    ) -> Result<PyIter<'_, T>, PyRunnerError> {
+        let name = name.to_string();
+        let handle = self.create_handle(move |_, module| {
+            Ok(lookup(module, &name)?
+                .call1(args)?
+                .try_iter()?
+                .into_any()
+                .unbind())
+        })?;
+        Ok(PyIter {
+            module: self,
+            handle: Some(handle),
+            item: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_iter() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "def count(n):\n",
+                "    for i in range(n):\n",
+                "        yield i * i\n",
+                "def broken():\n",
+                "    yield 1\n",
+                "    raise ValueError('broken')\n",
+            ),
+        )
+        .unwrap();
+
+        let module = PythonModule::new_project(dir.join("main.py")).unwrap();
+        let squares = module
+            .call_iter::<i64>("count", (4,))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(squares, vec![0, 1, 4, 9]);
+
+        let mut items = module.call_iter::<i64>("broken", ()).unwrap();
+        assert_eq!(items.next().unwrap().unwrap(), 1);
+        assert_eq!(
+            items.next().unwrap().unwrap_err().exception_type(),
+            "ValueError"
+        );
+        assert!(items.next().is_none());
+
+        assert!(module.call_iter::<i64>("missing", ()).is_err());
+    }
+}
@@ -1,38 +1,203 @@
-use crossbeam::channel::{self, Sender};
-use nanoid::nanoid;
+use crossbeam::channel::Sender;
 use pyo3::Python;
 use pyo3::prelude::*;
 use pyo3::types::PyDict;
 use std::env;
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::thread;
+use std::time::{Duration, Instant};
 
-/// sets env variable PYTHONPATH
-/// `set_venv("./venv", "python3.11")`
-pub fn set_venv(venv: &str, python_version: &str) {
-    unsafe {
-        env::set_var(
-            "PYTHONPATH",
-            format!("{venv}/lib/{python_version}/site-packages",),
-        );
+// lets generated code, which refers to `::py_runner`, compile in the crate's own tests
+#[cfg(test)]
+extern crate self as py_runner;
+
+#[cfg(feature = "numpy")]
+mod array;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod asyncio;
+mod attribute;
+mod audit;
+mod builder;
+mod bytecode;
+mod bytes;
+mod call;
+mod cancel;
+mod code;
+mod codegen;
+mod config;
+mod context;
+#[cfg(feature = "serde")]
+mod convert;
+mod deadline;
+mod describe;
+mod discover;
+mod dlpack;
+mod error;
+mod event;
+mod figure;
+mod function;
+mod handle;
+mod health;
+mod helper;
+mod host;
+mod importer;
+mod instance;
+#[cfg(feature = "integrity")]
+mod integrity;
+mod interface;
+mod iter;
+mod limit;
+#[cfg(feature = "log")]
+mod logging;
+mod memory;
+mod output;
+#[cfg(feature = "pil")]
+mod pil;
+mod plugin;
+mod pool;
+mod provision;
+mod python_path;
+mod queue;
+mod release;
+mod retry;
+mod runtime;
+mod sandbox;
+mod schedule;
+mod script;
+mod session;
+mod state;
+mod stats;
+#[cfg(feature = "tokio")]
+mod stream;
+mod subprocess;
+mod supervisor;
+mod syntax;
+mod task;
+#[cfg(feature = "tracing")]
+mod trace;
+mod venv;
+#[cfg(feature = "watch")]
+mod watch;
+mod watchdog;
+mod worker;
+
+#[cfg(feature = "numpy")]
+pub use array::{ArrayElement, ArrayView, SharedArray};
+#[cfg(feature = "arrow")]
+pub use arrow::{ArrowBatch, ArrowColumn};
+pub use audit::AuditEvent;
+pub use builder::PythonModuleBuilder;
+pub use bytecode::compile_bytecode;
+pub use bytes::{BytesView, SharedBytes};
+pub use call::Call;
+pub use cancel::CancellationToken;
+pub use code::CodeRunner;
+pub use codegen::{generate_bindings, generate_bindings_from_source};
+pub use config::InterpreterConfig;
+pub use deadline::Deadline;
+pub use describe::{CallableInfo, CallableKind, ModuleInfo, ParameterInfo, ParameterKind};
+pub use discover::{PythonInstall, PythonSource, discover_pythons};
+pub use dlpack::{SharedTensor, Tensor, TensorDevice, TensorDtype, TensorElement};
+pub use error::{ErrorKind, PyRunnerError};
+pub use event::Event;
+pub use figure::{Figure, FigureFormat, take_figures};
+pub use function::PyFunction;
+pub use handle::PyHandle;
+pub use health::Status;
+pub use host::HostFunction;
+pub use importer::SourceProvider;
+pub use instance::PyInstance;
+pub use interface::{Interface, Mismatch};
+pub use iter::PyIter;
+pub use memory::{MemoryStats, TracedMemory};
+pub use output::{CapturedOutput, OutputLine, Stream};
+#[cfg(feature = "pil")]
+pub use pil::{EncodedImage, Image};
+pub use plugin::PluginHost;
+pub use pool::PythonPool;
+pub use provision::Provisioner;
+pub use py_runner_macros::py_impl;
+pub use python_path::PythonPath;
+pub use queue::{PendingTask, Priority, QueuePolicy, TaskId};
+pub use retry::RetryPolicy;
+pub use runtime::PythonRuntime;
+pub use sandbox::Sandbox;
+pub use schedule::ScheduleHandle;
+pub use script::{execute_script, execute_script_in};
+pub use session::PythonSession;
+pub use stats::{Stats, TaskMetrics};
+#[cfg(feature = "tokio")]
+pub use stream::PyStream;
+pub use subprocess::{Resource, ResourceLimits};
+pub use supervisor::{RestartPolicy, Restartable, Supervised};
+pub use syntax::{SyntaxDiagnostic, check_syntax, check_syntax_source};
+pub use task::{TaskHandle, join_all};
+pub use venv::Venv;
+pub use watchdog::{Hang, HangAction};
+pub use worker::{ExitReason, ShutdownMode};
+
+/// Prepends the environment's `site-packages` to PYTHONPATH, and sets CONDA_PREFIX for conda
+/// environments
+/// `set_venv("./venv", "python3.11").unwrap()`
+///
+/// Fails if the path contains the platform's separator. See [`PythonPath`] for more entries
+/// and [`Venv`] for an activation that also works after the interpreter started.
+pub fn set_venv(venv: &str, python_version: &str) -> Result<(), PyRunnerError> {
+    let root = Path::new(venv);
+    // Windows environments, venv or conda, don't name the version
+    let windows = root.join("Lib").join("site-packages");
+    let site_packages = match windows.is_dir() {
+        true => windows,
+        false => root.join("lib").join(python_version).join("site-packages"),
+    };
+    PythonPath::builder().prepend(site_packages).apply()?;
+    if root.join("conda-meta").is_dir() {
+        unsafe {
+            env::set_var("CONDA_PREFIX", root);
+        }
     }
+    Ok(())
 }
 
+type Task = Box<dyn FnOnce(&Python, &Bound<'_, PyAny>) + Send>;
+
 pub struct PythonModule {
-    task_sender: Sender<Option<Box<dyn FnOnce(&Python, &Bound<'_, PyAny>) + Send>>>,
-    thread_handle: thread::JoinHandle<PyResult<()>>,
+    task_sender: Sender<Option<Task>>,
+    bounds: Option<queue::Bounds>,
+    thread_handle: Option<thread::JoinHandle<PyResult<()>>>,
+    exit_receiver: crossbeam::channel::Receiver<()>,
+    control: Arc<worker::Control>,
+    /// Wakes up the worker of a deferred module, `false` stops it before the import
+    start: Option<Sender<bool>>,
+    /// Outcome of a deferred import until [`start`](Self::start) received it
+    init: std::sync::Mutex<Option<Receiver<Result<(), PyRunnerError>>>>,
+    exceptions: error::ExceptionMap,
+    events: Arc<event::Events>,
+    #[cfg(feature = "watch")]
+    watcher: Option<notify::RecommendedWatcher>,
 }
 
 impl Drop for PythonModule {
     fn drop(&mut self) {
-        self.task_sender.send(None).unwrap();
+        // the worker may already be gone, then there is nobody left to stop
+        if !self.control.close() {
+            self.signal_start(false);
+            let _ = self.push(None);
+        }
     }
 }
 
 impl PythonModule {
     /// Runs action on the imported module
+    ///
+    /// A panic in `call` fails the action with [`ErrorKind::Panicked`], the worker keeps
+    /// running.
     ///```rs
     /// module
     ///    .action(|py, module| module.call_method1("add", (1, 2))?.extract::<i64>())
@@ -40,119 +205,310 @@ impl PythonModule {
     /// ```
     pub fn action<T: Send + 'static>(
         &self,
-        call: fn(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T>,
-    ) -> PyResult<T> {
-        if self.thread_handle.is_finished() {
-            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
-                "Python thread has exited",
-            ));
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        let receiver = self.submit(call)?;
+        receiver.recv().map_err(|_| self.terminated_error())?
+    }
+
+    /// Runs action on the imported module, giving up after `timeout`
+    ///
+    /// Returns a `TimeoutError` if no result arrived in time. The task keeps running on the
+    /// worker and its result is discarded, later actions are queued behind it.
+    /// ```rs
+    /// module
+    ///    .action_timeout(|py, module| module.call_method1("add", (1, 2))?.extract::<i64>(), Duration::from_secs(1))
+    ///    .unwrap();
+    /// ```
+    pub fn action_timeout<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+        timeout: Duration,
+    ) -> Result<T, PyRunnerError> {
+        let receiver = self.submit(call)?;
+        match receiver.recv_timeout(timeout) {
+            Ok(v) => v,
+            Err(RecvTimeoutError::Timeout) => Err(PyRunnerError::timeout(format!(
+                "Action did not finish within {timeout:?}"
+            ))),
+            Err(RecvTimeoutError::Disconnected) => Err(self.terminated_error()),
         }
+    }
+
+    /// Runs action on the imported module without blocking the calling thread
+    ///```rs
+    /// module
+    ///    .action_async(|py, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+    ///    .await
+    ///    .unwrap();
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn action_async<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        self.dispatch(call, move |result| {
+            let _ = sender.send(result);
+        })?;
+        receiver.await.map_err(|_| self.terminated_error())?
+    }
+
+    /// Queues action on the imported module and returns without waiting for it
+    ///```rs
+    /// let handle = module.spawn(|py, module| module.call_method1("track", ("login",)).map(|_| ())).unwrap();
+    /// // fire-and-forget: drop the handle, or collect the result later
+    /// handle.join().unwrap();
+    /// ```
+    pub fn spawn<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<TaskHandle<T>, PyRunnerError> {
+        let (handle, completer) = TaskHandle::new();
+        let call = self.prepare(call);
+        let id = self.queue(Box::new(move |py: &Python, module: &Bound<'_, PyAny>| {
+            if !completer.start() {
+                return completer.cancelled();
+            }
+            completer.complete(call(py, module));
+        }))?;
+        Ok(handle.queued(id, &self.control))
+    }
 
+    fn submit<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<Receiver<Result<T, PyRunnerError>>, PyRunnerError> {
         let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        self.dispatch(call, move |result| {
+            let _ = sender.send(result);
+        })?;
+        Ok(receiver)
+    }
 
-        let task: Box<dyn FnOnce(&Python, &Bound<'_, PyAny>) + Send> =
-            Box::new(move |py: &Python, module: &Bound<'_, PyAny>| {
-                let result = call(py, module);
-                let _ = sender.send(result);
-            });
+    /// Queues `call` on the worker thread, `reply` receives its result
+    fn dispatch<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+        reply: impl FnOnce(Result<T, PyRunnerError>) + Send + 'static,
+    ) -> Result<(), PyRunnerError> {
+        let call = self.prepare(call);
+        let reply = queue::PendingReply::new(reply);
+        self.queue(Box::new(move |py: &Python, module: &Bound<'_, PyAny>| {
+            reply.send(call(py, module));
+        }))
+        .map(|_| ())
+    }
+
+    /// Wraps `call` for the worker: converts its error or panic and records its metrics
+    fn prepare<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> Result<T, PyRunnerError> + Send + 'static
+    {
+        let exceptions = self.exceptions.clone();
+        let control = self.control.clone();
+        let queued = Instant::now();
+        move |py, module| {
+            let started = Instant::now();
+            let result = guard(py, module, &exceptions, call);
+            control.metrics.record(queued, started, result.is_err());
+            control.limits.record(&result);
+            #[cfg(feature = "tracing")]
+            trace::record_outcome(&result);
+            result
+        }
+    }
 
-        self.task_sender
-            .send(Some(task))
-            .map_err(|_| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Task send failed"))?;
+    fn queue(&self, task: Task) -> Result<TaskId, PyRunnerError> {
+        if !self.is_alive() {
+            return Err(self.exited_error());
+        }
 
-        receiver.recv().unwrap()
+        self.signal_start(true);
+        let (id, ticket) = self.track(Priority::Normal, task)?;
+        self.push(Some(ticket))?;
+        Ok(id)
     }
 
     /// Loads a Python module from a directory
     /// `let module = PythonModule::new_module(Path::new("./my-module")).unwrap();`
-    pub fn new_module(path: &Path) -> PyResult<PythonModule> {
-        let init_file = path.join("__init__.py");
-        Self::new_project(init_file)
+    pub fn new_module(path: &Path) -> Result<PythonModule, PyRunnerError> {
+        PythonModuleBuilder::new_module(path).build()
     }
 
     /// Loads a Python project from root file
     /// `let project = PythonModule::new_project(Path::new("./my-project/main.py").into()).unwrap()`
-    pub fn new_project(init_file: PathBuf) -> PyResult<PythonModule> {
-        if !init_file.is_file() {
-            return Err(PyErr::new::<pyo3::exceptions::PyFileNotFoundError, _>(
-                format!("No {} found", init_file.display()),
-            ));
-        }
-        let module_name = nanoid!(16);
-        let (task_sender, task_receiver) =
-            channel::unbounded::<Option<Box<dyn FnOnce(&Python, &Bound<'_, PyAny>) + Send>>>();
-        let (init_sender, init_receiver) = std::sync::mpsc::sync_channel::<PyResult<()>>(0);
-
-        let thread_handle = thread::spawn(move || {
-            let v: PyResult<()> = Python::with_gil(|py| {
-                let init = || {
-                    let importlib_util = PyModule::import(py, "importlib.util")?;
-
-                    let spec = importlib_util
-                        .getattr("spec_from_file_location")?
-                        .call1((&module_name, init_file))?;
-
-                    let module = importlib_util
-                        .getattr("module_from_spec")?
-                        .call1((spec.clone(),))?;
-                    let sys = py.import("sys")?;
-                    let modules = sys.getattr("modules")?;
-                    modules.set_item(module_name, &module)?;
-                    let loader = spec.getattr("loader")?;
-                    loader.call_method1("exec_module", (module.clone(),))?;
-                    Ok(module)
-                };
-                match init() {
-                    Ok(module) => {
-                        let _ = init_sender.send(Ok(()));
-                        while let Ok(Some(task)) = py.allow_threads(|| task_receiver.recv()) {
-                            task(&py, &module);
-                        }
-                    }
-                    Err(e) => {
-                        let _ = init_sender.send(Err(e));
-                    }
-                }
+    pub fn new_project(init_file: PathBuf) -> Result<PythonModule, PyRunnerError> {
+        PythonModuleBuilder::new_project(init_file).build()
+    }
 
-                Ok(())
-            });
-            v
-        });
-        if let Ok(v) = init_receiver.recv() {
-            v?;
-        }
+    /// Like [`new_project`](Self::new_project), but fails with a `TimeoutError` if the import
+    /// takes longer than `timeout`, see [`PythonModuleBuilder::init_timeout`]
+    /// `let project = PythonModule::new_project_timeout("./my-project/main.py".into(), Duration::from_secs(10)).unwrap()`
+    pub fn new_project_timeout(
+        init_file: PathBuf,
+        timeout: Duration,
+    ) -> Result<PythonModule, PyRunnerError> {
+        PythonModuleBuilder::new_project(init_file)
+            .init_timeout(timeout)
+            .build()
+    }
 
-        Ok(PythonModule {
-            task_sender,
-            thread_handle,
-        })
+    /// Like [`new_project`](Self::new_project), but awaits the import instead of blocking
+    /// `let project = PythonModule::new_project_async("./my-project/main.py".into()).await.unwrap()`
+    #[cfg(feature = "tokio")]
+    pub async fn new_project_async(init_file: PathBuf) -> Result<PythonModule, PyRunnerError> {
+        PythonModuleBuilder::new_project(init_file)
+            .build_async()
+            .await
+    }
+
+    /// Loads a Python module from `source`, see [`PythonModuleBuilder::from_source`]
+    /// `let module = PythonModule::from_source("plugin", include_str!("plugin.py")).unwrap();`
+    pub fn from_source(name: &str, source: &str) -> Result<PythonModule, PyRunnerError> {
+        PythonModuleBuilder::from_source(name, source).build()
+    }
+
+    /// Loads a Python module from a zip archive, see [`PythonModuleBuilder::new_zipped`]
+    /// `let module = PythonModule::new_zipped(Path::new("plugins/analytics.pyz")).unwrap();`
+    pub fn new_zipped(path: &Path) -> Result<PythonModule, PyRunnerError> {
+        PythonModuleBuilder::new_zipped(path).build()
+    }
+
+    /// Re-executes the module source in place
+    ///
+    /// Submodules of a package are dropped from `sys.modules` so they are imported again.
+    /// Objects created from the previous version keep referencing the old code.
+    pub fn reload(&self) -> Result<(), PyRunnerError> {
+        self.action(|py, module| reload_module(*py, module))
+    }
+
+    /// Removes the module and its submodules from `sys.modules` and stops the worker
+    ///
+    /// Queued tasks run first. Once the worker dropped the module, a garbage collection
+    /// pass frees its reference cycles, so load/unload cycles don't leak. Objects still
+    /// referenced elsewhere, e.g. by other modules, stay alive.
+    pub fn unload(self) -> Result<(), PyRunnerError> {
+        self.action(|py, module| {
+            let name = module.getattr("__name__")?.extract::<String>()?;
+            remove_modules(*py, &name, true)
+        })?;
+        self.join()?;
+        Python::with_gil(|py| py.import("gc")?.call_method0("collect").map(|_| ()))?;
+        Ok(())
     }
 }
 
-pub fn execute_code_(s: &str) -> PyResult<()> {
+/// Runs `call` on the worker, converting its exception or panic
+fn guard<T>(
+    py: &Python<'_>,
+    module: &Bound<'_, PyAny>,
+    exceptions: &error::ExceptionMap,
+    call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T>,
+) -> Result<T, PyRunnerError> {
+    catch_panic(|| call(py, module).map_err(|e| exceptions.convert(*py, e)))
+}
+
+/// Runs `call`, converting a panic into [`ErrorKind::Panicked`]
+fn catch_panic<T>(call: impl FnOnce() -> Result<T, PyRunnerError>) -> Result<T, PyRunnerError> {
+    match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(result) => result,
+        Err(payload) => Err(PyRunnerError::new(
+            ErrorKind::Panicked,
+            format!("Action panicked: {}", worker::panic_message(payload)),
+        )),
+    }
+}
+
+/// Re-executes `module` and drops its submodules from `sys.modules`
+fn reload_module(py: Python<'_>, module: &Bound<'_, PyAny>) -> PyResult<()> {
+    let name = module.getattr("__name__")?.extract::<String>()?;
+    remove_modules(py, &name, false)?;
+    py.import("importlib")?.call_method0("invalidate_caches")?;
+    module
+        .getattr("__spec__")?
+        .getattr("loader")?
+        .call_method1("exec_module", (module,))?;
+    Ok(())
+}
+
+/// Drops the submodules of `name` and, if `including` is set, `name` from `sys.modules`
+fn remove_modules(py: Python<'_>, name: &str, including: bool) -> PyResult<()> {
+    let modules = py.import("sys")?.getattr("modules")?;
+    let prefix = format!("{name}.");
+    let keys = modules.call_method0("keys")?.try_iter()?;
+    let removed = keys
+        .map(|key| key?.extract::<String>())
+        .collect::<PyResult<Vec<_>>>()?
+        .into_iter()
+        .filter(|key| key.starts_with(&prefix) || (including && key == name));
+    for key in removed {
+        modules.del_item(key)?;
+    }
+    Ok(())
+}
+
+/// Converts Python source, a NUL byte is reported as `ValueError`
+pub(crate) fn c_code(code: &str) -> PyResult<CString> {
+    CString::new(code).map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
+pub fn execute_code_(s: &str) -> Result<(), PyRunnerError> {
     execute_code::<()>(s, |_, _| Ok(()))
 }
 
 /// Runs Python code
+///
+/// A `SyntaxError`, an exception raised by the code or a NUL byte in `s` is returned as
+/// error, including the offending source line. See [`CodeRunner`] to pass in globals.
 pub fn execute_code<T>(
     s: &str,
     f: fn(Python<'_>, Bound<'_, PyDict>) -> PyResult<T>,
-) -> PyResult<T> {
-    Python::with_gil(|py| {
-        let c_string = CString::new(s).expect("CString::new failed");
-
-        let c_str: &CStr = c_string.as_c_str();
-        let globals = PyDict::new(py);
+) -> Result<T, PyRunnerError> {
+    CodeRunner::new(s).run_with(|py, globals| f(py, globals.clone()))
+}
 
-        py.run(c_str, Some(&globals), None).unwrap();
-        f(py, globals)
-    })
+/// Evaluates a Python expression and extracts its value
+///```rs
+/// Python::with_gil(|py| {
+///     let globals = PyDict::new(py);
+///     globals.set_item("x", 2)?;
+///     eval_code::<i64>("1 + 2 * x", &globals)
+/// })
+/// .unwrap();
+/// ```
+pub fn eval_code<'py, T: FromPyObject<'py>>(
+    expression: &str,
+    globals: &Bound<'py, PyDict>,
+) -> PyResult<T> {
+    globals
+        .py()
+        .eval(&c_code(expression)?, Some(globals), None)?
+        .extract()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_eval_code() {
+        let value = Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("x", 2)?;
+            eval_code::<i64>("1 + 2 * x", &globals)
+        })
+        .unwrap();
+        assert_eq!(value, 5);
+
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            assert!(eval_code::<i64>("x = 1", &globals).is_err());
+            assert!(eval_code::<i64>("'text'", &globals).is_err());
+        });
+    }
+
     #[test]
     fn test_execute_code() {
         let x = execute_code("x = '10'", |_, globals| {
@@ -161,6 +517,19 @@ mod tests {
         .unwrap();
 
         assert_eq!(x, "10");
+
+        let err = execute_code_("x = 1\nif x\n    pass").unwrap_err();
+        assert_eq!(err.exception_type(), "SyntaxError");
+        assert_eq!(err.line(), Some(2));
+        assert_eq!(err.source_line(), Some("if x"));
+        assert!(err.traceback().unwrap().contains("SyntaxError"));
+
+        let err = execute_code_("x = 1\ny = x / 0").unwrap_err();
+        assert_eq!(err.exception_type(), "ZeroDivisionError");
+        assert_eq!(err.source_line(), Some("y = x / 0"));
+
+        let err = execute_code_("x = '\0'").unwrap_err();
+        assert_eq!(err.exception_type(), "ValueError");
     }
 
     #[test]
@@ -180,4 +549,553 @@ mod tests {
             .unwrap();
         assert_eq!(sum, 3)
     }
+
+    #[test]
+    fn test_from_source() {
+        let module = PythonModule::from_source(
+            "embedded_plugin",
+            "import sys\ndef add(a, b):\n    return a + b\ndef fail():\n    raise ValueError('embedded')\n",
+        )
+        .unwrap();
+        let (sum, name) = module
+            .action(|py, module| {
+                let sum = module.call_method1("add", (1, 2))?.extract::<i64>()?;
+                let modules = py.import("sys")?.getattr("modules")?;
+                let name = modules.get_item("embedded_plugin")?.getattr("__name__")?;
+                Ok((sum, name.extract::<String>()?))
+            })
+            .unwrap();
+        assert_eq!(sum, 3);
+        assert_eq!(name, "embedded_plugin");
+        module.reload().unwrap();
+
+        let err = module
+            .action(|_, module| module.call_method0("fail").map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.file(), Some("<embedded_plugin>"));
+        assert_eq!(err.source_line(), Some("raise ValueError('embedded')"));
+
+        let Err(err) = PythonModule::from_source("broken_plugin", "def broken(:\n") else {
+            panic!("broken source loaded");
+        };
+        assert_eq!(err.exception_type(), "SyntaxError");
+        assert_eq!(err.kind(), &ErrorKind::InitFailed);
+    }
+
+    #[test]
+    fn test_package() {
+        let id = nanoid::nanoid!(8).replace('-', "_");
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let package = dir.join(format!("package_{id}"));
+        std::fs::create_dir_all(package.join("sub")).unwrap();
+        std::fs::write(package.join("__init__.py"), "from .sub import answer\n").unwrap();
+        std::fs::write(package.join("utils.py"), "def helper():\n    return 21\n").unwrap();
+        std::fs::write(
+            package.join("sub").join("__init__.py"),
+            format!(
+                "from ..utils import helper\nfrom package_{id}.utils import helper as absolute\ndef answer():\n    return helper() + absolute()\n"
+            ),
+        )
+        .unwrap();
+        let module = PythonModuleBuilder::new_module(&package)
+            .module_name(format!("package_{id}"))
+            .build()
+            .unwrap();
+        let (answer, name) = module
+            .action(|_, module| {
+                let answer = module.call_method0("answer")?.extract::<i64>()?;
+                Ok((answer, module.getattr("__name__")?.extract::<String>()?))
+            })
+            .unwrap();
+        assert_eq!(answer, 42);
+        assert_eq!(name, format!("package_{id}"));
+
+        let namespace = dir.join(format!("namespace_{id}"));
+        std::fs::create_dir_all(&namespace).unwrap();
+        std::fs::write(
+            namespace.join("tools.py"),
+            "def double(x):\n    return 2 * x\n",
+        )
+        .unwrap();
+        let module = PythonModule::new_module(&namespace).unwrap();
+        let doubled = module
+            .action(|py, module| {
+                let name = module.getattr("__name__")?.extract::<String>()?;
+                let tools = py
+                    .import("importlib")?
+                    .call_method1("import_module", (format!("{name}.tools"),))?;
+                tools.call_method1("double", (4,))?.extract::<i64>()
+            })
+            .unwrap();
+        assert_eq!(doubled, 8);
+
+        // a package named like the standard library doesn't shadow it for other modules
+        let json = dir.join("json");
+        std::fs::create_dir_all(&json).unwrap();
+        std::fs::write(json.join("__init__.py"), "SHADOW = True\n").unwrap();
+        let module = PythonModule::new_module(&json).unwrap();
+        let shadowed = module
+            .action(|py, _| py.import("json")?.hasattr("SHADOW"))
+            .unwrap();
+        assert!(!shadowed);
+    }
+
+    #[test]
+    fn test_unload() {
+        let name = format!("unload_{}", nanoid::nanoid!(8).replace('-', "_"));
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .module_name(&name)
+            .build()
+            .unwrap();
+        let probe = module
+            .action(|py, module| {
+                let probe = py.import("weakref")?.call_method1("ref", (module,))?;
+                Ok(probe.unbind())
+            })
+            .unwrap();
+        module.unload().unwrap();
+
+        Python::with_gil(|py| {
+            let modules = py.import("sys")?.getattr("modules")?;
+            assert!(!modules.contains(&name)?);
+            assert!(!modules.contains(format!("{name}.calc"))?);
+            assert!(probe.bind(py).call0()?.is_none());
+            PyResult::Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_new_zipped() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let package = dir.join("tree").join("zipped_analytics");
+        std::fs::create_dir_all(package.join("stats")).unwrap();
+        std::fs::write(
+            package.join("__init__.py"),
+            concat!(
+                "from importlib import resources\n",
+                "from .stats import mean\n",
+                "def greeting():\n",
+                "    return resources.files(__name__).joinpath('greeting.txt').read_text()\n",
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            package.join("stats").join("__init__.py"),
+            "def mean(values):\n    return sum(values) / len(values)\n",
+        )
+        .unwrap();
+        std::fs::write(package.join("greeting.txt"), "hello from the archive").unwrap();
+        let archive = dir.join("zipped_analytics.pyz");
+        CodeRunner::new(concat!(
+            "import os, zipfile\n",
+            "with zipfile.ZipFile(archive, 'w') as bundle:\n",
+            "    for root, _, files in os.walk(tree):\n",
+            "        for name in files:\n",
+            "            path = os.path.join(root, name)\n",
+            "            bundle.write(path, os.path.relpath(path, tree))\n",
+        ))
+        .global("archive", archive.to_str().unwrap().to_string())
+        .global("tree", dir.join("tree").to_str().unwrap().to_string())
+        .run()
+        .unwrap();
+
+        let module = PythonModule::new_zipped(&archive).unwrap();
+        let (mean, greeting) = module
+            .action(|_, module| {
+                let mean = module
+                    .call_method1("mean", (vec![1, 2, 3],))?
+                    .extract::<f64>()?;
+                let greeting = module.call_method0("greeting")?.extract::<String>()?;
+                Ok((mean, greeting))
+            })
+            .unwrap();
+        assert_eq!(mean, 2.0);
+        assert_eq!(greeting, "hello from the archive");
+
+        assert!(PythonModule::new_zipped(&dir.join("missing.pyz")).is_err());
+    }
+
+    #[test]
+    fn test_action_captures() {
+        let module1 = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let (a, b) = (40, 2);
+        let sum = module1
+            .action(move |_, module| module.call_method1("add", (a, b))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(sum, 42)
+    }
+
+    #[test]
+    fn test_action_timeout() {
+        let module1 = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let err = module1
+            .action_timeout(
+                |py, _| {
+                    py.import("time")?.call_method1("sleep", (0.5,))?;
+                    Ok(())
+                },
+                Duration::from_millis(50),
+            )
+            .unwrap_err();
+        assert!(err.is_instance_of::<pyo3::exceptions::PyTimeoutError>());
+        assert_eq!(err.kind(), &ErrorKind::Timeout);
+
+        // the timeout doesn't wait for the GIL held by the action
+        let started = Instant::now();
+        let err = module1
+            .action_timeout(
+                |_, _| {
+                    thread::sleep(Duration::from_millis(500));
+                    Ok(())
+                },
+                Duration::from_millis(50),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Timeout);
+        assert_eq!(err.exception_type(), "TimeoutError");
+        assert!(started.elapsed() < Duration::from_millis(400));
+
+        let sum = module1
+            .action_timeout(
+                |_, module| module.call_method1("add", (1, 2))?.extract::<i64>(),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert_eq!(sum, 3)
+    }
+
+    #[test]
+    fn test_init_timeout() {
+        const SLOW: &str = "import time\ntime.sleep(0.5)\n";
+        let Err(err) = PythonModuleBuilder::from_source("slow_init", SLOW)
+            .init_timeout(Duration::from_millis(50))
+            .build()
+        else {
+            panic!("slow module loaded in time");
+        };
+        assert_eq!(err.kind(), &ErrorKind::Timeout);
+
+        let project = PythonModule::new_project_timeout(
+            "./my-project/main.py".into(),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert!(project.is_alive());
+    }
+
+    #[test]
+    fn test_warmup() {
+        let module = PythonModuleBuilder::from_source("warmed_up", "model = None\n")
+            .preload(["json", "decimal"])
+            .warmup(|_, module| module.setattr("model", "loaded"))
+            .build()
+            .unwrap();
+        let (model, preloaded) = module
+            .action(|py, module| {
+                let modules = py.import("sys")?.getattr("modules")?;
+                Ok((
+                    module.getattr("model")?.extract::<String>()?,
+                    modules.contains("json")? && modules.contains("decimal")?,
+                ))
+            })
+            .unwrap();
+        assert_eq!(model, "loaded");
+        assert!(preloaded);
+
+        let Err(err) = PythonModuleBuilder::from_source("warmup_missing", "")
+            .preload(["py_runner_missing_module"])
+            .build()
+        else {
+            panic!("missing module preloaded");
+        };
+        assert_eq!(err.exception_type(), "ModuleNotFoundError");
+        assert_eq!(err.kind(), &ErrorKind::InitFailed);
+    }
+
+    #[test]
+    fn test_deferred() {
+        const COUNTED: &str = "import builtins\nbuiltins.py_runner_deferred = getattr(builtins, 'py_runner_deferred', 0) + 1\ndef add(a, b):\n    return a + b\n";
+        let imports = || {
+            Python::with_gil(|py| {
+                py.import("builtins")?
+                    .getattr("py_runner_deferred")
+                    .map_or(Ok(0), |v| v.extract::<i64>())
+            })
+            .unwrap()
+        };
+        let unused = PythonModuleBuilder::from_source("deferred_unused", COUNTED)
+            .deferred(true)
+            .build()
+            .unwrap();
+        assert!(unused.shutdown(ShutdownMode::DrainQueue));
+        assert_eq!(unused.exit_reason(), Some(ExitReason::Shutdown));
+
+        let module = PythonModuleBuilder::from_source("deferred_started", COUNTED)
+            .deferred(true)
+            .build()
+            .unwrap();
+        assert_eq!(imports(), 0);
+        module.start().unwrap();
+        assert_eq!(imports(), 1);
+        module.start().unwrap();
+        let sum = module
+            .action(|_, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(sum, 3);
+
+        let module = PythonModuleBuilder::from_source("deferred_action", COUNTED)
+            .deferred(true)
+            .build()
+            .unwrap();
+        let sum = module
+            .action(|_, module| module.call_method1("add", (2, 3))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(sum, 5);
+        assert_eq!(imports(), 2);
+
+        let broken = PythonModuleBuilder::from_source("deferred_broken", "def broken(:\n")
+            .deferred(true)
+            .build()
+            .unwrap();
+        let err = broken.action(|_, _| Ok(())).unwrap_err();
+        let ErrorKind::WorkerDead {
+            reason: Some(ExitReason::Error(traceback)),
+        } = err.kind()
+        else {
+            panic!("unexpected error {err}");
+        };
+        assert!(traceback.contains("SyntaxError"));
+        let broken = PythonModuleBuilder::from_source("deferred_broken", "def broken(:\n")
+            .deferred(true)
+            .build()
+            .unwrap();
+        let err = broken.start().unwrap_err();
+        assert_eq!(err.exception_type(), "SyntaxError");
+        assert_eq!(err.kind(), &ErrorKind::InitFailed);
+        assert!(broken.start().is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_build_async() {
+        let project = PythonModule::new_project_async("./my-project/main.py".into())
+            .await
+            .unwrap();
+        assert!(project.is_alive());
+
+        let Err(err) =
+            PythonModuleBuilder::from_source("slow_init_async", "import time\ntime.sleep(0.5)\n")
+                .init_timeout(Duration::from_millis(50))
+                .build_async()
+                .await
+        else {
+            panic!("slow module loaded in time");
+        };
+        assert_eq!(err.kind(), &ErrorKind::Timeout);
+
+        let Err(err) = PythonModuleBuilder::from_source("broken_async", "def broken(:\n")
+            .build_async()
+            .await
+        else {
+            panic!("broken source loaded");
+        };
+        assert_eq!(err.kind(), &ErrorKind::InitFailed);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_action_async() {
+        let module1 = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let sum = module1
+            .action_async(|_, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+            .await
+            .unwrap();
+        assert_eq!(sum, 3)
+    }
+
+    #[test]
+    fn test_action_traceback() {
+        let project1 = PythonModule::new_project(Path::new("./my-project/main.py").into()).unwrap();
+        let err = project1
+            .action(|_, module| module.call_method1("divide", (1, 0))?.extract::<f64>())
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "ZeroDivisionError");
+        assert_eq!(err.line(), Some(6));
+        assert!(err.file().unwrap().ends_with("main.py"));
+        assert!(err.traceback().unwrap().contains("return a / b"));
+    }
+
+    #[test]
+    fn test_map_exception() {
+        #[derive(Debug, PartialEq)]
+        enum StoreError {
+            NotFound(String),
+            Store,
+        }
+        impl std::fmt::Display for StoreError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{self:?}")
+            }
+        }
+        impl std::error::Error for StoreError {}
+
+        let source = "class StoreError(Exception): pass\nclass NotFound(StoreError): pass\ndef get(key):\n    raise NotFound(key)\ndef put(key):\n    raise StoreError(key)\ndef clear():\n    raise KeyError('all')\n";
+        let module = PythonModuleBuilder::from_source("mapped_store", source)
+            .map_exception("mapped_store.StoreError", |_| StoreError::Store)
+            .map_exception("mapped_store.NotFound", |e| {
+                StoreError::NotFound(e.message().to_string())
+            })
+            .build()
+            .unwrap();
+        let call = |name: &'static str| {
+            module
+                .action(move |_, module| module.call_method1(name, ("key",)).map(|_| ()))
+                .unwrap_err()
+        };
+        let err = call("get");
+        assert_eq!(err.exception_type(), "mapped_store.NotFound");
+        assert_eq!(
+            err.mapped::<StoreError>(),
+            Some(&StoreError::NotFound("key".to_string()))
+        );
+        assert_eq!(call("put").mapped::<StoreError>(), Some(&StoreError::Store));
+        assert_eq!(call("clear").mapped::<StoreError>(), None);
+        assert!(err.mapped::<std::io::Error>().is_none());
+
+        let Err(err) = PythonModuleBuilder::from_source("mapped_init", "raise KeyError('x')\n")
+            .map_exception("KeyError", |_| StoreError::Store)
+            .build()
+        else {
+            panic!("module raising on import loaded");
+        };
+        assert_eq!(err.mapped::<StoreError>(), Some(&StoreError::Store));
+    }
+
+    #[test]
+    fn test_builder() {
+        let module1 = PythonModuleBuilder::new_module("./my-module")
+            .module_name("builder_test_module")
+            .env("PY_RUNNER_BUILDER_TEST", "1")
+            .lazy(true)
+            .build()
+            .unwrap();
+        let (name, env) = module1
+            .action(|py, module| {
+                let env = py
+                    .import("os")?
+                    .getattr("environ")?
+                    .get_item("PY_RUNNER_BUILDER_TEST")?
+                    .extract::<String>()?;
+                Ok((module.getattr("__name__")?.extract::<String>()?, env))
+            })
+            .unwrap();
+        assert_eq!(name, "builder_test_module");
+        assert_eq!(env, "1");
+        let sum = module1
+            .action(|_, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(sum, 3)
+    }
+
+    #[test]
+    fn test_reload() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("__init__.py"), "from .value import VALUE\n").unwrap();
+        std::fs::write(dir.join("value.py"), "VALUE = 1\n").unwrap();
+
+        let module1 = PythonModule::new_module(dir).unwrap();
+        let get = |module: &PythonModule| {
+            module
+                .action(|_, module| module.getattr("VALUE")?.extract::<i64>())
+                .unwrap()
+        };
+        assert_eq!(get(&module1), 1);
+
+        std::fs::write(dir.join("value.py"), "VALUE = 1000\n").unwrap();
+        module1.reload().unwrap();
+        assert_eq!(get(&module1), 1000);
+    }
+
+    #[test]
+    fn test_spawn() {
+        let module1 = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let handles = (0..3)
+            .map(|i| {
+                module1
+                    .spawn(move |_, module| module.call_method1("add", (i, i))?.extract::<i64>())
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let sums = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sums, [0, 2, 4]);
+
+        let handle = module1.spawn(|_, _| Ok(())).unwrap();
+        while handle.try_join().is_none() {
+            thread::yield_now();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_task_handle() {
+        let module1 = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let slow = module1
+            .spawn(|py, _| {
+                py.import("time")?.call_method1("sleep", (0.2,))?;
+                Ok(())
+            })
+            .unwrap();
+        while !module1.pending_tasks().is_empty() {
+            thread::yield_now();
+        }
+        let cancelled = module1.spawn(|_, _| Ok(())).unwrap();
+        assert!(cancelled.cancel());
+        assert!(module1.pending_tasks().is_empty());
+        assert!(slow.join_timeout(Duration::from_millis(10)).is_err());
+        assert!(!slow.cancel());
+        slow.join_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(cancelled.join().unwrap_err().kind(), &ErrorKind::Cancelled);
+
+        let sum = module1
+            .spawn(|_, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(sum, 3);
+
+        let handles = (0..3)
+            .map(|i| module1.spawn(move |_, _| Ok(i)).unwrap())
+            .collect::<Vec<_>>();
+        let results = join_all(handles)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(results, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_drop_dead_worker() {
+        let module1 = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        module1
+            .queue(Box::new(|_, _| panic!("worker dies")))
+            .unwrap();
+        let handle = module1.spawn(|_, _| Ok(())).unwrap();
+        let err = handle.join().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::WorkerDead { .. }));
+        while module1.is_alive() {
+            thread::yield_now();
+        }
+        let err = module1.action(|_, _| Ok(())).unwrap_err();
+        let ErrorKind::WorkerDead { reason } = err.kind() else {
+            panic!("unexpected error {err}");
+        };
+        assert_eq!(reason, &Some(ExitReason::Panic("worker dies".to_string())));
+        drop(module1);
+    }
 }
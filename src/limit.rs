@@ -0,0 +1,245 @@
+use crate::{ErrorKind, PyRunnerError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Admission checks of a module, configured with
+/// [`PythonModuleBuilder::rate_limit`](crate::PythonModuleBuilder::rate_limit),
+/// [`max_concurrent`](crate::PythonModuleBuilder::max_concurrent) and
+/// [`circuit_breaker`](crate::PythonModuleBuilder::circuit_breaker)
+#[derive(Default)]
+pub(crate) struct Limits {
+    rate: Option<Mutex<Bucket>>,
+    max_concurrent: Option<usize>,
+    in_flight: Arc<AtomicUsize>,
+    breaker: Option<Arc<Mutex<Breaker>>>,
+}
+
+/// Token bucket refilled with `per_second` tokens, holding at most one second's worth
+struct Bucket {
+    per_second: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+struct Breaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Circuit,
+}
+
+#[derive(Clone, Copy)]
+enum Circuit {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { probing: bool },
+}
+
+/// Admitted task, releases its concurrency slot and a pending probe when dropped
+pub(crate) struct Permit {
+    in_flight: Option<Arc<AtomicUsize>>,
+    probe: Option<Arc<Mutex<Breaker>>>,
+}
+
+impl Limits {
+    pub(crate) fn new(
+        per_second: Option<f64>,
+        max_concurrent: Option<usize>,
+        breaker: Option<(u32, Duration)>,
+    ) -> Self {
+        Self {
+            rate: per_second.map(|per_second| {
+                Mutex::new(Bucket {
+                    per_second,
+                    tokens: per_second,
+                    refilled: Instant::now(),
+                })
+            }),
+            max_concurrent,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            breaker: breaker.map(|(threshold, cooldown)| {
+                Arc::new(Mutex::new(Breaker {
+                    threshold: threshold.max(1),
+                    cooldown,
+                    state: Circuit::Closed { failures: 0 },
+                }))
+            }),
+        }
+    }
+
+    /// Fails with [`ErrorKind::CircuitOpen`] or [`ErrorKind::RateLimited`] if the task must
+    /// not be queued
+    pub(crate) fn admit(&self) -> Result<Permit, PyRunnerError> {
+        let mut permit = Permit {
+            in_flight: None,
+            probe: None,
+        };
+        if let Some(breaker) = &self.breaker {
+            // the worker records outcomes while holding the GIL, so the error is only
+            // created once the lock is released
+            let probe = {
+                let mut state = breaker.lock().unwrap();
+                match state.state {
+                    Circuit::Closed { .. } => Ok(false),
+                    Circuit::Open { until } if Instant::now() < until => {
+                        Err("Circuit breaker is open after repeated failures")
+                    }
+                    Circuit::HalfOpen { probing: true } => {
+                        Err("Circuit breaker waits for its probe")
+                    }
+                    Circuit::Open { .. } | Circuit::HalfOpen { probing: false } => {
+                        state.state = Circuit::HalfOpen { probing: true };
+                        Ok(true)
+                    }
+                }
+            };
+            match probe {
+                Ok(true) => permit.probe = Some(breaker.clone()),
+                Ok(false) => {}
+                Err(message) => return Err(PyRunnerError::new(ErrorKind::CircuitOpen, message)),
+            }
+        }
+        if let Some(max) = self.max_concurrent {
+            let admitted = self
+                .in_flight
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                    (n < max).then_some(n + 1)
+                })
+                .is_ok();
+            if !admitted {
+                return Err(PyRunnerError::new(
+                    ErrorKind::RateLimited,
+                    format!("More than {max} tasks in flight"),
+                ));
+            }
+            permit.in_flight = Some(self.in_flight.clone());
+        }
+        if let Some(rate) = &self.rate {
+            let limited = {
+                let mut bucket = rate.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+                bucket.tokens =
+                    (bucket.tokens + elapsed * bucket.per_second).min(bucket.per_second);
+                bucket.refilled = now;
+                let limited = bucket.tokens < 1.0;
+                if !limited {
+                    bucket.tokens -= 1.0;
+                }
+                limited.then_some(bucket.per_second)
+            };
+            if let Some(per_second) = limited {
+                return Err(PyRunnerError::new(
+                    ErrorKind::RateLimited,
+                    format!("More than {per_second} calls per second"),
+                ));
+            }
+        }
+        Ok(permit)
+    }
+
+    /// Feeds the outcome of a task into the circuit breaker
+    pub(crate) fn record<T>(&self, result: &Result<T, PyRunnerError>) {
+        let Some(breaker) = &self.breaker else {
+            return;
+        };
+        let mut breaker = breaker.lock().unwrap();
+        let failed = match result {
+            Ok(_) => false,
+            Err(e) if e.kind() == &ErrorKind::Python => true,
+            // only exceptions raised by the module trip the breaker
+            Err(_) => return,
+        };
+        breaker.state = match (breaker.state, failed) {
+            (_, false) => Circuit::Closed { failures: 0 },
+            (Circuit::Closed { failures }, true) if failures + 1 < breaker.threshold => {
+                Circuit::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (_, true) => Circuit::Open {
+                until: Instant::now() + breaker.cooldown,
+            },
+        };
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(in_flight) = &self.in_flight {
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+        // the probe was dropped before it ran, the next call probes instead
+        if let Some(breaker) = &self.probe {
+            let mut breaker = breaker.lock().unwrap();
+            if let Circuit::HalfOpen { probing: true } = breaker.state {
+                breaker.state = Circuit::HalfOpen { probing: false };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ErrorKind, PythonModuleBuilder};
+    use pyo3::prelude::*;
+    use std::thread;
+    use std::time::Duration;
+
+    const FLAKY: &str = "import time\nhealthy = False\ndef call():\n    if not healthy:\n        raise ConnectionError('down')\n    return 1\ndef slow():\n    time.sleep(0.3)\n";
+
+    #[test]
+    fn test_rate_limit() {
+        let module = PythonModuleBuilder::from_source("limit_rate", FLAKY)
+            .rate_limit(2.0)
+            .build()
+            .unwrap();
+        let noop = || module.action(|_, _| Ok(()));
+        noop().unwrap();
+        noop().unwrap();
+        assert_eq!(noop().unwrap_err().kind(), &ErrorKind::RateLimited);
+        thread::sleep(Duration::from_millis(600));
+        noop().unwrap();
+    }
+
+    #[test]
+    fn test_max_concurrent() {
+        let module = PythonModuleBuilder::from_source("limit_concurrent", FLAKY)
+            .max_concurrent(1)
+            .build()
+            .unwrap();
+        let slow = module.spawn(|_, module| module.call_method0("slow").map(|_| ()));
+        thread::sleep(Duration::from_millis(50));
+        let err = module.action(|_, _| Ok(())).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::RateLimited);
+        slow.unwrap().join().unwrap();
+        // the slot is released right after the result was sent
+        thread::sleep(Duration::from_millis(50));
+        module.action(|_, _| Ok(())).unwrap();
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let module = PythonModuleBuilder::from_source("limit_breaker", FLAKY)
+            .circuit_breaker(2, Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let call = || module.action(|_, module| module.call_method0("call")?.extract::<i64>());
+        for _ in 0..2 {
+            assert_eq!(call().unwrap_err().kind(), &ErrorKind::Python);
+        }
+        assert_eq!(call().unwrap_err().kind(), &ErrorKind::CircuitOpen);
+
+        // the probe after the cooldown fails and opens the circuit again
+        thread::sleep(Duration::from_millis(250));
+        assert_eq!(call().unwrap_err().kind(), &ErrorKind::Python);
+        assert_eq!(call().unwrap_err().kind(), &ErrorKind::CircuitOpen);
+
+        thread::sleep(Duration::from_millis(250));
+        module
+            .action(|_, module| module.setattr("healthy", true))
+            .unwrap();
+        assert_eq!(call().unwrap(), 1);
+        assert_eq!(call().unwrap(), 1);
+    }
+}
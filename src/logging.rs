@@ -0,0 +1,127 @@
+use crate::helper::load_helper;
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+
+const HANDLER: &str = include_str!("logging/handler.py");
+
+/// Adds a handler to Python's root logger that forwards records to the `log` crate
+///
+/// Installed once per interpreter. The root logger's level is set to match
+/// [`log::max_level`], the Python logger name becomes the target of the record and
+/// formatted exceptions are part of the message.
+pub(crate) fn install(py: Python<'_>) -> PyResult<()> {
+    let handler = load_helper(py, "logging", HANDLER)?;
+    let sink = PyCFunction::new_closure(
+        py,
+        None,
+        None,
+        |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+            let (level, name, message, file, line) =
+                args.extract::<(u32, String, String, String, u32)>()?;
+            log::logger().log(
+                &log::Record::builder()
+                    .level(level_from_python(level))
+                    .target(&name)
+                    .args(format_args!("{message}"))
+                    .file(Some(&file))
+                    .line(Some(line))
+                    .build(),
+            );
+            Ok::<_, PyErr>(())
+        },
+    )?;
+    handler.call_method1("install", (sink, level_to_python(log::max_level())))?;
+    Ok(())
+}
+
+fn level_from_python(level: u32) -> log::Level {
+    match level {
+        40.. => log::Level::Error,
+        30..40 => log::Level::Warn,
+        20..30 => log::Level::Info,
+        10..20 => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+fn level_to_python(level: log::LevelFilter) -> u32 {
+    match level {
+        log::LevelFilter::Off => 100,
+        log::LevelFilter::Error => 40,
+        log::LevelFilter::Warn => 30,
+        log::LevelFilter::Info => 20,
+        log::LevelFilter::Debug => 10,
+        log::LevelFilter::Trace => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PythonModuleBuilder;
+    use pyo3::prelude::*;
+    use std::sync::Mutex;
+
+    static RECORDS: Mutex<Vec<(log::Level, String, String)>> = Mutex::new(Vec::new());
+
+    struct Logger;
+
+    impl log::Log for Logger {
+        fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            RECORDS.lock().unwrap().push((
+                record.level(),
+                record.target().to_string(),
+                record.args().to_string(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_forward_logging() {
+        log::set_logger(&Logger).unwrap();
+        log::set_max_level(log::LevelFilter::Info);
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import logging\n",
+                "logger = logging.getLogger('plugin.worker')\n",
+                "def work():\n",
+                "    logger.debug('hidden')\n",
+                "    logger.info('started %d', 3)\n",
+                "    try:\n",
+                "        1 / 0\n",
+                "    except ZeroDivisionError:\n",
+                "        logger.exception('failed')\n",
+            ),
+        )
+        .unwrap();
+
+        let module = PythonModuleBuilder::new_project(dir.join("main.py"))
+            .forward_logging(true)
+            .build()
+            .unwrap();
+        module
+            .action(|_, module| module.call_method0("work").map(|_| ()))
+            .unwrap();
+
+        let records = RECORDS.lock().unwrap();
+        let records = records
+            .iter()
+            .filter(|(_, target, _)| target == "plugin.worker")
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].0, log::Level::Info);
+        assert_eq!(records[0].2, "started 3");
+        assert_eq!(records[1].0, log::Level::Error);
+        assert!(records[1].2.starts_with("failed\nTraceback"));
+        assert!(records[1].2.contains("ZeroDivisionError"));
+    }
+}
@@ -0,0 +1,28 @@
+import logging
+
+
+class RustHandler(logging.Handler):
+    """Passes records to the Rust `log` crate"""
+
+    def __init__(self, sink):
+        super().__init__()
+        self.sink = sink
+
+    def emit(self, record):
+        try:
+            self.sink(
+                record.levelno,
+                record.name,
+                self.format(record),
+                record.pathname,
+                record.lineno,
+            )
+        except Exception:
+            self.handleError(record)
+
+
+def install(sink, level):
+    root = logging.getLogger()
+    if not any(isinstance(handler, RustHandler) for handler in root.handlers):
+        root.addHandler(RustHandler(sink))
+        root.setLevel(level)
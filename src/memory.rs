@@ -0,0 +1,119 @@
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+
+/// Memory usage of the interpreter running a module, see [`PythonModule::memory_stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// `sys.getallocatedblocks()`, memory blocks currently held by the allocator
+    pub allocated_blocks: usize,
+    /// Objects tracked by the garbage collector
+    pub gc_objects: usize,
+    /// `gc.get_count()`, allocations since the last collection of each generation
+    pub gc_counts: [usize; 3],
+    /// Whether the automatic garbage collection is enabled
+    pub gc_enabled: bool,
+    /// Current and peak bytes traced by `tracemalloc`, `None` unless tracing was enabled
+    /// with [`PythonModule::trace_allocations`]
+    pub traced: Option<TracedMemory>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TracedMemory {
+    pub current: usize,
+    pub peak: usize,
+}
+
+impl PythonModule {
+    /// Reads the memory counters of the interpreter the module runs in
+    ///
+    /// Modules share the interpreter, so the numbers cover all of them. For the subprocess
+    /// backend they describe this process, not the child.
+    ///```rs
+    /// let stats = module.memory_stats().unwrap();
+    /// if stats.allocated_blocks > 10_000_000 {
+    ///     module.gc_collect().unwrap();
+    /// }
+    /// ```
+    pub fn memory_stats(&self) -> Result<MemoryStats, PyRunnerError> {
+        self.action(|py, _| {
+            let gc = py.import("gc")?;
+            let tracemalloc = py.import("tracemalloc")?;
+            let traced = match tracemalloc.call_method0("is_tracing")?.extract()? {
+                true => {
+                    let (current, peak) = tracemalloc
+                        .call_method0("get_traced_memory")?
+                        .extract::<(usize, usize)>()?;
+                    Some(TracedMemory { current, peak })
+                }
+                false => None,
+            };
+            Ok(MemoryStats {
+                allocated_blocks: py
+                    .import("sys")?
+                    .call_method0("getallocatedblocks")?
+                    .extract()?,
+                gc_objects: gc.call_method0("get_objects")?.len()?,
+                gc_counts: gc
+                    .call_method0("get_count")?
+                    .extract::<(usize, usize, usize)>()
+                    .map(|(first, second, third)| [first, second, third])?,
+                gc_enabled: gc.call_method0("isenabled")?.extract()?,
+                traced,
+            })
+        })
+    }
+
+    /// Runs a full garbage collection and returns the number of unreachable objects found
+    pub fn gc_collect(&self) -> Result<usize, PyRunnerError> {
+        self.action(|py, _| py.import("gc")?.call_method0("collect")?.extract())
+    }
+
+    /// Turns the automatic garbage collection on or off, [`gc_collect`](Self::gc_collect)
+    /// still works while it is off
+    pub fn set_gc_enabled(&self, enabled: bool) -> Result<(), PyRunnerError> {
+        self.action(move |py, _| {
+            let method = if enabled { "enable" } else { "disable" };
+            py.import("gc")?.call_method0(method).map(|_| ())
+        })
+    }
+
+    /// Starts or stops `tracemalloc`, which adds [`TracedMemory`] to the stats at the cost of
+    /// slower allocations
+    pub fn trace_allocations(&self, enabled: bool) -> Result<(), PyRunnerError> {
+        self.action(move |py, _| {
+            let method = if enabled { "start" } else { "stop" };
+            py.import("tracemalloc")?.call_method0(method).map(|_| ())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_stats() {
+        let source = "class Node:\n    def __init__(self):\n        self.me = self\ndef leak(n):\n    global kept\n    kept = [bytearray(1024) for _ in range(n)]\n    for _ in range(n):\n        Node()\n";
+        let module = PythonModule::from_source("memory_leaky", source).unwrap();
+        let stats = module.memory_stats().unwrap();
+        assert!(stats.allocated_blocks > 0 && stats.gc_objects > 0);
+        assert!(stats.traced.is_none());
+
+        module.trace_allocations(true).unwrap();
+        module.set_gc_enabled(false).unwrap();
+        module
+            .action(|_, module| module.call_method1("leak", (100,)).map(|_| ()))
+            .unwrap();
+        let stats = module.memory_stats().unwrap();
+        assert!(!stats.gc_enabled);
+        let traced = stats.traced.unwrap();
+        assert!(traced.current >= 100 * 1024 && traced.peak >= traced.current);
+        // other modules of the process may collect the cycles first
+        module.gc_collect().unwrap();
+
+        module.set_gc_enabled(true).unwrap();
+        module.trace_allocations(false).unwrap();
+        let stats = module.memory_stats().unwrap();
+        assert!(stats.gc_enabled && stats.traced.is_none());
+    }
+}
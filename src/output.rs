@@ -0,0 +1,206 @@
+use crate::helper::load_helper;
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+use pyo3::types::{PyCFunction, PyDict, PyTuple};
+use std::sync::{Arc, Mutex};
+
+const STREAM: &str = include_str!("output/stream.py");
+
+pub(crate) type Sink = Arc<dyn Fn(OutputLine) + Send + Sync>;
+
+/// Stream a line of output was written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Line printed by a module, see [`PythonModuleBuilder::output`](crate::PythonModuleBuilder::output)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLine {
+    pub stream: Stream,
+    /// The line without its newline
+    pub line: String,
+}
+
+/// Sends every line the current thread writes to `sys.stdout` or `sys.stderr` to `sink`
+///
+/// Both streams are replaced once per interpreter by a stream that routes writes by thread,
+/// threads without a sink keep writing to the original streams. A trailing line without
+/// newline is sent on `flush()`.
+pub(crate) fn attach(py: Python<'_>, sink: &Sink) -> PyResult<()> {
+    let output = load_helper(py, "output", STREAM)?;
+    let callback = |stream: Stream| {
+        let sink = sink.clone();
+        PyCFunction::new_closure(
+            py,
+            None,
+            None,
+            move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                let (line,) = args.extract::<(String,)>()?;
+                sink(OutputLine { stream, line });
+                Ok::<_, PyErr>(())
+            },
+        )
+    };
+    output.call_method1(
+        "attach",
+        (callback(Stream::Stdout)?, callback(Stream::Stderr)?),
+    )?;
+    Ok(())
+}
+
+/// What a task printed, see [`PythonModule::action_captured`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapturedOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// `sys.stdout` and `sys.stderr` replaced by `io.StringIO` buffers
+struct Capture<'py> {
+    sys: Bound<'py, PyModule>,
+    previous: (Bound<'py, PyAny>, Bound<'py, PyAny>),
+    buffers: (Bound<'py, PyAny>, Bound<'py, PyAny>),
+}
+
+impl<'py> Capture<'py> {
+    fn start(py: Python<'py>) -> PyResult<Self> {
+        let sys = py.import("sys")?;
+        let io = py.import("io")?;
+        let previous = (sys.getattr("stdout")?, sys.getattr("stderr")?);
+        let buffers = (io.call_method0("StringIO")?, io.call_method0("StringIO")?);
+        sys.setattr("stdout", &buffers.0)?;
+        sys.setattr("stderr", &buffers.1)?;
+        Ok(Self {
+            sys,
+            previous,
+            buffers,
+        })
+    }
+
+    /// Restores the previous streams and returns what was written
+    fn finish(self) -> PyResult<CapturedOutput> {
+        self.sys.setattr("stdout", self.previous.0)?;
+        self.sys.setattr("stderr", self.previous.1)?;
+        Ok(CapturedOutput {
+            stdout: self.buffers.0.call_method0("getvalue")?.extract()?,
+            stderr: self.buffers.1.call_method0("getvalue")?.extract()?,
+        })
+    }
+}
+
+impl PythonModule {
+    /// Runs action while `sys.stdout` and `sys.stderr` are redirected into buffers
+    ///
+    /// The output is returned even if the action failed. The streams are shared by every
+    /// module of the interpreter, output of tasks running concurrently on other workers is
+    /// captured as well.
+    ///```rs
+    /// let (result, output) = module.action_captured(|_, module| module.call_method0("main").map(|_| ()));
+    /// println!("{}", output.stdout);
+    /// ```
+    pub fn action_captured<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> (Result<T, PyRunnerError>, CapturedOutput) {
+        let output = Arc::new(Mutex::new(CapturedOutput::default()));
+        let slot = output.clone();
+        let result = self.action(move |py, module| {
+            let capture = Capture::start(*py)?;
+            let result = call(py, module);
+            *slot.lock().unwrap() = capture.finish()?;
+            result
+        });
+        let output = std::mem::take(&mut *output.lock().unwrap());
+        (result, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_action_captured() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let (result, output) = module.action_captured(|py, _| {
+            py.run(
+                c"import sys\nprint('hello')\nprint('oops', file=sys.stderr)",
+                None,
+                None,
+            )?;
+            Ok(1)
+        });
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(
+            output,
+            CapturedOutput {
+                stdout: "hello\n".to_string(),
+                stderr: "oops\n".to_string()
+            }
+        );
+
+        let (result, output) = module.action_captured(|py, _| {
+            py.run(c"print('before')\nraise ValueError('failed')", None, None)
+        });
+        assert!(result.is_err());
+        assert_eq!(output.stdout, "before\n");
+
+        let restored = module
+            .action(|py, _| {
+                let sys = py.import("sys")?;
+                Ok(sys.getattr("stdout")?.is(&sys.getattr("__stdout__")?))
+            })
+            .unwrap();
+        assert!(restored);
+    }
+
+    #[test]
+    fn test_output() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import sys\n",
+                "print('imported')\n",
+                "def work():\n",
+                "    print('step 1')\n",
+                "    sys.stderr.write('warn')\n",
+                "    sys.stderr.write('ing\\nlast')\n",
+                "    sys.stderr.flush()\n",
+            ),
+        )
+        .unwrap();
+
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        let module = crate::PythonModuleBuilder::new_project(dir.join("main.py"))
+            .output(sender)
+            .build()
+            .unwrap();
+        module
+            .action(|_, module| module.call_method0("work").map(|_| ()))
+            .unwrap();
+        let line = |stream, line: &str| OutputLine {
+            stream,
+            line: line.to_string(),
+        };
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                line(Stream::Stdout, "imported"),
+                line(Stream::Stdout, "step 1"),
+                line(Stream::Stderr, "warning"),
+                line(Stream::Stderr, "last"),
+            ]
+        );
+
+        let (result, output) =
+            module.action_captured(|py, _| py.run(c"print('captured')", None, None));
+        result.unwrap();
+        assert_eq!(output.stdout, "captured\n");
+        assert!(receiver.is_empty());
+    }
+}
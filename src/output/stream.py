@@ -0,0 +1,45 @@
+import sys
+import threading
+
+_local = threading.local()
+
+
+class ThreadStream:
+    """Sends complete lines written by threads with a sink to it, other writes go to the
+    original stream"""
+
+    def __init__(self, original, name):
+        self.original = original
+        self.name = name
+
+    def write(self, text):
+        state = getattr(_local, self.name, None)
+        if state is None:
+            if self.original is None:
+                return len(text)
+            return self.original.write(text)
+        sink, pending = state
+        *lines, pending = (pending + text).split("\n")
+        state[1] = pending
+        for line in lines:
+            sink(line)
+        return len(text)
+
+    def flush(self):
+        state = getattr(_local, self.name, None)
+        if state is not None and state[1]:
+            state[0](state[1])
+            state[1] = ""
+        elif state is None and self.original is not None:
+            self.original.flush()
+
+    def __getattr__(self, name):
+        return getattr(self.original, name)
+
+
+def attach(stdout, stderr):
+    for name, sink in (("stdout", stdout), ("stderr", stderr)):
+        stream = getattr(sys, name)
+        if not isinstance(stream, ThreadStream):
+            setattr(sys, name, ThreadStream(stream, name))
+        setattr(_local, name, [sink, ""])
@@ -0,0 +1,192 @@
+use image::{DynamicImage, GrayAlphaImage, GrayImage, RgbImage, RgbaImage};
+use pyo3::prelude::*;
+
+/// `image::DynamicImage` exchanged with Pillow's `PIL.Image.Image`
+///
+/// 8 bit grayscale, grayscale with alpha, RGB and RGBA images keep their layout as PIL modes
+/// `L`, `LA`, `RGB` and `RGBA`, other images are converted to RGBA in both directions,
+/// like palette images from Python or 16 bit images from Rust.
+///```rs
+/// let frame = Image(image::DynamicImage::ImageRgb8(pixels));
+/// let annotated: Image = module.action(move |_, module| module.call_method1("detect", (frame,))?.extract())?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image(pub DynamicImage);
+
+impl Image {
+    pub fn into_inner(self) -> DynamicImage {
+        self.0
+    }
+}
+
+impl From<DynamicImage> for Image {
+    fn from(image: DynamicImage) -> Self {
+        Self(image)
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Image {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let size = (self.0.width(), self.0.height());
+        let (mode, data) = match self.0 {
+            DynamicImage::ImageLuma8(image) => ("L", image.into_raw()),
+            DynamicImage::ImageLumaA8(image) => ("LA", image.into_raw()),
+            DynamicImage::ImageRgb8(image) => ("RGB", image.into_raw()),
+            DynamicImage::ImageRgba8(image) => ("RGBA", image.into_raw()),
+            image => ("RGBA", image.into_rgba8().into_raw()),
+        };
+        let bytes = pyo3::types::PyBytes::new(py, &data);
+        py.import("PIL.Image")?
+            .call_method1("frombytes", (mode, size, bytes))
+    }
+}
+
+impl<'py> FromPyObject<'py> for Image {
+    fn extract_bound(object: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let mode = object.getattr("mode")?.extract::<String>()?;
+        let image = match mode.as_str() {
+            "L" | "LA" | "RGB" | "RGBA" => object.clone(),
+            _ => object.call_method1("convert", ("RGBA",))?,
+        };
+        let (width, height) = image.getattr("size")?.extract::<(u32, u32)>()?;
+        let data = image.call_method0("tobytes")?.extract::<Vec<u8>>()?;
+        let image = match mode.as_str() {
+            "L" => GrayImage::from_raw(width, height, data).map(DynamicImage::ImageLuma8),
+            "LA" => GrayAlphaImage::from_raw(width, height, data).map(DynamicImage::ImageLumaA8),
+            "RGB" => RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8),
+            _ => RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8),
+        };
+        image.map(Self).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "{width}x{height} {mode} image has too few bytes"
+            ))
+        })
+    }
+}
+
+/// Image file contents like PNG or JPEG, decoded by Pillow when converted to Python
+///```rs
+/// let photo = EncodedImage(std::fs::read("photo.jpg")?);
+/// let thumbnail = module.action(move |_, module| {
+///     let thumbnail = module.call_method1("thumbnail", (photo,))?;
+///     EncodedImage::from_pil(&thumbnail, "PNG")
+/// })?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedImage(pub Vec<u8>);
+
+impl EncodedImage {
+    /// Encodes the `PIL.Image.Image` `image` in `format`, e.g. `"PNG"` or `"JPEG"`
+    pub fn from_pil(image: &Bound<'_, PyAny>, format: &str) -> PyResult<Self> {
+        let file = image.py().import("io")?.call_method0("BytesIO")?;
+        let kwargs = pyo3::types::PyDict::new(image.py());
+        kwargs.set_item("format", format)?;
+        image.call_method("save", (&file,), Some(&kwargs))?;
+        Ok(Self(file.call_method0("getvalue")?.extract()?))
+    }
+}
+
+impl<'py> IntoPyObject<'py> for EncodedImage {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let bytes = pyo3::types::PyBytes::new(py, &self.0);
+        let file = py.import("io")?.call_method1("BytesIO", (bytes,))?;
+        let image = py.import("PIL.Image")?.call_method1("open", (file,))?;
+        // decodes now, Pillow reads lazily from the file
+        image.call_method0("load")?;
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModule;
+
+    /// Stands in for Pillow, storing images as `format;mode;width;height;pixels` files
+    const PIL_STUB: &str = r#"
+import sys, types
+
+class Image:
+    def __init__(self, mode, size, data):
+        self.mode, self.size, self.data = mode, size, bytes(data)
+
+    def tobytes(self):
+        return self.data
+
+    def convert(self, mode):
+        assert self.mode == "P" and mode == "RGBA"
+        return Image(mode, self.size, b"".join(bytes([v, v, v, 255]) for v in self.data))
+
+    def save(self, file, format):
+        file.write(f"{format};{self.mode};{self.size[0]};{self.size[1]};".encode() + self.data)
+
+    def load(self):
+        pass
+
+def frombytes(mode, size, data):
+    return Image(mode, size, data)
+
+def open(file):
+    _, mode, width, height, data = file.read().split(b";", 4)
+    return Image(mode.decode(), (int(width), int(height)), data)
+
+pil = types.ModuleType("PIL")
+pil.Image = types.ModuleType("PIL.Image")
+pil.Image.__dict__.update(Image=Image, frombytes=frombytes, open=open)
+sys.modules.update({"PIL": pil, "PIL.Image": pil.Image})
+"#;
+
+    #[test]
+    fn test_image() {
+        let module = PythonModule::from_source("pil_stub", PIL_STUB).unwrap();
+        let pixels = RgbImage::from_raw(2, 1, vec![255, 0, 0, 0, 0, 255]).unwrap();
+        let image = Image(DynamicImage::ImageRgb8(pixels));
+        let expected = image.clone();
+        let (size, roundtrip, converted, wide, encoded) = module
+            .action(move |py, _| {
+                let object = image.into_pyobject(*py)?;
+                let size = object.getattr("size")?.extract::<(u32, u32)>()?;
+                let roundtrip = object.extract::<Image>()?;
+                let palette = py
+                    .import("PIL.Image")?
+                    .call_method1("frombytes", ("P", (1, 1), vec![7u8]))?;
+                // 16 bit images arrive as RGBA
+                let wide = DynamicImage::ImageLuma16(image::ImageBuffer::from_pixel(
+                    1,
+                    1,
+                    image::Luma([u16::MAX]),
+                ));
+                let wide = Image(wide).into_pyobject(*py)?;
+                let wide = (
+                    wide.getattr("mode")?.extract::<String>()?,
+                    wide.extract::<Image>()?,
+                );
+                let encoded = EncodedImage::from_pil(&object, "PNG")?;
+                let decoded = encoded.clone().into_pyobject(*py)?.extract::<Image>()?;
+                Ok((
+                    size,
+                    roundtrip,
+                    palette.extract::<Image>()?,
+                    wide,
+                    (encoded, decoded),
+                ))
+            })
+            .unwrap();
+        assert_eq!(size, (2, 1));
+        assert_eq!(roundtrip, expected);
+        assert_eq!(converted.0.as_bytes(), [7, 7, 7, 255]);
+        assert!(matches!(converted.0, DynamicImage::ImageRgba8(_)));
+        assert_eq!(wide.0, "RGBA");
+        assert_eq!(wide.1.0.as_bytes(), [255, 255, 255, 255]);
+        assert!(encoded.0.0.starts_with(b"PNG;RGB;2;1;"));
+        assert_eq!(encoded.1, expected);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::{PyRunnerError, PythonModule, PythonModuleBuilder};
+use pyo3::call::PyCallArgs;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+type Configure = Box<dyn Fn(PythonModuleBuilder) -> PythonModuleBuilder + Send + Sync>;
+
+/// Discovers Python plugins in a directory and loads each into its own [`PythonModule`]
+///
+/// Every subdirectory with an entry point is a plugin named after the directory. The entry
+/// point is, in this order:
+/// - `entry-point` in the `[tool.py-runner]` table of `pyproject.toml`, relative to the
+///   plugin directory (reading it requires Python 3.11+)
+/// - `plugin.py`
+/// - `__init__.py`, which loads the directory as package
+///
+/// A plugin may define the hooks `on_load()`, called once it is loaded, `on_unload()`,
+/// called before it is disabled, unloaded or the host is dropped, and
+/// `on_config_change(config)`, see [`update_config`](Self::update_config).
+///```rs
+/// let plugins = PluginHost::new("./plugins");
+/// plugins.scan().unwrap();
+/// let analytics = plugins.get("analytics").unwrap();
+/// plugins.disable("legacy");
+/// ```
+pub struct PluginHost {
+    dir: PathBuf,
+    configure: Configure,
+    api_versions: Option<RangeInclusive<u32>>,
+    plugins: Mutex<BTreeMap<String, Plugin>>,
+}
+
+struct Plugin {
+    entry: PathBuf,
+    module: Option<Arc<PythonModule>>,
+}
+
+impl PluginHost {
+    /// Plugin host for the subdirectories of `dir`, nothing is loaded before [`scan`](Self::scan)
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            configure: Box::new(|builder| builder),
+            api_versions: None,
+            plugins: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Adjusts the builder of every plugin, e.g. to expose host functions
+    pub fn configure(
+        mut self,
+        configure: impl Fn(PythonModuleBuilder) -> PythonModuleBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.configure = Box::new(configure);
+        self
+    }
+
+    /// Only loads plugins whose `PLUGIN_API_VERSION` lies in `versions`
+    ///
+    /// Plugins declaring another version or none at all fail to load with an `ImportError`.
+    pub fn api_versions(mut self, versions: RangeInclusive<u32>) -> Self {
+        self.api_versions = Some(versions);
+        self
+    }
+
+    /// Loads every plugin found that isn't known yet and returns their names
+    ///
+    /// Disabled plugins stay disabled. Fails on the first plugin that can't be loaded, the
+    /// plugins loaded before stay enabled.
+    pub fn scan(&self) -> Result<Vec<String>, PyRunnerError> {
+        let mut found = Vec::new();
+        for entry in std::fs::read_dir(&self.dir).map_err(PyErr::from)? {
+            let path = entry.map_err(PyErr::from)?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !path.is_dir() || self.plugins.lock().unwrap().contains_key(name) {
+                continue;
+            }
+            if let Some(entry) = entry_point(&path)? {
+                found.push((name.to_string(), entry));
+            }
+        }
+        found.sort();
+
+        let mut names = Vec::new();
+        for (name, entry) in found {
+            let module = self.load(&entry)?;
+            self.plugins.lock().unwrap().insert(
+                name.clone(),
+                Plugin {
+                    entry,
+                    module: Some(module),
+                },
+            );
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    /// Names of all known plugins, enabled or not
+    pub fn names(&self) -> Vec<String> {
+        self.plugins.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// The module of the plugin `name`, `None` if it is unknown or disabled
+    pub fn get(&self, name: &str) -> Option<Arc<PythonModule>> {
+        self.plugins.lock().unwrap().get(name)?.module.clone()
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Loads a disabled plugin again, from its current source
+    pub fn enable(&self, name: &str) -> Result<(), PyRunnerError> {
+        let entry = match self.plugins.lock().unwrap().get(name) {
+            Some(Plugin {
+                module: Some(_), ..
+            }) => return Ok(()),
+            Some(plugin) => plugin.entry.clone(),
+            None => {
+                return Err(PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!(
+                    "No plugin named {name}"
+                ))
+                .into());
+            }
+        };
+        let module = self.load(&entry)?;
+        if let Some(plugin) = self.plugins.lock().unwrap().get_mut(name) {
+            plugin.module = Some(module);
+        }
+        Ok(())
+    }
+
+    /// Drops the module of the plugin but keeps it known, returns false if it wasn't enabled
+    ///
+    /// Calls `on_unload` first, the worker stops once the last [`get`](Self::get) reference
+    /// is gone.
+    pub fn disable(&self, name: &str) -> bool {
+        let module = self
+            .plugins
+            .lock()
+            .unwrap()
+            .get_mut(name)
+            .and_then(|plugin| plugin.module.take());
+        module.map(|module| unload_hook(&module)).is_some()
+    }
+
+    /// Forgets the plugin, the next [`scan`](Self::scan) loads it again if it still exists
+    pub fn unload(&self, name: &str) -> bool {
+        let plugin = self.plugins.lock().unwrap().remove(name);
+        if let Some(module) = plugin.as_ref().and_then(|plugin| plugin.module.as_ref()) {
+            unload_hook(module);
+        }
+        plugin.is_some()
+    }
+
+    /// Passes `config` to `on_config_change` of every enabled plugin
+    ///
+    /// Stops at the first plugin raising an exception.
+    pub fn update_config<V>(&self, config: V) -> Result<(), PyRunnerError>
+    where
+        V: for<'py> IntoPyObject<'py> + Clone + Send + 'static,
+    {
+        let modules = self
+            .plugins
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|plugin| plugin.module.clone())
+            .collect::<Vec<_>>();
+        for module in modules {
+            let config = config.clone();
+            module.action(move |_, module| hook(module, "on_config_change", (config,)))?;
+        }
+        Ok(())
+    }
+
+    fn load(&self, entry: &Path) -> Result<Arc<PythonModule>, PyRunnerError> {
+        let builder = if entry.file_name().is_some_and(|name| name == "__init__.py") {
+            PythonModuleBuilder::new_module(entry.parent().unwrap_or(Path::new(".")))
+        } else {
+            PythonModuleBuilder::new(entry)
+        };
+        let module = (self.configure)(builder).build()?;
+        if let Some(versions) = self.api_versions.clone() {
+            let entry = entry.display().to_string();
+            module.action(move |_, module| check_api_version(module, &entry, &versions))?;
+        }
+        module.action(|_, module| hook(module, "on_load", ()))?;
+        Ok(Arc::new(module))
+    }
+}
+
+impl Drop for PluginHost {
+    fn drop(&mut self) {
+        let plugins = std::mem::take(self.plugins.get_mut().unwrap());
+        for module in plugins.into_values().filter_map(|plugin| plugin.module) {
+            unload_hook(&module);
+        }
+    }
+}
+
+/// Calls the hook `name` if the plugin defines it
+fn hook<'py>(module: &Bound<'py, PyAny>, name: &str, args: impl PyCallArgs<'py>) -> PyResult<()> {
+    if module.hasattr(name)? {
+        module.getattr(name)?.call1(args)?;
+    }
+    Ok(())
+}
+
+/// `on_unload` hook, whose errors are ignored since the plugin goes away regardless
+fn unload_hook(module: &PythonModule) {
+    let _ = module.action(|_, module| hook(module, "on_unload", ()));
+}
+
+fn check_api_version(
+    module: &Bound<'_, PyAny>,
+    entry: &str,
+    versions: &RangeInclusive<u32>,
+) -> PyResult<()> {
+    let supported = format!("{}..={}", versions.start(), versions.end());
+    if !module.hasattr("PLUGIN_API_VERSION")? {
+        return Err(PyErr::new::<pyo3::exceptions::PyImportError, _>(format!(
+            "Plugin {entry} declares no PLUGIN_API_VERSION, the host supports {supported}"
+        )));
+    }
+    let version = module.getattr("PLUGIN_API_VERSION")?.extract::<u32>()?;
+    if !versions.contains(&version) {
+        return Err(PyErr::new::<pyo3::exceptions::PyImportError, _>(format!(
+            "Plugin {entry} declares API version {version}, the host supports {supported}"
+        )));
+    }
+    Ok(())
+}
+
+/// Entry point of the plugin in `dir`, `None` if it isn't a plugin
+fn entry_point(dir: &Path) -> Result<Option<PathBuf>, PyRunnerError> {
+    let pyproject = dir.join("pyproject.toml");
+    if pyproject.is_file() {
+        let source = std::fs::read_to_string(&pyproject).map_err(PyErr::from)?;
+        let entry = Python::with_gil(|py| -> PyResult<Option<String>> {
+            let config = py.import("tomllib")?.call_method1("loads", (source,))?;
+            let Ok(tool) = config
+                .get_item("tool")
+                .and_then(|tool| tool.get_item("py-runner"))
+            else {
+                return Ok(None);
+            };
+            match tool.get_item("entry-point") {
+                Ok(entry) => entry.extract().map(Some),
+                Err(_) => Ok(None),
+            }
+        })?;
+        if let Some(entry) = entry {
+            return Ok(Some(dir.join(entry)));
+        }
+    }
+    Ok(["plugin.py", "__init__.py"]
+        .into_iter()
+        .map(|file| dir.join(file))
+        .find(|entry| entry.is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_host() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for plugin in ["alpha", "beta", "gamma", "notes"] {
+            std::fs::create_dir_all(dir.join(plugin)).unwrap();
+        }
+        std::fs::write(dir.join("alpha/plugin.py"), "NAME = 'alpha'\n").unwrap();
+        std::fs::write(
+            dir.join("beta/pyproject.toml"),
+            "[project]\nname = 'beta'\n\n[tool.py-runner]\nentry-point = 'src/main.py'\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("beta/src")).unwrap();
+        std::fs::write(dir.join("beta/src/main.py"), "NAME = 'beta'\n").unwrap();
+        std::fs::write(dir.join("gamma/__init__.py"), "from .name import NAME\n").unwrap();
+        std::fs::write(dir.join("gamma/name.py"), "NAME = 'gamma'\n").unwrap();
+        std::fs::write(dir.join("notes/README.md"), "not a plugin\n").unwrap();
+
+        let plugins = PluginHost::new(dir);
+        assert_eq!(plugins.scan().unwrap(), ["alpha", "beta", "gamma"]);
+        assert!(plugins.scan().unwrap().is_empty());
+        for name in plugins.names() {
+            let module = plugins.get(&name).unwrap();
+            let declared = module
+                .action(|_, module| module.getattr("NAME")?.extract::<String>())
+                .unwrap();
+            assert_eq!(declared, name);
+        }
+
+        assert!(plugins.disable("beta"));
+        assert!(!plugins.disable("beta"));
+        assert!(plugins.get("beta").is_none());
+        assert_eq!(plugins.names(), ["alpha", "beta", "gamma"]);
+        plugins.enable("beta").unwrap();
+        assert!(plugins.is_enabled("beta"));
+
+        assert!(plugins.unload("alpha"));
+        assert_eq!(plugins.names(), ["beta", "gamma"]);
+        assert_eq!(plugins.scan().unwrap(), ["alpha"]);
+        assert!(plugins.enable("missing").is_err());
+    }
+
+    #[test]
+    fn test_plugin_hooks() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let log = dir.join("hooks.log");
+        let write_plugin = |path: PathBuf, version: &str| {
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(
+                path.join("plugin.py"),
+                format!(
+                    concat!(
+                        "{version}\n",
+                        "def record(event):\n",
+                        "    with open({log:?}, 'a') as log:\n",
+                        "        log.write(event + '\\n')\n",
+                        "def on_load():\n",
+                        "    record('load')\n",
+                        "def on_unload():\n",
+                        "    record('unload')\n",
+                        "def on_config_change(config):\n",
+                        "    record('config ' + config['mode'])\n",
+                    ),
+                    version = version,
+                    log = log.to_str().unwrap(),
+                ),
+            )
+            .unwrap();
+        };
+        write_plugin(
+            dir.join("current").join("current"),
+            "PLUGIN_API_VERSION = 2",
+        );
+        write_plugin(
+            dir.join("outdated").join("outdated"),
+            "PLUGIN_API_VERSION = 1",
+        );
+        write_plugin(dir.join("undeclared").join("undeclared"), "");
+
+        let plugins = PluginHost::new(dir.join("current")).api_versions(2..=3);
+        assert_eq!(plugins.scan().unwrap(), ["current"]);
+        plugins
+            .update_config(std::collections::HashMap::from([("mode", "fast")]))
+            .unwrap();
+        assert!(plugins.disable("current"));
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            "load\nconfig fast\nunload\n"
+        );
+
+        let err = PluginHost::new(dir.join("outdated"))
+            .api_versions(2..=3)
+            .scan()
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "ImportError");
+        assert!(err.message().contains("declares API version 1"));
+        let err = PluginHost::new(dir.join("undeclared"))
+            .api_versions(2..=3)
+            .scan()
+            .unwrap_err();
+        assert!(err.message().contains("declares no PLUGIN_API_VERSION"));
+    }
+}
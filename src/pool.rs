@@ -0,0 +1,344 @@
+use crate::{ErrorKind, PyRunnerError, PythonModule, PythonModuleBuilder, Task};
+use crossbeam::channel::{self, Sender};
+use pyo3::prelude::*;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+
+/// Several workers with the same module loaded, `action` calls go to the least busy one
+///
+/// [`new`](Self::new) runs every worker in its own `python` process, so CPU-bound actions run
+/// in parallel. [`new_in_process`](Self::new_in_process) keeps the workers in this process,
+/// where they share one GIL. Modules keeping state per session can route the calls of a
+/// session to the same worker with [`action_keyed`](Self::action_keyed).
+///```rs
+/// let pool = PythonPool::new("./my-module", 4).unwrap();
+/// pool.action(|py, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+///    .unwrap();
+/// ```
+pub struct PythonPool {
+    workers: Vec<Worker>,
+    shared: Option<Shared>,
+}
+
+struct Worker {
+    module: PythonModule,
+    in_flight: AtomicUsize,
+}
+
+impl PythonPool {
+    /// Loads the module (directory) or project (file) at `path` into `n_workers` workers, each
+    /// in its own process, see [`PythonModule::new_subprocess`]
+    pub fn new(path: impl AsRef<Path>, n_workers: usize) -> Result<PythonPool, PyRunnerError> {
+        let path = path.as_ref();
+        if cfg!(Py_GIL_DISABLED) {
+            return Self::shared(PythonModuleBuilder::new(path), n_workers);
+        }
+        Self::from_builder(n_workers, || {
+            PythonModuleBuilder::new(path).subprocess(true)
+        })
+    }
+
+    /// Loads the module (directory) or project (file) at `path` into `n_workers` worker threads
+    /// of this process
+    ///
+    /// The workers take turns holding the GIL, so only actions waiting on I/O overlap.
+    pub fn new_in_process(
+        path: impl AsRef<Path>,
+        n_workers: usize,
+    ) -> Result<PythonPool, PyRunnerError> {
+        let path = path.as_ref();
+        Self::from_builder(n_workers, || PythonModuleBuilder::new(path))
+    }
+
+    /// Loads the module once and runs actions on `n_threads` threads taking them from one
+    /// queue
+    ///
+    /// The threads share the module and its state and take turns holding the GIL, which lets
+    /// actions waiting on I/O overlap. The module can't use the subprocess backend.
+    ///```rs
+    /// let pool = PythonPool::shared(PythonModuleBuilder::new("./my-module"), 8).unwrap();
+    /// ```
+    pub fn shared(
+        builder: PythonModuleBuilder,
+        n_threads: usize,
+    ) -> Result<PythonPool, PyRunnerError> {
+        if n_threads == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "A pool needs at least one worker",
+            )
+            .into());
+        }
+        let module = builder.build()?;
+        if module.control.child.get().is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "A shared pool doesn't support the subprocess backend",
+            )
+            .into());
+        }
+        let handles = module.action(move |_, module| {
+            Ok((0..n_threads)
+                .map(|_| module.clone().unbind())
+                .collect::<Vec<_>>())
+        })?;
+        let (sender, receiver) = channel::unbounded::<Task>();
+        let threads = handles
+            .into_iter()
+            .map(|handle| {
+                let receiver = receiver.clone();
+                thread::spawn(move || {
+                    Python::with_gil(|py| {
+                        let module = handle.bind(py);
+                        while let Ok(task) = py.allow_threads(|| receiver.recv()) {
+                            task(&py, module);
+                        }
+                    })
+                })
+            })
+            .collect();
+        Ok(PythonPool {
+            workers: Vec::new(),
+            shared: Some(Shared {
+                module,
+                sender: Some(sender),
+                threads,
+            }),
+        })
+    }
+
+    /// Builds `n_workers` workers from the builders returned by `builder`
+    pub fn from_builder(
+        n_workers: usize,
+        builder: impl Fn() -> PythonModuleBuilder,
+    ) -> Result<PythonPool, PyRunnerError> {
+        if n_workers == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "A pool needs at least one worker",
+            )
+            .into());
+        }
+        let workers = (0..n_workers)
+            .map(|_| {
+                Ok(Worker {
+                    module: builder().build()?,
+                    in_flight: AtomicUsize::new(0),
+                })
+            })
+            .collect::<Result<_, PyRunnerError>>()?;
+        Ok(PythonPool {
+            workers,
+            shared: None,
+        })
+    }
+
+    /// Number of workers
+    pub fn len(&self) -> usize {
+        match &self.shared {
+            Some(shared) => shared.threads.len(),
+            None => self.workers.len(),
+        }
+    }
+
+    /// Always `false`, a pool has at least one worker
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Runs action on the worker with the fewest tasks in flight
+    pub fn action<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        if let Some(shared) = &self.shared {
+            return shared.run(call);
+        }
+        let worker = self
+            .workers
+            .iter()
+            .min_by_key(|worker| worker.in_flight.load(Ordering::Relaxed))
+            .expect("pool has at least one worker");
+        worker.run(call)
+    }
+
+    /// Runs action on the worker `key` is assigned to, the same key always uses the same
+    /// worker
+    ///
+    /// The threads of a [`shared`](Self::shared) pool share their state, any of them runs it.
+    ///```rs
+    /// pool.action_keyed(&session_id, |_, module| module.call_method1("next_page", ())?.extract::<String>())
+    ///    .unwrap();
+    /// ```
+    pub fn action_keyed<T: Send + 'static>(
+        &self,
+        key: &impl Hash,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        if let Some(shared) = &self.shared {
+            return shared.run(call);
+        }
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let index = hasher.finish() % self.workers.len() as u64;
+        self.workers[index as usize].run(call)
+    }
+}
+
+impl Worker {
+    fn run<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let result = self.module.action(call);
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Threads of a shared pool, the module's own worker keeps the module alive
+struct Shared {
+    module: PythonModule,
+    sender: Option<Sender<Task>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Shared {
+    fn run<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        let call = self.module.prepare(call);
+        let (reply, result) = channel::bounded(1);
+        let task: Task = Box::new(move |py, module| {
+            let _ = reply.send(call(py, module));
+        });
+        let terminated = || {
+            let message = "Pool thread terminated during the call";
+            PyRunnerError::new(ErrorKind::WorkerDead { reason: None }, message)
+        };
+        let sender = self.sender.as_ref().expect("sender lives until drop");
+        sender.send(task).map_err(|_| terminated())?;
+        result.recv().map_err(|_| terminated())?
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // the threads stop once the queue is closed and empty
+        self.sender.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_pool() {
+        assert!(PythonPool::new("./my-module", 0).is_err());
+
+        let pool = Arc::new(PythonPool::new("./my-module", 3).unwrap());
+        assert_eq!(pool.len(), 3);
+        // every worker is a child process
+        let mut children = pool
+            .workers
+            .iter()
+            .map(|worker| *worker.module.control.child.get().unwrap())
+            .collect::<Vec<u32>>();
+        children.sort();
+        children.dedup();
+        assert_eq!(children.len(), 3);
+        let handles = (0..6)
+            .map(|i| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    pool.action(move |_, module| {
+                        module.call_method1("add", (i, 1))?.extract::<i64>()
+                    })
+                    .unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        let sums = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sums, [1, 2, 3, 4, 5, 6]);
+
+        let pool = PythonPool::new_in_process("./my-module", 2).unwrap();
+        assert!(
+            pool.workers
+                .iter()
+                .all(|worker| worker.module.control.child.get().is_none())
+        );
+        let sum = pool
+            .action(|_, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(sum, 3);
+    }
+
+    #[test]
+    fn test_action_keyed() {
+        const COUNTER: &str =
+            "hits = 0\ndef hit():\n    global hits\n    hits += 1\n    return hits\n";
+        let pool = PythonPool::from_builder(3, || {
+            PythonModuleBuilder::from_source("sticky_counter", COUNTER)
+        })
+        .unwrap();
+        let hit = |key: &str| {
+            pool.action_keyed(&key, |_, module| {
+                module.call_method0("hit")?.extract::<i64>()
+            })
+            .unwrap()
+        };
+        assert_eq!([hit("alice"), hit("alice"), hit("alice")], [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_shared_pool() {
+        const SOURCE: &str = "import time\nhits = 0\ndef hit():\n    global hits\n    hits += 1\ndef wait():\n    time.sleep(0.3)\n";
+        let builder = || PythonModuleBuilder::from_source("shared_pool", SOURCE);
+        assert!(PythonPool::shared(builder(), 0).is_err());
+        let err = PythonPool::shared(PythonModuleBuilder::new("./my-module").subprocess(true), 2)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.message(),
+            "A shared pool doesn't support the subprocess backend"
+        );
+
+        let pool = Arc::new(PythonPool::shared(builder(), 3).unwrap());
+        assert_eq!(pool.len(), 3);
+        for _ in 0..10 {
+            pool.action(|_, module| module.call_method0("hit").map(|_| ()))
+                .unwrap();
+        }
+        let hits = pool.action_keyed(&"any", |_, module| module.getattr("hits")?.extract::<i64>());
+        assert_eq!(hits.unwrap(), 10);
+
+        // the threads wait for the sleeps at the same time
+        let started = std::time::Instant::now();
+        let handles = (0..3)
+            .map(|_| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    pool.action(|_, module| module.call_method0("wait").map(|_| ()))
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        assert!(started.elapsed() < std::time::Duration::from_millis(850));
+
+        let err = pool
+            .action(|_, module| module.call_method0("missing").map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "AttributeError");
+    }
+}
@@ -0,0 +1,220 @@
+use crate::venv::run;
+use crate::{PyRunnerError, Venv};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Written into an environment once it is complete, holds the lock hash
+const MARKER: &str = ".py-runner-lock";
+
+/// Creates the environment of a project from its `uv.lock` or `poetry.lock` with `uv`
+///
+/// Every lock gets its own environment in the cache directory, named by a hash of
+/// `pyproject.toml` and the lock file, so it is only installed again after either changed.
+/// Processes sharing a cache directory must not provision at the same time.
+///```rs
+/// let venv = Provisioner::new("./service").provision().unwrap();
+/// venv.activate().unwrap();
+/// let module = PythonModule::new_module("./service/src/service").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Provisioner {
+    project: PathBuf,
+    cache_dir: PathBuf,
+    python: Option<String>,
+    uv: PathBuf,
+    poetry: PathBuf,
+}
+
+/// Lock file a project is installed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lock {
+    Uv,
+    Poetry,
+}
+
+impl Provisioner {
+    /// Provisions the project in `project`, caching environments in its `.py-runner/envs`
+    pub fn new(project: impl AsRef<Path>) -> Self {
+        let project = project.as_ref().to_path_buf();
+        Provisioner {
+            cache_dir: project.join(".py-runner").join("envs"),
+            project,
+            python: None,
+            uv: "uv".into(),
+            poetry: "poetry".into(),
+        }
+    }
+
+    /// Directory the environments are kept in
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = dir.into();
+        self
+    }
+
+    /// Interpreter request passed to `uv --python`, e.g. `"3.12"`
+    pub fn python(mut self, python: impl Into<String>) -> Self {
+        self.python = Some(python.into());
+        self
+    }
+
+    /// `uv` executable, found on `PATH` by default
+    pub fn uv(mut self, uv: impl Into<PathBuf>) -> Self {
+        self.uv = uv.into();
+        self
+    }
+
+    /// `poetry` executable for projects locked with `poetry.lock`, found on `PATH` by default
+    pub fn poetry(mut self, poetry: impl Into<PathBuf>) -> Self {
+        self.poetry = poetry.into();
+        self
+    }
+
+    /// Hex SHA-256 of `pyproject.toml`, the lock file and the interpreter request
+    pub fn lock_hash(&self) -> Result<String, PyRunnerError> {
+        let lock = self.lock()?;
+        let mut content = Vec::new();
+        for file in ["pyproject.toml", lock.file_name()] {
+            content.extend(fs::read(self.project.join(file)).map_err(PyErr::from)?);
+            content.push(0);
+        }
+        content.extend(self.python.as_deref().unwrap_or_default().as_bytes());
+        Python::with_gil(|py| {
+            py.import("hashlib")?
+                .call_method1("sha256", (PyBytes::new(py, &content),))?
+                .call_method0("hexdigest")?
+                .extract()
+        })
+        .map_err(PyRunnerError::from)
+    }
+
+    /// Returns the environment for the current lock file, installing it first if needed
+    pub fn provision(&self) -> Result<Venv, PyRunnerError> {
+        let lock = self.lock()?;
+        let hash = self.lock_hash()?;
+        fs::create_dir_all(&self.cache_dir).map_err(PyErr::from)?;
+        // absolute, the tools run in the project directory
+        let env = fs::canonicalize(&self.cache_dir)
+            .map_err(PyErr::from)?
+            .join(&hash[..16]);
+        if fs::read_to_string(env.join(MARKER)).is_ok_and(|marker| marker == hash) {
+            return Venv::open(&env);
+        }
+        // left behind by a failed attempt, scripts in an environment can't be moved
+        if env.exists() {
+            fs::remove_dir_all(&env).map_err(PyErr::from)?;
+        }
+        match lock {
+            Lock::Uv => {
+                let mut sync = self.command(&self.uv);
+                sync.args(["sync", "--frozen", "--no-install-project"])
+                    .env("UV_PROJECT_ENVIRONMENT", &env);
+                run(self.with_python(&mut sync))?;
+            }
+            Lock::Poetry => {
+                let mut venv = self.command(&self.uv);
+                venv.arg("venv").arg(&env);
+                run(self.with_python(&mut venv))?;
+                // poetry installs into the environment named by VIRTUAL_ENV
+                run(self
+                    .command(&self.poetry)
+                    .args(["install", "--no-root", "--sync"])
+                    .env("VIRTUAL_ENV", &env))?;
+            }
+        }
+        let venv = Venv::open(&env)?;
+        fs::write(env.join(MARKER), &hash).map_err(PyErr::from)?;
+        Ok(venv)
+    }
+
+    fn lock(&self) -> Result<Lock, PyRunnerError> {
+        [Lock::Uv, Lock::Poetry]
+            .into_iter()
+            .find(|lock| self.project.join(lock.file_name()).is_file())
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyFileNotFoundError, _>(format!(
+                    "No uv.lock or poetry.lock found in {}",
+                    self.project.display()
+                ))
+                .into()
+            })
+    }
+
+    fn command(&self, program: &Path) -> Command {
+        let mut command = Command::new(program);
+        command.current_dir(&self.project);
+        command
+    }
+
+    fn with_python<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        if let Some(python) = &self.python {
+            command.arg("--python").arg(python);
+        }
+        command
+    }
+}
+
+impl Lock {
+    fn file_name(self) -> &'static str {
+        match self {
+            Lock::Uv => "uv.lock",
+            Lock::Poetry => "poetry.lock",
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Stands in for `uv sync`, creates the environment and counts its runs
+    const FAKE_UV: &str = r#"#!/bin/sh
+echo "$@" >> "$(dirname "$0")/calls"
+mkdir -p "$UV_PROJECT_ENVIRONMENT/lib/python3.99/site-packages"
+echo "version = 3.99.0" > "$UV_PROJECT_ENVIRONMENT/pyvenv.cfg"
+"#;
+
+    #[test]
+    fn test_provision() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let project = root.join("project");
+        fs::create_dir_all(&project).unwrap();
+        let uv = root.join("uv");
+        fs::write(&uv, FAKE_UV).unwrap();
+        fs::set_permissions(&uv, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let provisioner = Provisioner::new(&project).uv(&uv).python("3.99");
+        assert!(provisioner.provision().is_err());
+        fs::write(
+            project.join("pyproject.toml"),
+            "[project]\nname = \"demo\"\n",
+        )
+        .unwrap();
+        fs::write(project.join("uv.lock"), "version = 1\n").unwrap();
+
+        let venv = provisioner.provision().unwrap();
+        assert!(venv.site_packages().ends_with("python3.99/site-packages"));
+        let again = provisioner.provision().unwrap();
+        assert_eq!(again.root(), venv.root());
+        let calls = fs::read_to_string(root.join("calls")).unwrap();
+        assert_eq!(calls, "sync --frozen --no-install-project --python 3.99\n");
+
+        // a new lock gets a new environment
+        let hash = provisioner.lock_hash().unwrap();
+        fs::write(project.join("uv.lock"), "version = 2\n").unwrap();
+        assert_ne!(provisioner.lock_hash().unwrap(), hash);
+        let updated = provisioner.provision().unwrap();
+        assert_ne!(updated.root(), venv.root());
+        assert_eq!(
+            fs::read_to_string(root.join("calls"))
+                .unwrap()
+                .lines()
+                .count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use crate::PyRunnerError;
+use pyo3::prelude::*;
+use std::env;
+use std::path::PathBuf;
+
+/// Entries put in front of and behind the existing ones of `PYTHONPATH`, or of `sys.path`
+/// with [`PythonModuleBuilder::python_path`](crate::PythonModuleBuilder::python_path)
+///```rs
+/// PythonPath::builder()
+///     .prepend("./venv/lib/python3.11/site-packages")
+///     .append("./vendor")
+///     .apply()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PythonPath {
+    prepend: Vec<PathBuf>,
+    append: Vec<PathBuf>,
+}
+
+impl PythonPath {
+    pub fn builder() -> Self {
+        Self::default()
+    }
+
+    /// Puts `path` in front of the existing entries, after the ones prepended before
+    pub fn prepend(mut self, path: impl Into<PathBuf>) -> Self {
+        self.prepend.push(path.into());
+        self
+    }
+
+    /// Puts `path` behind the existing entries and the ones appended before
+    pub fn append(mut self, path: impl Into<PathBuf>) -> Self {
+        self.append.push(path.into());
+        self
+    }
+
+    /// The entries in front of the existing ones
+    pub fn prepended(&self) -> &[PathBuf] {
+        &self.prepend
+    }
+
+    /// The entries behind the existing ones
+    pub fn appended(&self) -> &[PathBuf] {
+        &self.append
+    }
+
+    /// Combines the entries with `current`, keeping only the first of duplicates and dropping
+    /// empty ones
+    pub fn resolve(&self, current: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        for path in self
+            .prepend
+            .iter()
+            .cloned()
+            .chain(current)
+            .chain(self.append.iter().cloned())
+        {
+            if !path.as_os_str().is_empty() && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    /// Updates `PYTHONPATH` of this process, joined with the platform's separator
+    ///
+    /// Only processes started from Rust later read it, and the embedded interpreter if it
+    /// isn't initialized yet.
+    pub fn apply(&self) -> Result<(), PyRunnerError> {
+        let current = env::var_os("PYTHONPATH")
+            .map(|current| env::split_paths(&current).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let joined = env::join_paths(self.resolve(current)).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid PYTHONPATH: {e}"))
+        })?;
+        unsafe {
+            env::set_var("PYTHONPATH", joined);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let path = PythonPath::builder()
+            .prepend("/venv/site-packages")
+            .prepend("/first")
+            .append("/vendor")
+            .append("/existing");
+        let resolved = path.resolve([
+            "/existing".into(),
+            "".into(),
+            "/first".into(),
+            "/other".into(),
+        ]);
+        let expected = [
+            "/venv/site-packages",
+            "/first",
+            "/existing",
+            "/other",
+            "/vendor",
+        ];
+        assert_eq!(resolved, expected.map(PathBuf::from));
+
+        let invalid = PythonPath::builder().append(if cfg!(windows) { "a;b" } else { "a:b" });
+        assert!(invalid.apply().is_err());
+    }
+
+    #[test]
+    fn test_python_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for name in ["first", "last"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            std::fs::write(
+                dir.join(name).join("shadow.py"),
+                format!("NAME = '{name}'\n"),
+            )
+            .unwrap();
+        }
+        std::fs::write(dir.join("main.py"), "import sys\nfrom shadow import NAME\n").unwrap();
+
+        let module = crate::PythonModuleBuilder::new_project(dir.join("main.py"))
+            .subprocess(true)
+            .sys_path(dir.join("first"))
+            .python_path(PythonPath::builder().append(dir.join("last")))
+            .build()
+            .unwrap();
+        assert_eq!(module.get::<String>("NAME").unwrap(), "first");
+        let sys_path = module.get::<Vec<PathBuf>>("sys.path").unwrap();
+        let first = sys_path.iter().position(|path| path.ends_with("first"));
+        let last = sys_path.iter().position(|path| path.ends_with("last"));
+        assert!(first.unwrap() < last.unwrap());
+    }
+}
@@ -0,0 +1,488 @@
+use crate::worker::Control;
+use crate::{ErrorKind, PyRunnerError, PythonModule, Task};
+use crossbeam::channel::{Receiver, TrySendError};
+use pyo3::prelude::*;
+use std::cell::Cell;
+use std::collections::{BTreeMap, VecDeque};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// What queuing a task does while the queue is full, see
+/// [`PythonModuleBuilder::queue_capacity`](crate::PythonModuleBuilder::queue_capacity)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Blocks the caller until the worker took a task
+    Block,
+    /// Fails the new task with [`ErrorKind::QueueFull`]
+    FailFast,
+    /// Fails the oldest queued task with [`ErrorKind::QueueFull`] to make room
+    DropOldest,
+}
+
+/// Order in which queued tasks run, see [`PythonModule::action_with_priority`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// Runs only while no other task is queued, e.g. for bulk background work
+    Low,
+    /// Runs in queue order, used by every other action
+    #[default]
+    Normal,
+    /// Runs before every queued normal task, e.g. for user-facing requests
+    High,
+}
+
+/// Tasks queued with a priority other than [`Priority::Normal`], which share the channel
+#[derive(Default)]
+pub(crate) struct Lanes {
+    high: Mutex<VecDeque<Task>>,
+    low: Mutex<VecDeque<Task>>,
+}
+
+impl Lanes {
+    fn lane(&self, priority: Priority) -> &Mutex<VecDeque<Task>> {
+        match priority {
+            Priority::Low => &self.low,
+            _ => &self.high,
+        }
+    }
+
+    fn push(&self, priority: Priority, task: Task) {
+        self.lane(priority).lock().unwrap().push_back(task);
+    }
+
+    pub(crate) fn pop_high(&self) -> Option<Task> {
+        self.high.lock().unwrap().pop_front()
+    }
+
+    /// Next task to run while the channel is empty
+    pub(crate) fn pop(&self) -> Option<Task> {
+        self.pop_high()
+            .or_else(|| self.low.lock().unwrap().pop_front())
+    }
+
+    /// Drops every task, returns how many there were
+    pub(crate) fn clear(&self) -> usize {
+        // taken out first, dropping a task may queue another one
+        let high = std::mem::take(&mut *self.high.lock().unwrap());
+        let low = std::mem::take(&mut *self.low.lock().unwrap());
+        high.len() + low.len()
+    }
+}
+
+/// Id of a queued task, see [`PythonModule::pending_tasks`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+/// Task waiting in the queue of a module, see [`PythonModule::pending_tasks`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingTask {
+    pub id: TaskId,
+    pub priority: Priority,
+    pub queued: Instant,
+}
+
+/// Queued tasks that didn't start yet, the channel only carries tickets for them
+#[derive(Default)]
+pub(crate) struct Backlog {
+    next_id: AtomicU64,
+    tasks: Mutex<BTreeMap<TaskId, (PendingTask, Task)>>,
+    /// Task taken from the backlog that is running right now
+    running: Mutex<Option<TaskId>>,
+}
+
+impl Backlog {
+    fn insert(&self, priority: Priority, task: Task) -> TaskId {
+        let id = TaskId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let pending = PendingTask {
+            id,
+            priority,
+            queued: Instant::now(),
+        };
+        self.tasks.lock().unwrap().insert(id, (pending, task));
+        id
+    }
+
+    fn take(&self, id: TaskId) -> Option<Task> {
+        self.tasks.lock().unwrap().remove(&id).map(|(_, task)| task)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.tasks.lock().unwrap().len()
+    }
+
+    /// Drops the task of `id`, which fails with [`ErrorKind::Cancelled`], `false` if it
+    /// already started or doesn't exist
+    pub(crate) fn cancel(&self, id: TaskId) -> bool {
+        let task = self.take(id);
+        let cancelled = task.is_some();
+        drop_as(Dropped::Cancelled, task);
+        cancelled
+    }
+
+    pub(crate) fn running(&self) -> Option<TaskId> {
+        *self.running.lock().unwrap()
+    }
+}
+
+/// Runs the task of `id` from the backlog, or drops it if the ticket is dropped first
+struct Ticket {
+    id: TaskId,
+    control: Arc<Control>,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        drop(self.control.backlog.take(self.id));
+    }
+}
+
+/// Why the task dropped right now on this thread didn't run
+#[derive(Clone, Copy)]
+enum Dropped {
+    QueueFull,
+    Cancelled,
+}
+
+thread_local! {
+    static DROPPED: Cell<Option<Dropped>> = const { Cell::new(None) };
+}
+
+fn drop_as(reason: Dropped, task: impl Sized) {
+    DROPPED.with(|dropped| dropped.set(Some(reason)));
+    drop(task);
+    DROPPED.with(|dropped| dropped.set(None));
+}
+
+/// Error for the task dropped right now, `None` if it was dropped because the worker exited
+pub(crate) fn dropped_error() -> Option<PyRunnerError> {
+    DROPPED.with(Cell::get).map(|reason| match reason {
+        Dropped::QueueFull => PyRunnerError::new(ErrorKind::QueueFull, "Task queue is full"),
+        Dropped::Cancelled => {
+            PyRunnerError::new(ErrorKind::Cancelled, "Task was cancelled before it started")
+        }
+    })
+}
+
+/// Reply of a queued task, receives [`ErrorKind::QueueFull`] or [`ErrorKind::Cancelled`]
+/// if the task is dropped before it ran
+pub(crate) struct PendingReply<T, F: FnOnce(Result<T, PyRunnerError>)> {
+    reply: Option<F>,
+    result: PhantomData<fn(T)>,
+}
+
+impl<T, F: FnOnce(Result<T, PyRunnerError>)> PendingReply<T, F> {
+    pub(crate) fn new(reply: F) -> Self {
+        Self {
+            reply: Some(reply),
+            result: PhantomData,
+        }
+    }
+
+    pub(crate) fn send(mut self, result: Result<T, PyRunnerError>) {
+        if let Some(reply) = self.reply.take() {
+            reply(result);
+        }
+    }
+}
+
+impl<T, F: FnOnce(Result<T, PyRunnerError>)> Drop for PendingReply<T, F> {
+    fn drop(&mut self) {
+        // otherwise the worker exited, the caller notices the dropped reply
+        if let Some(reply) = self.reply.take()
+            && let Some(err) = dropped_error()
+        {
+            reply(Err(err));
+        }
+    }
+}
+
+/// Bounded queue of a module, `overflow` is kept to drop the oldest task
+pub(crate) struct Bounds {
+    pub(crate) policy: QueuePolicy,
+    pub(crate) overflow: Option<Receiver<Option<Task>>>,
+}
+
+impl PythonModule {
+    /// Runs action on the imported module ahead of or behind other queued tasks
+    ///
+    /// [`Priority::High`] tasks run before every queued normal task, but after the running
+    /// one. [`Priority::Low`] tasks wait until the queue is empty. Tasks of the same priority
+    /// run in order. High and low tasks are exempt from the
+    /// [`queue_capacity`](crate::PythonModuleBuilder::queue_capacity): they neither count
+    /// towards it nor run into its [`QueuePolicy`], so a full queue doesn't hold back a
+    /// high priority request.
+    ///```rs
+    /// let page = module
+    ///     .action_with_priority(Priority::High, |_, module| module.call_method0("render")?.extract::<String>())
+    ///     .unwrap();
+    /// ```
+    pub fn action_with_priority<T: Send + 'static>(
+        &self,
+        priority: Priority,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        if priority == Priority::Normal {
+            return self.action(call);
+        }
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        let call = self.prepare(call);
+        let reply = PendingReply::new(move |result| {
+            let _ = sender.send(result);
+        });
+        self.queue_with_priority(
+            priority,
+            Box::new(move |py: &Python, module: &Bound<'_, PyAny>| reply.send(call(py, module))),
+        )?;
+        receiver.recv().map_err(|_| self.terminated_error())?
+    }
+
+    fn queue_with_priority(&self, priority: Priority, task: Task) -> Result<TaskId, PyRunnerError> {
+        if !self.is_alive() {
+            return Err(self.exited_error());
+        }
+        self.signal_start(true);
+        let (id, ticket) = self.track(priority, task)?;
+        self.control.lanes.push(priority, ticket);
+        // wakes up an idle worker, a full queue wakes it up anyway
+        let _ = self.task_sender.try_send(Some(Box::new(|_, _| ())));
+        if !self.is_alive() {
+            // the worker exited in the meantime, nobody runs the task anymore
+            self.control.lanes.clear();
+        }
+        Ok(id)
+    }
+
+    /// Adds `task` to the backlog and returns the ticket to queue instead, fails if the
+    /// module's limits reject it
+    pub(crate) fn track(
+        &self,
+        priority: Priority,
+        task: Task,
+    ) -> Result<(TaskId, Task), PyRunnerError> {
+        let permit = self.control.limits.admit()?;
+        #[cfg(feature = "tracing")]
+        let task = crate::trace::instrument(task);
+        let id = self.control.backlog.insert(priority, task);
+        let ticket = Ticket {
+            id,
+            control: self.control.clone(),
+        };
+        let ticket: Task = Box::new(move |py: &Python, module: &Bound<'_, PyAny>| {
+            let _permit = permit;
+            let backlog = &ticket.control.backlog;
+            if let Some(task) = backlog.take(ticket.id) {
+                *backlog.running.lock().unwrap() = Some(ticket.id);
+                task(py, module);
+                *backlog.running.lock().unwrap() = None;
+            }
+        });
+        Ok((id, ticket))
+    }
+
+    /// Tasks queued by actions and [`spawn`](Self::spawn) that didn't start yet, oldest first
+    ///```rs
+    /// for task in module.pending_tasks() {
+    ///     if task.queued.elapsed() > Duration::from_secs(30) {
+    ///         module.cancel_pending(task.id);
+    ///     }
+    /// }
+    /// ```
+    pub fn pending_tasks(&self) -> Vec<PendingTask> {
+        let tasks = self.control.backlog.tasks.lock().unwrap();
+        tasks.values().map(|(pending, _)| *pending).collect()
+    }
+
+    /// Removes the task `id` from the queue, it fails with [`ErrorKind::Cancelled`]
+    ///
+    /// Returns `false` if the task already started or doesn't exist.
+    pub fn cancel_pending(&self, id: TaskId) -> bool {
+        self.control.backlog.cancel(id)
+    }
+
+    /// Cancels every pending task like [`cancel_pending`](Self::cancel_pending), returns how
+    /// many there were
+    ///
+    /// The running task isn't affected, see [`interrupt`](Self::interrupt).
+    pub fn drain(&self) -> usize {
+        let tasks = std::mem::take(&mut *self.control.backlog.tasks.lock().unwrap());
+        let drained = tasks.len();
+        drop_as(Dropped::Cancelled, tasks);
+        drained
+    }
+
+    /// Sends `task` to the worker, `None` stops it
+    ///
+    /// Stopping never fails fast, it waits for room or pushes out the oldest task.
+    pub(crate) fn push(&self, mut task: Option<Task>) -> Result<(), PyRunnerError> {
+        let Some(bounds) = &self.bounds else {
+            return self.task_sender.send(task).map_err(|_| self.exited_error());
+        };
+        loop {
+            match self.task_sender.try_send(task) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(_)) => return Err(self.exited_error()),
+                Err(TrySendError::Full(rejected)) => match (&bounds.overflow, bounds.policy) {
+                    (Some(overflow), _) => {
+                        if let Ok(oldest) = overflow.try_recv() {
+                            drop_as(Dropped::QueueFull, oldest);
+                        }
+                        task = rejected;
+                    }
+                    (None, QueuePolicy::FailFast) if rejected.is_some() => {
+                        return Err(PyRunnerError::new(
+                            ErrorKind::QueueFull,
+                            "Task queue is full",
+                        ));
+                    }
+                    _ => {
+                        return self
+                            .task_sender
+                            .send(rejected)
+                            .map_err(|_| self.exited_error());
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModuleBuilder;
+    use std::time::{Duration, Instant};
+
+    /// Module with a capacity of 1 whose worker is busy for `seconds`
+    fn busy(policy: QueuePolicy, seconds: f64) -> PythonModule {
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .queue_capacity(1, policy)
+            .build()
+            .unwrap();
+        module
+            .spawn(move |py, _| {
+                py.import("time")?.call_method1("sleep", (seconds,))?;
+                Ok(())
+            })
+            .unwrap();
+        while !module.task_sender.is_empty() {
+            std::thread::yield_now();
+        }
+        module
+    }
+
+    #[test]
+    fn test_priority() {
+        let module = PythonModuleBuilder::from_source("prioritized", "order = []\n")
+            .build()
+            .unwrap();
+        let append = |name: &'static str| {
+            move |_: &Python<'_>, module: &Bound<'_, PyAny>| {
+                module.getattr("order")?.call_method1("append", (name,))?;
+                Ok(())
+            }
+        };
+        module
+            .spawn(|py, _| {
+                py.import("time")?.call_method1("sleep", (0.1,))?;
+                Ok(())
+            })
+            .unwrap();
+        let first = module.spawn(append("normal 1")).unwrap();
+        let second = module.spawn(append("normal 2")).unwrap();
+        std::thread::scope(|scope| {
+            let low = scope.spawn(|| module.action_with_priority(Priority::Low, append("low")));
+            let high = scope.spawn(|| module.action_with_priority(Priority::High, append("high")));
+            low.join().unwrap().unwrap();
+            high.join().unwrap().unwrap();
+        });
+        first.join().unwrap();
+        second.join().unwrap();
+        let order = module
+            .action(|_, module| module.getattr("order")?.extract::<Vec<String>>())
+            .unwrap();
+        assert_eq!(order, ["high", "normal 1", "normal 2", "low"]);
+    }
+
+    #[test]
+    fn test_pending_tasks() {
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .build()
+            .unwrap();
+        module
+            .spawn(|py, _| {
+                py.import("time")?.call_method1("sleep", (0.1,))?;
+                Ok(())
+            })
+            .unwrap();
+        while !module.pending_tasks().is_empty() {
+            std::thread::yield_now();
+        }
+        let first = module.spawn(|_, _| Ok(1)).unwrap();
+        let second = module.spawn(|_, _| Ok(2)).unwrap();
+        let third = module.submit(|_, _| Ok(3)).unwrap();
+        let pending = module.pending_tasks();
+        assert_eq!(pending.len(), 3);
+        assert_eq!(pending[0].id, first.id());
+        assert_eq!(pending[1].priority, Priority::Normal);
+
+        assert!(module.cancel_pending(first.id()));
+        assert!(!module.cancel_pending(first.id()));
+        assert_eq!(first.join().unwrap_err().kind(), &ErrorKind::Cancelled);
+        assert_eq!(module.drain(), 2);
+        assert_eq!(second.join().unwrap_err().kind(), &ErrorKind::Cancelled);
+        assert_eq!(
+            third.recv().unwrap().unwrap_err().kind(),
+            &ErrorKind::Cancelled
+        );
+        assert!(module.pending_tasks().is_empty());
+        assert_eq!(module.action(|_, _| Ok(4)).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let module = busy(QueuePolicy::FailFast, 0.1);
+        let queued = module.spawn(|_, _| Ok(1)).unwrap();
+        let Err(err) = module.spawn(|_, _| Ok(2)) else {
+            panic!("task queued into a full queue");
+        };
+        assert_eq!(err.kind(), &ErrorKind::QueueFull);
+        assert_eq!(queued.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let module = busy(QueuePolicy::DropOldest, 0.1);
+        let oldest = module.submit(|_, _| Ok(1)).unwrap();
+        let spawned = module.spawn(|_, _| Ok(2)).unwrap();
+        let newest = module.spawn(|_, _| Ok(3)).unwrap();
+        assert_eq!(
+            oldest.recv().unwrap().unwrap_err().kind(),
+            &ErrorKind::QueueFull
+        );
+        assert_eq!(spawned.join().unwrap_err().kind(), &ErrorKind::QueueFull);
+        assert_eq!(newest.join().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_priority_capacity() {
+        let module = busy(QueuePolicy::FailFast, 0.1);
+        let queued = module.spawn(|_, _| Ok(1)).unwrap();
+        assert!(module.spawn(|_, _| Ok(2)).is_err());
+        let high = module.action_with_priority(Priority::High, |_, _| Ok(3));
+        let low = module.action_with_priority(Priority::Low, |_, _| Ok(4));
+        assert_eq!((high.unwrap(), low.unwrap()), (3, 4));
+        assert_eq!(queued.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_block() {
+        let module = busy(QueuePolicy::Block, 0.1);
+        let started = Instant::now();
+        module.spawn(|_, _| Ok(())).unwrap();
+        let queued = module.spawn(|_, _| Ok(())).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        queued.join().unwrap();
+    }
+}
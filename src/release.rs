@@ -0,0 +1,70 @@
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+
+impl PythonModule {
+    /// Runs `prepare` on the module, then `work` on its result without holding the GIL
+    ///
+    /// Meant for long Rust work on data converted from Python. Other threads run Python code
+    /// and the main thread handles signals meanwhile, later actions of this module still wait
+    /// for `work`.
+    ///```rs
+    /// let histogram = module
+    ///     .action_released(
+    ///         |_, module| module.getattr("samples")?.extract::<Vec<f64>>(),
+    ///         |samples| bucket(&samples),
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn action_released<D, T>(
+        &self,
+        prepare: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<D> + Send + 'static,
+        work: impl FnOnce(D) -> T + Send + 'static,
+    ) -> Result<T, PyRunnerError>
+    where
+        D: Send,
+        T: Send + 'static,
+    {
+        self.action(move |py, module| {
+            let data = prepare(py, module)?;
+            Ok(py.allow_threads(|| work(data)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_action_released() {
+        let module = PythonModule::from_source("released", "samples = [3, 1, 2]\n").unwrap();
+        let (sorted, other) = module
+            .action_released(
+                |_, module| module.getattr("samples")?.extract::<Vec<i64>>(),
+                |mut samples| {
+                    samples.sort();
+                    // would wait for the GIL forever if the worker still held it
+                    let (sender, receiver) = mpsc::channel();
+                    std::thread::spawn(move || {
+                        let sum = Python::with_gil(|py| py.eval(c"1 + 1", None, None)?.extract());
+                        let _ = sender.send(sum);
+                    });
+                    let other = receiver.recv_timeout(Duration::from_secs(5));
+                    (samples, other.ok().and_then(|sum: PyResult<i64>| sum.ok()))
+                },
+            )
+            .unwrap();
+        assert_eq!(sorted, [1, 2, 3]);
+        assert_eq!(other, Some(2));
+
+        let err = module
+            .action_released(
+                |_, module| module.getattr("missing")?.extract::<i64>(),
+                |_| (),
+            )
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "AttributeError");
+    }
+}
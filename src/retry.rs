@@ -0,0 +1,212 @@
+use crate::{ErrorKind, PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+type Retryable = Arc<dyn Fn(&PyRunnerError) -> bool + Send + Sync>;
+
+/// How often and on which errors [`PythonModule::action_with_retry`] retries
+///
+/// Without any `retry_on*` condition every Python exception is retried, otherwise only the
+/// errors matching one of the conditions.
+///```rs
+/// let policy = RetryPolicy::exponential(3)
+///     .retry_on_mapped::<TransientError>()
+///     .max_delay(Duration::from_secs(2));
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    multiplier: u32,
+    retry_on: Vec<Retryable>,
+}
+
+impl RetryPolicy {
+    /// Retries up to `retries` times, waiting 100ms before the first retry and doubling the
+    /// delay after each one, up to 10s
+    pub fn exponential(retries: u32) -> Self {
+        Self {
+            retries,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2,
+            retry_on: Vec::new(),
+        }
+    }
+
+    /// Retries up to `retries` times, waiting about `delay` before each one
+    pub fn fixed(retries: u32, delay: Duration) -> Self {
+        Self {
+            base_delay: delay,
+            max_delay: delay,
+            multiplier: 1,
+            ..Self::exponential(retries)
+        }
+    }
+
+    /// Delay before the first retry
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Upper bound of the delay between two attempts
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Retries errors for which `retryable` returns true
+    pub fn retry_on(
+        mut self,
+        retryable: impl Fn(&PyRunnerError) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_on.push(Arc::new(retryable));
+        self
+    }
+
+    /// Retries exceptions whose [`exception_type`](PyRunnerError::exception_type) is
+    /// `exception_type`
+    pub fn retry_on_exception(self, exception_type: impl Into<String>) -> Self {
+        let exception_type = exception_type.into();
+        self.retry_on(move |e| e.exception_type() == exception_type)
+    }
+
+    /// Retries exceptions mapped to `E`, see
+    /// [`PythonModuleBuilder::map_exception`](crate::PythonModuleBuilder::map_exception)
+    pub fn retry_on_mapped<E: std::error::Error + 'static>(self) -> Self {
+        self.retry_on(|e| e.mapped::<E>().is_some())
+    }
+
+    /// Retries errors of `kind`, like [`ErrorKind::Timeout`]
+    pub fn retry_on_kind(self, kind: ErrorKind) -> Self {
+        self.retry_on(move |e| e.kind() == &kind)
+    }
+
+    fn should_retry(&self, err: &PyRunnerError) -> bool {
+        match self.retry_on.is_empty() {
+            true => err.kind() == &ErrorKind::Python,
+            false => self.retry_on.iter().any(|retryable| retryable(err)),
+        }
+    }
+
+    /// Delay before retry number `retry`, starting at 0, jittered between 50% and 100%
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .multiplier
+            .checked_pow(retry)
+            .and_then(|factor| self.base_delay.checked_mul(factor))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        let jitter = RandomState::new().hash_one(retry) as f64 / u64::MAX as f64;
+        delay.mul_f64(0.5 + jitter / 2.0)
+    }
+}
+
+impl PythonModule {
+    /// Runs action on the imported module, retrying it according to `policy`
+    ///
+    /// Each attempt is queued as its own task, the caller sleeps between attempts. The error
+    /// of the last attempt is returned once the retries are used up or an error isn't
+    /// retryable. Dead workers are never retried.
+    ///```rs
+    /// let rows = module
+    ///     .action_with_retry(
+    ///         |_, module| module.call_method0("fetch")?.extract::<Vec<String>>(),
+    ///         RetryPolicy::exponential(3).retry_on_mapped::<TransientError>(),
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn action_with_retry<T: Send + 'static>(
+        &self,
+        call: impl Fn(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + Sync + 'static,
+        policy: RetryPolicy,
+    ) -> Result<T, PyRunnerError> {
+        let call = Arc::new(call);
+        let mut retry = 0;
+        loop {
+            let attempt = call.clone();
+            let err = match self.action(move |py, module| attempt(py, module)) {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            let dead = matches!(err.kind(), ErrorKind::WorkerDead { .. });
+            if dead || retry >= policy.retries || !policy.should_retry(&err) {
+                return Err(err);
+            }
+            thread::sleep(policy.delay(retry));
+            retry += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModuleBuilder;
+
+    #[derive(Debug)]
+    struct TransientError;
+
+    impl std::fmt::Display for TransientError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "transient")
+        }
+    }
+
+    impl std::error::Error for TransientError {}
+
+    const FLAKY: &str = "class TransientError(Exception): pass\nattempts = 0\ndef fetch(fail):\n    global attempts\n    attempts += 1\n    if attempts <= fail:\n        raise TransientError(attempts)\n    return attempts\ndef broken():\n    global attempts\n    attempts += 1\n    raise ValueError('bad')\n";
+
+    #[test]
+    fn test_retry() {
+        let module = PythonModuleBuilder::from_source("retry_flaky", FLAKY)
+            .map_exception("retry_flaky.TransientError", |_| TransientError)
+            .build()
+            .unwrap();
+        let policy = RetryPolicy::exponential(3)
+            .base_delay(Duration::from_millis(1))
+            .retry_on_mapped::<TransientError>();
+        let fetch = |fail: i64, policy: RetryPolicy| {
+            module.action(|_, module| module.setattr("attempts", 0))?;
+            module.action_with_retry(
+                move |_, module| module.call_method1("fetch", (fail,))?.extract::<i64>(),
+                policy,
+            )
+        };
+        assert_eq!(fetch(2, policy.clone()).unwrap(), 3);
+        let err = fetch(5, policy.clone()).unwrap_err();
+        assert!(err.mapped::<TransientError>().is_some());
+        assert_eq!(err.message(), "4");
+
+        module
+            .action(|_, module| module.setattr("attempts", 0))
+            .unwrap();
+        let broken = module.action_with_retry(
+            |_, module| module.call_method0("broken").map(|_| ()),
+            policy,
+        );
+        assert_eq!(broken.unwrap_err().exception_type(), "ValueError");
+        let attempts = module
+            .action(|_, module| module.getattr("attempts")?.extract::<i64>())
+            .unwrap();
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::exponential(10)
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(1));
+        let first = policy.delay(0);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let third = policy.delay(2);
+        assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+        assert!(policy.delay(40) <= Duration::from_secs(1));
+        let fixed = RetryPolicy::fixed(3, Duration::from_millis(10));
+        assert!(fixed.delay(5) <= Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{ErrorKind, PyRunnerError, PythonModuleBuilder};
+use crossbeam::channel::{self, Sender};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::thread;
+
+type Modules = HashMap<String, Py<PyAny>>;
+type RuntimeTask = Box<dyn FnOnce(Python<'_>, &mut Modules) + Send>;
+
+/// One worker thread hosting many named modules
+///```rs
+/// let runtime = PythonRuntime::new();
+/// let name = runtime.load_module("./plugins/foo").unwrap();
+/// runtime
+///    .action(&name, |py, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+///    .unwrap();
+/// ```
+pub struct PythonRuntime {
+    task_sender: Sender<Option<RuntimeTask>>,
+    thread_handle: thread::JoinHandle<()>,
+}
+
+impl Drop for PythonRuntime {
+    fn drop(&mut self) {
+        let _ = self.task_sender.send(None);
+    }
+}
+
+impl Default for PythonRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PythonRuntime {
+    /// Spawns the worker thread, modules are loaded later
+    pub fn new() -> PythonRuntime {
+        let (task_sender, task_receiver) = channel::unbounded::<Option<RuntimeTask>>();
+        let thread_handle = thread::spawn(move || {
+            Python::with_gil(|py| {
+                let mut modules = Modules::new();
+                while let Ok(Some(task)) = py.allow_threads(|| task_receiver.recv()) {
+                    task(py, &mut modules);
+                }
+            })
+        });
+        PythonRuntime {
+            task_sender,
+            thread_handle,
+        }
+    }
+
+    /// Loads a Python module from a directory, registered under the directory name
+    pub fn load_module(&self, path: impl AsRef<Path>) -> Result<String, PyRunnerError> {
+        let path = path.as_ref();
+        let name = file_name(path.file_name())?;
+        self.load(&name, PythonModuleBuilder::new_module(path))?;
+        Ok(name)
+    }
+
+    /// Loads a Python project from root file, registered under the file stem
+    pub fn load_project(&self, init_file: impl AsRef<Path>) -> Result<String, PyRunnerError> {
+        let init_file = init_file.as_ref();
+        let name = file_name(init_file.file_stem())?;
+        self.load(&name, PythonModuleBuilder::new_project(init_file))?;
+        Ok(name)
+    }
+
+    /// Loads a module configured by `builder` and registers it as `name`
+    ///
+    /// An already loaded module with the same name is replaced.
+    pub fn load(&self, name: &str, builder: PythonModuleBuilder) -> Result<(), PyRunnerError> {
+        builder.validate()?;
+        let name = name.to_string();
+        self.run(move |py, modules| {
+            let module = builder.import(py)?;
+            if let Some(replaced) = modules.insert(name, module.unbind()) {
+                crate::sandbox::forget(py, replaced.bind(py))?;
+                crate::host::forget(py, replaced.bind(py))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Names of all loaded modules
+    pub fn module_names(&self) -> Result<Vec<String>, PyRunnerError> {
+        self.run(|_, modules| Ok(modules.keys().cloned().collect()))
+    }
+
+    /// Runs action on the module registered as `name`
+    pub fn action<T: Send + 'static>(
+        &self,
+        name: &str,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        let name = name.to_string();
+        self.run(move |py, modules| {
+            let module = modules.get(&name).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!(
+                    "No module named {name} loaded"
+                ))
+            })?;
+            call(&py, module.bind(py))
+        })
+    }
+
+    fn run<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(Python<'_>, &mut Modules) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        let exited = || {
+            PyRunnerError::new(
+                ErrorKind::WorkerDead { reason: None },
+                "Python thread has exited",
+            )
+        };
+        if self.thread_handle.is_finished() {
+            return Err(exited());
+        }
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        // a panicking task fails alone, the thread keeps serving the other modules
+        let task: RuntimeTask = Box::new(move |py, modules| {
+            let _ = sender.send(crate::catch_panic(|| {
+                call(py, modules).map_err(|e| PyRunnerError::from_py(py, e))
+            }));
+        });
+        self.task_sender.send(Some(task)).map_err(|_| exited())?;
+
+        receiver.recv().map_err(|_| exited())?
+    }
+}
+
+fn file_name(name: Option<&std::ffi::OsStr>) -> Result<String, PyRunnerError> {
+    name.map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>("Path has no file name").into()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime() {
+        let runtime = PythonRuntime::new();
+        let module = runtime.load_module("./my-module").unwrap();
+        let project = runtime.load_project("./my-project/main.py").unwrap();
+        assert_eq!(module, "my-module");
+        assert_eq!(project, "main");
+
+        let mut names = runtime.module_names().unwrap();
+        names.sort();
+        assert_eq!(names, ["main", "my-module"]);
+
+        for name in [&module, &project] {
+            let sum = runtime
+                .action(name, |_, module| {
+                    module.call_method1("add", (1, 2))?.extract::<i64>()
+                })
+                .unwrap();
+            assert_eq!(sum, 3);
+        }
+        assert!(runtime.action("missing", |_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "integrity")]
+    fn test_runtime_integrity() {
+        let runtime = PythonRuntime::new();
+        let builder = || PythonModuleBuilder::from_source("runtime_signed", "VALUE = 1\n");
+        let digest = builder().digest().unwrap();
+        let err = runtime
+            .load("signed", builder().verify_sha256("0".repeat(64)))
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Integrity);
+        assert!(runtime.module_names().unwrap().is_empty());
+
+        runtime
+            .load("signed", builder().verify_sha256(digest))
+            .unwrap();
+        assert_eq!(runtime.module_names().unwrap(), ["signed"]);
+    }
+
+    #[test]
+    fn test_runtime_panic() {
+        let runtime = PythonRuntime::new();
+        let name = runtime.load_project("./my-project/main.py").unwrap();
+        let err = runtime
+            .action(&name, |_, _| -> PyResult<()> { panic!("boom") })
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Panicked);
+        assert!(err.message().contains("boom"));
+
+        let sum = runtime
+            .action(&name, |_, module| {
+                module.call_method1("add", (1, 2))?.extract::<i64>()
+            })
+            .unwrap();
+        assert_eq!(sum, 3);
+    }
+}
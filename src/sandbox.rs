@@ -0,0 +1,273 @@
+use crate::helper::{find, load_helper};
+use pyo3::prelude::*;
+use std::path::Path;
+
+pub(crate) const SANDBOX: &str = include_str!("sandbox/sandbox.py");
+
+/// Guardrails for semi-trusted module code, see
+/// [`PythonModuleBuilder::sandbox`](crate::PythonModuleBuilder::sandbox)
+///
+/// The restrictions apply to the code of the module file itself: it gets its own builtins
+/// without the removed ones and with an `__import__` checking the allow- and denylist, and an
+/// audit hook raises `PermissionError` for denied operations triggered from its functions,
+/// also through the libraries it calls. Submodules of a package are not restricted. This is
+/// not a security boundary, determined code can escape it; run untrusted code in a separate
+/// process.
+///```rs
+/// let sandbox = Sandbox::new()
+///     .allow_imports(["json", "math", "re"])
+///     .remove_builtins(["open", "exec", "eval"])
+///     .deny_network(true)
+///     .deny_subprocess(true);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    allowed_imports: Option<Vec<String>>,
+    denied_imports: Vec<String>,
+    removed_builtins: Vec<String>,
+    deny_files: bool,
+    deny_network: bool,
+    deny_subprocess: bool,
+}
+
+impl Sandbox {
+    /// Sandbox without any restriction
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes `open`, `exec`, `eval`, `compile`, `breakpoint` and `input` and denies file,
+    /// network and subprocess operations
+    pub fn strict() -> Self {
+        Self::new()
+            .remove_builtins(["open", "exec", "eval", "compile", "breakpoint", "input"])
+            .deny_files(true)
+            .deny_network(true)
+            .deny_subprocess(true)
+    }
+
+    /// Only lets the module import these top-level modules and their submodules, relative
+    /// imports are always allowed
+    pub fn allow_imports(mut self, modules: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.allowed_imports
+            .get_or_insert_with(Vec::new)
+            .extend(modules.into_iter().map(Into::into));
+        self
+    }
+
+    /// Fails imports of these modules with an `ImportError`, a top-level name also denies its
+    /// submodules
+    pub fn deny_imports(mut self, modules: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.denied_imports
+            .extend(modules.into_iter().map(Into::into));
+        self
+    }
+
+    /// Hides these builtins from the module, removing `__import__` disables imports
+    pub fn remove_builtins(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.removed_builtins
+            .extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Denies opening, removing and renaming files, except for imports
+    pub fn deny_files(mut self, deny: bool) -> Self {
+        self.deny_files = deny;
+        self
+    }
+
+    /// Denies creating and using sockets
+    pub fn deny_network(mut self, deny: bool) -> Self {
+        self.deny_network = deny;
+        self
+    }
+
+    /// Denies starting processes, `os.system` and `os.fork`
+    pub fn deny_subprocess(mut self, deny: bool) -> Self {
+        self.deny_subprocess = deny;
+        self
+    }
+
+    /// Restricts `module` before it is executed
+    pub(crate) fn apply(&self, py: Python<'_>, module: &Bound<'_, PyAny>) -> PyResult<()> {
+        let operations = [
+            ("files", self.deny_files),
+            ("network", self.deny_network),
+            ("subprocess", self.deny_subprocess),
+        ]
+        .into_iter()
+        .filter_map(|(name, denied)| denied.then_some(name))
+        .collect::<Vec<_>>();
+        helper(py)?.call_method1(
+            "restrict",
+            (
+                module,
+                self.allowed_imports.clone(),
+                self.denied_imports.clone(),
+                self.removed_builtins.clone(),
+                operations,
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+/// Confines the file access of `module` to `root`, see
+/// [`PythonModuleBuilder::fs_root`](crate::PythonModuleBuilder::fs_root)
+pub(crate) fn confine(
+    py: Python<'_>,
+    module: &Bound<'_, PyAny>,
+    root: &Path,
+    package_dir: &Path,
+) -> PyResult<()> {
+    helper(py)?.call_method1(
+        "confine",
+        (module, root.as_os_str(), vec![package_dir.as_os_str()]),
+    )?;
+    Ok(())
+}
+
+/// Drops everything registered for `module`, called once its worker let go of it
+pub(crate) fn forget(py: Python<'_>, module: &Bound<'_, PyAny>) -> PyResult<()> {
+    // nothing was registered before the helper was loaded
+    if let Some(helper) = find(py, "sandbox") {
+        helper.call_method1("forget", (module,))?;
+    }
+    Ok(())
+}
+
+/// `sandbox.py`, loaded once per process so there is a single audit hook
+pub(crate) fn helper(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    load_helper(py, "sandbox", SANDBOX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PythonModule, PythonModuleBuilder};
+
+    const SCRIPT: &str = "import json\ndef dump(value):\n    return json.dumps(value)\ndef load(name):\n    __import__(name)\ndef read():\n    open('notes.txt')\ndef run():\n    import os\n    return os.system('true')\ndef evaluate(code):\n    return eval(code)\n";
+
+    #[test]
+    fn test_sandbox() {
+        let module = PythonModuleBuilder::from_source("sandbox_script", SCRIPT)
+            .sandbox(
+                Sandbox::new()
+                    .allow_imports(["json", "os"])
+                    .deny_imports(["os.path"])
+                    .remove_builtins(["eval"])
+                    .deny_files(true)
+                    .deny_subprocess(true),
+            )
+            .build()
+            .unwrap();
+        let dumped = module
+            .action(|_, module| module.call_method1("dump", ([1, 2],))?.extract::<String>())
+            .unwrap();
+        assert_eq!(dumped, "[1, 2]");
+
+        let exception = |name, arg| {
+            let err = module
+                .action(move |_, module| {
+                    match arg {
+                        Some(arg) => module.call_method1(name, (arg,)),
+                        None => module.call_method0(name),
+                    }
+                    .map(|_| ())
+                })
+                .unwrap_err();
+            err.exception_type().to_string()
+        };
+        assert_eq!(exception("load", Some("socket")), "ImportError");
+        assert_eq!(exception("load", Some("os.path")), "ImportError");
+        assert_eq!(exception("read", None), "PermissionError");
+        assert_eq!(exception("run", None), "PermissionError");
+        assert_eq!(exception("evaluate", Some("1 + 1")), "NameError");
+
+        // other modules of the interpreter are not restricted
+        let other = PythonModule::from_source("sandbox_other", SCRIPT).unwrap();
+        other
+            .action(|_, module| module.call_method0("run").map(|_| ()))
+            .unwrap();
+        other
+            .action(|_, module| module.call_method1("load", ("socket",)).map(|_| ()))
+            .unwrap();
+
+        // dropping the module drops its restrictions
+        let namespace = module
+            .action(|_, module| Ok(module.getattr("__dict__")?.unbind()))
+            .unwrap();
+        let registered = || {
+            Python::with_gil(|py| {
+                let guarded = helper(py)?.getattr("guarded")?;
+                let namespace = namespace.bind(py);
+                for entry in guarded.try_iter()? {
+                    if entry?.get_item(0)?.is(namespace) {
+                        return Ok(true);
+                    }
+                }
+                Ok::<_, PyErr>(false)
+            })
+            .unwrap()
+        };
+        assert!(registered());
+        module.join().unwrap();
+        assert!(!registered());
+    }
+
+    #[test]
+    fn test_fs_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("link.txt")).unwrap();
+        let source = "import os\ndef write(path, text):\n    with open(path, 'w') as f:\n        f.write(text)\ndef read(path):\n    with open(path) as f:\n        return f.read()\ndef remove(path):\n    os.remove(path)\n";
+        let module = PythonModuleBuilder::from_source("root_script", source)
+            .fs_root(&root)
+            .build()
+            .unwrap();
+
+        let call = |name: &'static str, args: Vec<String>| {
+            module.action(move |py, module| {
+                module
+                    .getattr(name)?
+                    .call1(pyo3::types::PyTuple::new(*py, args)?)?
+                    .extract::<Option<String>>()
+            })
+        };
+        let inside = root.join("notes.txt").to_string_lossy().to_string();
+        call("write", vec![inside.clone(), "notes".into()]).unwrap();
+        assert_eq!(
+            call("read", vec![inside.clone()]).unwrap().unwrap(),
+            "notes"
+        );
+        call("remove", vec![inside]).unwrap();
+
+        let secret = dir.join("secret.txt").to_string_lossy().to_string();
+        for (name, args) in [
+            ("read", vec![secret.clone()]),
+            ("write", vec![secret.clone(), "leaked".into()]),
+            ("remove", vec![secret.clone()]),
+            ("read", vec!["../secret.txt".into()]),
+        ] {
+            let err = call(name, args).unwrap_err();
+            assert_eq!(err.exception_type(), "PermissionError");
+        }
+        #[cfg(unix)]
+        {
+            let link = root.join("link.txt").to_string_lossy().to_string();
+            let err = call("read", vec![link]).unwrap_err();
+            assert!(err.message().contains("outside of the file system root"));
+        }
+
+        // other modules of the interpreter are not confined
+        let other = PythonModule::from_source("root_other", source).unwrap();
+        let text = other
+            .action(move |_, module| module.call_method1("read", (secret,))?.extract::<String>())
+            .unwrap();
+        assert_eq!(text, "secret");
+    }
+}
@@ -0,0 +1,143 @@
+# Restricts the code of single modules: their builtins, their imports and, through an
+# audit hook, the operations they may trigger. The same hook reports audit events of
+# watched modules.
+import builtins
+import os
+import sys
+
+FILE_EVENTS = ("open", "os.remove", "os.rename", "os.rmdir", "os.mkdir", "os.chmod",
+               "os.chown", "os.truncate", "os.symlink", "os.link", "shutil.")
+SUBPROCESS_EVENTS = ("subprocess.Popen", "os.system", "os.exec", "os.posix_spawn",
+                     "os.spawn", "os.fork", "os.forkpty", "os.startfile", "pty.spawn")
+
+# Indices of the path arguments of file events, all but `open` and listings write
+PATH_ARGS = {"open": (0,), "os.listdir": (0,), "os.scandir": (0,), "os.remove": (0,),
+             "os.rename": (0, 1), "os.rmdir": (0,), "os.mkdir": (0,), "os.chmod": (0,),
+             "os.chown": (0,), "os.truncate": (0,), "os.utime": (0,), "os.symlink": (0, 1),
+             "os.link": (0, 1), "shutil.copyfile": (0, 1), "shutil.copymode": (0, 1),
+             "shutil.copystat": (0, 1), "shutil.copytree": (0, 1), "shutil.move": (0, 1),
+             "shutil.rmtree": (0,), "shutil.make_archive": (0,), "shutil.unpack_archive": (0, 1)}
+READ_ONLY = ("os.listdir", "os.scandir")
+WRITE_FLAGS = os.O_WRONLY | os.O_RDWR | os.O_APPEND | os.O_CREAT | os.O_TRUNC
+
+# (module globals, denied categories) of every sandboxed module in this interpreter
+guarded = []
+# (module globals or None for every frame, root, readable directories) of every confined module
+confined = []
+# [module globals, event prefixes, callback] of every audit subscription
+watchers = []
+installed = False
+
+
+def install():
+    global installed
+    if not installed:
+        sys.addaudithook(audit)
+        installed = True
+
+
+def category(event):
+    if event.startswith(FILE_EVENTS):
+        return "files"
+    if event.startswith("socket."):
+        return "network"
+    if event.startswith(SUBPROCESS_EVENTS):
+        return "subprocess"
+    return None
+
+
+def origins():
+    """Globals of the frames that triggered the event, up to the import system"""
+    frame = sys._getframe(2)
+    while frame is not None:
+        # files read by the import system are governed by the import rules instead
+        if frame.f_code.co_filename.startswith("<frozen importlib"):
+            return
+        yield frame.f_globals
+        frame = frame.f_back
+
+
+def beneath(path, directory):
+    return path == directory or path.startswith(directory.rstrip(os.sep) + os.sep)
+
+
+def escapes(event, args, root, readable):
+    """First path of the event outside of `root`, reading is also allowed in `readable`"""
+    writing = event not in READ_ONLY and (event != "open" or args[2] & WRITE_FLAGS)
+    for index in PATH_ARGS[event]:
+        path = args[index] if index < len(args) else None
+        if isinstance(path, int):
+            continue
+        path = os.path.realpath(os.fsdecode(path if path is not None else "."))
+        allowed = (root,) if writing else (root, *readable)
+        if not any(beneath(path, directory) for directory in allowed):
+            return path
+    return None
+
+
+def audit(event, args):
+    kind = category(event) if guarded else None
+    watching = [watcher for watcher in watchers if event.startswith(watcher[1])]
+    rooted = confined if event in PATH_ARGS else ()
+    if kind is None and not watching and not rooted:
+        return
+    for depth, namespace in enumerate(origins()):
+        for restricted, root, readable in rooted:
+            if restricted is namespace or (restricted is None and depth == 0):
+                path = escapes(event, args, root, readable)
+                if path is not None:
+                    raise PermissionError(f"{path} is outside of the file system root {root}")
+        for watcher in [watcher for watcher in watching if watcher[0] is namespace]:
+            watching.remove(watcher)
+            # the callback returns False once nobody listens anymore
+            if watcher[2](event, [repr(arg) for arg in args]) is False:
+                watchers.remove(watcher)
+        for restricted, denied in guarded:
+            if restricted is namespace and kind in denied:
+                raise PermissionError(f"Sandbox denies {event}")
+
+
+def watch(module, prefixes, callback):
+    """Calls `callback` with the audit events starting with one of `prefixes` that `module`
+    triggers"""
+    install()
+    watchers.append([vars(module), tuple(prefixes), callback])
+
+
+def confine(module, root, readable):
+    """Confines the file access of `module`, or of all code but imports if it is `None`, to
+    `root`; `readable` directories may still be read"""
+    install()
+    resolve = os.path.realpath
+    readable = tuple(resolve(path) for path in (*readable, *sys.path) if path)
+    confined.append((vars(module) if module is not None else None, resolve(root), readable))
+
+
+def restrict(module, allowed, denied, removed, operations):
+    """Gives `module` its own builtins and registers it with the audit hook"""
+    original_import = builtins.__import__
+
+    def guarded_import(name, globals=None, locals=None, fromlist=(), level=0):
+        if level == 0:
+            top = name.partition(".")[0]
+            if (allowed is not None and top not in allowed) or top in denied or name in denied:
+                raise ImportError(f"Sandbox denies importing {name}", name=name)
+        return original_import(name, globals, locals, fromlist, level)
+
+    namespace = dict(vars(builtins))
+    namespace["__import__"] = guarded_import
+    for name in removed:
+        namespace.pop(name, None)
+    module.__builtins__ = namespace
+
+    if operations:
+        install()
+        guarded.append((vars(module), frozenset(operations)))
+
+
+def forget(module):
+    """Drops the restrictions, file system root and audit subscriptions of `module`"""
+    namespace = vars(module)
+    guarded[:] = [entry for entry in guarded if entry[0] is not namespace]
+    confined[:] = [entry for entry in confined if entry[0] is not namespace]
+    watchers[:] = [watcher for watcher in watchers if watcher[0] is not namespace]
@@ -0,0 +1,231 @@
+use crate::error::ExceptionMap;
+use crate::{PyRunnerError, PythonModule, Task, guard};
+use pyo3::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type ScheduledCall = Box<dyn FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<()> + Send>;
+
+/// Scheduled task started by [`PythonModule::schedule_every`] or
+/// [`PythonModule::schedule_at`]
+///
+/// Dropping the handle doesn't cancel the schedule, it ends with the module.
+pub struct ScheduleHandle {
+    state: Arc<Schedule>,
+    timer: thread::Thread,
+}
+
+#[derive(Default)]
+struct Schedule {
+    cancelled: AtomicBool,
+    /// A run is queued but didn't start yet, later runs are skipped until it did
+    queued: AtomicBool,
+    runs: AtomicUsize,
+    error: Mutex<Option<PyRunnerError>>,
+}
+
+impl ScheduleHandle {
+    /// Stops the schedule, a run already queued still happens
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.timer.unpark();
+    }
+
+    /// Whether [`cancel`](Self::cancel) was called
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// How often the task ran so far
+    pub fn runs(&self) -> usize {
+        self.state.runs.load(Ordering::SeqCst)
+    }
+
+    /// Error of the latest failed run, if it wasn't taken yet
+    pub fn take_error(&self) -> Option<PyRunnerError> {
+        self.state.error.lock().unwrap().take()
+    }
+}
+
+impl PythonModule {
+    /// Queues `call` on the worker every `period`, starting one `period` from now
+    ///
+    /// A run is skipped while the previous one is still queued. Errors don't stop the
+    /// schedule, the latest one is kept in the handle.
+    ///```rs
+    /// let refresh = module
+    ///     .schedule_every(Duration::from_secs(60), |_, module| {
+    ///         module.call_method0("refresh_cache").map(|_| ())
+    ///     })
+    ///     .unwrap();
+    /// // later
+    /// refresh.cancel();
+    /// ```
+    pub fn schedule_every(
+        &self,
+        period: Duration,
+        call: impl Fn(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<()> + Send + Sync + 'static,
+    ) -> Result<ScheduleHandle, PyRunnerError> {
+        let call = Arc::new(call);
+        self.schedule(Instant::now() + period, Some(period), move || {
+            let call = call.clone();
+            Some(
+                Box::new(move |py: &Python, module: &Bound<'_, PyAny>| call(py, module))
+                    as ScheduledCall,
+            )
+        })
+    }
+
+    /// Queues `call` on the worker once `at` is reached
+    ///```rs
+    /// module
+    ///     .schedule_at(Instant::now() + Duration::from_secs(5), |_, module| {
+    ///         module.call_method0("heartbeat").map(|_| ())
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn schedule_at(
+        &self,
+        at: Instant,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<()> + Send + 'static,
+    ) -> Result<ScheduleHandle, PyRunnerError> {
+        let mut call = Some(call);
+        self.schedule(at, None, move || {
+            let call = call.take()?;
+            Some(
+                Box::new(move |py: &Python, module: &Bound<'_, PyAny>| call(py, module))
+                    as ScheduledCall,
+            )
+        })
+    }
+
+    /// Starts the timer thread queueing the runs `next_run` creates
+    fn schedule(
+        &self,
+        at: Instant,
+        period: Option<Duration>,
+        mut next_run: impl FnMut() -> Option<ScheduledCall> + Send + 'static,
+    ) -> Result<ScheduleHandle, PyRunnerError> {
+        if !self.is_alive() {
+            return Err(self.exited_error());
+        }
+        self.signal_start(true);
+
+        let state = Arc::new(Schedule::default());
+        let timer_state = state.clone();
+        let task_sender = self.task_sender.clone();
+        let control = self.control.clone();
+        let exceptions = self.exceptions.clone();
+        let timer = thread::Builder::new()
+            .name("py-runner-schedule".to_string())
+            .spawn(move || {
+                let state = timer_state;
+                let mut next = at;
+                loop {
+                    let now = Instant::now();
+                    if state.cancelled.load(Ordering::SeqCst) || control.is_closed() {
+                        return;
+                    }
+                    if now < next {
+                        thread::park_timeout(next - now);
+                        continue;
+                    }
+                    if !state.queued.swap(true, Ordering::SeqCst) {
+                        let Some(call) = next_run() else {
+                            return;
+                        };
+                        let task = run(state.clone(), exceptions.clone(), call);
+                        if task_sender.send(Some(task)).is_err() {
+                            return;
+                        }
+                    }
+                    match period {
+                        // missed runs are skipped instead of queued back to back
+                        Some(period) => next = (next + period).max(now),
+                        None => return,
+                    }
+                }
+            })
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Failed to start the schedule: {e}"
+                ))
+            })?
+            .thread()
+            .clone();
+        Ok(ScheduleHandle { state, timer })
+    }
+}
+
+/// Task running `call` once and recording its outcome in `state`
+fn run(state: Arc<Schedule>, exceptions: ExceptionMap, call: ScheduledCall) -> Task {
+    Box::new(move |py: &Python, module: &Bound<'_, PyAny>| {
+        state.queued.store(false, Ordering::SeqCst);
+        let result = guard(py, module, &exceptions, call);
+        state.runs.fetch_add(1, Ordering::SeqCst);
+        if let Err(e) = result {
+            *state.error.lock().unwrap() = Some(e);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKS: &str = "ticks = 0\ndef tick():\n    global ticks\n    ticks += 1\n    if ticks == 2:\n        raise ValueError('second tick')\n";
+
+    fn ticks(module: &PythonModule) -> i64 {
+        module
+            .action(|_, module| module.getattr("ticks")?.extract())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_schedule_every() {
+        let module = PythonModule::from_source("schedule_ticks", TICKS).unwrap();
+        let handle = module
+            .schedule_every(Duration::from_millis(30), |_, module| {
+                module.call_method0("tick").map(|_| ())
+            })
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+        handle.cancel();
+        assert!(handle.is_cancelled());
+        // a run queued right before the cancel still happens
+        thread::sleep(Duration::from_millis(50));
+        let runs = handle.runs();
+        assert!(runs >= 3, "only {runs} runs");
+        assert_eq!(ticks(&module), runs as i64);
+        assert_eq!(handle.take_error().unwrap().exception_type(), "ValueError");
+        assert!(handle.take_error().is_none());
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(handle.runs(), runs);
+    }
+
+    #[test]
+    fn test_schedule_at() {
+        let module = PythonModule::from_source("schedule_once", TICKS).unwrap();
+        let at = Instant::now() + Duration::from_millis(50);
+        let handle = module
+            .schedule_at(at, |_, module| module.call_method0("tick").map(|_| ()))
+            .unwrap();
+        assert_eq!(ticks(&module), 0);
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(handle.runs(), 1);
+        assert_eq!(ticks(&module), 1);
+
+        let cancelled = module
+            .schedule_at(Instant::now() + Duration::from_millis(50), |_, module| {
+                module.call_method0("tick").map(|_| ())
+            })
+            .unwrap();
+        cancelled.cancel();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cancelled.runs(), 0);
+        assert_eq!(ticks(&module), 1);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::PyRunnerError;
+use pyo3::exceptions::PySystemExit;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::path::Path;
+
+/// Runs a standalone script like `python path args...` and returns its exit status
+///
+/// The script runs as `__main__` on the calling thread, with `sys.argv` set to the path and
+/// `args` and the script's directory in front of `sys.path`. Both are restored afterwards.
+/// `SystemExit` is turned into the exit status like the interpreter does, other exceptions
+/// are returned as error.
+///```rs
+/// let status = execute_script(Path::new("./tools/cli.py"), &["--verbose", "build"]).unwrap();
+/// ```
+pub fn execute_script(path: &Path, args: &[&str]) -> Result<i32, PyRunnerError> {
+    Python::with_gil(|py| run(py, path, args, None).map_err(|e| PyRunnerError::from_py(py, e)))
+}
+
+/// Like [`execute_script`], but changes the working directory to `dir` while the script runs
+///
+/// The working directory belongs to the whole process, so other threads resolving relative
+/// paths meanwhile see `dir` as well.
+pub fn execute_script_in(path: &Path, args: &[&str], dir: &Path) -> Result<i32, PyRunnerError> {
+    Python::with_gil(|py| run(py, path, args, Some(dir)).map_err(|e| PyRunnerError::from_py(py, e)))
+}
+
+fn run(py: Python<'_>, path: &Path, args: &[&str], dir: Option<&Path>) -> PyResult<i32> {
+    let path = std::path::absolute(path)?;
+    let sys = py.import("sys")?;
+    let os = py.import("os")?;
+    let argv = sys.getattr("argv")?;
+    let sys_path = sys.getattr("path")?.downcast_into::<PyList>()?;
+    let saved_path = sys_path.call_method0("copy")?;
+    let cwd = os.call_method0("getcwd")?;
+
+    let mut script_argv = vec![path.to_string_lossy().into_owned()];
+    script_argv.extend(args.iter().map(|arg| arg.to_string()));
+    sys.setattr("argv", script_argv)?;
+    if let Some(parent) = path.parent() {
+        sys_path.insert(0, parent)?;
+    }
+    let result = (|| {
+        if let Some(dir) = dir {
+            os.call_method1("chdir", (dir,))?;
+        }
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("run_name", "__main__")?;
+        py.import("runpy")?
+            .call_method("run_path", (&path,), Some(&kwargs))
+            .map(|_| ())
+    })();
+
+    os.call_method1("chdir", (cwd,))?;
+    sys.setattr("argv", argv)?;
+    sys_path.set_slice(0, sys_path.len(), &saved_path)?;
+    match result {
+        Ok(()) => Ok(0),
+        Err(e) if e.is_instance_of::<PySystemExit>(py) => exit_status(py, &e),
+        Err(e) => Err(e),
+    }
+}
+
+/// Exit status of `SystemExit(code)`, printing a non-integer code to stderr like Python
+fn exit_status(py: Python<'_>, err: &PyErr) -> PyResult<i32> {
+    let code = err.value(py).getattr("code")?;
+    if code.is_none() {
+        return Ok(0);
+    }
+    if let Ok(code) = code.extract::<i32>() {
+        return Ok(code);
+    }
+    py.import("sys")?
+        .getattr("stderr")?
+        .call_method1("write", (format!("{}\n", code.str()?),))?;
+    Ok(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute_script() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("cli.py"),
+            concat!(
+                "import os, sys\n",
+                "if __name__ == '__main__':\n",
+                "    if sys.argv[1] == 'cwd':\n",
+                "        sys.exit(0 if os.getcwd() == sys.argv[2] else 3)\n",
+                "    if sys.argv[1] == 'fail':\n",
+                "        raise RuntimeError('broken')\n",
+                "    sys.exit(int(sys.argv[2]) if len(sys.argv) > 2 else None)\n",
+            ),
+        )
+        .unwrap();
+
+        let script = dir.join("cli.py");
+        let argv = Python::with_gil(|py| py.import("sys")?.getattr("argv")?.len()).unwrap();
+        assert_eq!(execute_script(&script, &["exit"]).unwrap(), 0);
+        assert_eq!(execute_script(&script, &["exit", "7"]).unwrap(), 7);
+        let err = execute_script(&script, &["fail"]).unwrap_err();
+        assert_eq!(err.exception_type(), "RuntimeError");
+
+        // changing to another directory would race tests using relative paths
+        let cwd = std::env::current_dir().unwrap();
+        let cwd_arg = cwd.to_str().unwrap();
+        assert_eq!(execute_script(&script, &["cwd", cwd_arg]).unwrap(), 0);
+        assert_eq!(
+            execute_script_in(&script, &["cwd", cwd_arg], Path::new(".")).unwrap(),
+            0
+        );
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+        let restored = Python::with_gil(|py| py.import("sys")?.getattr("argv")?.len()).unwrap();
+        assert_eq!(restored, argv);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{PyRunnerError, c_code};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Globals kept alive across runs, for REPL-like incremental evaluation
+///
+/// Code runs on the calling thread, like [`execute_code`](crate::execute_code).
+///```rs
+/// let session = PythonSession::new();
+/// session.run("x = 1").unwrap();
+/// assert_eq!(session.eval::<i64>("x + 1").unwrap(), 2);
+/// ```
+pub struct PythonSession {
+    globals: Py<PyDict>,
+}
+
+impl Default for PythonSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PythonSession {
+    pub fn new() -> Self {
+        Python::with_gil(|py| Self {
+            globals: PyDict::new(py).unbind(),
+        })
+    }
+
+    /// Executes statements, their assignments stay visible to later runs
+    pub fn run(&self, code: &str) -> Result<(), PyRunnerError> {
+        Python::with_gil(|py| {
+            py.run(&c_code(code)?, Some(self.globals.bind(py)), None)
+                .map_err(|e| PyRunnerError::from_py(py, e).with_source(code))
+        })
+    }
+
+    /// Evaluates an expression and extracts its value
+    pub fn eval<T>(&self, expression: &str) -> Result<T, PyRunnerError>
+    where
+        T: for<'py> FromPyObject<'py>,
+    {
+        Python::with_gil(|py| {
+            py.eval(&c_code(expression)?, Some(self.globals.bind(py)), None)
+                .and_then(|value| value.extract())
+                .map_err(|e| PyRunnerError::from_py(py, e).with_source(expression))
+        })
+    }
+
+    /// Gives access to the globals, e.g. to set inputs or read several results
+    pub fn with_globals<T>(
+        &self,
+        f: impl FnOnce(Python<'_>, &Bound<'_, PyDict>) -> PyResult<T>,
+    ) -> Result<T, PyRunnerError> {
+        Python::with_gil(|py| {
+            f(py, self.globals.bind(py)).map_err(|e| PyRunnerError::from_py(py, e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let session = PythonSession::new();
+        session.run("x = 1").unwrap();
+        session.run("def double(v):\n    return v * 2").unwrap();
+        assert_eq!(session.eval::<i64>("double(x + 1)").unwrap(), 4);
+
+        session
+            .with_globals(|_, globals| globals.set_item("name", "py"))
+            .unwrap();
+        assert_eq!(session.eval::<String>("name.upper()").unwrap(), "PY");
+
+        let err = session.eval::<i64>("missing").unwrap_err();
+        assert_eq!(err.exception_type(), "NameError");
+        assert!(session.run("x = 1\0").is_err());
+        assert!(
+            !PythonSession::new()
+                .eval::<bool>("'x' in globals()")
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+impl PythonModule {
+    /// Pickles the module globals `keys` into bytes, see [`restore_state`](Self::restore_state)
+    ///
+    /// Fails with an `AttributeError` if a global doesn't exist and with a
+    /// `pickle.PicklingError` or `TypeError` if a value can't be pickled, like an open file.
+    ///```rs
+    /// let state = module.snapshot_state(&["counter", "cache"]).unwrap();
+    /// std::fs::write("plugin.state", &state).unwrap();
+    /// ```
+    pub fn snapshot_state(&self, keys: &[&str]) -> Result<Vec<u8>, PyRunnerError> {
+        let keys = keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+        self.action(move |py, module| {
+            let state = PyDict::new(*py);
+            for key in keys {
+                state.set_item(&key, module.getattr(&key)?)?;
+            }
+            py.import("pickle")?
+                .call_method1("dumps", (state,))?
+                .extract()
+        })
+    }
+
+    /// Sets the module globals pickled by [`snapshot_state`](Self::snapshot_state)
+    ///
+    /// Unpickling runs arbitrary code, only restore bytes this crate produced.
+    pub fn restore_state(&self, state: &[u8]) -> Result<(), PyRunnerError> {
+        let state = state.to_vec();
+        self.action(move |py, module| {
+            let state = py
+                .import("pickle")?
+                .call_method1("loads", (PyBytes::new(*py, &state),))?;
+            for (key, value) in state.downcast::<PyDict>()? {
+                module.setattr(key.downcast::<pyo3::types::PyString>()?, value)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER: &str = "hits = 0\nseen = []\ndef hit(name):\n    global hits\n    hits += 1\n    seen.append(name)\n    return hits\n";
+
+    #[test]
+    fn test_state() {
+        let module = PythonModule::from_source("state_counter", COUNTER).unwrap();
+        let hit = |module: &PythonModule, name: &'static str| {
+            module
+                .action(move |_, module| module.call_method1("hit", (name,))?.extract::<i64>())
+                .unwrap()
+        };
+        hit(&module, "a");
+        hit(&module, "b");
+        let state = module.snapshot_state(&["hits", "seen"]).unwrap();
+
+        let restarted = PythonModule::from_source("state_counter_restarted", COUNTER).unwrap();
+        restarted.restore_state(&state).unwrap();
+        assert_eq!(hit(&restarted, "c"), 3);
+        let seen = restarted
+            .action(|_, module| module.getattr("seen")?.extract::<Vec<String>>())
+            .unwrap();
+        assert_eq!(seen, ["a", "b", "c"]);
+
+        let err = module.snapshot_state(&["missing"]).unwrap_err();
+        assert_eq!(err.exception_type(), "AttributeError");
+        assert!(module.restore_state(b"not a pickle").is_err());
+    }
+}
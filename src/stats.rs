@@ -0,0 +1,178 @@
+use crate::PythonModule;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Number of recent latencies kept for percentiles
+const SAMPLES: usize = 1024;
+
+type Exporter = Box<dyn Fn(&TaskMetrics) + Send + Sync>;
+
+/// Snapshot of a module's counters, see [`PythonModule::stats`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Tasks waiting in the queue
+    pub pending: usize,
+    /// Actions and spawned tasks that finished
+    pub executed: u64,
+    /// Finished tasks that returned an error
+    pub errors: u64,
+    /// Mean time from queueing to finishing over all tasks
+    pub mean_latency: Duration,
+    /// Median latency of the last 1024 tasks
+    pub p50_latency: Duration,
+    /// 99th percentile latency of the last 1024 tasks
+    pub p99_latency: Duration,
+    /// Time since the worker was spawned
+    pub uptime: Duration,
+}
+
+/// Timings of a single task, passed to the exporter set with
+/// [`PythonModule::export_metrics`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskMetrics {
+    pub queue_wait: Duration,
+    pub execution: Duration,
+    pub failed: bool,
+}
+
+pub(crate) struct Metrics {
+    started: Instant,
+    executed: AtomicU64,
+    errors: AtomicU64,
+    total_latency_us: AtomicU64,
+    latencies: Mutex<VecDeque<Duration>>,
+    exporter: Mutex<Option<Exporter>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            executed: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            total_latency_us: AtomicU64::new(0),
+            latencies: Mutex::new(VecDeque::with_capacity(SAMPLES)),
+            exporter: Mutex::new(None),
+        }
+    }
+}
+
+impl Metrics {
+    /// Records a task that was queued at `queued` and started running at `started`
+    pub(crate) fn record(&self, queued: Instant, started: Instant, failed: bool) {
+        let task = TaskMetrics {
+            queue_wait: started - queued,
+            execution: started.elapsed(),
+            failed,
+        };
+        let latency = task.queue_wait + task.execution;
+        self.executed.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_latency_us
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+        {
+            let mut latencies = self.latencies.lock().unwrap();
+            if latencies.len() == SAMPLES {
+                latencies.pop_front();
+            }
+            latencies.push_back(latency);
+        }
+        if let Some(exporter) = &*self.exporter.lock().unwrap() {
+            exporter(&task);
+        }
+    }
+}
+
+impl PythonModule {
+    /// Counters of the tasks run by this module
+    ///```rs
+    /// let stats = module.stats();
+    /// if stats.pending > 100 {
+    ///     eprintln!("worker falls behind, p99 latency {:?}", stats.p99_latency);
+    /// }
+    /// ```
+    pub fn stats(&self) -> Stats {
+        let metrics = &self.control.metrics;
+        let executed = metrics.executed.load(Ordering::Relaxed);
+        let mut latencies = metrics
+            .latencies
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        latencies.sort();
+        let percentile = |p: usize| {
+            latencies
+                .get((latencies.len() * p / 100).min(latencies.len().saturating_sub(1)))
+                .copied()
+                .unwrap_or_default()
+        };
+        Stats {
+            pending: self.control.backlog.len(),
+            executed,
+            errors: metrics.errors.load(Ordering::Relaxed),
+            mean_latency: Duration::from_micros(
+                metrics.total_latency_us.load(Ordering::Relaxed) / executed.max(1),
+            ),
+            p50_latency: percentile(50),
+            p99_latency: percentile(99),
+            uptime: metrics.started.elapsed(),
+        }
+    }
+
+    /// Calls `exporter` on the worker thread after every finished task, replacing a previous one
+    pub fn export_metrics(&self, exporter: impl Fn(&TaskMetrics) + Send + Sync + 'static) {
+        *self.control.metrics.exporter.lock().unwrap() = Some(Box::new(exporter));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::prelude::*;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_stats() {
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        let exported = Arc::new(Mutex::new(Vec::new()));
+        let sink = exported.clone();
+        module.export_metrics(move |task| sink.lock().unwrap().push(*task));
+
+        let blocker = module
+            .spawn(|py, _| {
+                py.import("time")?.call_method1("sleep", (0.05,))?;
+                Ok(())
+            })
+            .unwrap();
+        let queued = module.spawn(|_, _| Ok(())).unwrap();
+        assert!(module.stats().pending >= 1);
+        blocker.join().unwrap();
+        queued.join().unwrap();
+        assert!(
+            module
+                .action(|_, module| module.getattr("missing").map(|_| ()))
+                .is_err()
+        );
+
+        let stats = module.stats();
+        assert_eq!(stats.pending, 0);
+        assert_eq!(stats.executed, 3);
+        assert_eq!(stats.errors, 1);
+        assert!(stats.p99_latency >= Duration::from_millis(50));
+        assert!(stats.p50_latency <= stats.p99_latency);
+        assert!(stats.uptime >= stats.p99_latency);
+
+        let exported = exported.lock().unwrap();
+        assert_eq!(exported.len(), 3);
+        assert!(exported[0].execution >= Duration::from_millis(50));
+        assert!(exported[1].queue_wait >= Duration::from_millis(40));
+        assert!(exported[2].failed);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::call::lookup;
+use crate::{PyHandle, PyRunnerError, PythonModule};
+use futures_core::Stream;
+use pyo3::call::PyCallArgs;
+use pyo3::exceptions::PyStopAsyncIteration;
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::oneshot;
+
+type Next<T> = oneshot::Receiver<Result<Option<T>, PyRunnerError>>;
+
+/// Items of a Python async iterator, each `__anext__` is awaited on the worker
+///
+/// On a module with an event loop
+/// ([`PythonModuleBuilder::event_loop`](crate::PythonModuleBuilder::event_loop)) the items
+/// are awaited on that loop, otherwise the stream runs its own loop. Ends after the first
+/// error.
+///```rs
+/// let mut tokens = module.call_stream::<String>("generate", ("prompt",)).unwrap();
+/// while let Some(token) = tokens.next().await {
+///     print!("{}", token.unwrap());
+/// }
+/// ```
+pub struct PyStream<'a, T> {
+    module: &'a PythonModule,
+    /// `(async iterator, own event loop or None)`
+    handle: Option<PyHandle>,
+    next: Option<Next<T>>,
+}
+
+impl<T> PyStream<'_, T>
+where
+    T: for<'py> FromPyObject<'py> + Send + 'static,
+{
+    fn request(&self, handle: PyHandle) -> Result<Next<T>, PyRunnerError> {
+        let (sender, receiver) = oneshot::channel();
+        let state = handle.clone();
+        self.module.dispatch_await(
+            move |py, _| {
+                let state = handle.bind(*py)?;
+                let next = state.get_item(0)?.call_method0("__anext__")?;
+                let event_loop = state.get_item(1)?;
+                if event_loop.is_none() {
+                    Ok(next)
+                } else {
+                    event_loop.call_method1("run_until_complete", (next,))
+                }
+            },
+            move |py, result| match result {
+                Ok(item) => Ok(Some(item.extract()?)),
+                Err(e) if e.is_instance_of::<PyStopAsyncIteration>(py) => {
+                    let event_loop = state.bind(py)?.get_item(1)?;
+                    if !event_loop.is_none() {
+                        event_loop.call_method0("close")?;
+                    }
+                    Ok(None)
+                }
+                Err(e) => Err(e),
+            },
+            move |result| {
+                let _ = sender.send(result);
+            },
+        )?;
+        Ok(receiver)
+    }
+}
+
+impl<T> Stream for PyStream<'_, T>
+where
+    T: for<'py> FromPyObject<'py> + Send + 'static,
+{
+    type Item = Result<T, PyRunnerError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let next = match &mut this.next {
+            Some(next) => next,
+            None => {
+                let Some(handle) = this.handle.clone() else {
+                    return Poll::Ready(None);
+                };
+                match this.request(handle) {
+                    Ok(next) => this.next.insert(next),
+                    Err(e) => {
+                        this.handle = None;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+            }
+        };
+        let result = match Pin::new(next).poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.next = None;
+        match result {
+            Ok(Ok(Some(item))) => Poll::Ready(Some(Ok(item))),
+            Ok(Ok(None)) => {
+                this.handle = None;
+                Poll::Ready(None)
+            }
+            Ok(Err(e)) => {
+                this.handle = None;
+                Poll::Ready(Some(Err(e)))
+            }
+            Err(_) => {
+                this.handle = None;
+                Poll::Ready(Some(Err(this.module.exited_error())))
+            }
+        }
+    }
+}
+
+impl PythonModule {
+    /// Calls `name`, which may be a dotted path, and streams the returned async iterable
+    ///
+    /// Works with async generators and any other async iterable. Blocks while the call
+    /// itself runs, the items are awaited lazily.
+    pub fn call_stream<T>(
+        &self,
+        name: &str,
+        args: impl for<'py> PyCallArgs<'py> + Send + 'static,
+    ) -> Result<PyStream<'_, T>, PyRunnerError> {
+        let name = name.to_string();
+        let handle = self.create_handle(move |py, module| {
+            let iterator = lookup(module, &name)?
+                .call1(args)?
+                .call_method0("__aiter__")?;
+            let asyncio = py.import("asyncio")?;
+            let event_loop = match asyncio.call_method0("_get_running_loop")? {
+                running if running.is_none() => asyncio.call_method0("new_event_loop")?,
+                _ => py.None().into_bound(*py),
+            };
+            Ok(PyTuple::new(*py, [iterator, event_loop])?
+                .into_any()
+                .unbind())
+        })?;
+        Ok(PyStream {
+            module: self,
+            handle: Some(handle),
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModuleBuilder;
+    use std::future::poll_fn;
+
+    async fn collect(mut stream: PyStream<'_, i64>) -> Vec<Result<i64, PyRunnerError>> {
+        let mut items = Vec::new();
+        while let Some(item) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            items.push(item);
+        }
+        items
+    }
+
+    #[tokio::test]
+    async fn test_call_stream() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import asyncio\n",
+                "async def tokens(n):\n",
+                "    for i in range(n):\n",
+                "        await asyncio.sleep(0)\n",
+                "        yield i\n",
+                "async def broken():\n",
+                "    yield 1\n",
+                "    raise ValueError('broken')\n",
+            ),
+        )
+        .unwrap();
+
+        for event_loop in [false, true] {
+            let module = PythonModuleBuilder::new_project(dir.join("main.py"))
+                .event_loop(event_loop)
+                .build()
+                .unwrap();
+            let items = collect(module.call_stream("tokens", (3,)).unwrap()).await;
+            let items = items.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(items, vec![0, 1, 2]);
+
+            let items = collect(module.call_stream("broken", ()).unwrap()).await;
+            assert_eq!(items.len(), 2);
+            assert_eq!(*items[0].as_ref().unwrap(), 1);
+            assert_eq!(
+                items[1].as_ref().unwrap_err().exception_type(),
+                "ValueError"
+            );
+        }
+    }
+}
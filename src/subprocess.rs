@@ -0,0 +1,417 @@
+use crate::{PyRunnerError, PythonModule, PythonModuleBuilder, PythonPath};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::ffi::CString;
+use std::path::Path;
+use std::time::Duration;
+
+const HOST: &str = include_str!("subprocess/host.py");
+const PROXY: &str = include_str!("subprocess/proxy.py");
+
+impl PythonModule {
+    /// Loads a Python module (directory) or project (file) into a separate `python` process
+    ///
+    /// Actions run in this process against a stand-in for the module: attribute reads are
+    /// copied over with `pickle` and function calls are executed in the child process. Values
+    /// coming back are limited to builtin data, `datetime`, `decimal` and `uuid` types, so the
+    /// child can't run code in this process, and exceptions of the module arrive as stand-ins
+    /// of the same name. A crash of the child only fails the pending and later actions with a
+    /// `ConnectionError`.
+    /// `let module = PythonModule::new_subprocess("./my-module").unwrap();`
+    pub fn new_subprocess(path: impl AsRef<Path>) -> Result<PythonModule, PyRunnerError> {
+        PythonModuleBuilder::new(path).subprocess(true).build()
+    }
+}
+
+/// Limits of the child process of a subprocess module, see
+/// [`PythonModuleBuilder::resource_limits`]
+///
+/// Exceeding one fails the call with [`ErrorKind::ResourceLimitExceeded`](crate::ErrorKind).
+/// `task_timeout` kills the child, so later calls fail with a `ConnectionError`. The limits
+/// need a POSIX system: the process limits use `resource.setrlimit` and `task_timeout` waits
+/// for the child's pipe with `select.select`, building a module with limits fails on Windows.
+///```rs
+/// let limits = ResourceLimits {
+///     max_memory: Some(512 << 20),
+///     task_timeout: Some(Duration::from_secs(30)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Address space of the child in bytes, allocations beyond it raise `MemoryError`
+    pub max_memory: Option<u64>,
+    /// CPU time the child may use over its lifetime, rounded up to whole seconds
+    pub cpu_time: Option<Duration>,
+    /// Number of file descriptors the child may hold open
+    pub open_files: Option<u64>,
+    /// Wall-clock time a single call may take, POSIX only like the other limits
+    pub task_timeout: Option<Duration>,
+}
+
+/// Limit a call ran into, see [`ErrorKind::ResourceLimitExceeded`](crate::ErrorKind)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    Memory,
+    CpuTime,
+    OpenFiles,
+    WallClock,
+}
+
+impl Resource {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "memory" => Some(Resource::Memory),
+            "cpu_time" => Some(Resource::CpuTime),
+            "open_files" => Some(Resource::OpenFiles),
+            "wall_clock" => Some(Resource::WallClock),
+            _ => None,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// `name=value,...` as parsed by the child
+    fn spec(&self) -> String {
+        let cpu_time = self
+            .cpu_time
+            .map(|cpu| cpu.as_secs_f64().ceil().max(1.0) as u64);
+        [
+            ("memory", self.max_memory),
+            ("cpu_time", cpu_time),
+            ("open_files", self.open_files),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some(format!("{name}={}", value?)))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+/// Settings forwarded to the child process
+pub(crate) struct Child<'a> {
+    pub python: Option<&'a Path>,
+    pub init_file: &'a Path,
+    pub module_name: &'a str,
+    pub sys_path: &'a PythonPath,
+    pub env: &'a [(String, String)],
+    pub working_dir: Option<&'a Path>,
+    pub limits: Option<&'a ResourceLimits>,
+    pub root: Option<&'a Path>,
+}
+
+/// Starts the child process and returns the stand-in module once the import finished
+pub(crate) fn spawn<'py>(py: Python<'py>, child: Child<'_>) -> PyResult<Bound<'py, PyAny>> {
+    let namespace = PyDict::new(py);
+    let proxy = CString::new(PROXY).expect("proxy source contains no NUL byte");
+    py.run(&proxy, Some(&namespace), None)?;
+
+    let sys = py.import("sys")?;
+    let os = py.import("os")?;
+    let python = match child.python {
+        Some(python) => python.as_os_str().into_pyobject(py)?.into_any(),
+        None => {
+            let executable = sys.getattr("executable")?;
+            if executable.is_truthy()? {
+                executable
+            } else {
+                "python3".into_pyobject(py)?.into_any()
+            }
+        }
+    };
+    let argv = (
+        python,
+        "-c",
+        HOST,
+        child.init_file.as_os_str(),
+        child.module_name,
+        child.limits.map(ResourceLimits::spec).unwrap_or_default(),
+        child.root.map(Path::as_os_str).unwrap_or_default(),
+        child
+            .root
+            .map(|_| crate::sandbox::SANDBOX)
+            .unwrap_or_default(),
+    );
+
+    let env = PyDict::new(py);
+    env.call_method1("update", (os.getattr("environ")?,))?;
+    for (key, value) in child.env {
+        env.set_item(key, value)?;
+    }
+    if !child.sys_path.prepended().is_empty() || !child.sys_path.appended().is_empty() {
+        let current = match env.get_item("PYTHONPATH")? {
+            Some(current) => std::env::split_paths(&current.extract::<String>()?).collect(),
+            None => Vec::new(),
+        };
+        let joined = std::env::join_paths(child.sys_path.resolve(current))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        env.set_item("PYTHONPATH", joined)?;
+    }
+    let cwd = child.working_dir.map(|dir| dir.as_os_str());
+    let task_timeout = child
+        .limits
+        .and_then(|limits| limits.task_timeout)
+        .map(|timeout| timeout.as_secs_f64());
+
+    namespace
+        .get_item("RemoteModule")?
+        .expect("proxy defines RemoteModule")
+        .call1((argv, env, cwd, task_timeout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_subprocess() {
+        let module = PythonModule::new_subprocess("./my-project/main.py").unwrap();
+        let sum = module
+            .action(|_, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(sum, 3);
+
+        let err = module
+            .action(|_, module| module.call_method1("divide", (1, 0))?.extract::<f64>())
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "ZeroDivisionError");
+        assert!(err.message().contains("division by zero"));
+    }
+
+    #[test]
+    fn test_subprocess_crash() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import os\n",
+                "VALUE = os.environ['PY_RUNNER_SUBPROCESS']\n",
+                "def pid():\n",
+                "    return os.getpid()\n",
+                "def crash():\n",
+                "    os._exit(3)\n",
+            ),
+        )
+        .unwrap();
+
+        let module = PythonModuleBuilder::new_project(dir.join("main.py"))
+            .subprocess(true)
+            .env("PY_RUNNER_SUBPROCESS", "child")
+            .build()
+            .unwrap();
+        let (value, pid) = module
+            .action(|_, module| {
+                Ok((
+                    module.getattr("VALUE")?.extract::<String>()?,
+                    module.call_method0("pid")?.extract::<u32>()?,
+                ))
+            })
+            .unwrap();
+        assert_eq!(value, "child");
+        assert_ne!(pid, std::process::id());
+
+        let err = module
+            .action(|_, module| module.call_method0("crash").map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "ConnectionError");
+        assert!(
+            module
+                .action(|_, module| module.call_method0("pid").map(|_| ()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_subprocess_untrusted_pickle() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let marker = dir.join("pwned");
+        std::fs::write(
+            dir.join("main.py"),
+            format!(
+                concat!(
+                    "import datetime, os\n",
+                    "class Evil:\n",
+                    "    def __reduce__(self):\n",
+                    "        return (os.system, ('touch {marker}',))\n",
+                    "class EvilError(Exception):\n",
+                    "    def __reduce__(self):\n",
+                    "        return (os.system, ('touch {marker}',))\n",
+                    "def value():\n",
+                    "    return Evil()\n",
+                    "def fail():\n",
+                    "    raise EvilError('nope')\n",
+                    "def data():\n",
+                    "    return {{'when': datetime.date(2024, 1, 2), 'ids': {{1, 2}}}}\n",
+                ),
+                marker = marker.display()
+            ),
+        )
+        .unwrap();
+        let module = PythonModuleBuilder::new_project(dir.join("main.py"))
+            .subprocess(true)
+            .build()
+            .unwrap();
+
+        let err = module
+            .action(|_, module| module.call_method0("value").map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "_pickle.UnpicklingError");
+        assert!(err.message().contains("system can't be sent"));
+        let err = module
+            .action(|_, module| module.call_method0("fail").map(|_| ()))
+            .unwrap_err();
+        assert!(err.exception_type().ends_with(".EvilError"));
+        assert_eq!(err.message(), "nope");
+        assert!(!marker.exists());
+
+        // plain data still arrives
+        let when = module
+            .action(|_, module| {
+                let data = module.call_method0("data")?;
+                data.get_item("when")?
+                    .call_method0("isoformat")?
+                    .extract::<String>()
+            })
+            .unwrap();
+        assert_eq!(when, "2024-01-02");
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        use crate::ErrorKind;
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("main.py"),
+            concat!(
+                "import time\n",
+                "def ping():\n",
+                "    return 1\n",
+                "def allocate():\n",
+                "    return len(bytearray(1 << 30))\n",
+                "def open_many():\n",
+                "    return [open(__file__) for _ in range(100)]\n",
+                "def spin():\n",
+                "    while True:\n",
+                "        pass\n",
+                "def nap():\n",
+                "    time.sleep(5)\n",
+            ),
+        )
+        .unwrap();
+        let limited = |limits: ResourceLimits| {
+            PythonModuleBuilder::new_project(dir.join("main.py"))
+                .subprocess(true)
+                .resource_limits(limits)
+                .build()
+                .unwrap()
+        };
+        let exceeded = |module: &PythonModule, name: &'static str| {
+            let err = module
+                .action(move |_, module| module.call_method0(name).map(|_| ()))
+                .unwrap_err();
+            assert_eq!(err.exception_type(), "py_runner.ResourceLimitExceeded");
+            match err.kind() {
+                ErrorKind::ResourceLimitExceeded { resource } => *resource,
+                kind => panic!("unexpected {kind:?}"),
+            }
+        };
+
+        let module = limited(ResourceLimits {
+            max_memory: Some(512 << 20),
+            open_files: Some(64),
+            ..Default::default()
+        });
+        assert_eq!(exceeded(&module, "allocate"), Resource::Memory);
+        assert_eq!(exceeded(&module, "open_many"), Resource::OpenFiles);
+        // the child survives both
+        let pong = module
+            .action(|_, module| module.call_method0("ping")?.extract::<i64>())
+            .unwrap();
+        assert_eq!(pong, 1);
+
+        let module = limited(ResourceLimits {
+            cpu_time: Some(Duration::from_secs(1)),
+            ..Default::default()
+        });
+        assert_eq!(exceeded(&module, "spin"), Resource::CpuTime);
+
+        let module = limited(ResourceLimits {
+            task_timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        });
+        assert_eq!(exceeded(&module, "nap"), Resource::WallClock);
+
+        let err = PythonModuleBuilder::new_project(dir.join("main.py"))
+            .resource_limits(ResourceLimits::default())
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.message().contains("subprocess backend"));
+    }
+
+    #[test]
+    fn test_subprocess_fs_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("app/data")).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::fs::write(
+            dir.join("app/main.py"),
+            concat!(
+                "import ctypes, ctypes.util, os\n",
+                "def write(path, text):\n",
+                "    with open(path, 'w') as f:\n",
+                "        f.write(text)\n",
+                "def read(path):\n",
+                "    with open(path) as f:\n",
+                "        return f.read()\n",
+                "def raw_open(path):\n",
+                "    libc = ctypes.CDLL(None, use_errno=True)\n",
+                "    fd = libc.open(path.encode(), os.O_RDONLY)\n",
+                "    if fd >= 0:\n",
+                "        os.close(fd)\n",
+                "    return ctypes.get_errno() if fd < 0 else 0\n",
+            ),
+        )
+        .unwrap();
+        let module = PythonModuleBuilder::new_project(dir.join("app/main.py"))
+            .subprocess(true)
+            .fs_root(dir.join("app/data"))
+            .build()
+            .unwrap();
+
+        let inside = dir.join("app/data/notes.txt").to_string_lossy().to_string();
+        let secret = dir.join("secret.txt").to_string_lossy().to_string();
+        let text = module
+            .action(move |_, module| {
+                module.call_method1("write", (&inside, "notes"))?;
+                module.call_method1("read", (inside,))?.extract::<String>()
+            })
+            .unwrap();
+        assert_eq!(text, "notes");
+        let path = secret.clone();
+        let err = module
+            .action(move |_, module| module.call_method1("read", (path,)).map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.exception_type(), "PermissionError");
+
+        // code that bypasses the audit hook is stopped by Landlock where the kernel has it
+        let landlock = PythonModule::from_source("root_landlock", "import ctypes\n")
+            .unwrap()
+            .action(|py, _| {
+                let libc = py.import("ctypes")?.getattr("CDLL")?.call1((py.None(),))?;
+                libc.call_method1("syscall", (444, py.None(), 0, 1))?
+                    .extract::<i64>()
+            })
+            .unwrap()
+            > 0;
+        let errno = module
+            .action(move |_, module| module.call_method1("raw_open", (secret,))?.extract::<i32>())
+            .unwrap();
+        assert_eq!(errno != 0, cfg!(target_os = "linux") && landlock);
+    }
+}
@@ -0,0 +1,196 @@
+# Runs inside the child process: imports the module and answers requests.
+# Every frame is a 4 byte big-endian length followed by a pickle payload.
+import errno
+import importlib.util
+import os
+import pickle
+import struct
+import sys
+import traceback
+
+
+def read(stream):
+    header = stream.read(4)
+    if len(header) < 4:
+        return None
+    (size,) = struct.unpack(">I", header)
+    return pickle.loads(stream.read(size))
+
+
+def write(stream, value):
+    data = pickle.dumps(value)
+    stream.write(struct.pack(">I", len(data)) + data)
+    stream.flush()
+
+
+class LimitExceeded(Exception):
+    def __init__(self, resource, message):
+        super().__init__(message)
+        self.resource = resource
+
+
+def apply_limits(spec):
+    """Applies limits given as `name=value,...`, returns the names of the applied ones"""
+    limits = dict(item.split("=") for item in spec.split(",") if item)
+    if not limits:
+        return set()
+    import resource
+    import signal
+
+    kinds = {
+        "memory": getattr(resource, "RLIMIT_AS", resource.RLIMIT_DATA),
+        "cpu_time": resource.RLIMIT_CPU,
+        "open_files": resource.RLIMIT_NOFILE,
+    }
+    for name, value in limits.items():
+        value = int(value)
+        _, hard = resource.getrlimit(kinds[name])
+        if name == "cpu_time":
+            # SIGXCPU at the soft limit reports it, the hard limit a second later kills
+            hard = value + 1 if hard == resource.RLIM_INFINITY else min(hard, value + 1)
+        resource.setrlimit(kinds[name], (value, hard))
+    if "cpu_time" in limits:
+
+        def exceeded(signum, frame):
+            raise LimitExceeded("cpu_time", "CPU time limit exceeded")
+
+        signal.signal(signal.SIGXCPU, exceeded)
+    return set(limits)
+
+
+def landlock(root, readable):
+    """Lets the kernel enforce the file system root on Linux, returns whether it did"""
+    if not sys.platform.startswith("linux"):
+        return False
+    import ctypes
+
+    libc = ctypes.CDLL(None, use_errno=True)
+    libc.syscall.restype = ctypes.c_long
+    # landlock_create_ruleset, landlock_add_rule and landlock_restrict_self
+    create, add, restrict = 444, 445, 446
+    abi = libc.syscall(create, None, ctypes.c_size_t(0), ctypes.c_uint32(1))
+    if abi < 1:
+        return False
+    # the access rights up to MAKE_SYM exist since ABI 1, REFER since 2 and TRUNCATE since 3
+    everything = (1 << (13 + min(abi - 1, 2))) - 1
+    execute, write_file, read_file, read_dir = 1 << 0, 1 << 1, 1 << 2, 1 << 3
+    # rights that apply to files rather than directories, TRUNCATE is bit 14
+    file_rights = everything & (execute | write_file | read_file | 1 << 14)
+
+    class PathBeneath(ctypes.Structure):
+        _pack_ = 1
+        _fields_ = [("allowed_access", ctypes.c_uint64), ("parent_fd", ctypes.c_int32)]
+
+    handled = ctypes.c_uint64(everything)
+    ruleset = libc.syscall(create, ctypes.byref(handled), ctypes.c_size_t(8), ctypes.c_uint32(0))
+    if ruleset < 0:
+        return False
+    try:
+        # shared libraries of extension modules are loaded from the system directories
+        system = ("/usr", "/lib", "/lib64", sys.prefix, sys.base_prefix, sys.exec_prefix)
+        rules = [(root, everything)]
+        rules += [(path, execute | read_file | read_dir) for path in (*readable, *system)]
+        for path, access in rules:
+            try:
+                fd = os.open(path, os.O_PATH | os.O_CLOEXEC)
+            except OSError:
+                continue
+            try:
+                if not os.path.isdir(path):
+                    access &= file_rights
+                rule = PathBeneath(access, fd)
+                libc.syscall(add, ruleset, 1, ctypes.byref(rule), ctypes.c_uint32(0))
+            finally:
+                os.close(fd)
+        # PR_SET_NO_NEW_PRIVS
+        if libc.prctl(38, 1, 0, 0, 0) != 0:
+            return False
+        return libc.syscall(restrict, ruleset, ctypes.c_uint32(0)) == 0
+    finally:
+        os.close(ruleset)
+
+
+def confine(root, sandbox, init_file):
+    """Confines the file access of this process to `root`, see `sandbox.py`"""
+    import types
+
+    # kept out of `sys.modules`, so the module can't reach it
+    helper = types.ModuleType("_py_runner_sandbox")
+    exec(sandbox, vars(helper))
+    readable = [os.path.dirname(os.path.abspath(init_file))]
+    helper.confine(None, root, readable)
+    landlock(os.path.realpath(root), [*readable, *sys.path])
+
+
+def exceeded_limit(exc, limits):
+    if isinstance(exc, LimitExceeded):
+        return exc.resource
+    if isinstance(exc, MemoryError) and "memory" in limits:
+        return "memory"
+    if isinstance(exc, OSError) and exc.errno == errno.EMFILE and "open_files" in limits:
+        return "open_files"
+    return None
+
+
+def error(exc, limits):
+    tb = "".join(traceback.format_exception(type(exc), exc, exc.__traceback__))
+    resource = exceeded_limit(exc, limits)
+    if resource is not None:
+        return ("limit", (resource, str(exc) or f"{resource} limit exceeded", tb))
+    try:
+        payload = pickle.dumps(exc)
+    except Exception:
+        payload = None
+    return ("err", (type(exc).__module__, type(exc).__qualname__, str(exc), tb, payload))
+
+
+def main(init_file, module_name, limits, root, sandbox):
+    requests = os.fdopen(os.dup(0), "rb")
+    responses = os.fdopen(os.dup(1), "wb")
+    # keep stray output of the module away from the protocol streams
+    os.dup2(os.open(os.devnull, os.O_RDONLY), 0)
+    os.dup2(2, 1)
+    sys.stdout = sys.stderr
+
+    try:
+        limits = apply_limits(limits)
+        if root:
+            confine(root, sandbox, init_file)
+    except BaseException as exc:
+        write(responses, error(exc, set()))
+        return
+    try:
+        spec = importlib.util.spec_from_file_location(module_name, init_file)
+        module = importlib.util.module_from_spec(spec)
+        sys.modules[module_name] = module
+        spec.loader.exec_module(module)
+    except BaseException as exc:
+        write(responses, error(exc, limits))
+        return
+    write(responses, ("ok", None))
+
+    while (request := read(requests)) is not None:
+        kind, name, args, kwargs = request
+        try:
+            # dotted names are resolved here, the objects on the way may not be picklable
+            *parents, name = name.split(".")
+            target = module
+            for parent in parents:
+                target = getattr(target, parent)
+            if kind == "setattr":
+                setattr(target, name, *args)
+                write(responses, ("ok", None))
+                continue
+            value = getattr(target, name)
+            if kind == "call":
+                response = ("ok", value(*args, **kwargs))
+            elif callable(value):
+                response = ("callable", None)
+            else:
+                response = ("ok", value)
+            write(responses, response)
+        except BaseException as exc:
+            write(responses, error(exc, limits))
+
+
+main(*sys.argv[1:6])
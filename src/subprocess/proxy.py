@@ -0,0 +1,151 @@
+# Runs inside the host process: stands in for the module living in the child process.
+import builtins
+import io
+import pickle
+import select
+import struct
+import subprocess
+
+# classes a response may name besides the builtin exceptions, unpickling one runs no code
+# of the child
+RESPONSE_CLASSES = {
+    ("builtins", "bytearray"),
+    ("builtins", "complex"),
+    ("builtins", "frozenset"),
+    ("builtins", "range"),
+    ("builtins", "set"),
+    ("builtins", "slice"),
+    ("collections", "OrderedDict"),
+    ("datetime", "date"),
+    ("datetime", "datetime"),
+    ("datetime", "time"),
+    ("datetime", "timedelta"),
+    ("datetime", "timezone"),
+    ("decimal", "Decimal"),
+    ("uuid", "SafeUUID"),
+    ("uuid", "UUID"),
+}
+
+
+class ResponseUnpickler(pickle.Unpickler):
+    """Unpickles data only: the child may be confined and must not run code in this process"""
+
+    def find_class(self, module, name):
+        if (module, name) in RESPONSE_CLASSES:
+            return super().find_class(module, name)
+        value = getattr(builtins, name, None) if module == "builtins" else None
+        if isinstance(value, type) and issubclass(value, BaseException):
+            return value
+        raise pickle.UnpicklingError(
+            f"{module}.{name} can't be sent by the Python subprocess"
+        )
+
+
+def loads(data):
+    return ResponseUnpickler(io.BytesIO(data)).load()
+
+
+class ResourceLimitExceeded(Exception):
+    def __init__(self, resource, message):
+        super().__init__(message)
+        self.resource = resource
+
+
+# recognized by the Rust side, see `ErrorKind::ResourceLimitExceeded`
+ResourceLimitExceeded.__module__ = "py_runner"
+
+
+class RemoteFunction:
+    def __init__(self, module, name):
+        self._module = module
+        self._name = name
+
+    def __call__(self, *args, **kwargs):
+        return self._module._request("call", self._name, args, kwargs)
+
+    def __repr__(self):
+        return f"<remote function {self._name}>"
+
+
+class RemoteModule:
+    def __init__(self, argv, env, cwd, task_timeout):
+        self._process = subprocess.Popen(
+            argv, stdin=subprocess.PIPE, stdout=subprocess.PIPE, env=env, cwd=cwd
+        )
+        self._task_timeout = task_timeout
+        self._unpack(self._read())
+
+    def __getattr__(self, name):
+        if name.startswith("_"):
+            raise AttributeError(name)
+        return self._request("getattr", name, (), {})
+
+    def __setattr__(self, name, value):
+        if name.startswith("_"):
+            object.__setattr__(self, name, value)
+        else:
+            self._request("setattr", name, (value,), {})
+
+    def _request(self, kind, name, args, kwargs):
+        data = pickle.dumps((kind, name, args, kwargs))
+        try:
+            self._process.stdin.write(struct.pack(">I", len(data)) + data)
+            self._process.stdin.flush()
+        except BrokenPipeError:
+            pass  # reported by _read
+        response = self._read(self._task_timeout)
+        if response[0] == "callable":
+            return RemoteFunction(self, name)
+        return self._unpack(response)
+
+    def _read(self, timeout=None):
+        if timeout is not None:
+            ready, _, _ = select.select([self._process.stdout], [], [], timeout)
+            if not ready:
+                self._process.kill()
+                self._process.wait()
+                raise ResourceLimitExceeded(
+                    "wall_clock", f"Call did not finish within {timeout}s"
+                )
+        header = self._process.stdout.read(4)
+        if len(header) < 4:
+            code = self._process.wait()
+            raise ConnectionError(f"Python subprocess exited with code {code}")
+        (size,) = struct.unpack(">I", header)
+        return loads(self._process.stdout.read(size))
+
+    @staticmethod
+    def _unpack(response):
+        status, value = response
+        if status == "ok":
+            return value
+        if status == "limit":
+            resource, message, tb = value
+            exc = ResourceLimitExceeded(resource, message)
+            if hasattr(exc, "add_note"):
+                exc.add_note("Raised in Python subprocess:\n" + tb)
+            raise exc
+        module, qualname, message, tb, payload = value
+        try:
+            exc = loads(payload)
+        except Exception:
+            # a stand-in of the same name for exceptions defined by the module
+            names = {"__module__": module, "__qualname__": qualname}
+            exc = type(qualname.rpartition(".")[2], (Exception,), names)(message)
+        if hasattr(exc, "add_note"):
+            exc.add_note("Raised in Python subprocess:\n" + tb)
+        raise exc
+
+    def close(self):
+        process = self.__dict__.get("_process")
+        if process is None or process.poll() is not None:
+            return
+        process.stdin.close()
+        try:
+            process.wait(timeout=5)
+        except subprocess.TimeoutExpired:
+            process.kill()
+            process.wait()
+
+    def __del__(self):
+        self.close()
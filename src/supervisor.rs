@@ -0,0 +1,247 @@
+use crate::{PyRunnerError, PythonModule};
+use pyo3::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Something [`Supervised`] can watch and replace
+pub trait Restartable {
+    /// Whether the instance can still run tasks
+    fn is_alive(&self) -> bool;
+
+    /// Hands the last state snapshot to a restarted instance, ignores it by default
+    fn restore_state(&self, _state: &[u8]) -> Result<(), PyRunnerError> {
+        Ok(())
+    }
+}
+
+impl Restartable for PythonModule {
+    fn is_alive(&self) -> bool {
+        PythonModule::is_alive(self)
+    }
+
+    fn restore_state(&self, state: &[u8]) -> Result<(), PyRunnerError> {
+        PythonModule::restore_state(self, state)
+    }
+}
+
+/// When and how often [`Supervised`] recreates a dead instance
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    max_restarts: Option<usize>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    /// Unlimited restarts, backoff doubling from 100ms up to 30s
+    fn default() -> Self {
+        Self {
+            max_restarts: None,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Gives up after `max_restarts` restarts
+    pub fn max_restarts(mut self, max_restarts: usize) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    /// Waits `initial` before the first restart, doubling up to `max` for every further one
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    fn delay(&self, restart: usize) -> Duration {
+        let factor = 1u32.checked_shl(restart.min(31) as u32).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Recreates the wrapped instance whenever it died
+///```rs
+/// let module = Supervised::new(|| PythonModule::new_module(Path::new("./plugin")), RestartPolicy::default()).unwrap();
+/// module.action(|py, module| module.call_method1("add", (1, 2))?.extract::<i64>()).unwrap();
+/// ```
+pub struct Supervised<T> {
+    factory: Box<dyn Fn() -> Result<T, PyRunnerError> + Send + Sync>,
+    policy: RestartPolicy,
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    current: Arc<T>,
+    restarts: usize,
+    snapshot: Option<Vec<u8>>,
+}
+
+impl<T: Restartable> Supervised<T> {
+    /// Creates the first instance with `factory`, which is called again for every restart
+    pub fn new(
+        factory: impl Fn() -> Result<T, PyRunnerError> + Send + Sync + 'static,
+        policy: RestartPolicy,
+    ) -> Result<Self, PyRunnerError> {
+        let current = Arc::new(factory()?);
+        Ok(Self {
+            factory: Box::new(factory),
+            policy,
+            state: Mutex::new(State {
+                current,
+                restarts: 0,
+                snapshot: None,
+            }),
+        })
+    }
+
+    /// Returns a live instance, restarting a dead one first
+    ///
+    /// Blocks for the backoff of the policy and fails once the restart limit is reached.
+    /// A restarted instance gets the last state snapshot restored.
+    pub fn get(&self) -> Result<Arc<T>, PyRunnerError> {
+        let mut state = self.state.lock().unwrap();
+        while !state.current.is_alive() {
+            if self
+                .policy
+                .max_restarts
+                .is_some_and(|max| state.restarts >= max)
+            {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Gave up after {} restarts",
+                    state.restarts
+                ))
+                .into());
+            }
+            thread::sleep(self.policy.delay(state.restarts));
+            state.restarts += 1;
+            let current = (self.factory)()?;
+            if let Some(snapshot) = &state.snapshot {
+                current.restore_state(snapshot)?;
+            }
+            state.current = Arc::new(current);
+        }
+        Ok(state.current.clone())
+    }
+
+    /// Number of restarts so far
+    pub fn restarts(&self) -> usize {
+        self.state.lock().unwrap().restarts
+    }
+}
+
+impl Supervised<PythonModule> {
+    /// Runs action on a live module, see [`PythonModule::action`]
+    pub fn action<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Python<'_>, &Bound<'_, PyAny>) -> PyResult<T> + Send + 'static,
+    ) -> Result<T, PyRunnerError> {
+        self.get()?.action(call)
+    }
+
+    /// Snapshots the module globals `keys`, see [`PythonModule::snapshot_state`]
+    ///
+    /// The snapshot is restored into every restarted module until the next one is taken.
+    /// State changed after the last snapshot is lost when the worker dies.
+    pub fn snapshot_state(&self, keys: &[&str]) -> Result<Vec<u8>, PyRunnerError> {
+        let snapshot = self.get()?.snapshot_state(keys)?;
+        self.state.lock().unwrap().snapshot = Some(snapshot.clone());
+        Ok(snapshot)
+    }
+
+    /// Restores `state`, e.g. saved by a previous process, and keeps it for restarts
+    pub fn restore_state(&self, state: &[u8]) -> Result<(), PyRunnerError> {
+        self.get()?.restore_state(state)?;
+        self.state.lock().unwrap().snapshot = Some(state.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_supervised() {
+        let policy = RestartPolicy::default()
+            .max_restarts(1)
+            .backoff(Duration::from_millis(1), Duration::from_millis(10));
+        let module = Supervised::new(
+            || PythonModule::new_module(Path::new("./my-module")),
+            policy,
+        )
+        .unwrap();
+        assert_eq!(module.restarts(), 0);
+
+        let crash = || {
+            module
+                .get()
+                .unwrap()
+                .queue(Box::new(|_, _| panic!("plugin crashed")))
+                .unwrap();
+            while module.state.lock().unwrap().current.is_alive() {
+                thread::yield_now();
+            }
+        };
+        crash();
+        let sum = module
+            .action(|_, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(sum, 3);
+        assert_eq!(module.restarts(), 1);
+
+        crash();
+        assert!(module.action(|_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_supervised_state() {
+        let policy = RestartPolicy::default().backoff(Duration::ZERO, Duration::ZERO);
+        let module = Supervised::new(
+            || PythonModule::from_source("supervised_state", "hits = 0\n"),
+            policy,
+        )
+        .unwrap();
+        let hits = |value: Option<i64>| {
+            module
+                .action(move |_, module| {
+                    if let Some(value) = value {
+                        module.setattr("hits", value)?;
+                    }
+                    module.getattr("hits")?.extract::<i64>()
+                })
+                .unwrap()
+        };
+        hits(Some(5));
+        module.snapshot_state(&["hits"]).unwrap();
+        hits(Some(6));
+
+        module
+            .get()
+            .unwrap()
+            .queue(Box::new(|_, _| panic!("plugin crashed")))
+            .unwrap();
+        while module.state.lock().unwrap().current.is_alive() {
+            thread::yield_now();
+        }
+        assert_eq!(hits(None), 5);
+        assert_eq!(module.restarts(), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy =
+            RestartPolicy::default().backoff(Duration::from_secs(1), Duration::from_secs(5));
+        assert_eq!(policy.delay(0), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(4));
+        assert_eq!(policy.delay(3), Duration::from_secs(5));
+        assert_eq!(policy.delay(100), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,183 @@
+use pyo3::exceptions::PySyntaxError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Syntax error found by [`check_syntax`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxDiagnostic {
+    /// File the error is in, `<name>` for [`check_syntax_source`]
+    pub file: String,
+    /// 1-based line, `None` if the file couldn't be read at all
+    pub line: Option<usize>,
+    /// 1-based column
+    pub column: Option<usize>,
+    pub end_line: Option<usize>,
+    pub end_column: Option<usize>,
+    /// e.g. `invalid syntax` or `expected ':'`
+    pub message: String,
+    /// Source of the offending line
+    pub text: Option<String>,
+}
+
+/// Compiles a Python file, or every `.py` file of a directory, without executing anything
+///
+/// Reports one diagnostic per broken file, including errors only the compiler finds like
+/// `return` outside of a function. Hidden entries and `__pycache__` are skipped.
+///```rs
+/// if let Err(diagnostics) = check_syntax("./plugins/analytics") {
+///     for d in diagnostics {
+///         eprintln!("{}:{}:{}: {}", d.file, d.line.unwrap_or(0), d.column.unwrap_or(0), d.message);
+///     }
+/// }
+/// ```
+pub fn check_syntax(path: impl AsRef<Path>) -> Result<(), Vec<SyntaxDiagnostic>> {
+    let mut files = Vec::new();
+    let mut diagnostics = Vec::new();
+    let path = path.as_ref();
+    match path.is_dir() {
+        true => collect(path, &mut files, &mut diagnostics),
+        false => files.push(path.to_path_buf()),
+    }
+    Python::with_gil(|py| {
+        for file in files {
+            let name = file.to_string_lossy().to_string();
+            let diagnostic = match fs::read(&file) {
+                Ok(source) => compile(py, &name, &source),
+                Err(err) => Some(unreadable(name, err)),
+            };
+            diagnostics.extend(diagnostic);
+        }
+    });
+    match diagnostics.is_empty() {
+        true => Ok(()),
+        false => Err(diagnostics),
+    }
+}
+
+/// Like [`check_syntax`] for `source` held in memory, reported as file `<name>`
+pub fn check_syntax_source(name: &str, source: &str) -> Result<(), Vec<SyntaxDiagnostic>> {
+    let diagnostic = Python::with_gil(|py| compile(py, &format!("<{name}>"), source.as_bytes()));
+    match diagnostic {
+        Some(diagnostic) => Err(vec![diagnostic]),
+        None => Ok(()),
+    }
+}
+
+/// `.py` files below `dir` in path order
+fn collect(dir: &Path, files: &mut Vec<PathBuf>, diagnostics: &mut Vec<SyntaxDiagnostic>) {
+    let entries = match fs::read_dir(dir).and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+    {
+        Ok(entries) => entries,
+        Err(err) => return diagnostics.push(unreadable(dir.to_string_lossy().to_string(), err)),
+    };
+    let mut paths = entries.iter().map(|entry| entry.path()).collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || name == "__pycache__" {
+            continue;
+        }
+        if path.is_dir() {
+            collect(&path, files, diagnostics);
+        } else if path.extension().is_some_and(|ext| ext == "py") {
+            files.push(path);
+        }
+    }
+}
+
+fn compile(py: Python<'_>, file: &str, source: &[u8]) -> Option<SyntaxDiagnostic> {
+    let compiled = (|| {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("dont_inherit", true)?;
+        py.import("builtins")?
+            .getattr("compile")?
+            .call((source, file, "exec"), Some(&kwargs))
+            .map(|_| ())
+    })();
+    let err = compiled.err()?;
+    let value = err.value(py);
+    if !err.is_instance_of::<PySyntaxError>(py) {
+        // e.g. a ValueError for null bytes
+        return Some(SyntaxDiagnostic {
+            file: file.to_string(),
+            line: None,
+            column: None,
+            end_line: None,
+            end_column: None,
+            message: value.to_string(),
+            text: None,
+        });
+    }
+    let attr = |name: &str| {
+        value
+            .getattr(name)
+            .and_then(|v| v.extract::<Option<usize>>())
+    };
+    let text = value
+        .getattr("text")
+        .and_then(|v| v.extract::<Option<String>>())
+        .ok()
+        .flatten();
+    Some(SyntaxDiagnostic {
+        file: file.to_string(),
+        line: attr("lineno").ok().flatten(),
+        column: attr("offset").ok().flatten(),
+        end_line: attr("end_lineno").ok().flatten(),
+        end_column: attr("end_offset").ok().flatten(),
+        message: value
+            .getattr("msg")
+            .and_then(|v| v.extract::<String>())
+            .unwrap_or_else(|_| value.to_string()),
+        text: text.map(|text| text.trim_end().to_string()),
+    })
+}
+
+fn unreadable(file: String, err: std::io::Error) -> SyntaxDiagnostic {
+    SyntaxDiagnostic {
+        file,
+        line: None,
+        column: None,
+        end_line: None,
+        end_column: None,
+        message: err.to_string(),
+        text: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_syntax() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("pkg/sub")).unwrap();
+        std::fs::write(dir.join("pkg/__init__.py"), "import sys\nsys.exit(1)\n").unwrap();
+        std::fs::write(dir.join("pkg/broken.py"), "def f(:\n    pass\n").unwrap();
+        std::fs::write(dir.join("pkg/sub/outside.py"), "x = 1\nreturn x\n").unwrap();
+        std::fs::write(dir.join("pkg/sub/notes.txt"), "not python (").unwrap();
+
+        // valid code is only compiled, not executed
+        assert_eq!(check_syntax(dir.join("pkg/__init__.py")), Ok(()));
+        let diagnostics = check_syntax(dir.join("pkg")).unwrap_err();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics[0].file.ends_with("broken.py"));
+        assert_eq!(diagnostics[0].line, Some(1));
+        assert_eq!(diagnostics[0].column, Some(7));
+        assert_eq!(diagnostics[0].text.as_deref(), Some("def f(:"));
+        assert!(diagnostics[1].file.ends_with("outside.py"));
+        assert_eq!(diagnostics[1].line, Some(2));
+        assert_eq!(diagnostics[1].message, "'return' outside function");
+
+        let diagnostics = check_syntax(dir.join("pkg/missing.py")).unwrap_err();
+        assert_eq!(diagnostics[0].line, None);
+
+        assert_eq!(check_syntax_source("ok", "x = [1, 2]\n"), Ok(()));
+        let diagnostics = check_syntax_source("indented", "if True:\nx = 1\n").unwrap_err();
+        assert_eq!(diagnostics[0].file, "<indented>");
+        assert_eq!(diagnostics[0].line, Some(2));
+    }
+}
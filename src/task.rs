@@ -0,0 +1,211 @@
+use crate::worker::Control;
+use crate::{ErrorKind, PyRunnerError, TaskId};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// Result of a task queued with [`PythonModule::spawn`](crate::PythonModule::spawn)
+///
+/// The result can be polled with [`try_join`](Self::try_join), waited for with
+/// [`join`](Self::join) / [`join_timeout`](Self::join_timeout) or awaited, the handle is a
+/// [`Future`]. Dropping the handle doesn't cancel the task, its result is discarded.
+pub struct TaskHandle<T> {
+    shared: Arc<Shared<T>>,
+    id: Option<TaskId>,
+    /// State of the module whose backlog holds the task until it starts
+    control: Weak<Control>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    finished: Condvar,
+}
+
+struct State<T> {
+    started: bool,
+    cancelled: bool,
+    result: Option<Result<T, PyRunnerError>>,
+    waker: Option<Waker>,
+}
+
+/// Worker side of a [`TaskHandle`]
+///
+/// Reports the worker as exited if dropped without a result, e.g. when the queue is
+/// dropped together with the worker thread, unless the queue dropped the task on purpose.
+pub(crate) struct Completer<T> {
+    shared: Option<Arc<Shared<T>>>,
+}
+
+impl<T> TaskHandle<T> {
+    pub(crate) fn new() -> (Self, Completer<T>) {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                started: false,
+                cancelled: false,
+                result: None,
+                waker: None,
+            }),
+            finished: Condvar::new(),
+        });
+        (
+            Self {
+                shared: shared.clone(),
+                id: None,
+                control: Weak::new(),
+            },
+            Completer {
+                shared: Some(shared),
+            },
+        )
+    }
+
+    pub(crate) fn queued(mut self, id: TaskId, control: &Arc<Control>) -> Self {
+        self.id = Some(id);
+        self.control = Arc::downgrade(control);
+        self
+    }
+
+    /// Id of the task in [`PythonModule::pending_tasks`](crate::PythonModule::pending_tasks)
+    pub fn id(&self) -> TaskId {
+        self.id.expect("spawn sets the id")
+    }
+
+    /// Blocks until the task finished
+    pub fn join(self) -> Result<T, PyRunnerError> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self.shared.finished.wait(state).unwrap();
+        }
+    }
+
+    /// Blocks until the task finished or `timeout` passed
+    ///
+    /// Returns a `TimeoutError` in the latter case, the handle can be joined again later.
+    pub fn join_timeout(&self, timeout: Duration) -> Result<T, PyRunnerError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(PyRunnerError::timeout(format!(
+                    "Task did not finish within {timeout:?}"
+                )));
+            }
+            state = self
+                .shared
+                .finished
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Returns the result if the task already finished
+    pub fn try_join(&self) -> Option<Result<T, PyRunnerError>> {
+        self.shared.state.lock().unwrap().result.take()
+    }
+
+    /// Whether a result is ready to be joined
+    pub fn is_finished(&self) -> bool {
+        self.shared.state.lock().unwrap().result.is_some()
+    }
+
+    /// Cancels the task if the worker didn't start it yet
+    ///
+    /// Returns `false` if the task is already running or finished. A cancelled task leaves
+    /// [`pending_tasks`](crate::PythonModule::pending_tasks) and resolves to
+    /// [`ErrorKind::Cancelled`].
+    pub fn cancel(&self) -> bool {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            if state.started {
+                return false;
+            }
+            state.cancelled = true;
+        }
+        // dropping the task finishes the handle, which takes the state lock again
+        if let (Some(id), Some(control)) = (self.id, self.control.upgrade()) {
+            control.backlog.cancel(id);
+        }
+        true
+    }
+}
+
+impl<T> Future for TaskHandle<T> {
+    type Output = Result<T, PyRunnerError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Completer<T> {
+    /// Marks the task as running, `false` if it was cancelled
+    pub(crate) fn start(&self) -> bool {
+        let Some(shared) = &self.shared else {
+            return false;
+        };
+        let mut state = shared.state.lock().unwrap();
+        state.started = true;
+        !state.cancelled
+    }
+
+    pub(crate) fn complete(mut self, result: Result<T, PyRunnerError>) {
+        if let Some(shared) = self.shared.take() {
+            shared.finish(result);
+        }
+    }
+
+    pub(crate) fn cancelled(self) {
+        self.complete(Err(PyRunnerError::new(
+            ErrorKind::Cancelled,
+            "Task was cancelled",
+        )));
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            shared.finish(Err(crate::queue::dropped_error().unwrap_or_else(|| {
+                PyRunnerError::new(
+                    ErrorKind::WorkerDead { reason: None },
+                    "Python thread has exited",
+                )
+            })));
+        }
+    }
+}
+
+impl<T> Shared<T> {
+    fn finish(&self, result: Result<T, PyRunnerError>) {
+        let mut state = self.state.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.finished.notify_all();
+    }
+}
+
+/// Blocks until every task finished, results are in the order of `handles`
+pub fn join_all<T>(
+    handles: impl IntoIterator<Item = TaskHandle<T>>,
+) -> Vec<Result<T, PyRunnerError>> {
+    handles.into_iter().map(TaskHandle::join).collect()
+}
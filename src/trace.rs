@@ -0,0 +1,136 @@
+use crate::{PyRunnerError, Task};
+use pyo3::prelude::*;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tracing::Span;
+use tracing::field::Empty;
+
+static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Span of the task running on this worker thread
+    static TASK_SPAN: RefCell<Option<Span>> = const { RefCell::new(None) };
+}
+
+/// Runs `task` inside a `python_task` span
+///
+/// The span is created on the calling thread, so it is a child of the caller's current span.
+/// It records the task id, how long the task waited in the queue, how long it ran while
+/// holding the GIL and its outcome.
+pub(crate) fn instrument(task: Task) -> Task {
+    let span = tracing::debug_span!(
+        "python_task",
+        task_id = NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed),
+        queue_wait_us = Empty,
+        execution_us = Empty,
+        outcome = Empty,
+    );
+    let queued = Instant::now();
+    Box::new(move |py: &Python, module: &Bound<'_, PyAny>| {
+        let _entered = span.enter();
+        span.record("queue_wait_us", queued.elapsed().as_micros() as u64);
+        let previous = TASK_SPAN.replace(Some(span.clone()));
+        let started = Instant::now();
+        task(py, module);
+        span.record("execution_us", started.elapsed().as_micros() as u64);
+        TASK_SPAN.set(previous);
+    })
+}
+
+/// Records the outcome of the task running on this thread
+pub(crate) fn record_outcome<T>(result: &Result<T, PyRunnerError>) {
+    let outcome = match result {
+        Ok(_) => "ok",
+        Err(_) => "error",
+    };
+    TASK_SPAN.with_borrow(|span| {
+        if let Some(span) = span {
+            span.record("outcome", outcome);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PythonModule;
+    use pyo3::prelude::*;
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    type Fields = HashMap<String, String>;
+    type Recorded = Vec<(Option<String>, Fields)>;
+
+    /// Collects the fields and the parent of every `python_task` span
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Recorded>>);
+
+    struct Visitor<'a>(&'a mut Fields);
+
+    impl Visit for Visitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+
+    impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Spans {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            if attrs.metadata().name() != "python_task" {
+                return;
+            }
+            let parent = ctx.span(id).unwrap().parent().map(|p| p.name().to_string());
+            let mut fields = Fields::new();
+            attrs.record(&mut Visitor(&mut fields));
+            fields.insert("id".to_string(), id.into_u64().to_string());
+            self.0.lock().unwrap().push((parent, fields));
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            let mut spans = self.0.lock().unwrap();
+            if let Some((_, fields)) = spans
+                .iter_mut()
+                .find(|(_, fields)| fields["id"] == id.into_u64().to_string())
+            {
+                values.record(&mut Visitor(fields));
+            }
+        }
+    }
+
+    #[test]
+    fn test_task_span() {
+        let spans = Spans::default();
+        let subscriber = tracing_subscriber::registry().with(spans.clone());
+        let module = PythonModule::new_module(Path::new("./my-module")).unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            let _request = tracing::info_span!("request").entered();
+            module
+                .action(|_, module| module.call_method1("add", (1, 2)).map(|_| ()))
+                .unwrap();
+            assert!(
+                module
+                    .action(|_, module| module.getattr("missing").map(|_| ()))
+                    .is_err()
+            );
+        });
+        // the span is closed after the result was sent
+        module.shutdown(crate::ShutdownMode::DrainQueue);
+
+        let spans = spans.0.lock().unwrap();
+        assert_eq!(spans.len(), 2);
+        for (parent, fields) in spans.iter() {
+            assert_eq!(parent.as_deref(), Some("request"));
+            assert!(fields.contains_key("task_id"));
+            assert!(fields.contains_key("queue_wait_us"));
+            assert!(fields.contains_key("execution_us"));
+        }
+        assert_eq!(spans[0].1["outcome"], "\"ok\"");
+        assert_eq!(spans[1].1["outcome"], "\"error\"");
+    }
+}
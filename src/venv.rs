@@ -0,0 +1,399 @@
+use crate::{PyRunnerError, PythonPath};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A virtual environment created by `python -m venv` or `virtualenv`, or a conda environment
+///```rs
+/// let venv = Venv::open("./venv").unwrap();
+/// venv.activate().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Venv {
+    root: PathBuf,
+    config: HashMap<String, String>,
+    site_packages: PathBuf,
+    conda: bool,
+}
+
+impl Venv {
+    /// Reads `pyvenv.cfg` and locates `site-packages` for the platform's layout
+    ///
+    /// Conda environments have no `pyvenv.cfg` and are recognized by their `conda-meta`
+    /// directory instead.
+    pub fn open(root: impl AsRef<Path>) -> Result<Venv, PyRunnerError> {
+        let root = root.as_ref();
+        let cfg = root.join("pyvenv.cfg");
+        let conda = !cfg.is_file() && root.join("conda-meta").is_dir();
+        let config = match conda {
+            true => HashMap::new(),
+            false => parse_cfg(&fs::read_to_string(&cfg).map_err(|_| {
+                PyErr::new::<pyo3::exceptions::PyFileNotFoundError, _>(format!(
+                    "No {} found",
+                    cfg.display()
+                ))
+            })?),
+        };
+        let site_packages = find_site_packages(root, &config).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyFileNotFoundError, _>(format!(
+                "No site-packages found in {}",
+                root.display()
+            ))
+        })?;
+
+        Ok(Venv {
+            root: root.to_path_buf(),
+            config,
+            site_packages,
+            conda,
+        })
+    }
+
+    /// Opens the conda environment activated in the calling shell, named by `CONDA_PREFIX`
+    pub fn conda_active() -> Result<Venv, PyRunnerError> {
+        let prefix = env::var_os("CONDA_PREFIX").ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyFileNotFoundError, _>(
+                "No conda environment is active, CONDA_PREFIX is not set",
+            )
+        })?;
+        Self::open(prefix)
+    }
+
+    /// Creates a new environment with `python -m venv` and opens it
+    /// `let venv = Venv::create("./venv", "python3.11").unwrap();`
+    pub fn create(root: impl AsRef<Path>, python: impl AsRef<Path>) -> Result<Venv, PyRunnerError> {
+        let root = root.as_ref();
+        run(Command::new(python.as_ref())
+            .arg("-m")
+            .arg("venv")
+            .arg(root))?;
+        Self::open(root)
+    }
+
+    /// Installs packages into the environment with pip
+    /// `venv.pip_install(["numpy", "requests"]).unwrap();`
+    pub fn pip_install<I, S>(&self, packages: I) -> Result<(), PyRunnerError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        run(self.pip().arg("install").args(packages))
+    }
+
+    /// Installs a requirements file into the environment with pip
+    /// `venv.install_requirements("requirements.txt").unwrap();`
+    pub fn install_requirements(
+        &self,
+        requirements: impl AsRef<Path>,
+    ) -> Result<(), PyRunnerError> {
+        run(self
+            .pip()
+            .arg("install")
+            .arg("-r")
+            .arg(requirements.as_ref()))
+    }
+
+    fn pip(&self) -> Command {
+        let mut command = Command::new(self.executable());
+        command.args(["-m", "pip", "--disable-pip-version-check"]);
+        command
+    }
+
+    /// Root directory of the environment
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `site-packages` directory of the environment
+    pub fn site_packages(&self) -> &Path {
+        &self.site_packages
+    }
+
+    /// Value of a `pyvenv.cfg` key, e.g. `home` or `version`, always `None` for conda
+    pub fn config(&self, key: &str) -> Option<&str> {
+        self.config.get(key).map(String::as_str)
+    }
+
+    /// Whether this is a conda environment
+    pub fn is_conda(&self) -> bool {
+        self.conda
+    }
+
+    /// Directory containing the environment's executables (`bin` or `Scripts`, the root of
+    /// a conda environment on Windows)
+    pub fn bin_dir(&self) -> PathBuf {
+        match (cfg!(windows), self.conda) {
+            (true, true) => self.root.clone(),
+            (true, false) => self.root.join("Scripts"),
+            (false, _) => self.root.join("bin"),
+        }
+    }
+
+    /// The environment's Python executable
+    pub fn executable(&self) -> PathBuf {
+        if cfg!(windows) {
+            self.bin_dir().join("python.exe")
+        } else {
+            self.bin_dir().join("python")
+        }
+    }
+
+    /// Directories `activate` puts in front of `PATH`
+    fn path_dirs(&self) -> Vec<PathBuf> {
+        match (cfg!(windows), self.conda) {
+            // like `conda activate`, which also adds the DLLs of the environment
+            (true, true) => vec![
+                self.root.clone(),
+                self.root.join("Library").join("bin"),
+                self.root.join("Scripts"),
+            ],
+            _ => vec![self.bin_dir()],
+        }
+    }
+
+    /// Activates the environment for this process
+    ///
+    /// Sets `VIRTUAL_ENV` (`CONDA_PREFIX` and `CONDA_DEFAULT_ENV` for conda) and puts the
+    /// environment in front of `PYTHONPATH` and `PATH` for interpreters started later, then
+    /// updates `sys.prefix`, `sys.exec_prefix`,
+    /// `sys.executable` and `sys.path` of the embedded interpreter.
+    pub fn activate(&self) -> Result<(), PyRunnerError> {
+        let mut path = self.path_dirs();
+        if let Some(current) = env::var_os("PATH") {
+            path.extend(env::split_paths(&current));
+        }
+        let path = env::join_paths(path).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid PATH: {e}"))
+        })?;
+        unsafe {
+            if self.conda {
+                env::set_var("CONDA_PREFIX", &self.root);
+                let name = self.root.file_name().unwrap_or(self.root.as_os_str());
+                env::set_var("CONDA_DEFAULT_ENV", name);
+            } else {
+                env::set_var("VIRTUAL_ENV", &self.root);
+            }
+            env::set_var("PATH", path);
+        }
+        PythonPath::builder().prepend(&self.site_packages).apply()?;
+
+        Python::with_gil(|py| {
+            let sys = py.import("sys")?;
+            sys.setattr("prefix", &self.root)?;
+            sys.setattr("exec_prefix", &self.root)?;
+            sys.setattr("executable", self.executable())?;
+            py.import("site")?
+                .call_method1("addsitedir", (&self.site_packages,))?;
+            Ok::<_, PyErr>(())
+        })?;
+        Ok(())
+    }
+}
+
+/// Runs `command` to completion, failing with its stderr on a non-zero exit code
+pub(crate) fn run(command: &mut Command) -> Result<(), PyRunnerError> {
+    let output = command.output().map_err(PyErr::from)?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+        "{:?} failed with {}: {}",
+        command,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+    .into())
+}
+
+fn parse_cfg(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn find_site_packages(root: &Path, config: &HashMap<String, String>) -> Option<PathBuf> {
+    // Windows
+    let windows = root.join("Lib").join("site-packages");
+    if windows.is_dir() {
+        return Some(windows);
+    }
+
+    // Linux and macOS, preferring the version recorded in pyvenv.cfg
+    let lib = root.join("lib");
+    let version = config
+        .get("version_info")
+        .or_else(|| config.get("version"))
+        .and_then(|v| {
+            let mut parts = v.split('.');
+            Some(format!("{}.{}", parts.next()?, parts.next()?))
+        });
+    if let Some(version) = version {
+        let site_packages = lib.join(format!("python{version}")).join("site-packages");
+        if site_packages.is_dir() {
+            return Some(site_packages);
+        }
+    }
+    fs::read_dir(&lib)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("python"))
+        .map(|entry| entry.path().join("site-packages"))
+        .find(|path| path.is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_missing() {
+        assert!(Venv::open("./does-not-exist").is_err());
+    }
+
+    #[test]
+    fn test_create() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let venv = Venv::create(root, "python3").unwrap();
+        assert!(venv.executable().is_file());
+        assert!(venv.site_packages().is_dir());
+
+        let requirements = root.join("requirements.txt");
+        fs::write(&requirements, "").unwrap();
+        venv.install_requirements(&requirements).unwrap();
+        assert!(venv.install_requirements(root.join("missing.txt")).is_err());
+    }
+
+    #[test]
+    fn test_open_conda() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let site_packages = root.join("lib").join("python3.99").join("site-packages");
+        fs::create_dir_all(&site_packages).unwrap();
+        assert!(Venv::open(root).is_err());
+
+        fs::create_dir_all(root.join("conda-meta")).unwrap();
+        let venv = Venv::open(root).unwrap();
+        assert!(venv.is_conda());
+        assert_eq!(venv.site_packages(), site_packages);
+        assert_eq!(venv.config("version"), None);
+        if cfg!(windows) {
+            assert_eq!(venv.executable(), root.join("python.exe"));
+        } else {
+            assert_eq!(venv.executable(), root.join("bin").join("python"));
+        }
+    }
+
+    #[test]
+    fn test_activate() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let site_packages = root.join("lib").join("python3.99").join("site-packages");
+        fs::create_dir_all(&site_packages).unwrap();
+        fs::write(
+            root.join("pyvenv.cfg"),
+            "home = /usr/bin\nversion = 3.99.1\n",
+        )
+        .unwrap();
+        fs::write(site_packages.join("venv_marker.py"), "VALUE = 7\n").unwrap();
+
+        let venv = Venv::open(root).unwrap();
+        assert_eq!(venv.site_packages(), site_packages);
+        assert_eq!(venv.config("home"), Some("/usr/bin"));
+
+        let path = env::var_os("PATH");
+        let python_path = env::var_os("PYTHONPATH");
+        unsafe {
+            env::set_var("PYTHONPATH", root.join("kept"));
+        }
+        let saved = Python::with_gil(|py| {
+            let sys = py.import("sys")?;
+            ["prefix", "exec_prefix", "executable"]
+                .map(|attr| sys.getattr(attr).map(|v| v.unbind()))
+                .into_iter()
+                .collect::<PyResult<Vec<_>>>()
+        })
+        .unwrap();
+        venv.activate().unwrap();
+
+        let value =
+            Python::with_gil(|py| py.import("venv_marker")?.getattr("VALUE")?.extract::<i64>())
+                .unwrap();
+        assert_eq!(value, 7);
+        assert_eq!(env::var_os("VIRTUAL_ENV"), Some(root.into()));
+        let entries = env::split_paths(&env::var_os("PYTHONPATH").unwrap()).collect::<Vec<_>>();
+        assert_eq!(entries, [site_packages, root.join("kept")]);
+
+        Python::with_gil(|py| {
+            let sys = py.import("sys")?;
+            for (attr, value) in ["prefix", "exec_prefix", "executable"].iter().zip(saved) {
+                sys.setattr(*attr, value)?;
+            }
+            Ok::<_, PyErr>(())
+        })
+        .unwrap();
+        unsafe {
+            env::remove_var("VIRTUAL_ENV");
+            match python_path {
+                Some(python_path) => env::set_var("PYTHONPATH", python_path),
+                None => env::remove_var("PYTHONPATH"),
+            }
+            if let Some(path) = path {
+                env::set_var("PATH", path);
+            }
+        }
+    }
+
+    /// Environment whose interpreter links to the running one, with `stack.NAME` set to `name`
+    #[cfg(unix)]
+    fn fake_venv(root: &Path, name: &str) -> Venv {
+        let site_packages = root.join("lib").join("python3.99").join("site-packages");
+        fs::create_dir_all(&site_packages).unwrap();
+        fs::create_dir_all(root.join("bin")).unwrap();
+        // not `sys.executable`, which may be a launcher like a pyenv shim
+        let prefix = Python::with_gil(|py| {
+            py.import("sys")?
+                .getattr("base_exec_prefix")?
+                .extract::<PathBuf>()
+        })
+        .unwrap();
+        let python = prefix.join("bin").join("python3");
+        let home = python.parent().unwrap().display().to_string();
+        std::os::unix::fs::symlink(python, root.join("bin").join("python")).unwrap();
+        fs::write(
+            root.join("pyvenv.cfg"),
+            format!("home = {home}\nversion = 3.99.0\n"),
+        )
+        .unwrap();
+        fs::write(site_packages.join("stack.py"), format!("NAME = '{name}'\n")).unwrap();
+        Venv::open(root).unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_venv_per_module() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let main = root.join("main.py");
+        fs::write(&main, "import sys\nfrom stack import NAME\n").unwrap();
+
+        let modules = ["a", "b"].map(|name| {
+            let venv = fake_venv(&root.join(name), name);
+            crate::PythonModuleBuilder::new_project(&main)
+                .venv(&venv)
+                .subprocess(true)
+                .build()
+                .unwrap()
+        });
+        for (module, name) in modules.iter().zip(["a", "b"]) {
+            assert_eq!(module.get::<String>("NAME").unwrap(), name);
+            let executable = module.get::<PathBuf>("sys.executable").unwrap();
+            assert_eq!(executable, root.join(name).join("bin").join("python"));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{PyRunnerError, PythonModule, PythonModuleBuilder, Task, reload_module};
+use notify::{RecursiveMode, Watcher};
+use pyo3::prelude::*;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+impl PythonModule {
+    /// Loads a module (directory) or project (file) and reloads it whenever a `.py` file
+    /// below its directory changes
+    ///
+    /// `on_reload` is called on the worker thread with the outcome of every reload.
+    ///```rs
+    /// let module = PythonModule::new_watched("./my-module", |result| {
+    ///     if let Err(e) = result {
+    ///         eprintln!("reload failed: {e}");
+    ///     }
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn new_watched(
+        path: impl AsRef<Path>,
+        on_reload: impl Fn(Result<(), PyRunnerError>) + Send + Sync + 'static,
+    ) -> Result<PythonModule, PyRunnerError> {
+        let path = path.as_ref();
+        let dir = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(Path::new("."))
+        };
+        let mut module = PythonModuleBuilder::new(path).build()?;
+
+        let task_sender = module.task_sender.clone();
+        let on_reload = Arc::new(on_reload);
+        let pending = Arc::new(AtomicBool::new(false));
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                let is_source = event
+                    .paths
+                    .iter()
+                    .any(|path| path.extension().is_some_and(|ext| ext == "py"));
+                if !is_source || event.kind.is_access() {
+                    return;
+                }
+                // editors emit several events per save, one queued reload covers all of them
+                if pending.swap(true, Ordering::SeqCst) {
+                    return;
+                }
+                let pending = pending.clone();
+                let on_reload = on_reload.clone();
+                let task: Task = Box::new(move |py: &Python, module: &Bound<'_, PyAny>| {
+                    pending.store(false, Ordering::SeqCst);
+                    on_reload(
+                        reload_module(*py, module).map_err(|e| PyRunnerError::from_py(*py, e)),
+                    );
+                });
+                let _ = task_sender.send(Some(task));
+            })
+            .map_err(watch_error)?;
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+        module.watcher = Some(watcher);
+
+        Ok(module)
+    }
+}
+
+fn watch_error(e: notify::Error) -> PyRunnerError {
+    PyErr::new::<pyo3::exceptions::PyOSError, _>(format!("Failed to watch module: {e}")).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_new_watched() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("__init__.py"), "VALUE = 1\n").unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let module = PythonModule::new_watched(dir, move |result| {
+            let _ = sender.send(result.is_ok());
+        })
+        .unwrap();
+
+        std::fs::write(dir.join("__init__.py"), "VALUE = 1000\n").unwrap();
+        assert!(receiver.recv_timeout(Duration::from_secs(10)).unwrap());
+        let value = module
+            .action(|_, module| module.getattr("VALUE")?.extract::<i64>())
+            .unwrap();
+        assert_eq!(value, 1000);
+    }
+}
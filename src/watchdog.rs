@@ -0,0 +1,180 @@
+use crate::TaskId;
+use crate::worker::Control;
+use crossbeam::channel::{Receiver, RecvTimeoutError};
+use pyo3::prelude::*;
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub(crate) type OnHang = Arc<dyn Fn(&Hang) + Send + Sync>;
+
+/// Task that held the worker longer than the watchdog limit, see
+/// [`PythonModuleBuilder::watchdog`](crate::PythonModuleBuilder::watchdog)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hang {
+    /// `None` for internal tasks like releasing a [`PyHandle`](crate::PyHandle)
+    pub task: Option<TaskId>,
+    pub running_for: Duration,
+}
+
+/// What the watchdog does with a hung task besides reporting it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HangAction {
+    /// Only calls the callback
+    #[default]
+    Notify,
+    /// Raises `KeyboardInterrupt` in the task like
+    /// [`PythonModule::interrupt`](crate::PythonModule::interrupt)
+    ///
+    /// Native code holding the GIL only sees it once it returns to Python, the watchdog keeps
+    /// reporting meanwhile.
+    Interrupt,
+    /// Kills the child process of a subprocess module, which fails the task with a
+    /// `ConnectionError`, other modules are interrupted instead
+    Kill,
+}
+
+pub(crate) struct Watchdog {
+    pub limit: Duration,
+    pub action: HangAction,
+    pub on_hang: OnHang,
+}
+
+impl Watchdog {
+    /// Checks the worker until it exits, reporting every task running longer than the limit
+    /// once
+    pub(crate) fn start(self, control: Arc<Control>, exit: Receiver<()>) {
+        let poll = (self.limit / 4).clamp(Duration::from_millis(5), Duration::from_secs(1));
+        thread::spawn(move || {
+            let mut reported = None;
+            while let Err(RecvTimeoutError::Timeout) = exit.recv_timeout(poll) {
+                let Some(since) = *control.busy_since.lock().unwrap() else {
+                    continue;
+                };
+                let running_for = since.elapsed();
+                if running_for < self.limit || reported == Some(since) {
+                    continue;
+                }
+                reported = Some(since);
+                (self.on_hang)(&Hang {
+                    task: control.backlog.running(),
+                    running_for,
+                });
+                self.act(&control, since);
+            }
+        });
+    }
+
+    /// Never waits for the GIL, which the hung task may hold in native code
+    fn act(&self, control: &Arc<Control>, since: Instant) {
+        // the hung task may have finished while the callback ran
+        if *control.busy_since.lock().unwrap() != Some(since) {
+            return;
+        }
+        match (self.action, control.child.get()) {
+            (HangAction::Notify, _) => {}
+            (HangAction::Kill, Some(&pid)) => kill(pid),
+            (HangAction::Interrupt | HangAction::Kill, _) => {
+                // the async exception needs the GIL, wait for it apart from the watchdog
+                let control = control.clone();
+                thread::spawn(move || {
+                    Python::with_gil(|_| {
+                        if *control.busy_since.lock().unwrap() == Some(since) {
+                            control.interrupt();
+                        }
+                    })
+                });
+            }
+        }
+    }
+}
+
+/// Kills the process `pid` with the system's tools
+fn kill(pid: u32) {
+    let pid = pid.to_string();
+    let _ = match cfg!(windows) {
+        true => Command::new("taskkill").args(["/F", "/PID", &pid]).output(),
+        false => Command::new("kill").args(["-9", &pid]).output(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorKind, PythonModuleBuilder};
+    use std::sync::Mutex;
+
+    const HANGING: &str = "import time\ndef hang():\n    while True:\n        time.sleep(0.01)\n";
+
+    #[test]
+    fn test_watchdog() {
+        let hangs = Arc::new(Mutex::new(Vec::new()));
+        let seen = hangs.clone();
+        let module = PythonModuleBuilder::from_source("watchdog_hang", HANGING)
+            .watchdog(
+                Duration::from_millis(100),
+                HangAction::Interrupt,
+                move |hang| {
+                    seen.lock().unwrap().push(hang.clone());
+                },
+            )
+            .build()
+            .unwrap();
+        let handle = module
+            .spawn(|_, module| module.call_method0("hang").map(|_| ()))
+            .unwrap();
+        let id = handle.id();
+        let err = handle.join().unwrap_err();
+        assert_eq!(err.exception_type(), "KeyboardInterrupt");
+        let hangs = hangs.lock().unwrap().clone();
+        assert_eq!(hangs.len(), 1);
+        assert_eq!(hangs[0].task, Some(id));
+        assert!(hangs[0].running_for >= Duration::from_millis(100));
+        module.action(|_, _| Ok(())).unwrap();
+    }
+
+    #[test]
+    fn test_watchdog_gil_held() {
+        let reported = Arc::new(Mutex::new(None));
+        let seen = reported.clone();
+        let module = PythonModuleBuilder::from_source("watchdog_gil", "")
+            .watchdog(
+                Duration::from_millis(50),
+                HangAction::Interrupt,
+                move |_| {
+                    *seen.lock().unwrap() = Some(Instant::now());
+                },
+            )
+            .build()
+            .unwrap();
+        // native code holding the GIL can't be interrupted, but is still reported in time
+        module
+            .action(|_, _| {
+                thread::sleep(Duration::from_millis(500));
+                Ok(())
+            })
+            .unwrap();
+        let finished = Instant::now();
+        let reported = reported.lock().unwrap().unwrap();
+        assert!(finished - reported > Duration::from_millis(200));
+        module.action(|_, _| Ok(())).unwrap();
+    }
+
+    #[test]
+    fn test_watchdog_kill() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("main.py"), HANGING).unwrap();
+        let module = PythonModuleBuilder::new_project(dir.join("main.py"))
+            .subprocess(true)
+            .watchdog(Duration::from_millis(100), HangAction::Kill, |_| {})
+            .build()
+            .unwrap();
+        let err = module
+            .action(|_, module| module.call_method0("hang").map(|_| ()))
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Python);
+        assert_eq!(err.exception_type(), "ConnectionError");
+    }
+}
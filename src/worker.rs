@@ -0,0 +1,582 @@
+use crate::handle::Handles;
+use crate::limit::Limits;
+use crate::queue::{Backlog, Lanes};
+use crate::stats::Metrics;
+use crate::{ErrorKind, PyRunnerError, PythonModule, Task};
+use crossbeam::channel::{Receiver, RecvTimeoutError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyCFunction, PyDict, PyTuple};
+use std::any::Any;
+use std::ffi::c_long;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How [`PythonModule::shutdown`] treats tasks that are still queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownMode {
+    /// Runs every queued task, then stops the worker
+    DrainQueue,
+    /// Discards queued tasks and stops after the running task
+    Immediate,
+    /// Like `DrainQueue`, but discards what is left after the duration and returns without
+    /// waiting for the running task
+    ForceAfter(Duration),
+}
+
+/// Why the worker thread of a [`PythonModule`] exited
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The module was shut down or dropped
+    Shutdown,
+    /// The worker failed with a Python exception, formatted with its traceback
+    Error(String),
+    /// The worker panicked, holds the panic message
+    Panic(String),
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitReason::Shutdown => write!(f, "shut down"),
+            ExitReason::Error(e) => write!(f, "failed with {e}"),
+            ExitReason::Panic(message) => write!(f, "panicked: {message}"),
+        }
+    }
+}
+
+/// State shared between a [`PythonModule`] and its worker thread
+#[derive(Default)]
+pub(crate) struct Control {
+    stop: AtomicBool,
+    closed: AtomicBool,
+    discarded: AtomicUsize,
+    /// `threading.get_ident()` of the worker, 0 until it is known
+    thread_id: AtomicU64,
+    /// Whether a task is running, only changed while holding the GIL
+    running: AtomicBool,
+    /// When the running task started, see [`watchdog`](crate::PythonModuleBuilder::watchdog)
+    pub(crate) busy_since: Mutex<Option<Instant>>,
+    interrupted: AtomicBool,
+    /// Process id of the child of a subprocess module
+    pub(crate) child: OnceLock<u32>,
+    pub(crate) handles: Handles,
+    pub(crate) lanes: Lanes,
+    pub(crate) backlog: Backlog,
+    pub(crate) metrics: Metrics,
+    pub(crate) limits: Limits,
+    exit_reason: Mutex<Option<ExitReason>>,
+}
+
+impl Control {
+    /// Whether the module already asked the worker to stop
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Raises `KeyboardInterrupt` in the running task, see [`PythonModule::interrupt`]
+    pub(crate) fn interrupt(&self) -> bool {
+        Python::with_gil(|_| {
+            let id = self.thread_id.load(Ordering::SeqCst);
+            if id == 0 || !self.running.load(Ordering::SeqCst) {
+                return false;
+            }
+            self.interrupted.store(true, Ordering::SeqCst);
+            // SAFETY: the GIL is held and the worker's thread state belongs to the main
+            // interpreter
+            unsafe {
+                ffi::PyThreadState_SetAsyncExc(id as c_long, ffi::PyExc_KeyboardInterrupt) > 0
+            }
+        })
+    }
+
+    /// Whether the worker is running a task
+    pub(crate) fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Marks the module as closed, returns whether it already was
+    pub(crate) fn close(&self) -> bool {
+        self.closed.swap(true, Ordering::SeqCst)
+    }
+}
+
+/// Runs the body of a worker thread and records why it exited
+///
+/// A panic is turned into the thread's error instead of unwinding further.
+pub(crate) fn run_worker(control: &Control, body: impl FnOnce() -> PyResult<()>) -> PyResult<()> {
+    let (reason, result) = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => (ExitReason::Shutdown, Ok(())),
+        Ok(Err(e)) => {
+            let message = Python::with_gil(|py| PyRunnerError::from_py(py, e.clone_ref(py)));
+            (ExitReason::Error(message.to_string()), Err(e))
+        }
+        Err(payload) => {
+            let message = panic_message(payload);
+            let e = PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                "Python thread panicked: {message}"
+            ));
+            (ExitReason::Panic(message), Err(e))
+        }
+    };
+    *control.exit_reason.lock().unwrap() = Some(reason);
+    result
+}
+
+/// Message a panic was started with
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|v| v.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string())
+}
+
+/// Runs tasks until the queue is closed or the worker is told to stop
+pub(crate) fn serve(
+    py: Python<'_>,
+    module: &Bound<'_, PyAny>,
+    task_receiver: &Receiver<Option<Task>>,
+    control: &Control,
+) {
+    control.handles.attach();
+    if let Ok(id) = py
+        .import("threading")
+        .and_then(|threading| threading.call_method0("get_ident")?.extract::<u64>())
+    {
+        control.thread_id.store(id, Ordering::SeqCst);
+    }
+    let next = || py.allow_threads(|| task_receiver.recv());
+    let run = |task: Task| {
+        control.running.store(true, Ordering::SeqCst);
+        *control.busy_since.lock().unwrap() = Some(Instant::now());
+        task(&py, module);
+        *control.busy_since.lock().unwrap() = None;
+        control.running.store(false, Ordering::SeqCst);
+        if control.interrupted.swap(false, Ordering::SeqCst) {
+            // SAFETY: the GIL is held, a null exception clears a pending interrupt that
+            // arrived too late to hit the task
+            unsafe {
+                ffi::PyThreadState_SetAsyncExc(
+                    control.thread_id.load(Ordering::SeqCst) as c_long,
+                    ptr::null_mut(),
+                );
+            }
+        }
+    };
+    while let Ok(Some(task)) = next() {
+        if control.stop.load(Ordering::SeqCst) {
+            control.discarded.fetch_add(1, Ordering::SeqCst);
+            break;
+        }
+        run_prioritized(control, task, || task_receiver.is_empty(), run);
+    }
+    finish_lanes(control, run);
+    let discarded = task_receiver.try_iter().flatten().count();
+    control.discarded.fetch_add(discarded, Ordering::SeqCst);
+    control.handles.clear();
+}
+
+/// Runs the [`Priority::High`](crate::Priority::High) tasks, then `task`, then prioritized
+/// tasks as long as `idle` says the channel is empty
+fn run_prioritized(control: &Control, task: Task, idle: impl Fn() -> bool, run: impl Fn(Task)) {
+    while let Some(high) = control.lanes.pop_high() {
+        run(high);
+    }
+    run(task);
+    while idle() && !control.stop.load(Ordering::SeqCst) {
+        let Some(next) = control.lanes.pop() else {
+            break;
+        };
+        run(next);
+    }
+}
+
+/// Runs the prioritized tasks left when the worker stops, unless it was told to discard them
+fn finish_lanes(control: &Control, run: impl Fn(Task)) {
+    while !control.stop.load(Ordering::SeqCst) {
+        let Some(next) = control.lanes.pop() else {
+            break;
+        };
+        run(next);
+    }
+    let discarded = control.lanes.clear();
+    control.discarded.fetch_add(discarded, Ordering::SeqCst);
+}
+
+/// Runs an asyncio event loop until the queue is closed or the worker is told to stop
+///
+/// Tasks are forwarded to the loop by a helper thread and run as loop callbacks, so
+/// coroutines awaited by [`PythonModule::action_await`] run concurrently. Tasks still running
+/// on the loop when it stops are cancelled.
+pub(crate) fn serve_loop(
+    py: Python<'_>,
+    module: &Bound<'_, PyAny>,
+    task_receiver: &Receiver<Option<Task>>,
+    control: &Arc<Control>,
+) -> PyResult<()> {
+    control.handles.attach();
+    let asyncio = py.import("asyncio")?;
+    let event_loop = asyncio.call_method0("new_event_loop")?;
+    asyncio.call_method1("set_event_loop", (&event_loop,))?;
+
+    let (ready_sender, ready_receiver) = crossbeam::channel::unbounded::<Option<Task>>();
+    let drain = {
+        let module = module.clone().unbind();
+        let event_loop = event_loop.clone().unbind();
+        let control = control.clone();
+        let task_receiver = task_receiver.clone();
+        PyCFunction::new_closure(
+            py,
+            None,
+            None,
+            move |args: &Bound<'_, PyTuple>, _: Option<&Bound<'_, PyDict>>| {
+                let py = args.py();
+                let run = |task: Task| task(&py, module.bind(py));
+                while let Ok(task) = ready_receiver.try_recv() {
+                    match task {
+                        Some(task) if !control.stop.load(Ordering::SeqCst) => {
+                            let idle = || ready_receiver.is_empty() && task_receiver.is_empty();
+                            run_prioritized(&control, task, idle, run);
+                        }
+                        task => {
+                            finish_lanes(&control, run);
+                            let discarded = usize::from(task.is_some())
+                                + ready_receiver.try_iter().flatten().count();
+                            control.discarded.fetch_add(discarded, Ordering::SeqCst);
+                            event_loop.call_method0(py, "stop")?;
+                            break;
+                        }
+                    }
+                }
+                Ok::<_, PyErr>(())
+            },
+        )?
+    };
+
+    // detached, it exits after forwarding the closing `None`
+    {
+        let task_receiver = task_receiver.clone();
+        let event_loop = event_loop.clone().unbind();
+        let drain = drain.clone().unbind();
+        thread::spawn(move || {
+            loop {
+                let task = task_receiver.recv().unwrap_or(None);
+                let last = task.is_none();
+                let _ = ready_sender.send(task);
+                // fails once the loop is closed, then nobody waits for the task anymore
+                let _ = Python::with_gil(|py| {
+                    event_loop.call_method1(py, "call_soon_threadsafe", (drain.bind(py),))
+                });
+                if last {
+                    break;
+                }
+            }
+        });
+    }
+
+    let result = event_loop.call_method0("run_forever").map(|_| ());
+    let pending = asyncio.call_method1("all_tasks", (&event_loop,))?;
+    for task in pending.try_iter()? {
+        task?.call_method0("cancel")?;
+    }
+    let gather = asyncio.getattr("gather")?.call(
+        PyTuple::new(py, pending.try_iter()?.collect::<PyResult<Vec<_>>>()?)?,
+        Some(&[("return_exceptions", true)].into_py_dict(py)?),
+    )?;
+    event_loop.call_method1("run_until_complete", (gather,))?;
+    event_loop.call_method0("close")?;
+    control.handles.clear();
+    result
+}
+
+impl PythonModule {
+    /// Stops the worker thread
+    ///
+    /// Returns `true` if every queued task ran. Later actions fail, [`join`](Self::join)
+    /// returns the worker's final result.
+    ///```rs
+    /// let finished = module.shutdown(ShutdownMode::ForceAfter(Duration::from_secs(5)));
+    /// ```
+    pub fn shutdown(&self, mode: ShutdownMode) -> bool {
+        if self.control.close() {
+            return self.wait_exit(None);
+        }
+        self.signal_start(false);
+        match mode {
+            ShutdownMode::DrainQueue => {
+                let _ = self.push(None);
+                self.wait_exit(None)
+            }
+            ShutdownMode::Immediate => {
+                self.control.stop.store(true, Ordering::SeqCst);
+                let _ = self.push(None);
+                self.wait_exit(None)
+            }
+            ShutdownMode::ForceAfter(timeout) => {
+                let _ = self.push(None);
+                if self.wait_exit(Some(timeout)) {
+                    return true;
+                }
+                self.control.stop.store(true, Ordering::SeqCst);
+                false
+            }
+        }
+    }
+
+    /// Drains the queue, waits for the worker thread and returns its final result
+    pub fn join(mut self) -> Result<(), PyRunnerError> {
+        if !self.control.close() {
+            self.signal_start(false);
+            let _ = self.push(None);
+        }
+        let Some(handle) = self.thread_handle.take() else {
+            return Ok(());
+        };
+        match handle.join() {
+            Ok(result) => result.map_err(PyRunnerError::from),
+            Err(_) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Python thread panicked",
+            )
+            .into()),
+        }
+    }
+
+    /// Imports a module built with [`PythonModuleBuilder::deferred`](crate::PythonModuleBuilder::deferred)
+    ///
+    /// Blocks until the import finished and returns its error. Returns right away if the
+    /// module was imported already, the first action starts the import as well but doesn't
+    /// wait for it.
+    ///```rs
+    /// let module = PythonModuleBuilder::new_module("./my-module").deferred(true).build()?;
+    /// // later, e.g. when the plugin is first needed
+    /// module.start()?;
+    /// ```
+    pub fn start(&self) -> Result<(), PyRunnerError> {
+        let mut init = self.init.lock().unwrap();
+        let Some(receiver) = init.take() else {
+            if self.is_alive() && self.exit_reason().is_none() {
+                return Ok(());
+            }
+            return Err(self.exited_error());
+        };
+        self.signal_start(true);
+        match receiver.recv() {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                // the worker exits right after a failed import
+                let _ = self.exit_receiver.recv();
+                Err(e.init_failed())
+            }
+            Err(_) => Err(self.terminated_error()),
+        }
+    }
+
+    /// Tells the worker of a deferred module to import it or to exit, the first call wins
+    pub(crate) fn signal_start(&self, start: bool) {
+        if let Some(sender) = &self.start {
+            let _ = sender.try_send(start);
+        }
+    }
+
+    /// Raises `KeyboardInterrupt` in the running task
+    ///
+    /// The exception is raised at the next Python bytecode, so a loop in Python is aborted
+    /// while a blocking call into C (e.g. `time.sleep`) finishes first. Returns `false` if no
+    /// task is running; the child of a subprocess module isn't
+    /// interrupted.
+    pub fn interrupt(&self) -> bool {
+        self.control.interrupt()
+    }
+
+    /// Whether the worker thread is still accepting tasks
+    pub fn is_alive(&self) -> bool {
+        !self.control.is_closed()
+            && self
+                .thread_handle
+                .as_ref()
+                .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Why the worker thread exited, `None` while it is running
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        self.control.exit_reason.lock().unwrap().clone()
+    }
+
+    /// Error returned for actions on a worker that exited
+    pub(crate) fn exited_error(&self) -> PyRunnerError {
+        let reason = self.exit_reason();
+        let message = match &reason {
+            Some(reason) => format!("Python thread has exited: {reason}"),
+            None => "Python thread has exited".to_string(),
+        };
+        PyRunnerError::new(ErrorKind::WorkerDead { reason }, message)
+    }
+
+    /// Error for a task the worker dropped without a result
+    ///
+    /// That only happens while the worker exits, e.g. if it panicked, so this waits for the
+    /// exit to report its reason.
+    pub(crate) fn terminated_error(&self) -> PyRunnerError {
+        let _ = self.exit_receiver.recv();
+        let reason = self.exit_reason();
+        let message = match &reason {
+            Some(reason) => format!("Python thread terminated during the call: {reason}"),
+            None => "Python thread terminated during the call".to_string(),
+        };
+        PyRunnerError::new(ErrorKind::WorkerDead { reason }, message)
+    }
+
+    /// Waits for the worker thread to exit, `false` if it didn't or discarded tasks
+    fn wait_exit(&self, timeout: Option<Duration>) -> bool {
+        let exited = match timeout {
+            Some(timeout) => matches!(
+                self.exit_receiver.recv_timeout(timeout),
+                Err(RecvTimeoutError::Disconnected)
+            ),
+            None => self.exit_receiver.recv().is_err(),
+        };
+        exited && self.control.discarded.load(Ordering::SeqCst) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PythonModuleBuilder;
+
+    fn sleep(module: &PythonModule, seconds: f64) -> crate::TaskHandle<()> {
+        module
+            .spawn(move |py, _| {
+                py.import("time")?.call_method1("sleep", (seconds,))?;
+                Ok(())
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_shutdown_drain() {
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .build()
+            .unwrap();
+        let handle = sleep(&module, 0.1);
+        assert!(module.shutdown(ShutdownMode::DrainQueue));
+        handle.join().unwrap();
+        assert!(module.action(|_, _| Ok(())).is_err());
+        module.join().unwrap();
+    }
+
+    #[test]
+    fn test_shutdown_immediate() {
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .build()
+            .unwrap();
+        let running = sleep(&module, 0.2);
+        while !running_started(&module) {
+            std::thread::yield_now();
+        }
+        let queued = sleep(&module, 0.2);
+        assert!(!module.shutdown(ShutdownMode::Immediate));
+        running.join().unwrap();
+        assert!(queued.join().is_err());
+    }
+
+    #[test]
+    fn test_shutdown_force_after() {
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .build()
+            .unwrap();
+        let _running = sleep(&module, 0.5);
+        assert!(!module.shutdown(ShutdownMode::ForceAfter(Duration::from_millis(50))));
+        module.join().unwrap();
+    }
+
+    #[test]
+    fn test_exit_reason() {
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .build()
+            .unwrap();
+        assert_eq!(module.exit_reason(), None);
+        let err = module
+            .action(|_, _| -> PyResult<()> { panic!("action fails") })
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Panicked);
+        assert!(err.message().contains("action fails"));
+        assert!(module.is_alive());
+
+        module
+            .queue(Box::new(|_, _| panic!("worker dies")))
+            .unwrap();
+        while module.exit_reason().is_none() {
+            std::thread::yield_now();
+        }
+        assert_eq!(
+            module.exit_reason(),
+            Some(ExitReason::Panic("worker dies".to_string()))
+        );
+        let err = module.action(|_, _| Ok(())).unwrap_err();
+        assert!(err.message().contains("worker dies"));
+        assert!(module.join().unwrap_err().message().contains("worker dies"));
+
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .build()
+            .unwrap();
+        module.shutdown(ShutdownMode::DrainQueue);
+        assert_eq!(module.exit_reason(), Some(ExitReason::Shutdown));
+    }
+
+    #[test]
+    fn test_terminated_during_call() {
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .build()
+            .unwrap();
+        module
+            .queue(Box::new(|py, _| {
+                let _ = py
+                    .import("time")
+                    .and_then(|t| t.call_method1("sleep", (0.05,)));
+                panic!("worker dies")
+            }))
+            .unwrap();
+        let err = module.action(|_, _| Ok(())).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::WorkerDead {
+                reason: Some(ExitReason::Panic("worker dies".to_string()))
+            }
+        );
+        assert!(err.message().contains("worker dies"));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let module = PythonModuleBuilder::new_module("./my-module")
+            .build()
+            .unwrap();
+        assert!(!module.interrupt());
+        let handle = module
+            .spawn(|py, _| {
+                py.run(c"while True: pass", None, None)?;
+                Ok(())
+            })
+            .unwrap();
+        while !module.interrupt() {
+            std::thread::yield_now();
+        }
+        let err = handle.join().unwrap_err();
+        assert_eq!(err.exception_type(), "KeyboardInterrupt");
+        let sum = module
+            .action(|_, module| module.call_method1("add", (1, 2))?.extract::<i64>())
+            .unwrap();
+        assert_eq!(sum, 3);
+    }
+
+    fn running_started(module: &PythonModule) -> bool {
+        module.task_sender.is_empty()
+    }
+}